@@ -0,0 +1,22 @@
+CREATE TABLE audit_log (
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    actor_user_id uuid NOT NULL REFERENCES users(user_id),
+    action TEXT NOT NULL,
+    target_id TEXT NULL,
+    ip TEXT NOT NULL,
+    user_agent TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+CREATE INDEX audit_log_created_at_idx ON audit_log (created_at);
+
+-- 审计日志只允许追加，禁止修改或删除
+CREATE FUNCTION reject_audit_log_modification() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_append_only
+    BEFORE UPDATE OR DELETE ON audit_log
+    FOR EACH ROW EXECUTE FUNCTION reject_audit_log_modification();
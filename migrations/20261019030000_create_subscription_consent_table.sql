@@ -0,0 +1,14 @@
+CREATE TABLE subscription_consent (
+    subscriber_id uuid NOT NULL REFERENCES subscription(id),
+    PRIMARY KEY (subscriber_id),
+    -- 订阅时展示给用户的同意声明原文
+    consent_text TEXT NOT NULL,
+    form_origin TEXT NOT NULL,
+    subscribe_ip TEXT NOT NULL,
+    subscribe_user_agent TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL,
+    -- 用户点击确认链接时记录
+    confirm_ip TEXT NULL,
+    confirm_user_agent TEXT NULL,
+    confirmed_at timestamptz NULL
+);
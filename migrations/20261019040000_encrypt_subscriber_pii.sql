@@ -0,0 +1,33 @@
+-- 订阅者的姓名及邮箱地址加密储存
+-- `email_index`为邮箱地址的HMAC，用于按邮箱查询及唯一性检查
+-- `pii_key_id`为加密使用的密钥id，轮换密钥时据此查找需要重新加密的行
+ALTER TABLE subscription
+    ADD COLUMN name_ciphertext TEXT NULL,
+    ADD COLUMN email_ciphertext TEXT NULL,
+    ADD COLUMN email_index TEXT NULL UNIQUE,
+    ADD COLUMN pii_key_id TEXT NULL,
+    ALTER COLUMN name DROP NOT NULL,
+    ALTER COLUMN email DROP NOT NULL;
+
+-- 已有的明文数据需要执行`rotate_pii_keys`加密，加密后明文列会被清空
+ALTER TABLE subscription
+    ADD CONSTRAINT subscription_pii_encrypted CHECK (
+        (pii_key_id IS NULL AND name IS NOT NULL AND email IS NOT NULL) OR
+        (pii_key_id IS NOT NULL AND name IS NULL AND email IS NULL AND
+            name_ciphertext IS NOT NULL AND email_ciphertext IS NOT NULL AND
+            email_index IS NOT NULL)
+    );
+
+-- 发送队列引用订阅者，不再复制邮箱地址
+ALTER TABLE issue_delivery_queue
+    ADD COLUMN subscriber_id uuid NULL REFERENCES subscription(id);
+UPDATE issue_delivery_queue q
+    SET subscriber_id = s.id
+    FROM subscription s
+    WHERE s.email = q.subscriber_email;
+DELETE FROM issue_delivery_queue WHERE subscriber_id IS NULL;
+ALTER TABLE issue_delivery_queue
+    DROP CONSTRAINT issue_delivery_queue_pkey,
+    DROP COLUMN subscriber_email,
+    ALTER COLUMN subscriber_id SET NOT NULL,
+    ADD PRIMARY KEY (newsletter_issue_id, subscriber_id);
@@ -0,0 +1,6 @@
+-- 管理员单点登录: 身份提供方的subject及已验证的邮箱地址
+-- 仅通过单点登录创建的管理员没有密码
+ALTER TABLE users
+    ADD COLUMN oidc_subject TEXT NULL UNIQUE,
+    ADD COLUMN email TEXT NULL UNIQUE,
+    ALTER COLUMN password_hash DROP NOT NULL;
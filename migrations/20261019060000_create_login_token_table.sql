@@ -0,0 +1,10 @@
+-- 管理员免密登录链接
+-- 只保存令牌及浏览器绑定值的哈希，令牌使用一次后即删除
+CREATE TABLE login_token (
+    token_hash TEXT NOT NULL,
+    PRIMARY KEY (token_hash),
+    user_id uuid NOT NULL REFERENCES users(user_id) ON DELETE CASCADE,
+    browser_binding_hash TEXT NOT NULL,
+    expires_at timestamptz NOT NULL
+);
+CREATE INDEX login_token_user_id_idx ON login_token (user_id);
@@ -0,0 +1,8 @@
+-- 订阅者管理页面的搜索及筛选
+-- 姓名及邮箱地址已加密，无法直接建立pg_trgm索引
+-- 改为储存每个三元组的HMAC盲索引，使用GIN索引按包含关系查询
+-- 已有的订阅者需执行一次`rotate_pii_keys`补全索引
+ALTER TABLE subscription ADD COLUMN search_trigrams TEXT[] NOT NULL DEFAULT '{}';
+CREATE INDEX subscription_search_trigrams_idx ON subscription USING GIN (search_trigrams);
+CREATE INDEX subscription_subscribed_at_idx ON subscription (subscribed_at);
+CREATE INDEX subscription_status_idx ON subscription (status);
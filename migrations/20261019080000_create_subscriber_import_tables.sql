@@ -0,0 +1,22 @@
+-- 批量导入订阅者
+-- 以待确认状态导入的订阅者，确认邮件由后台工作线程逐个发送
+CREATE TABLE confirmation_email_queue (
+    subscriber_id uuid NOT NULL REFERENCES subscription(id),
+    PRIMARY KEY (subscriber_id),
+    subscription_token TEXT NOT NULL,
+    enqueued_at timestamptz NOT NULL
+);
+-- 导入记录，被拒绝行的报告包含个人信息，加密后储存
+CREATE TABLE subscriber_import (
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    -- 通过命令行导入时为空
+    user_id uuid NULL REFERENCES users(user_id) ON DELETE SET NULL,
+    filename TEXT NOT NULL,
+    mode TEXT NOT NULL,
+    imported_count BIGINT NOT NULL,
+    rejected_count BIGINT NOT NULL,
+    report_ciphertext TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+CREATE INDEX subscriber_import_created_at_idx ON subscriber_import (created_at);
@@ -0,0 +1,39 @@
+-- 订阅者标签
+CREATE TABLE subscription_tag (
+    subscriber_id uuid NOT NULL REFERENCES subscription(id),
+    tag TEXT NOT NULL,
+    PRIMARY KEY (subscriber_id, tag)
+);
+CREATE INDEX subscription_tag_tag_idx ON subscription_tag (tag);
+-- 按标签、订阅时间及状态定义的订阅者分组
+-- 规则为空时不限制
+CREATE TABLE segment (
+    segment_id uuid NOT NULL,
+    PRIMARY KEY (segment_id),
+    name TEXT NOT NULL UNIQUE,
+    -- 必须包含全部标签
+    required_tags TEXT[] NOT NULL DEFAULT '{}',
+    -- 不能包含其中任何一个标签
+    excluded_tags TEXT[] NOT NULL DEFAULT '{}',
+    status TEXT NULL,
+    subscribed_from timestamptz NULL,
+    subscribed_to timestamptz NULL,
+    created_at timestamptz NOT NULL
+);
+-- 订阅者是否属于分组，统计分组人数及发布简报时共用同一规则
+CREATE FUNCTION subscriber_in_segment(s subscription, g segment) RETURNS boolean
+LANGUAGE sql STABLE AS $$
+    SELECT
+        (g.status IS NULL OR s.status = g.status) AND
+        (g.subscribed_from IS NULL OR s.subscribed_at >= g.subscribed_from) AND
+        (g.subscribed_to IS NULL OR s.subscribed_at < g.subscribed_to) AND
+        g.required_tags <@ ARRAY(
+            SELECT tag FROM subscription_tag WHERE subscriber_id = s.id
+        ) AND
+        NOT (g.excluded_tags && ARRAY(
+            SELECT tag FROM subscription_tag WHERE subscriber_id = s.id
+        ))
+$$;
+-- 简报发送的目标分组，为空时发送给全部已确认的订阅者
+ALTER TABLE newsletter_issue
+    ADD COLUMN segment_id uuid NULL REFERENCES segment(segment_id) ON DELETE SET NULL;
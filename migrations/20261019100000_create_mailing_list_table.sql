@@ -0,0 +1,38 @@
+-- 邮件列表，同一部署可运营多份简报
+-- 每个列表有独立的名称、发件人及确认邮件文案
+CREATE TABLE mailing_list (
+    list_id uuid NOT NULL,
+    PRIMARY KEY (list_id),
+    -- 订阅表单中使用的列表标识
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    -- 为空时使用配置中的发件人
+    sender TEXT NULL,
+    confirmation_subject TEXT NOT NULL,
+    confirmation_text TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+-- 已有的订阅者及简报归入默认列表，确认邮件文案保持不变
+INSERT INTO mailing_list (
+    list_id, slug, name, sender, confirmation_subject, confirmation_text, created_at
+)
+VALUES (gen_random_uuid(), 'default', 'Newsletter', NULL, 'Welcome!', 'Welcome to our tutorial!', now());
+
+-- 订阅按列表区分，同一邮箱地址可以订阅多个列表
+ALTER TABLE subscription
+    ADD COLUMN list_id uuid NULL REFERENCES mailing_list(list_id);
+UPDATE subscription SET list_id = (SELECT list_id FROM mailing_list WHERE slug = 'default');
+ALTER TABLE subscription
+    ALTER COLUMN list_id SET NOT NULL,
+    DROP CONSTRAINT subscription_email_key,
+    DROP CONSTRAINT subscription_email_index_key,
+    ADD CONSTRAINT subscription_list_id_email_key UNIQUE (list_id, email),
+    ADD CONSTRAINT subscription_list_id_email_index_key UNIQUE (list_id, email_index);
+CREATE INDEX subscription_email_index_idx ON subscription (email_index);
+
+-- 简报只发送给所属列表的订阅者
+ALTER TABLE newsletter_issue
+    ADD COLUMN list_id uuid NULL REFERENCES mailing_list(list_id);
+UPDATE newsletter_issue SET list_id = (SELECT list_id FROM mailing_list WHERE slug = 'default');
+ALTER TABLE newsletter_issue
+    ALTER COLUMN list_id SET NOT NULL;
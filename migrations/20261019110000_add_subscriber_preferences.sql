@@ -0,0 +1,6 @@
+-- 订阅者通过偏好设置页面自行修改的设置
+ALTER TABLE subscription
+    -- `html`同时发送HTML及纯文本，`text`只发送纯文本
+    ADD COLUMN email_format TEXT NOT NULL DEFAULT 'html',
+    -- 在此之前暂停接收简报，为空表示未暂停
+    ADD COLUMN paused_until timestamptz NULL;
@@ -0,0 +1,11 @@
+-- 订阅者修改邮箱地址的请求，新地址点击确认链接后才替换
+-- 只保存令牌的哈希，新邮箱地址加密保存，每个订阅者最多一个未确认的请求
+CREATE TABLE email_change_request (
+    token_hash TEXT NOT NULL,
+    PRIMARY KEY (token_hash),
+    subscriber_id uuid NOT NULL UNIQUE REFERENCES subscription(id),
+    new_email_ciphertext TEXT NOT NULL,
+    new_email_index TEXT NOT NULL,
+    requested_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL
+);
@@ -0,0 +1,6 @@
+-- 邮箱地址盲索引改为基于规范形式(不区分大小写)计算
+-- 已有的索引按原样输入的地址计算，为空表示尚未重建
+-- 邮箱地址加密储存，无法在迁移中比较，升级后执行`rotate_pii_keys`
+-- 重建索引并合并规范化后重复的订阅
+ALTER TABLE subscription
+    ADD COLUMN email_index_scheme TEXT NULL;
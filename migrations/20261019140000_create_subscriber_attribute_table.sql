@@ -0,0 +1,37 @@
+-- 管理员定义的订阅者自定义属性，如公司、国家、注册来源
+-- 属性值以JSONB储存在订阅中，可用于分组及简报内容的个性化
+-- 属性值不加密，不要定义包含敏感个人信息的属性
+CREATE TABLE subscriber_attribute (
+    -- 表单字段、CSV列及简报占位符中使用的标识
+    key TEXT NOT NULL,
+    PRIMARY KEY (key),
+    label TEXT NOT NULL,
+    -- `text`、`number`、`boolean`、`date`或`choice`
+    kind TEXT NOT NULL,
+    required BOOLEAN NOT NULL,
+    -- `choice`类型的可选值
+    choices TEXT[] NOT NULL DEFAULT '{}',
+    created_at timestamptz NOT NULL
+);
+
+ALTER TABLE subscription
+    ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}';
+CREATE INDEX subscription_attributes_idx ON subscription USING GIN (attributes);
+
+-- 分组可按属性值筛选，订阅者的属性需包含分组中的全部属性值
+ALTER TABLE segment
+    ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}';
+CREATE OR REPLACE FUNCTION subscriber_in_segment(s subscription, g segment) RETURNS boolean
+LANGUAGE sql STABLE AS $$
+    SELECT
+        (g.status IS NULL OR s.status = g.status) AND
+        (g.subscribed_from IS NULL OR s.subscribed_at >= g.subscribed_from) AND
+        (g.subscribed_to IS NULL OR s.subscribed_at < g.subscribed_to) AND
+        g.required_tags <@ ARRAY(
+            SELECT tag FROM subscription_tag WHERE subscriber_id = s.id
+        ) AND
+        NOT (g.excluded_tags && ARRAY(
+            SELECT tag FROM subscription_tag WHERE subscriber_id = s.id
+        )) AND
+        s.attributes @> g.attributes
+$$;
@@ -0,0 +1,16 @@
+-- 清理从未确认的订阅者: 订阅后超过一定时间仍未确认时再发送一次确认邮件，
+-- 提醒后再超过一定时间仍未确认则删除订阅者及其令牌
+ALTER TABLE subscription
+    ADD COLUMN confirmation_reminder_sent_at timestamptz NULL;
+CREATE INDEX subscription_pending_idx ON subscription (subscribed_at)
+    WHERE status = 'pending_confirmation';
+
+-- 每次清理的执行记录，在管理后台显示
+CREATE TABLE pending_retention_run (
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    started_at timestamptz NOT NULL,
+    finished_at timestamptz NOT NULL,
+    reminders_sent BIGINT NOT NULL,
+    purged BIGINT NOT NULL
+);
@@ -0,0 +1,3 @@
+-- 三元组索引改用单独派生的密钥计算，清空已有索引
+-- 服务启动时在后台重建，重建完成前这些行仍会作为搜索的候选结果
+UPDATE subscription SET search_trigrams = '{}';
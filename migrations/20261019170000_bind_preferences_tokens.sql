@@ -0,0 +1,17 @@
+-- 偏好设置令牌的签名包含该随机值，修改邮箱地址时更换，旧地址收到的链接随之失效
+-- 为空表示从未修改过邮箱地址，沿用不含随机值的令牌
+ALTER TABLE subscription
+    ADD COLUMN preferences_nonce TEXT NULL;
+
+-- 修改邮箱地址后发送到旧地址的撤销链接
+-- 只保存令牌的哈希，旧地址加密保存，撤销时该次及之后的修改全部撤销
+CREATE TABLE email_change_revert (
+    token_hash TEXT NOT NULL,
+    PRIMARY KEY (token_hash),
+    subscriber_id uuid NOT NULL REFERENCES subscription(id),
+    old_email_ciphertext TEXT NOT NULL,
+    old_email_index TEXT NOT NULL,
+    changed_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL
+);
+CREATE INDEX email_change_revert_subscriber_id_idx ON email_change_revert (subscriber_id);
@@ -0,0 +1,6 @@
+-- 导入前先保存导入记录，导入结束后更新状态及结果
+-- 已有的记录均为导入完成后保存的
+ALTER TABLE subscriber_import ADD COLUMN status TEXT NOT NULL DEFAULT 'completed';
+ALTER TABLE subscriber_import ALTER COLUMN status DROP DEFAULT;
+-- 导入完成前没有报告
+ALTER TABLE subscriber_import ALTER COLUMN report_ciphertext DROP NOT NULL;
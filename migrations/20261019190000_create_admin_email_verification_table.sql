@@ -0,0 +1,9 @@
+-- 管理员设置邮箱地址的验证请求，管理员登录后打开新地址收到的链接才生效
+-- 只保存令牌的哈希，每个管理员最多一个未验证的请求
+CREATE TABLE admin_email_verification (
+    token_hash TEXT NOT NULL,
+    PRIMARY KEY (token_hash),
+    user_id uuid NOT NULL UNIQUE REFERENCES users(user_id) ON DELETE CASCADE,
+    email TEXT NOT NULL,
+    expires_at timestamptz NOT NULL
+);
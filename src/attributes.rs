@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use askama::{Html, MarkupDisplay};
+use serde_json::{Map, Value};
+use sqlx::{types::chrono::NaiveDate, PgConnection, PgExecutor};
+use uuid::Uuid;
+
+use crate::domain::AttributeKey;
+
+/// 文本属性的最大长度
+const MAX_TEXT_LENGTH: usize = 256;
+/// 表单中属性输入框名称的前缀，避免与其他字段冲突
+pub const FORM_FIELD_PREFIX: &str = "attr_";
+
+/// 订阅者的属性值，属性标识 => 属性值
+pub type Attributes = Map<String, Value>;
+
+/// 属性类型
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AttributeKind {
+    Text,
+    Number,
+    Boolean,
+    /// 格式: YYYY-MM-DD
+    Date,
+    /// 只能是定义中的可选值之一
+    Choice,
+}
+
+impl AttributeKind {
+    pub const ALL: [AttributeKind; 5] = [
+        AttributeKind::Text,
+        AttributeKind::Number,
+        AttributeKind::Boolean,
+        AttributeKind::Date,
+        AttributeKind::Choice,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttributeKind::Text => "text",
+            AttributeKind::Number => "number",
+            AttributeKind::Boolean => "boolean",
+            AttributeKind::Date => "date",
+            AttributeKind::Choice => "choice",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<AttributeKind, String> {
+        Self::ALL
+            .into_iter()
+            .find(|k| k.as_str() == s)
+            .ok_or_else(|| format!("`{s}` is not a valid attribute type."))
+    }
+}
+
+#[derive(Debug)]
+pub struct AttributeDefinition {
+    pub key: String,
+    pub label: String,
+    pub kind: String,
+    pub required: bool,
+    pub choices: Vec<String>,
+}
+
+impl AttributeDefinition {
+    /// 表单输入框的类型
+    pub fn input_type(&self) -> &'static str {
+        match self.kind.as_str() {
+            "number" => "number",
+            "date" => "date",
+            _ => "text",
+        }
+    }
+
+    /// 校验并转换表单或CSV中的属性值，空值返回`None`
+    pub fn validate(&self, raw: &str) -> Result<Option<Value>, String> {
+        let raw = raw.trim();
+        if raw.is_empty() {
+            if self.required {
+                return Err(format!("{} is required.", self.label));
+            }
+            return Ok(None);
+        }
+        let invalid = || format!("`{raw}` is not a valid {}.", self.label);
+
+        let value = match AttributeKind::parse(&self.kind)? {
+            AttributeKind::Text => {
+                if raw.chars().count() > MAX_TEXT_LENGTH {
+                    return Err(format!(
+                        "{} must be at most {MAX_TEXT_LENGTH} characters.",
+                        self.label
+                    ));
+                }
+                Value::String(raw.to_owned())
+            }
+            // 整数按整数保存，否则邮件中的`12`会显示为`12.0`
+            AttributeKind::Number => raw
+                .parse::<i64>()
+                .map(serde_json::Number::from)
+                .ok()
+                .or_else(|| {
+                    let number = raw.parse::<f64>().ok()?;
+                    if number.fract() == 0.0 && number.abs() < i64::MAX as f64 {
+                        Some((number as i64).into())
+                    } else {
+                        serde_json::Number::from_f64(number)
+                    }
+                })
+                .map(Value::Number)
+                .ok_or_else(invalid)?,
+            AttributeKind::Boolean => match raw.to_ascii_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Value::Bool(true),
+                "false" | "no" | "off" | "0" => Value::Bool(false),
+                _ => return Err(invalid()),
+            },
+            AttributeKind::Date => NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+                .map(|date| Value::String(date.format("%Y-%m-%d").to_string()))
+                .map_err(|_| invalid())?,
+            AttributeKind::Choice => {
+                if !self.choices.iter().any(|choice| choice == raw) {
+                    return Err(invalid());
+                }
+                Value::String(raw.to_owned())
+            }
+        };
+
+        Ok(Some(value))
+    }
+}
+
+/// 新建属性的参数
+#[derive(Debug)]
+pub struct NewAttribute {
+    pub key: AttributeKey,
+    pub label: String,
+    pub kind: AttributeKind,
+    pub required: bool,
+    pub choices: Vec<String>,
+}
+
+/// 按属性定义校验全部属性值，`raw`返回某个属性的原始值
+///
+/// 未定义的属性被忽略，空值不写入结果
+pub fn validate_attributes<'a>(
+    definitions: &[AttributeDefinition],
+    raw: impl Fn(&str) -> Option<&'a str>,
+) -> Result<Attributes, String> {
+    let mut attributes = Attributes::new();
+    for definition in definitions {
+        let value = definition.validate(raw(&definition.key).unwrap_or_default())?;
+        if let Some(value) = value {
+            attributes.insert(definition.key.clone(), value);
+        }
+    }
+
+    Ok(attributes)
+}
+
+/// 校验表单中`attr_{key}`字段的属性值
+pub fn validate_form_attributes(
+    definitions: &[AttributeDefinition],
+    fields: &HashMap<String, String>,
+) -> Result<Attributes, String> {
+    validate_attributes(definitions, |key| {
+        fields
+            .get(&format!("{FORM_FIELD_PREFIX}{key}"))
+            .map(String::as_str)
+    })
+}
+
+/// 解析分组的属性条件，格式: `key=value, key=value`
+///
+/// 属性值按属性定义校验并转换，与订阅者储存的属性值比较
+pub fn parse_attribute_conditions(
+    s: &str,
+    definitions: &[AttributeDefinition],
+) -> Result<Attributes, String> {
+    let mut conditions = Attributes::new();
+    for condition in s.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        let Some((key, raw)) = condition.split_once('=') else {
+            return Err(format!("`{condition}` is not in the form key=value."));
+        };
+        let key = AttributeKey::parse(key)?;
+        let definition = definitions
+            .iter()
+            .find(|d| d.key == key.as_ref())
+            .ok_or_else(|| format!("`{}` is not a defined attribute.", key.as_ref()))?;
+        let value = definition
+            .validate(raw)?
+            .ok_or_else(|| format!("`{condition}` has no value."))?;
+        conditions.insert(definition.key.clone(), value);
+    }
+
+    Ok(conditions)
+}
+
+/// 属性值的`key=value`文本形式，逗号分隔
+pub fn format_attributes(attributes: &Attributes) -> String {
+    attributes
+        .keys()
+        .map(|key| format!("{key}={}", attribute_text(attributes, key)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// 数据库中JSONB列的属性值
+pub fn from_json(value: Value) -> Attributes {
+    match value {
+        Value::Object(attributes) => attributes,
+        _ => Attributes::new(),
+    }
+}
+
+/// 属性值的文本形式，未设置时为空字符串
+pub fn attribute_text(attributes: &Attributes, key: &str) -> String {
+    match attributes.get(key) {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Null) | None => String::new(),
+        Some(value) => value.to_string(),
+    }
+}
+
+/// 将简报内容中的`{{ key }}`替换为订阅者的属性值
+///
+/// 未设置的属性替换为空字符串，不是属性标识的占位符保持不变，HTML内容中的属性值需要转义
+pub fn personalise(content: &str, attributes: &Attributes, escape_html: bool) -> String {
+    let mut output = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}").map(|end| start + end) else {
+            break;
+        };
+        output.push_str(&rest[..start]);
+        match AttributeKey::parse(&rest[start + 2..end]) {
+            Ok(key) => {
+                let value = attribute_text(attributes, key.as_ref());
+                if escape_html {
+                    output.push_str(&MarkupDisplay::new_unsafe(&value, Html).to_string());
+                } else {
+                    output.push_str(&value);
+                }
+            }
+            Err(_) => output.push_str(&rest[start..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    output.push_str(rest);
+
+    output
+}
+
+/// 新建属性，标识已存在时返回`false`
+#[tracing::instrument(name = "新建订阅者属性", skip(executor))]
+pub async fn create_attribute(
+    executor: &mut PgConnection,
+    attribute: &NewAttribute,
+) -> anyhow::Result<bool> {
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO subscriber_attribute (key, label, kind, required, choices, created_at)
+        VALUES ($1, $2, $3, $4, $5, now())
+        ON CONFLICT (key) DO NOTHING
+        "#,
+        attribute.key.as_ref(),
+        attribute.label,
+        attribute.kind.as_str(),
+        attribute.required,
+        &attribute.choices,
+    )
+    .execute(executor)
+    .await
+    .context("failed to create subscriber attribute.")?
+    .rows_affected();
+
+    Ok(inserted > 0)
+}
+
+/// 全部属性定义，按创建顺序排列
+#[tracing::instrument(name = "查询订阅者属性", skip(executor))]
+pub async fn list_attributes(
+    executor: impl PgExecutor<'_>,
+) -> anyhow::Result<Vec<AttributeDefinition>> {
+    sqlx::query_as!(
+        AttributeDefinition,
+        r#"
+        SELECT key, label, kind, required, choices
+        FROM subscriber_attribute
+        ORDER BY created_at, key
+        "#
+    )
+    .fetch_all(executor)
+    .await
+    .context("failed to retrieve subscriber attributes.")
+}
+
+/// 替换订阅者的全部属性值
+#[tracing::instrument(name = "修改订阅者属性", skip(executor, attributes))]
+pub async fn set_subscriber_attributes(
+    executor: &mut PgConnection,
+    subscriber_id: Uuid,
+    attributes: &Attributes,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "UPDATE subscription SET attributes = $2 WHERE id = $1",
+        subscriber_id,
+        Value::Object(attributes.clone()),
+    )
+    .execute(executor)
+    .await
+    .context("failed to update subscriber attributes.")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+    use serde_json::json;
+
+    use super::{
+        format_attributes, parse_attribute_conditions, personalise, validate_attributes,
+        AttributeDefinition, Attributes,
+    };
+
+    fn definition(key: &str, kind: &str, required: bool) -> AttributeDefinition {
+        AttributeDefinition {
+            key: key.into(),
+            label: key.into(),
+            kind: kind.into(),
+            required,
+            choices: vec!["free".into(), "pro".into()],
+        }
+    }
+
+    #[test]
+    fn values_are_converted_to_their_type() {
+        let definitions = [
+            definition("company", "text", false),
+            definition("seats", "number", false),
+            definition("beta", "boolean", false),
+            definition("renewal", "date", false),
+            definition("plan", "choice", false),
+            definition("country", "text", false),
+        ];
+        let attributes = assert_ok!(validate_attributes(&definitions, |key| match key {
+            "company" => Some(" Acme "),
+            "seats" => Some("12"),
+            "beta" => Some("yes"),
+            "renewal" => Some("2026-01-31"),
+            "plan" => Some("pro"),
+            _ => None,
+        }));
+
+        assert_eq!(
+            serde_json::Value::Object(attributes),
+            json!({
+                "company": "Acme",
+                "seats": 12,
+                "beta": true,
+                "renewal": "2026-01-31",
+                "plan": "pro",
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let invalid = [
+            ("seats", "number", "many"),
+            ("beta", "boolean", "maybe"),
+            ("renewal", "date", "31/01/2026"),
+            ("plan", "choice", "enterprise"),
+        ];
+        for (key, kind, value) in invalid {
+            let definitions = [definition(key, kind, false)];
+            assert_err!(validate_attributes(&definitions, |_| Some(value)));
+        }
+        let definitions = [definition("company", "text", true)];
+        assert_err!(validate_attributes(&definitions, |_| Some(" ")));
+        let too_long = "a".repeat(257);
+        assert_err!(validate_attributes(&definitions, |_| Some(
+            too_long.as_str()
+        )));
+    }
+
+    #[test]
+    fn placeholders_are_replaced_with_attribute_values() {
+        let attributes: Attributes = json!({"company": "<Acme>", "seats": 3})
+            .as_object()
+            .unwrap()
+            .clone();
+
+        assert_eq!(
+            personalise(
+                "Hi {{ company }} ({{seats}}){{ unset }} {{ not a key }}",
+                &attributes,
+                false
+            ),
+            "Hi <Acme> (3) {{ not a key }}"
+        );
+        assert_eq!(
+            personalise("<p>{{ company }}</p>", &attributes, true),
+            "<p>&lt;Acme&gt;</p>"
+        );
+        assert_eq!(personalise("{{ company", &attributes, false), "{{ company");
+    }
+
+    #[test]
+    fn segment_conditions_are_validated_against_definitions() {
+        let definitions = [
+            definition("plan", "choice", true),
+            definition("beta", "boolean", false),
+        ];
+
+        let conditions = assert_ok!(parse_attribute_conditions(
+            " plan=pro, Beta = yes ,",
+            &definitions
+        ));
+        assert_eq!(format_attributes(&conditions), "beta=true, plan=pro");
+        assert_eq!(
+            assert_ok!(parse_attribute_conditions("", &definitions)).len(),
+            0
+        );
+        for invalid in ["plan", "plan=", "plan=enterprise", "country=NZ"] {
+            assert_err!(parse_attribute_conditions(invalid, &definitions));
+        }
+    }
+}
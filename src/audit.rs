@@ -0,0 +1,168 @@
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    PgExecutor, PgPool,
+};
+use uuid::Uuid;
+
+use crate::client_info::ClientInfo;
+
+/// 需要记录审计日志的特权操作
+pub enum AuditAction {
+    Login,
+    Logout,
+    ChangePassword,
+    ChangeEmail,
+    PublishIssue,
+    RevokeSession,
+    RevokeAllSessions,
+    ExportSubscriberData,
+    EraseSubscriberData,
+    ConfirmSubscriber,
+    UnsubscribeSubscriber,
+    DeleteSubscriber,
+    ImportSubscribers,
+    DownloadImportReport,
+    ExportSubscribers,
+    UpdateSubscriberTags,
+    CreateSegment,
+    DeleteSegment,
+    CreateList,
+    CreateAttribute,
+    UpdateSubscriberAttributes,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 21] = [
+        AuditAction::Login,
+        AuditAction::Logout,
+        AuditAction::ChangePassword,
+        AuditAction::ChangeEmail,
+        AuditAction::PublishIssue,
+        AuditAction::RevokeSession,
+        AuditAction::RevokeAllSessions,
+        AuditAction::ExportSubscriberData,
+        AuditAction::EraseSubscriberData,
+        AuditAction::ConfirmSubscriber,
+        AuditAction::UnsubscribeSubscriber,
+        AuditAction::DeleteSubscriber,
+        AuditAction::ImportSubscribers,
+        AuditAction::DownloadImportReport,
+        AuditAction::ExportSubscribers,
+        AuditAction::UpdateSubscriberTags,
+        AuditAction::CreateSegment,
+        AuditAction::DeleteSegment,
+        AuditAction::CreateList,
+        AuditAction::CreateAttribute,
+        AuditAction::UpdateSubscriberAttributes,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::Logout => "logout",
+            AuditAction::ChangePassword => "change_password",
+            AuditAction::ChangeEmail => "change_email",
+            AuditAction::PublishIssue => "publish_issue",
+            AuditAction::RevokeSession => "revoke_session",
+            AuditAction::RevokeAllSessions => "revoke_all_sessions",
+            AuditAction::ExportSubscriberData => "export_subscriber_data",
+            AuditAction::EraseSubscriberData => "erase_subscriber_data",
+            AuditAction::ConfirmSubscriber => "confirm_subscriber",
+            AuditAction::UnsubscribeSubscriber => "unsubscribe_subscriber",
+            AuditAction::DeleteSubscriber => "delete_subscriber",
+            AuditAction::ImportSubscribers => "import_subscribers",
+            AuditAction::DownloadImportReport => "download_import_report",
+            AuditAction::ExportSubscribers => "export_subscribers",
+            AuditAction::UpdateSubscriberTags => "update_subscriber_tags",
+            AuditAction::CreateSegment => "create_segment",
+            AuditAction::DeleteSegment => "delete_segment",
+            AuditAction::CreateList => "create_list",
+            AuditAction::CreateAttribute => "create_attribute",
+            AuditAction::UpdateSubscriberAttributes => "update_subscriber_attributes",
+        }
+    }
+}
+
+/// 追加一条审计日志
+///
+/// 需要与业务操作保持原子性时，传入业务所在的事务
+#[tracing::instrument(name = "记录审计日志", skip(executor, client), fields(action = action.as_str()))]
+pub async fn record_audit_log(
+    executor: impl PgExecutor<'_>,
+    actor_user_id: &Uuid,
+    action: AuditAction,
+    target_id: Option<&str>,
+    client: &ClientInfo,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log (id, actor_user_id, action, target_id, ip, user_agent, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        Uuid::new_v4(),
+        actor_user_id,
+        action.as_str(),
+        target_id,
+        client.ip,
+        client.user_agent,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub struct AuditLogEntry {
+    pub actor: String,
+    pub action: String,
+    pub target_id: Option<String>,
+    pub ip: String,
+    pub user_agent: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 审计日志查询条件，`None`表示不限制
+#[derive(Default)]
+pub struct AuditLogFilter {
+    pub action: Option<String>,
+    pub actor: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// 按条件查询审计日志，按时间倒序排列
+#[tracing::instrument(name = "查询审计日志", skip_all)]
+pub async fn search_audit_log(
+    pool: &PgPool,
+    filter: &AuditLogFilter,
+    limit: Option<i64>,
+) -> sqlx::Result<Vec<AuditLogEntry>> {
+    sqlx::query_as!(
+        AuditLogEntry,
+        r#"
+        SELECT
+            u.username AS actor,
+            a.action,
+            a.target_id,
+            a.ip,
+            a.user_agent,
+            a.created_at
+        FROM audit_log a
+        JOIN users u ON u.user_id = a.actor_user_id
+        WHERE
+            ($1::text IS NULL OR a.action = $1) AND
+            ($2::text IS NULL OR u.username = $2) AND
+            ($3::timestamptz IS NULL OR a.created_at >= $3) AND
+            ($4::timestamptz IS NULL OR a.created_at < $4)
+        ORDER BY a.created_at DESC
+        LIMIT $5
+        "#,
+        filter.action,
+        filter.actor,
+        filter.from,
+        filter.to,
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+}
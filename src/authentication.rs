@@ -1,5 +1,13 @@
+mod admin_email;
+mod magic_link;
 mod middleware;
+mod oidc;
 mod password;
+mod password_policy;
 
+pub use admin_email::*;
+pub use magic_link::*;
 pub use middleware::*;
+pub use oidc::*;
 pub use password::*;
+pub use password_policy::*;
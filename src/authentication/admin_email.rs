@@ -0,0 +1,124 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::magic_link::{random_token, sha256_hex};
+use crate::domain::SubscriberEmail;
+
+/// 验证链接的有效期
+const TOKEN_TTL_HOURS: i32 = 24;
+
+#[derive(thiserror::Error, Debug)]
+pub enum AdminEmailError {
+    #[error("the email address belongs to another admin.")]
+    Taken,
+    #[error("the verification link is invalid or has expired.")]
+    InvalidToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+/// 管理员当前的邮箱地址，免密登录链接发送到该地址
+#[tracing::instrument(name = "查询管理员邮箱", skip(pool))]
+pub async fn get_admin_email(pool: &PgPool, user_id: Uuid) -> anyhow::Result<Option<String>> {
+    sqlx::query_scalar!("SELECT email FROM users WHERE user_id = $1", user_id)
+        .fetch_one(pool)
+        .await
+        .context("failed to retrieve admin email.")
+}
+
+/// 保存设置邮箱地址的请求，返回发送到新地址的令牌
+///
+/// 同一管理员之前未验证的请求失效，地址属于其他管理员时拒绝
+#[tracing::instrument(name = "请求设置管理员邮箱", skip(pool, email))]
+pub async fn request_admin_email(
+    pool: &PgPool,
+    user_id: Uuid,
+    email: &SubscriberEmail,
+) -> Result<String, AdminEmailError> {
+    if email_taken(pool, user_id, email.as_ref()).await? {
+        return Err(AdminEmailError::Taken);
+    }
+
+    let token = random_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO admin_email_verification (token_hash, user_id, email, expires_at)
+        VALUES ($1, $2, $3, now() + make_interval(hours => $4))
+        ON CONFLICT (user_id) DO UPDATE
+        SET token_hash = EXCLUDED.token_hash,
+            email = EXCLUDED.email,
+            expires_at = EXCLUDED.expires_at
+        "#,
+        sha256_hex(&token),
+        user_id,
+        email.as_ref(),
+        TOKEN_TTL_HOURS,
+    )
+    .execute(pool)
+    .await
+    .context("failed to store admin email verification.")?;
+
+    Ok(token)
+}
+
+/// 验证令牌并设置邮箱地址，返回新地址
+///
+/// 令牌只对请求的管理员有效，转发的链接无法为其他管理员设置地址
+#[tracing::instrument(name = "验证管理员邮箱", skip(pool, token))]
+pub async fn confirm_admin_email(
+    pool: &PgPool,
+    user_id: Uuid,
+    token: &str,
+) -> Result<String, AdminEmailError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")?;
+    let email = sqlx::query_scalar!(
+        r#"
+        DELETE FROM admin_email_verification
+        WHERE token_hash = $1 AND user_id = $2 AND expires_at > now()
+        RETURNING email
+        "#,
+        sha256_hex(token),
+        user_id,
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+    .context("failed to consume admin email verification.")?
+    .ok_or(AdminEmailError::InvalidToken)?;
+
+    let updated = sqlx::query!(
+        "UPDATE users SET email = $1 WHERE user_id = $2",
+        email,
+        user_id,
+    )
+    .execute(transaction.as_mut())
+    .await;
+    match updated {
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Err(AdminEmailError::Taken)
+        }
+        updated => updated.context("failed to update admin email.")?,
+    };
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")?;
+
+    Ok(email)
+}
+
+async fn email_taken(pool: &PgPool, user_id: Uuid, email: &str) -> anyhow::Result<bool> {
+    let taken = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM users WHERE email = $1 AND user_id <> $2) AS "taken!""#,
+        email,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("failed to check admin email.")?;
+
+    Ok(taken)
+}
@@ -0,0 +1,126 @@
+use anyhow::Context;
+use rand::distributions::{Alphanumeric, DistString};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::AuthError;
+
+/// 免密登录链接
+///
+/// 令牌随邮件发送，浏览器绑定值保存在请求链接的浏览器的会话中
+/// 两者都匹配才能登录，转发的邮件在其他浏览器中无法使用
+pub struct MagicLink {
+    pub token: String,
+    pub browser_binding: String,
+}
+
+impl MagicLink {
+    pub fn generate() -> Self {
+        Self {
+            token: random_token(),
+            browser_binding: random_token(),
+        }
+    }
+}
+
+/// 按邮箱地址查找管理员
+#[tracing::instrument(name = "按邮箱查找管理员", skip_all)]
+pub async fn find_user_id_by_email(pool: &PgPool, email: &str) -> anyhow::Result<Option<Uuid>> {
+    sqlx::query_scalar!("SELECT user_id FROM users WHERE email = $1", email)
+        .fetch_optional(pool)
+        .await
+        .context("failed to look up user by email.")
+}
+
+/// 保存登录令牌，同一管理员之前的令牌全部失效
+#[tracing::instrument(name = "保存免密登录令牌", skip(pool, link))]
+pub async fn store_login_token(
+    pool: &PgPool,
+    user_id: Uuid,
+    link: &MagicLink,
+    ttl_seconds: u64,
+) -> anyhow::Result<()> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!("DELETE FROM login_token WHERE user_id = $1", user_id)
+        .execute(transaction.as_mut())
+        .await
+        .context("failed to delete previous login tokens.")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO login_token (token_hash, user_id, browser_binding_hash, expires_at)
+        VALUES ($1, $2, $3, now() + make_interval(secs => $4))
+        "#,
+        sha256_hex(&link.token),
+        user_id,
+        sha256_hex(&link.browser_binding),
+        ttl_seconds as f64,
+    )
+    .execute(transaction.as_mut())
+    .await
+    .context("failed to store login token.")?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+/// 使用登录令牌，返回对应的管理员
+///
+/// 浏览器绑定值匹配后才删除令牌，其他浏览器的尝试不会使请求者的链接失效
+/// 过期的令牌直接删除，过期或浏览器不匹配时返回`InvalidCredential`
+#[tracing::instrument(name = "使用免密登录令牌", skip_all)]
+pub async fn consume_login_token(
+    pool: &PgPool,
+    token: &str,
+    browser_binding: Option<&str>,
+) -> Result<Uuid, AuthError> {
+    let token_hash = sha256_hex(token);
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")?;
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id, browser_binding_hash, expires_at > now() AS "unexpired!"
+        FROM login_token
+        WHERE token_hash = $1
+        FOR UPDATE
+        "#,
+        token_hash,
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+    .context("failed to retrieve login token.")?
+    .ok_or_else(|| AuthError::InvalidCredential(anyhow::anyhow!("Unknown login token.")))?;
+
+    let bound =
+        browser_binding.map(sha256_hex).as_deref() == Some(row.browser_binding_hash.as_str());
+    if row.unexpired && !bound {
+        return Err(AuthError::InvalidCredential(anyhow::anyhow!(
+            "Login token was requested from another browser."
+        )));
+    }
+    sqlx::query!("DELETE FROM login_token WHERE token_hash = $1", token_hash)
+        .execute(transaction.as_mut())
+        .await
+        .context("failed to consume login token.")?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")?;
+    if !row.unexpired {
+        return Err(AuthError::InvalidCredential(anyhow::anyhow!(
+            "Login token has expired."
+        )));
+    }
+
+    Ok(row.user_id)
+}
+
+pub(super) fn random_token() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), 43)
+}
+
+pub(super) fn sha256_hex(value: &str) -> String {
+    hex::encode(Sha256::digest(value.as_bytes()))
+}
@@ -1,15 +1,18 @@
 use std::{fmt::Debug, ops::Deref};
 
 use actix_web::{
-    body::MessageBody,
+    body::{EitherBody, MessageBody},
     dev::{ServiceRequest, ServiceResponse},
-    error::InternalError,
     middleware::Next,
-    FromRequest, HttpMessage,
+    web, FromRequest, HttpMessage,
 };
+use actix_web_flash_messages::FlashMessage;
+use sqlx::types::chrono::Utc;
 use uuid::Uuid;
 
 use crate::{
+    config::{Config, WebConfig},
+    session_registry::SessionRegistry,
     session_state::TypedSession,
     util::{e500, see_other},
 };
@@ -33,7 +36,7 @@ impl Deref for UserId {
 
 pub async fn reject_anonymous_user(
     mut req: ServiceRequest,
-    next: Next<impl MessageBody>,
+    next: Next<impl MessageBody + 'static>,
 ) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
     let session = {
         let (http_request, payload) = req.parts_mut();
@@ -41,15 +44,74 @@ pub async fn reject_anonymous_user(
     }
     .await?;
 
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
-            req.extensions_mut().insert(UserId(user_id));
-            next.call(req).await
+    let user_id = match session.get_user_id().map_err(e500)? {
+        Some(user_id) => user_id,
+        // 以正常响应(而非错误)返回，外层中间件才能为重定向添加安全响应头
+        None => return Ok(req.into_response(see_other("/login")).map_into_right_body()),
+    };
+
+    let config = req
+        .app_data::<web::Data<Config>>()
+        .ok_or_else(|| e500("config is not configured."))?;
+    let registry = req
+        .app_data::<web::Data<SessionRegistry>>()
+        .ok_or_else(|| e500("session registry is not configured."))?;
+    let session_id = session.get_session_id().map_err(e500)?;
+
+    // 会话空闲超时或绝对超时，清除会话并要求重新登录
+    let now = Utc::now().timestamp();
+    let login_at = session.get_login_at().map_err(e500)?;
+    let last_activity_at = session.get_last_activity_at().map_err(e500)?;
+    if is_session_expired(&config.web, login_at, last_activity_at, now) {
+        if let Some(session_id) = session_id {
+            registry.revoke(&user_id, &session_id).await.map_err(e500)?;
         }
-        None => {
-            let e = anyhow::anyhow!("管理员未登录.");
-            let res = see_other("/login");
-            Err(InternalError::from_response(e, res).into())
+        return Ok(force_relogin(req, session, "会话已过期，请重新登录."));
+    }
+
+    // 会话已被吊销(或登录时未登记)，清除会话并要求重新登录
+    let is_active = match session_id {
+        Some(session_id) => registry
+            .is_active(&user_id, &session_id)
+            .await
+            .map_err(e500)?,
+        None => false,
+    };
+    if !is_active {
+        return Ok(force_relogin(req, session, "会话已失效，请重新登录."));
+    }
+
+    session.insert_last_activity_at(now).map_err(e500)?;
+    req.extensions_mut().insert(UserId(user_id));
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+/// 清除会话并重定向到登录页面
+/// 以正常响应(而非错误)返回，确保会话清除及闪现消息能够写入cookie
+fn force_relogin<B>(
+    req: ServiceRequest,
+    session: TypedSession,
+    message: &str,
+) -> ServiceResponse<EitherBody<B>> {
+    session.logout();
+    FlashMessage::error(message).send();
+    req.into_response(see_other("/login")).map_into_right_body()
+}
+
+/// 会话缺少登录时间或活动时间时同样视为已过期
+fn is_session_expired(
+    config: &WebConfig,
+    login_at: Option<i64>,
+    last_activity_at: Option<i64>,
+    now: i64,
+) -> bool {
+    match (login_at, last_activity_at) {
+        (Some(login_at), Some(last_activity_at)) => {
+            now - last_activity_at > config.session_idle_timeout_seconds as i64
+                || now - login_at > config.session_absolute_timeout_seconds as i64
         }
+        _ => true,
     }
 }
@@ -0,0 +1,400 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::distributions::{Alphanumeric, DistString};
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use sqlx::{types::chrono::Utc, PgPool};
+use uuid::Uuid;
+
+use crate::config::OidcConfig;
+
+#[derive(thiserror::Error, Debug)]
+pub enum OidcError {
+    #[error("OIDC login was rejected: {0}")]
+    Rejected(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+/// 授权请求中生成的一次性参数，保存在会话中，回调时校验
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PendingOidcLogin {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+/// 身份提供方的端点，通过discovery文档获取
+#[derive(serde::Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+}
+
+#[derive(serde::Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::One(aud) => aud == client_id,
+            Audience::Many(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    aud: Audience,
+    exp: i64,
+    nonce: Option<String>,
+    sub: String,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+}
+
+/// 通过身份提供方认证的管理员身份
+pub struct OidcIdentity {
+    pub subject: String,
+    /// 仅包含已验证的邮箱地址
+    pub email: Option<String>,
+}
+
+/// 管理员单点登录，使用授权码模式及PKCE
+///
+/// ID Token直接从token端点通过TLS获取，按规范可不校验签名，
+/// 但仍需校验issuer、audience、过期时间及nonce
+pub struct OidcClient {
+    client: reqwest::Client,
+    config: OidcConfig,
+    redirect_uri: String,
+}
+
+impl OidcClient {
+    pub fn new(config: OidcConfig, base_url: &str) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("failed to build oidc client.");
+
+        Self {
+            client,
+            config,
+            redirect_uri: format!("{base_url}/login/oidc/callback"),
+        }
+    }
+
+    pub fn auto_create_users(&self) -> bool {
+        self.config.auto_create_users
+    }
+
+    fn issuer(&self) -> &str {
+        self.config.issuer_url.trim_end_matches('/')
+    }
+
+    async fn discover(&self) -> anyhow::Result<ProviderMetadata> {
+        let metadata: ProviderMetadata = self
+            .client
+            .get(format!(
+                "{}/.well-known/openid-configuration",
+                self.issuer()
+            ))
+            .send()
+            .await
+            .context("failed to fetch oidc discovery document.")?
+            .error_for_status()?
+            .json()
+            .await
+            .context("failed to parse oidc discovery document.")?;
+        anyhow::ensure!(
+            metadata.issuer.trim_end_matches('/') == self.issuer(),
+            "oidc discovery document has unexpected issuer `{}`.",
+            metadata.issuer
+        );
+
+        Ok(metadata)
+    }
+
+    /// 生成授权请求，返回重定向地址及需要保存在会话中的参数
+    #[tracing::instrument(name = "发起OIDC授权请求", skip(self))]
+    pub async fn authorization_request(&self) -> anyhow::Result<(String, PendingOidcLogin)> {
+        let metadata = self.discover().await?;
+        let pending = PendingOidcLogin {
+            state: random_token(),
+            nonce: random_token(),
+            code_verifier: random_token(),
+        };
+        let url = reqwest::Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.config.client_id),
+                ("redirect_uri", &self.redirect_uri),
+                ("scope", "openid email profile"),
+                ("state", &pending.state),
+                ("nonce", &pending.nonce),
+                ("code_challenge", &code_challenge(&pending.code_verifier)),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .context("invalid oidc authorization endpoint.")?;
+
+        Ok((url.into(), pending))
+    }
+
+    /// 使用授权码换取ID Token并校验
+    #[tracing::instrument(name = "校验OIDC授权码", skip_all)]
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        pending: &PendingOidcLogin,
+    ) -> Result<OidcIdentity, OidcError> {
+        let metadata = self.discover().await?;
+        let response = self
+            .client
+            .post(&metadata.token_endpoint)
+            .basic_auth(
+                &self.config.client_id,
+                Some(self.config.client_secret.expose_secret()),
+            )
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.redirect_uri),
+                ("code_verifier", &pending.code_verifier),
+            ])
+            .send()
+            .await
+            .context("failed to call oidc token endpoint.")?;
+        if response.status().is_client_error() {
+            return Err(OidcError::Rejected(format!(
+                "token endpoint returned {}.",
+                response.status()
+            )));
+        }
+        let token: TokenResponse = response
+            .error_for_status()
+            .context("oidc token endpoint failed.")?
+            .json()
+            .await
+            .context("failed to parse oidc token response.")?;
+
+        let claims = decode_id_token(&token.id_token)?;
+        validate_claims(
+            &claims,
+            self.issuer(),
+            &self.config.client_id,
+            &pending.nonce,
+            Utc::now().timestamp(),
+        )?;
+
+        Ok(OidcIdentity {
+            subject: claims.sub,
+            email: claims.email.filter(|_| claims.email_verified),
+        })
+    }
+}
+
+/// 按subject匹配管理员，其次按已验证的邮箱匹配并关联subject
+///
+/// 均未匹配且允许自动创建时，以邮箱地址为用户名创建无密码的管理员
+#[tracing::instrument(name = "匹配OIDC管理员", skip(pool, identity), fields(subject = %identity.subject))]
+pub async fn find_or_create_oidc_user(
+    pool: &PgPool,
+    identity: &OidcIdentity,
+    auto_create: bool,
+) -> Result<Uuid, OidcError> {
+    let user_id = sqlx::query_scalar!(
+        "SELECT user_id FROM users WHERE oidc_subject = $1",
+        identity.subject,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to look up user by oidc subject.")?;
+    if let Some(user_id) = user_id {
+        return Ok(user_id);
+    }
+
+    let Some(email) = &identity.email else {
+        return Err(OidcError::Rejected(
+            "unknown subject without a verified email.".into(),
+        ));
+    };
+    let user_id = sqlx::query_scalar!(
+        r#"
+        UPDATE users
+        SET oidc_subject = $1
+        WHERE email = $2 AND oidc_subject IS NULL
+        RETURNING user_id
+        "#,
+        identity.subject,
+        email,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to link oidc subject to user.")?;
+    if let Some(user_id) = user_id {
+        return Ok(user_id);
+    }
+
+    if !auto_create {
+        return Err(OidcError::Rejected(
+            "no admin matches the oidc identity.".into(),
+        ));
+    }
+    let user_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, email, oidc_subject)
+        VALUES ($1, $2, $2, $3)
+        "#,
+        user_id,
+        email,
+        identity.subject,
+    )
+    .execute(pool)
+    .await
+    .context("failed to create user for oidc identity.")?;
+
+    Ok(user_id)
+}
+
+fn random_token() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), 43)
+}
+
+/// PKCE S256: `BASE64URL(SHA256(code_verifier))`
+fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+fn decode_id_token(id_token: &str) -> Result<IdTokenClaims, OidcError> {
+    let payload = id_token
+        .split('.')
+        .nth(1)
+        .ok_or_else(|| OidcError::Rejected("malformed id token.".into()))?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(|_| OidcError::Rejected("malformed id token.".into()))?;
+
+    serde_json::from_slice(&payload)
+        .map_err(|e| OidcError::Rejected(format!("invalid id token claims: {e}.")))
+}
+
+fn validate_claims(
+    claims: &IdTokenClaims,
+    issuer: &str,
+    client_id: &str,
+    nonce: &str,
+    now: i64,
+) -> Result<(), OidcError> {
+    if claims.iss.trim_end_matches('/') != issuer {
+        return Err(OidcError::Rejected(
+            "id token has unexpected issuer.".into(),
+        ));
+    }
+    if !claims.aud.contains(client_id) {
+        return Err(OidcError::Rejected(
+            "id token was issued for another client.".into(),
+        ));
+    }
+    if claims.exp <= now {
+        return Err(OidcError::Rejected("id token has expired.".into()));
+    }
+    if claims.nonce.as_deref() != Some(nonce) {
+        return Err(OidcError::Rejected("id token nonce does not match.".into()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+    use super::{code_challenge, decode_id_token, validate_claims, OidcError};
+
+    const ISSUER: &str = "https://idp.example";
+
+    fn id_token(claims: serde_json::Value) -> String {
+        format!(
+            "{}.{}.signature",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256"}"#),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        )
+    }
+
+    fn validate(claims: serde_json::Value) -> Result<(), OidcError> {
+        let claims = decode_id_token(&id_token(claims))?;
+        validate_claims(&claims, ISSUER, "tutorial", "nonce", 1000)
+    }
+
+    fn claims() -> serde_json::Value {
+        serde_json::json!({
+            "iss": ISSUER,
+            "aud": "tutorial",
+            "exp": 1300,
+            "nonce": "nonce",
+            "sub": "subject",
+        })
+    }
+
+    #[test]
+    fn code_challenge_matches_rfc7636_example() {
+        assert_eq!(
+            code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn valid_claims_are_accepted() {
+        assert!(validate(claims()).is_ok());
+
+        let mut claims = claims();
+        claims["aud"] = serde_json::json!(["other", "tutorial"]);
+        assert!(validate(claims).is_ok());
+    }
+
+    #[test]
+    fn invalid_claims_are_rejected() {
+        for (name, value) in [
+            ("iss", serde_json::json!("https://evil.example")),
+            ("aud", serde_json::json!("other")),
+            ("exp", serde_json::json!(1000)),
+            ("nonce", serde_json::json!("replayed")),
+            ("nonce", serde_json::Value::Null),
+        ] {
+            let mut claims = claims();
+            claims[name] = value;
+            assert!(
+                matches!(validate(claims), Err(OidcError::Rejected(_))),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_id_token_is_rejected() {
+        for id_token in ["", "abc", "a.!!!.c", "a.e30.c"] {
+            assert!(
+                matches!(decode_id_token(id_token), Err(OidcError::Rejected(_))),
+                "{id_token}"
+            );
+        }
+    }
+}
@@ -1,10 +1,13 @@
 use anyhow::Context;
-use argon2::{password_hash::SaltString, Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::{
+    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
+    PasswordVerifier, Version,
+};
 use secrecy::{ExposeSecret, SecretString};
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::telemetry::spawn_blocking_with_tracing;
+use crate::{config::PasswordHashConfig, telemetry::spawn_blocking_with_tracing};
 
 #[derive(thiserror::Error, Debug)]
 pub enum AuthError {
@@ -19,35 +22,53 @@ pub struct Credential {
     pub password: SecretString,
 }
 
-#[tracing::instrument(name = "Validate credential", skip(credential, pool))]
+#[tracing::instrument(name = "Validate credential", skip(credential, pool, hash_config))]
 /// 校验管理员凭证
 pub async fn validate_credential(
     credential: Credential,
     pool: &PgPool,
+    hash_config: &PasswordHashConfig,
 ) -> Result<uuid::Uuid, AuthError> {
-    let mut user_id = None;
-    let mut expected_password_hash = SecretString::from(
-        "$argon2id$v=19$m=65536,t=2,p=1$gZiV/M1gPc22ElAH/Jh1Hw$CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"   
-    );
-
-    // 管理员凭证查询成功后覆盖预设值
-    if let Some((stored_user_id, stored_password_hash)) =
+    let Some((user_id, expected_password_hash)) =
         get_stored_credential(&credential.username, pool).await?
-    {
-        user_id = Some(stored_user_id);
-        expected_password_hash = stored_password_hash;
+    else {
+        // 管理员不存在时，仍使用当前配置的参数计算一次哈希
+        // 使响应时间与校验密码时一致，避免通过时间差枚举用户名
+        let hash_config = hash_config.clone();
+        spawn_blocking_with_tracing(move || {
+            compute_password_hash(credential.password, &hash_config)
+        })
+        .await
+        .context("failed to spawn blocking task.")??;
+        return Err(AuthError::InvalidCredential(anyhow::anyhow!(
+            "Unknown username."
+        )));
     };
 
-    spawn_blocking_with_tracing(move || {
-        verify_password_hash(expected_password_hash, credential.password)
-    })
-    .await
-    .context("failed to spawn blocking task.")??;
+    let password_candidate = credential.password.clone();
+    let needs_rehash = {
+        let hash_config = hash_config.clone();
+        spawn_blocking_with_tracing(move || {
+            verify_password_hash(&expected_password_hash, password_candidate)?;
+            Ok::<_, AuthError>(needs_rehash(&expected_password_hash, &hash_config))
+        })
+        .await
+        .context("failed to spawn blocking task.")??
+    };
 
-    // 只有在存储中找到凭证，才会将`user_id`设置为`Some`
-    // 因此，即使默认密码与表单输入的密码匹配
-    // 也永远不会对不存在的管理员校验通过
-    user_id.ok_or_else(|| AuthError::InvalidCredential(anyhow::anyhow!("Unknown username.")))
+    // 已存储的哈希使用了较弱的参数或较旧的算法，使用当前配置重新计算
+    // 升级失败不影响本次登录
+    if needs_rehash {
+        if let Err(e) = change_password(user_id, credential.password, pool, hash_config).await {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "failed to upgrade password hash parameters."
+            );
+        }
+    }
+
+    Ok(user_id)
 }
 
 #[tracing::instrument(name = "Get stored credential", skip(username, pool))]
@@ -66,7 +87,8 @@ async fn get_stored_credential(
     .fetch_optional(pool)
     .await
     .context("failed to perform a query to retrieve stored credential.")?
-    .map(|row| (row.user_id, SecretString::from(row.password_hash)));
+    // 仅通过单点登录创建的管理员没有密码，按管理员不存在处理
+    .and_then(|row| Some((row.user_id, SecretString::from(row.password_hash?))));
 
     Ok(row)
 }
@@ -77,12 +99,13 @@ async fn get_stored_credential(
 )]
 /// 校验管理员密码
 fn verify_password_hash(
-    expected_password_hash: SecretString,
+    expected_password_hash: &SecretString,
     password_candidate: SecretString,
 ) -> Result<(), AuthError> {
     let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
         .context("failed to parse hash in PHC string format.")?;
 
+    // 使用PHC字符串中记录的算法及参数校验
     Argon2::default()
         .verify_password(
             password_candidate.expose_secret().as_bytes(),
@@ -92,15 +115,36 @@ fn verify_password_hash(
         .map_err(AuthError::InvalidCredential)
 }
 
-#[tracing::instrument(name = "修改密码", skip(password, pool))]
+/// 判断已存储的哈希是否需要使用当前配置重新计算
+///
+/// 算法不是argon2id、版本较旧或任一成本参数低于当前配置时返回`true`
+fn needs_rehash(password_hash: &SecretString, hash_config: &PasswordHashConfig) -> bool {
+    let Ok(password_hash) = PasswordHash::new(password_hash.expose_secret()) else {
+        return false;
+    };
+    let Ok(params) = Params::try_from(&password_hash) else {
+        return true;
+    };
+
+    Algorithm::try_from(password_hash.algorithm).ok() != Some(Algorithm::Argon2id)
+        || password_hash.version != Some(Version::V0x13.into())
+        || params.m_cost() < hash_config.memory_cost_kib
+        || params.t_cost() < hash_config.time_cost
+        || params.p_cost() < hash_config.parallelism
+}
+
+#[tracing::instrument(name = "修改密码", skip(password, pool, hash_config))]
 pub async fn change_password(
     user_id: Uuid,
     password: SecretString,
     pool: &PgPool,
+    hash_config: &PasswordHashConfig,
 ) -> Result<(), anyhow::Error> {
-    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
-        .await?
-        .context("failed to hash password.")?;
+    let hash_config = hash_config.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(password, &hash_config))
+            .await?
+            .context("failed to hash password.")?;
 
     sqlx::query!(
         r#"
@@ -118,11 +162,65 @@ pub async fn change_password(
     Ok(())
 }
 
-fn compute_password_hash(password: SecretString) -> Result<SecretString, anyhow::Error> {
+fn compute_password_hash(
+    password: SecretString,
+    hash_config: &PasswordHashConfig,
+) -> Result<SecretString, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::default()
+    let params = Params::new(
+        hash_config.memory_cost_kib,
+        hash_config.time_cost,
+        hash_config.parallelism,
+        None,
+    )
+    .context("invalid argon2 parameters.")?;
+    let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
         .hash_password(password.expose_secret().as_bytes(), &salt)?
         .to_string();
 
     Ok(SecretString::from(password_hash))
 }
+
+#[cfg(test)]
+mod tests {
+    use secrecy::SecretString;
+
+    use super::{compute_password_hash, needs_rehash};
+    use crate::config::PasswordHashConfig;
+
+    fn hash_config(memory_cost_kib: u32, time_cost: u32) -> PasswordHashConfig {
+        PasswordHashConfig {
+            memory_cost_kib,
+            time_cost,
+            parallelism: 1,
+        }
+    }
+
+    #[test]
+    fn hash_with_current_params_is_kept() {
+        let config = hash_config(19456, 2);
+        let hash = compute_password_hash(SecretString::from("password"), &config).unwrap();
+        assert!(!needs_rehash(&hash, &config));
+    }
+
+    #[test]
+    fn hash_with_weaker_params_is_upgraded() {
+        let weak_hash =
+            compute_password_hash(SecretString::from("password"), &hash_config(19456, 1)).unwrap();
+        assert!(needs_rehash(&weak_hash, &hash_config(19456, 2)));
+        assert!(needs_rehash(&weak_hash, &hash_config(65536, 1)));
+    }
+
+    #[test]
+    fn hash_with_older_algorithm_is_upgraded() {
+        let argon2i_hash = SecretString::from(
+            "$argon2i$v=19$m=19456,t=2,p=1$0mVXes31lUtxHCaU2SoY+Q$iv1c7KIGTy2uPjwnxp3vQ7wbUARgpShZUffUg+rJtbM",
+        );
+        assert!(needs_rehash(&argon2i_hash, &hash_config(19456, 2)));
+
+        let old_version_hash = SecretString::from(
+            "$argon2id$v=16$m=19456,t=2,p=1$0mVXes31lUtxHCaU2SoY+Q$iv1c7KIGTy2uPjwnxp3vQ7wbUARgpShZUffUg+rJtbM",
+        );
+        assert!(needs_rehash(&old_version_hash, &hash_config(19456, 2)));
+    }
+}
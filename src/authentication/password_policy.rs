@@ -0,0 +1,203 @@
+use std::collections::HashSet;
+
+use once_cell::sync::Lazy;
+use secrecy::{ExposeSecret, SecretString};
+
+use crate::config::PasswordPolicyConfig;
+
+/// 内置的常见/已泄露密码列表，比较时忽略大小写
+static COMMON_PASSWORDS: Lazy<HashSet<&'static str>> = Lazy::new(|| {
+    include_str!("common_passwords.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect()
+});
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum PasswordPolicyViolation {
+    #[error("密码长度不能少于{0}个字符.")]
+    TooShort(usize),
+    #[error("密码长度不能超过{0}个字符.")]
+    TooLong(usize),
+    #[error("密码不能包含用户名.")]
+    ContainsUsername,
+    #[error("新密码不能与当前密码相同.")]
+    SameAsCurrent,
+    #[error("密码过于常见或已被泄露.")]
+    Common,
+    #[error("密码强度不足，请混合使用大小写字母、数字及符号.")]
+    TooWeak,
+}
+
+/// 管理员密码策略
+///
+/// 修改密码、管理员命令行工具、邀请及重置密码均应使用同一策略校验新密码
+pub struct PasswordPolicy<'a> {
+    config: &'a PasswordPolicyConfig,
+}
+
+impl<'a> PasswordPolicy<'a> {
+    pub fn new(config: &'a PasswordPolicyConfig) -> Self {
+        Self { config }
+    }
+
+    /// 校验新密码，返回所有不符合的规则
+    pub fn check(
+        &self,
+        password: &SecretString,
+        username: &str,
+        current_password: Option<&SecretString>,
+    ) -> Result<(), Vec<PasswordPolicyViolation>> {
+        let password = password.expose_secret();
+        let length = password.chars().count();
+        let mut violations = Vec::new();
+
+        if length < self.config.min_length {
+            violations.push(PasswordPolicyViolation::TooShort(self.config.min_length));
+        }
+        if length > self.config.max_length {
+            violations.push(PasswordPolicyViolation::TooLong(self.config.max_length));
+        }
+        if !username.is_empty() && password.to_lowercase().contains(&username.to_lowercase()) {
+            violations.push(PasswordPolicyViolation::ContainsUsername);
+        }
+        if current_password.is_some_and(|c| c.expose_secret() == password) {
+            violations.push(PasswordPolicyViolation::SameAsCurrent);
+        }
+        if COMMON_PASSWORDS.contains(password.to_lowercase().as_str()) {
+            violations.push(PasswordPolicyViolation::Common);
+        }
+        if estimate_entropy(password) < self.config.min_entropy_bits {
+            violations.push(PasswordPolicyViolation::TooWeak);
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+/// 估算密码熵(单位bit)
+///
+/// 按密码中出现的字符种类估算字符空间大小，再乘以去除连续重复字符后的长度
+fn estimate_entropy(password: &str) -> f64 {
+    let mut pool_size = 0;
+    if password.chars().any(|c| c.is_ascii_lowercase()) {
+        pool_size += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_uppercase()) {
+        pool_size += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        pool_size += 10;
+    }
+    if password
+        .chars()
+        .any(|c| c.is_ascii_punctuation() || c == ' ')
+    {
+        pool_size += 33;
+    }
+    if !password.is_ascii() {
+        pool_size += 100;
+    }
+    if pool_size == 0 {
+        return 0.0;
+    }
+
+    let mut chars: Vec<char> = password.chars().collect();
+    chars.dedup();
+
+    chars.len() as f64 * (pool_size as f64).log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+    use secrecy::SecretString;
+
+    use super::{PasswordPolicy, PasswordPolicyViolation};
+    use crate::config::PasswordPolicyConfig;
+
+    fn config() -> PasswordPolicyConfig {
+        PasswordPolicyConfig {
+            min_length: 12,
+            max_length: 128,
+            min_entropy_bits: 60.0,
+        }
+    }
+
+    fn check(
+        password: &str,
+        username: &str,
+        current_password: Option<&str>,
+    ) -> Result<(), Vec<PasswordPolicyViolation>> {
+        let config = config();
+        let current_password = current_password.map(|p| SecretString::from(p.to_owned()));
+        PasswordPolicy::new(&config).check(
+            &SecretString::from(password.to_owned()),
+            username,
+            current_password.as_ref(),
+        )
+    }
+
+    #[test]
+    fn strong_password_is_accepted() {
+        assert_ok!(check("correct-Horse-battery-42", "admin", None));
+        assert_ok!(check("我的密码很长而且很复杂吧", "admin", None));
+    }
+
+    #[test]
+    fn password_is_too_short() {
+        let violations = check("aB3$eF7&", "admin", None).unwrap_err();
+        assert!(violations.contains(&PasswordPolicyViolation::TooShort(12)));
+    }
+
+    #[test]
+    fn password_is_too_long() {
+        let violations = check(&"aB3$".repeat(33), "admin", None).unwrap_err();
+        assert_eq!(violations, vec![PasswordPolicyViolation::TooLong(128)]);
+    }
+
+    #[test]
+    fn password_contains_username() {
+        let violations = check("xx-IceFruit-2025-yy", "icefruit", None).unwrap_err();
+        assert_eq!(violations, vec![PasswordPolicyViolation::ContainsUsername]);
+    }
+
+    #[test]
+    fn password_is_same_as_current() {
+        let password = "correct-Horse-battery-42";
+        let violations = check(password, "admin", Some(password)).unwrap_err();
+        assert_eq!(violations, vec![PasswordPolicyViolation::SameAsCurrent]);
+    }
+
+    #[test]
+    fn common_password_is_rejected() {
+        let violations = check("Password1234", "admin", None).unwrap_err();
+        assert!(violations.contains(&PasswordPolicyViolation::Common));
+    }
+
+    #[test]
+    fn low_entropy_password_is_rejected() {
+        assert_err!(check("aaaaaaaaaaaaaaaa", "admin", None));
+        assert_err!(check("111111111111111111", "admin", None));
+    }
+
+    #[test]
+    fn all_violations_are_reported() {
+        let violations = check("admin", "admin", Some("admin")).unwrap_err();
+        assert_eq!(
+            violations,
+            vec![
+                PasswordPolicyViolation::TooShort(12),
+                PasswordPolicyViolation::ContainsUsername,
+                PasswordPolicyViolation::SameAsCurrent,
+                PasswordPolicyViolation::Common,
+                PasswordPolicyViolation::TooWeak,
+            ]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! 批量导入订阅者命令行工具
+//!
+//! 用法: `import_subscribers <file.csv> [--list <slug>] [--consent-attested]`
+//!
+//! CSV需包含`name`和`email`两列，未指定`--list`时导入默认列表，
+//! 指定`--consent-attested`时导入为已确认，
+//! 否则导入为待确认，确认邮件由web服务的后台工作线程发送
+//! 被拒绝行的CSV报告输出到标准输出，同时保存在导入记录中
+use std::{fs::File, path::Path, time::Duration};
+
+use anyhow::Context;
+use sqlx::postgres::PgPoolOptions;
+use tutorial::{
+    client_info::ClientInfo,
+    mailing_lists::{get_list_by_slug, DEFAULT_LIST_SLUG},
+    pii::PiiCipher,
+    subscriber_import::{
+        finish_import, import_subscribers, start_import, ImportMode, ImportReport, ImportStatus,
+    },
+};
+
+const USAGE: &str = "usage: import_subscribers <file.csv> [--list <slug>] [--consent-attested]";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().context(USAGE)?;
+    let mut slug = DEFAULT_LIST_SLUG.to_owned();
+    let mut mode = ImportMode::Pending;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--list" => slug = args.next().context(USAGE)?,
+            "--consent-attested" => mode = ImportMode::Confirmed,
+            _ => anyhow::bail!(USAGE),
+        }
+    }
+    let file = File::open(&path).with_context(|| format!("failed to open `{path}`."))?;
+    let filename = Path::new(&path)
+        .file_name()
+        .map_or_else(|| path.clone(), |name| name.to_string_lossy().into_owned());
+
+    let config = tutorial::config::config();
+    let pii_cipher = PiiCipher::from_config(&config.pii_encryption)?;
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_secs(5))
+        .connect_with(config.database.with_db())
+        .await
+        .context("failed to connect to database.")?;
+    let list = get_list_by_slug(&pool, &slug)
+        .await?
+        .with_context(|| format!("mailing list `{slug}` does not exist."))?;
+
+    // 同意记录中的客户端信息，标明来自命令行
+    let client = ClientInfo {
+        ip: "localhost".into(),
+        user_agent: "import_subscribers".into(),
+    };
+    let import_id = start_import(&pool, None, &filename, mode).await?;
+    let mut report = ImportReport::default();
+    let outcome = import_subscribers(
+        &pool,
+        &pii_cipher,
+        file,
+        list.list_id,
+        mode,
+        &format!("import:{filename}"),
+        &client,
+        &mut report,
+    )
+    .await;
+    let status = match outcome {
+        Ok(()) => ImportStatus::Completed,
+        Err(_) => ImportStatus::Failed,
+    };
+    finish_import(&pool, &pii_cipher, import_id, status, &report).await?;
+    outcome?;
+
+    print!("{}", report.rejected_csv()?);
+    eprintln!(
+        "imported {} subscribers, rejected {} rows.",
+        report.imported,
+        report.rejected.len()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+//! 订阅者加密密钥轮换工具
+//!
+//! 用法: `rotate_pii_keys [batch_size]`
+//!
+//! 使用`pii_encryption.active_key_id`对应的密钥重新加密所有订阅者及导入报告
+//! 服务启动时会在后台自动完成订阅者的重新加密、索引重建及重复订阅的合并，
+//! 此工具用于在停机维护时一次性完成，并同时重新加密导入报告
+//! 旧密钥需在执行完毕后才能从配置中移除
+use std::time::Duration;
+
+use anyhow::Context;
+use sqlx::postgres::PgPoolOptions;
+use tutorial::{
+    pii::{rotate_all_keys, PiiCipher},
+    subscriber_import::rotate_import_reports,
+};
+
+const DEFAULT_BATCH_SIZE: i64 = 500;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let batch_size = match std::env::args().nth(1) {
+        Some(arg) => arg.parse().context("usage: rotate_pii_keys [batch_size]")?,
+        None => DEFAULT_BATCH_SIZE,
+    };
+
+    let config = tutorial::config::config();
+    let pii_cipher = PiiCipher::from_config(&config.pii_encryption)?;
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_secs(5))
+        .connect_with(config.database.with_db())
+        .await
+        .context("failed to connect to database.")?;
+
+    let total = rotate_all_keys(&pool, &pii_cipher, batch_size).await?;
+    println!("re-encrypted {total} subscribers.");
+    let reports = rotate_import_reports(&pool, &pii_cipher).await?;
+    println!("re-encrypted {reports} import reports.");
+    println!(
+        "done, all subscribers are encrypted with key `{}`.",
+        pii_cipher.active_key_id()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+//! 订阅者数据命令行工具
+//!
+//! 用法:
+//! - `subscriber_data export <email>` 以JSON格式将邮箱地址相关的全部数据输出到标准输出
+//! - `subscriber_data erase <email>` 在一个事务中删除邮箱地址相关的全部数据
+use std::time::Duration;
+
+use anyhow::Context;
+use sqlx::postgres::PgPoolOptions;
+use tutorial::{
+    pii::PiiCipher,
+    subscriber_data::{erase_subscriber_data, export_subscriber_data},
+};
+
+const USAGE: &str = "usage: subscriber_data <export|erase> <email>";
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let [command, email] = args.as_slice() else {
+        anyhow::bail!(USAGE);
+    };
+    let email = email.trim();
+
+    let config = tutorial::config::config();
+    let pii_cipher = PiiCipher::from_config(&config.pii_encryption)?;
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(Duration::from_secs(5))
+        .connect_with(config.database.with_db())
+        .await
+        .context("failed to connect to database.")?;
+
+    match command.as_str() {
+        "export" => {
+            let bundle = export_subscriber_data(&pool, &pii_cipher, email).await?;
+            println!("{}", serde_json::to_string_pretty(&bundle)?);
+        }
+        "erase" => {
+            let mut transaction = pool.begin().await?;
+            let subscriber_ids =
+                erase_subscriber_data(transaction.as_mut(), &pii_cipher, email).await?;
+            transaction.commit().await?;
+            if subscriber_ids.is_empty() {
+                println!("no subscriber found.");
+            }
+            for id in subscriber_ids {
+                println!("erased subscriber {id}.");
+            }
+        }
+        _ => anyhow::bail!(USAGE),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use std::{
+    convert::Infallible,
+    future::{ready, Ready},
+};
+
+use actix_web::{dev::Payload, http::header::USER_AGENT, FromRequest, HttpRequest};
+
+/// 发起请求的客户端信息
+pub struct ClientInfo {
+    pub ip: String,
+    pub user_agent: String,
+}
+
+impl ClientInfo {
+    pub fn from_http_request(request: &HttpRequest) -> Self {
+        let ip = request
+            .connection_info()
+            .realip_remote_addr()
+            .unwrap_or("unknown")
+            .to_owned();
+        let user_agent = request
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or("unknown")
+            .to_owned();
+
+        Self { ip, user_agent }
+    }
+}
+
+impl FromRequest for ClientInfo {
+    type Error = Infallible;
+    type Future = Ready<Result<ClientInfo, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(ClientInfo::from_http_request(req)))
+    }
+}
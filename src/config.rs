@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use secrecy::{ExposeSecret, SecretString};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::{
@@ -11,6 +13,16 @@ pub struct Config {
     pub database: DBConfig,
     pub email_client: EmailCientConfig,
     pub redis_uri: SecretString,
+    pub password_policy: PasswordPolicyConfig,
+    pub password_hash: PasswordHashConfig,
+    pub security_headers: SecurityHeadersConfig,
+    pub proof_of_work: ProofOfWorkConfig,
+    pub subscription_guard: SubscriptionGuardConfig,
+    pub pii_encryption: PiiEncryptionConfig,
+    pub pending_retention: PendingRetentionConfig,
+    pub rate_limit: RateLimitConfig,
+    // 未配置时不启用单点登录
+    pub oidc: Option<OidcConfig>,
 }
 
 #[derive(serde::Deserialize)]
@@ -22,7 +34,21 @@ pub struct WebConfig {
     // 本地环境: http://127.0.0.1:8000
     // 生产环境: https://pro.tutorial.com
     pub base_url: String,
+    // 当前的签名密钥，用于会话、闪现消息及各类签名令牌
     pub hmac_secret: SecretString,
+    // 轮换前的旧密钥，仅用于校验，旧会话全部过期后即可移除
+    // 环境变量中以`,`分隔，如: `APP_WEB__PREVIOUS_HMAC_SECRETS=old1,old2`
+    #[serde(default)]
+    pub previous_hmac_secrets: Vec<SecretString>,
+    // 管理员会话的空闲超时时间，超过该时间未访问则需要重新登录
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub session_idle_timeout_seconds: u64,
+    // 管理员会话的绝对超时时间，自登录起超过该时间则需要重新登录
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub session_absolute_timeout_seconds: u64,
+    // 免密登录链接的有效期
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub magic_link_ttl_seconds: u64,
 }
 
 #[derive(serde::Deserialize)]
@@ -67,6 +93,120 @@ pub struct EmailCientConfig {
     pub timeout_milliseconds: u64,
 }
 
+#[derive(serde::Deserialize)]
+pub struct PasswordPolicyConfig {
+    pub min_length: usize,
+    pub max_length: usize,
+    // 密码熵的最小估算值(单位bit)
+    pub min_entropy_bits: f64,
+}
+
+// argon2id成本参数
+// 提高参数后，管理员下次登录时会自动使用新参数重新计算密码哈希
+#[derive(serde::Deserialize, Clone)]
+pub struct PasswordHashConfig {
+    pub memory_cost_kib: u32,
+    pub time_cost: u32,
+    pub parallelism: u32,
+}
+
+// 安全响应头策略，按环境配置
+#[derive(serde::Deserialize)]
+pub struct SecurityHeadersConfig {
+    // `{nonce}`会被替换为本次请求的nonce，如: `script-src 'self' {nonce}`
+    pub content_security_policy: String,
+    // HSTS仅在生产环境(HTTPS)下启用，未配置时不发送
+    pub strict_transport_security: Option<String>,
+    pub frame_options: String,
+    pub referrer_policy: String,
+}
+
+// 订阅表单的工作量证明
+#[derive(serde::Deserialize, Clone)]
+pub struct ProofOfWorkConfig {
+    pub enabled: bool,
+    // 解的哈希值需要的前导0 bit数，每增加1，客户端平均计算量翻倍
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub difficulty_bits: u32,
+    // 挑战的有效期
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_seconds: u64,
+}
+
+// 订阅表单的反垃圾策略
+#[derive(serde::Deserialize, Clone)]
+pub struct SubscriptionGuardConfig {
+    // 额外屏蔽的邮箱域名，与内置的一次性邮箱域名列表合并
+    pub blocklist: Vec<String>,
+    // 始终允许的邮箱域名，优先于屏蔽列表
+    pub allowlist: Vec<String>,
+    // 屏蔽列表文件，每行一个域名，修改后定期重新加载
+    pub blocklist_path: Option<String>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub reload_interval_seconds: u64,
+    // 表单从渲染到提交的最短时间，0表示不检查
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_fill_seconds: u64,
+}
+
+// 订阅者个人信息的字段级加密
+#[derive(serde::Deserialize, Clone)]
+pub struct PiiEncryptionConfig {
+    // 加密新数据使用的密钥id
+    pub active_key_id: String,
+    // 密钥id => 32字节密钥(hex编码)，id只能使用小写字母、数字及下划线
+    // 轮换密钥时新增密钥并修改`active_key_id`，旧密钥需保留至`rotate_pii_keys`执行完毕
+    pub keys: HashMap<String, SecretString>,
+    // 计算邮箱地址盲索引的HMAC密钥，修改后需要重建所有索引
+    pub blind_index_key: SecretString,
+    // 判断邮箱地址是否重复时应用服务商的规则，如Gmail忽略`.`及`+`后缀
+    // 修改后需执行`rotate_pii_keys`重建索引并合并重复的订阅
+    #[serde(default)]
+    pub provider_email_rules: bool,
+}
+
+// 清理从未确认的订阅者
+#[derive(serde::Deserialize, Clone)]
+pub struct PendingRetentionConfig {
+    // 订阅后超过该时间仍未确认，再发送一次确认邮件
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub reminder_after_hours: u64,
+    // 发送提醒后超过该时间仍未确认，删除订阅者及其令牌
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub purge_after_hours: u64,
+    // 两次清理的间隔
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub interval_seconds: u64,
+}
+
+// 按客户端IP限制请求频率
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitConfig {
+    // 每个时间窗口内允许的请求数
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_requests: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub interval_seconds: u64,
+    // redis中计数键的前缀，redis被多个应用共用时用于区分
+    #[serde(default)]
+    pub key_prefix: Option<String>,
+}
+
+// 管理员单点登录(OpenID Connect授权码模式及PKCE)
+#[derive(serde::Deserialize, Clone)]
+pub struct OidcConfig {
+    // 身份提供方的issuer，discovery文档位于`{issuer_url}/.well-known/openid-configuration`
+    pub issuer_url: String,
+    pub client_id: String,
+    pub client_secret: SecretString,
+    // 未匹配到管理员时，使用已验证的邮箱地址自动创建管理员
+    #[serde(default)]
+    pub auto_create_users: bool,
+    // 禁用密码登录，管理员只能通过单点登录
+    #[serde(default)]
+    pub disable_password_login: bool,
+}
+
 enum Enviroment {
     Local,
     Production,
@@ -121,7 +261,10 @@ pub fn config() -> Config {
         .add_source(
             config::Environment::with_prefix("APP")
                 .prefix_separator("_")
-                .separator("__"),
+                .separator("__")
+                .try_parsing(true)
+                .list_separator(",")
+                .with_list_parse_key("web.previous_hmac_secrets"),
         )
         .build()
         .expect("failed to read config.")
@@ -0,0 +1,178 @@
+use anyhow::Context;
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    PgConnection, PgPool,
+};
+use uuid::Uuid;
+
+use crate::{client_info::ClientInfo, pii::PiiCipher};
+
+/// 订阅表单中展示的同意声明
+///
+/// 修改措辞时，已有的同意记录仍保留订阅时展示的原文
+pub const CONSENT_TEXT: &str = "I agree to receive the newsletter by email \
+    and understand that I can unsubscribe at any time.";
+
+/// 记录订阅时的同意证据，与新增订阅者在同一事务中执行
+#[tracing::instrument(name = "记录订阅同意", skip(executor, client))]
+pub async fn record_consent(
+    executor: &mut PgConnection,
+    subscriber_id: Uuid,
+    form_origin: &str,
+    client: &ClientInfo,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_consent (
+            subscriber_id, consent_text, form_origin,
+            subscribe_ip, subscribe_user_agent, subscribed_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        subscriber_id,
+        CONSENT_TEXT,
+        form_origin,
+        client.ip,
+        client.user_agent,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// 导入时由管理员确认已取得同意的订阅者的同意声明
+pub const IMPORT_ATTESTED_CONSENT_TEXT: &str = "Imported by an admin who attested that \
+    the subscriber agreed to receive the newsletter.";
+
+/// 导入后需通过确认邮件取得同意的订阅者的同意声明
+pub const IMPORT_PENDING_CONSENT_TEXT: &str = "Imported by an admin. \
+    The subscriber is asked to confirm by email before receiving the newsletter.";
+
+/// 记录导入订阅者的同意证据，客户端信息为执行导入的管理员或命令行
+///
+/// 管理员确认已取得同意(`attested`)时同时记录为已确认
+#[tracing::instrument(name = "记录导入订阅者的同意", skip(executor, client))]
+pub async fn record_import_consent(
+    executor: &mut PgConnection,
+    subscriber_id: Uuid,
+    form_origin: &str,
+    client: &ClientInfo,
+    attested: bool,
+) -> sqlx::Result<()> {
+    let consent_text = if attested {
+        IMPORT_ATTESTED_CONSENT_TEXT
+    } else {
+        IMPORT_PENDING_CONSENT_TEXT
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_consent (
+            subscriber_id, consent_text, form_origin,
+            subscribe_ip, subscribe_user_agent, subscribed_at,
+            confirm_ip, confirm_user_agent, confirmed_at
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, now(),
+            CASE WHEN $6 THEN $4 END,
+            CASE WHEN $6 THEN $5 END,
+            CASE WHEN $6 THEN now() END
+        )
+        "#,
+        subscriber_id,
+        consent_text,
+        form_origin,
+        client.ip,
+        client.user_agent,
+        attested,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// 记录确认订阅时的客户端信息，重复点击确认链接时保留首次确认的记录
+#[tracing::instrument(name = "记录订阅确认", skip(executor, client))]
+pub async fn record_confirmation(
+    executor: &mut PgConnection,
+    subscriber_id: Uuid,
+    client: &ClientInfo,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE subscription_consent
+        SET confirm_ip = $2, confirm_user_agent = $3, confirmed_at = now()
+        WHERE subscriber_id = $1 AND confirmed_at IS NULL
+        "#,
+        subscriber_id,
+        client.ip,
+        client.user_agent,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub struct ConsentRecord {
+    pub email: String,
+    pub consent_text: String,
+    pub form_origin: String,
+    pub subscribe_ip: String,
+    pub subscribe_user_agent: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub confirm_ip: Option<String>,
+    pub confirm_user_agent: Option<String>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+/// 按邮箱查询同意记录，`email`为`None`时查询最近的记录
+#[tracing::instrument(name = "查询订阅同意记录", skip(pool, pii_cipher, email))]
+pub async fn search_consent_records(
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    email: Option<&str>,
+    limit: i64,
+) -> anyhow::Result<Vec<ConsentRecord>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            s.email_ciphertext AS "email_ciphertext!",
+            c.consent_text,
+            c.form_origin,
+            c.subscribe_ip,
+            c.subscribe_user_agent,
+            c.subscribed_at,
+            c.confirm_ip,
+            c.confirm_user_agent,
+            c.confirmed_at
+        FROM subscription_consent c
+        JOIN subscription s ON s.id = c.subscriber_id
+        WHERE s.pii_key_id IS NOT NULL AND ($1::text IS NULL OR s.email_index = $1)
+        ORDER BY c.subscribed_at DESC
+        LIMIT $2
+        "#,
+        email.map(|e| pii_cipher.blind_index(e)),
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to retrieve consent records.")?;
+
+    rows.into_iter()
+        .map(|r| {
+            Ok(ConsentRecord {
+                email: pii_cipher.decrypt(&r.email_ciphertext)?,
+                consent_text: r.consent_text,
+                form_origin: r.form_origin,
+                subscribe_ip: r.subscribe_ip,
+                subscribe_user_agent: r.subscribe_user_agent,
+                subscribed_at: r.subscribed_at,
+                confirm_ip: r.confirm_ip,
+                confirm_user_agent: r.confirm_user_agent,
+                confirmed_at: r.confirmed_at,
+            })
+        })
+        .collect()
+}
@@ -1,9 +1,17 @@
+mod attribute_key;
+mod email_format;
+mod list_slug;
 mod subscriber;
 mod subscriber_email;
 mod subscriber_name;
 mod subscriber_status;
+mod subscriber_tag;
 
+pub use attribute_key::AttributeKey;
+pub use email_format::EmailFormat;
+pub use list_slug::ListSlug;
 pub use subscriber::Subscriber;
 pub use subscriber_email::*;
 pub use subscriber_name::SubscriberName;
 pub use subscriber_status::SubscriberStatus;
+pub use subscriber_tag::SubscriberTag;
@@ -0,0 +1,50 @@
+/// 自定义属性的标识，以小写ASCII字母开头，只允许小写ASCII字母、数字和`_`
+#[derive(Debug)]
+pub struct AttributeKey(String);
+
+/// 订阅者的固有字段，不能用作属性标识
+const RESERVED_KEYS: [&str; 2] = ["name", "email"];
+
+impl AttributeKey {
+    pub fn parse(s: &str) -> Result<AttributeKey, String> {
+        let key = s.trim().to_ascii_lowercase();
+        let is_valid = (1..=64).contains(&key.len())
+            && key.starts_with(|c: char| c.is_ascii_lowercase())
+            && key
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+
+        if !is_valid || RESERVED_KEYS.contains(&key.as_str()) {
+            return Err(format!("`{s}` is not a valid attribute key."));
+        }
+        Ok(Self(key))
+    }
+}
+
+impl AsRef<str> for AttributeKey {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+
+    use crate::domain::AttributeKey;
+
+    #[test]
+    fn keys_are_trimmed_and_lowercased() {
+        let key = assert_ok!(AttributeKey::parse(" Signup_Source2 "));
+        assert_eq!(key.as_ref(), "signup_source2");
+    }
+
+    #[test]
+    fn invalid_keys_are_rejected() {
+        assert_err!(AttributeKey::parse(""));
+        assert_err!(AttributeKey::parse("2fa"));
+        assert_err!(AttributeKey::parse("signup-source"));
+        assert_err!(AttributeKey::parse("email"));
+        assert_err!(AttributeKey::parse(&"a".repeat(65)));
+    }
+}
@@ -0,0 +1,26 @@
+/// 订阅者接收简报的格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmailFormat {
+    /// 同时发送HTML及纯文本，由邮件客户端选择
+    Html,
+    /// 只发送纯文本
+    Text,
+}
+
+impl EmailFormat {
+    pub const ALL: [EmailFormat; 2] = [EmailFormat::Html, EmailFormat::Text];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailFormat::Html => "html",
+            EmailFormat::Text => "text",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<EmailFormat, String> {
+        Self::ALL
+            .into_iter()
+            .find(|f| f.as_str() == s)
+            .ok_or_else(|| format!("`{s}` is not a valid email format."))
+    }
+}
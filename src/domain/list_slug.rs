@@ -0,0 +1,46 @@
+/// 邮件列表标识，用于订阅表单及链接，只允许小写ASCII字母、数字和`-`
+#[derive(Debug)]
+pub struct ListSlug(String);
+
+impl ListSlug {
+    pub fn parse(s: &str) -> Result<ListSlug, String> {
+        let slug = s.trim().to_ascii_lowercase();
+        let is_valid = (1..=64).contains(&slug.len())
+            && slug
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+
+        if !is_valid {
+            return Err(format!("`{s}` is not a valid list identifier."));
+        }
+        Ok(Self(slug))
+    }
+}
+
+impl AsRef<str> for ListSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+
+    use crate::domain::ListSlug;
+
+    #[test]
+    fn slugs_are_trimmed_and_lowercased() {
+        let slug = assert_ok!(ListSlug::parse(" Rust-Weekly2 "));
+        assert_eq!(slug.as_ref(), "rust-weekly2");
+    }
+
+    #[test]
+    fn invalid_slugs_are_rejected() {
+        assert_err!(ListSlug::parse(""));
+        assert_err!(ListSlug::parse("rust weekly"));
+        assert_err!(ListSlug::parse("rust_weekly"));
+        assert_err!(ListSlug::parse("周刊"));
+        assert_err!(ListSlug::parse(&"a".repeat(65)));
+    }
+}
@@ -1,16 +1,44 @@
 use validator::ValidateEmail;
 
+/// 支持Gmail规则(忽略用户名中的`.`及`+`后缀)的域名
+const GMAIL_DOMAINS: [&str; 2] = ["gmail.com", "googlemail.com"];
+
+/// 支持`+`后缀别名的域名
+const PLUS_ADDRESSING_DOMAINS: [&str; 8] = [
+    "outlook.com",
+    "hotmail.com",
+    "live.com",
+    "icloud.com",
+    "me.com",
+    "fastmail.com",
+    "protonmail.com",
+    "proton.me",
+];
+
 #[derive(Debug)]
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
+    /// 校验并规范化邮箱地址
+    ///
+    /// 去掉首尾空白，域名转为小写，国际化域名转为punycode，用户名保持原样
     pub fn parse(s: &str) -> Result<SubscriberEmail, String> {
-        if s.validate_email() {
-            Ok(Self(s.into()))
+        let invalid = || format!("`{s}` is not a valid subscriber email.");
+        let (local, domain) = s.trim().rsplit_once('@').ok_or_else(invalid)?;
+        let domain = idna::domain_to_ascii(domain).map_err(|_| invalid())?;
+        let email = format!("{local}@{domain}");
+
+        if email.validate_email() {
+            Ok(Self(email))
         } else {
-            Err(format!("`{s}` is not a valid subscriber email."))
+            Err(invalid())
         }
     }
+
+    /// 邮箱地址的域名部分
+    pub fn domain(&self) -> &str {
+        self.0.rsplit_once('@').map(|(_, d)| d).unwrap_or_default()
+    }
 }
 
 impl AsRef<str> for SubscriberEmail {
@@ -19,13 +47,37 @@ impl AsRef<str> for SubscriberEmail {
     }
 }
 
+/// 判断邮箱地址是否重复时使用的规范形式
+///
+/// 整个地址转为小写，`provider_rules`为真时再去掉服务商忽略的部分，
+/// 如Gmail的`a.b+news@gmail.com`与`ab@gmail.com`为同一邮箱
+pub fn canonical_email(email: &str, provider_rules: bool) -> String {
+    let email = email.trim().to_lowercase();
+    let Some((local, domain)) = email.rsplit_once('@') else {
+        return email;
+    };
+    let domain = idna::domain_to_ascii(domain).unwrap_or_else(|_| domain.to_owned());
+    if !provider_rules {
+        return format!("{local}@{domain}");
+    }
+
+    let untagged = local.split_once('+').map_or(local, |(local, _)| local);
+    if GMAIL_DOMAINS.contains(&domain.as_str()) {
+        format!("{}@gmail.com", untagged.replace('.', ""))
+    } else if PLUS_ADDRESSING_DOMAINS.contains(&domain.as_str()) {
+        format!("{untagged}@{domain}")
+    } else {
+        format!("{local}@{domain}")
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use claim::assert_err;
     use fake::{faker::internet::en::SafeEmail, Fake};
     use rand::{rngs::StdRng, SeedableRng};
 
-    use crate::domain::SubscriberEmail;
+    use crate::domain::{canonical_email, SubscriberEmail};
 
     // --------单元测试SubscriberEmail start--------
     #[derive(Clone, Debug)]
@@ -66,4 +118,46 @@ mod tests {
         let email = "@github.com";
         assert_err!(SubscriberEmail::parse(email));
     }
+
+    #[test]
+    fn domain_is_normalised_and_local_part_is_kept() {
+        let email = SubscriberEmail::parse("  Foo.Bar@Example.COM ").unwrap();
+        assert_eq!(email.as_ref(), "Foo.Bar@example.com");
+    }
+
+    #[test]
+    fn idn_domain_is_converted_to_punycode() {
+        let email = SubscriberEmail::parse("user@Bücher.example").unwrap();
+        assert_eq!(email.as_ref(), "user@xn--bcher-kva.example");
+        assert_eq!(email.domain(), "xn--bcher-kva.example");
+    }
+
+    #[test]
+    fn canonical_email_ignores_case() {
+        assert_eq!(
+            canonical_email("Foo@Example.com", false),
+            canonical_email("foo@example.COM", false)
+        );
+        // 未启用服务商规则时`.`及`+`后缀有区别
+        assert_eq!(
+            canonical_email("A.B+news@Gmail.com", false),
+            "a.b+news@gmail.com"
+        );
+    }
+
+    #[test]
+    fn canonical_email_applies_provider_rules() {
+        assert_eq!(
+            canonical_email("A.B+news@googlemail.com", true),
+            "ab@gmail.com"
+        );
+        assert_eq!(
+            canonical_email("someone+news@Outlook.com", true),
+            "someone@outlook.com"
+        );
+        assert_eq!(
+            canonical_email("first.last+news@example.com", true),
+            "first.last+news@example.com"
+        );
+    }
 }
@@ -1,13 +1,21 @@
 pub enum SubscriberStatus {
     PendingConfirmation,
     Confirmed,
+    Unsubscribed,
 }
 
 impl SubscriberStatus {
+    pub const ALL: [SubscriberStatus; 3] = [
+        SubscriberStatus::PendingConfirmation,
+        SubscriberStatus::Confirmed,
+        SubscriberStatus::Unsubscribed,
+    ];
+
     pub fn as_str(&self) -> &str {
         match self {
             SubscriberStatus::PendingConfirmation => "pending_confirmation",
             SubscriberStatus::Confirmed => "confirmed",
+            SubscriberStatus::Unsubscribed => "unsubscribed",
         }
     }
 }
@@ -0,0 +1,72 @@
+/// 订阅者标签，统一为小写，只允许字母、数字、`-`和`_`
+#[derive(Debug)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    pub fn parse(s: &str) -> Result<SubscriberTag, String> {
+        let tag = s.trim().to_lowercase();
+        let is_valid = (1..=64).contains(&tag.chars().count())
+            && tag
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+
+        if !is_valid {
+            return Err(format!("`{s}` is not a valid tag."));
+        }
+        Ok(Self(tag))
+    }
+
+    /// 解析逗号分隔的标签列表，忽略空项及重复项
+    pub fn parse_list(s: &str) -> Result<Vec<SubscriberTag>, String> {
+        let mut tags: Vec<SubscriberTag> = Vec::new();
+        for tag in s.split(',').filter(|t| !t.trim().is_empty()) {
+            let tag = Self::parse(tag)?;
+            if !tags.iter().any(|t| t.0 == tag.0) {
+                tags.push(tag);
+            }
+        }
+
+        Ok(tags)
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+
+    use crate::domain::SubscriberTag;
+
+    #[test]
+    fn tags_are_trimmed_and_lowercased() {
+        let tag = assert_ok!(SubscriberTag::parse("  Early-Adopter_2 "));
+        assert_eq!(tag.as_ref(), "early-adopter_2");
+
+        let tag = assert_ok!(SubscriberTag::parse("读者"));
+        assert_eq!(tag.as_ref(), "读者");
+    }
+
+    #[test]
+    fn invalid_tags_are_rejected() {
+        assert_err!(SubscriberTag::parse(""));
+        assert_err!(SubscriberTag::parse("   "));
+        assert_err!(SubscriberTag::parse("two words"));
+        assert_err!(SubscriberTag::parse("a,b"));
+        assert_err!(SubscriberTag::parse(&"a".repeat(65)));
+    }
+
+    #[test]
+    fn tag_lists_skip_empty_and_duplicate_items() {
+        let tags = assert_ok!(SubscriberTag::parse_list("vip, Beta,,vip ,"));
+        let tags: Vec<_> = tags.iter().map(|t| t.as_ref()).collect();
+        assert_eq!(tags, ["vip", "beta"]);
+
+        assert!(assert_ok!(SubscriberTag::parse_list("")).is_empty());
+        assert_err!(SubscriberTag::parse_list("vip,not valid"));
+    }
+}
@@ -0,0 +1,457 @@
+use anyhow::Context;
+use rand::distributions::{Alphanumeric, DistString};
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{domain::SubscriberEmail, email_client::EmailCient, pii::PiiCipher};
+
+/// 确认链接的有效期
+const TOKEN_TTL_HOURS: i32 = 24;
+/// 发送到旧地址的撤销链接的有效期
+const REVERT_TTL_DAYS: i32 = 7;
+
+#[derive(thiserror::Error, Debug)]
+pub enum EmailChangeError {
+    #[error("the new email address is the current one.")]
+    SameAddress,
+    #[error("the new email address is already subscribed to the same mailing list.")]
+    AlreadySubscribed,
+    #[error("the email change link is invalid or has expired.")]
+    InvalidToken,
+    #[error("the previous email address is already subscribed to the same mailing list.")]
+    PreviousAddressTaken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+/// 已确认的邮箱地址修改
+pub struct EmailChange {
+    pub subscriber_id: Uuid,
+    pub old_email: String,
+    pub new_email: String,
+    /// 新的偏好设置令牌随机值，旧地址收到的偏好设置链接已失效
+    pub preferences_nonce: String,
+    /// 发送到旧地址的撤销令牌
+    pub revert_token: String,
+}
+
+/// 已撤销的邮箱地址修改
+pub struct EmailRevert {
+    pub subscriber_id: Uuid,
+    /// 恢复的旧地址
+    pub email: String,
+    pub preferences_nonce: String,
+}
+
+/// 保存修改邮箱地址的请求，返回发送到新地址的令牌
+///
+/// 同一订阅者之前未确认的请求失效，新地址已订阅了相同的列表时拒绝
+#[tracing::instrument(
+    name = "请求修改邮箱地址",
+    skip(pool, pii_cipher, old_email, new_email)
+)]
+pub async fn request_email_change(
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    subscriber_id: Uuid,
+    old_email: &str,
+    new_email: &SubscriberEmail,
+) -> Result<String, EmailChangeError> {
+    let (old_index, new_index) = (
+        pii_cipher.blind_index(old_email),
+        pii_cipher.blind_index(new_email.as_ref()),
+    );
+    if old_index == new_index {
+        return Err(EmailChangeError::SameAddress);
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")?;
+    if has_list_conflict(transaction.as_mut(), &old_index, &new_index).await? {
+        return Err(EmailChangeError::AlreadySubscribed);
+    }
+
+    let token = random_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO email_change_request (
+            token_hash, subscriber_id, new_email_ciphertext, new_email_index,
+            requested_at, expires_at
+        )
+        VALUES ($1, $2, $3, $4, now(), now() + make_interval(hours => $5))
+        ON CONFLICT (subscriber_id) DO UPDATE SET
+            token_hash = EXCLUDED.token_hash,
+            new_email_ciphertext = EXCLUDED.new_email_ciphertext,
+            new_email_index = EXCLUDED.new_email_index,
+            requested_at = EXCLUDED.requested_at,
+            expires_at = EXCLUDED.expires_at
+        "#,
+        sha256_hex(&token),
+        subscriber_id,
+        pii_cipher.encrypt(new_email.as_ref())?,
+        new_index,
+        TOKEN_TTL_HOURS,
+    )
+    .execute(transaction.as_mut())
+    .await
+    .context("failed to store email change request.")?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")?;
+
+    Ok(token)
+}
+
+/// 使用确认令牌，将旧地址在全部列表中的订阅替换为新地址，并签发发送到旧地址的撤销令牌
+///
+/// 令牌无论成功与否都会被删除。投递队列按订阅者id记录任务，
+/// 替换前等待正在发送的简报完成，尚未发送的简报之后会发送到新地址
+#[tracing::instrument(name = "确认修改邮箱地址", skip_all)]
+pub async fn confirm_email_change(
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    token: &str,
+) -> Result<EmailChange, EmailChangeError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")?;
+    let request = sqlx::query!(
+        r#"
+        DELETE FROM email_change_request
+        WHERE token_hash = $1
+        RETURNING
+            subscriber_id,
+            new_email_ciphertext,
+            new_email_index,
+            expires_at > now() AS "unexpired!"
+        "#,
+        sha256_hex(token),
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+    .context("failed to consume email change request.")?
+    .ok_or(EmailChangeError::InvalidToken)?;
+    let old_email = sqlx::query!(
+        r#"
+        SELECT email_ciphertext AS "email_ciphertext!", email_index AS "email_index!"
+        FROM subscription
+        WHERE id = $1 AND pii_key_id IS NOT NULL
+        FOR UPDATE
+        "#,
+        request.subscriber_id,
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+    .context("failed to retrieve subscriber.")?;
+    let outcome = match old_email {
+        Some(old) if request.unexpired => {
+            if has_list_conflict(
+                transaction.as_mut(),
+                &old.email_index,
+                &request.new_email_index,
+            )
+            .await?
+            {
+                Err(EmailChangeError::AlreadySubscribed)
+            } else {
+                let new_email = pii_cipher.decrypt(&request.new_email_ciphertext)?;
+                let preferences_nonce = replace_email(
+                    transaction.as_mut(),
+                    pii_cipher,
+                    &old.email_index,
+                    &new_email,
+                )
+                .await?;
+                let old_email = pii_cipher.decrypt(&old.email_ciphertext)?;
+                let revert_token = random_token();
+                sqlx::query!(
+                    r#"
+                    INSERT INTO email_change_revert (
+                        token_hash, subscriber_id, old_email_ciphertext, old_email_index,
+                        changed_at, expires_at
+                    )
+                    VALUES ($1, $2, $3, $4, now(), now() + make_interval(days => $5))
+                    "#,
+                    sha256_hex(&revert_token),
+                    request.subscriber_id,
+                    pii_cipher.encrypt(&old_email)?,
+                    old.email_index,
+                    REVERT_TTL_DAYS,
+                )
+                .execute(transaction.as_mut())
+                .await
+                .context("failed to store email change revert token.")?;
+                Ok(EmailChange {
+                    subscriber_id: request.subscriber_id,
+                    old_email,
+                    new_email,
+                    preferences_nonce,
+                    revert_token,
+                })
+            }
+        }
+        _ => Err(EmailChangeError::InvalidToken),
+    };
+    // 失败时也提交，令牌只能使用一次
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")?;
+
+    outcome
+}
+
+/// 使用旧地址收到的撤销令牌，将订阅者当前地址的全部订阅恢复为旧地址
+///
+/// 令牌无论成功与否都会被删除。该次之后的修改产生的撤销令牌一并删除，
+/// 偏好设置令牌的随机值更换，修改后的地址收到的链接全部失效
+#[tracing::instrument(name = "撤销修改邮箱地址", skip_all)]
+pub async fn revert_email_change(
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    token: &str,
+) -> Result<EmailRevert, EmailChangeError> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")?;
+    let revert = sqlx::query!(
+        r#"
+        DELETE FROM email_change_revert
+        WHERE token_hash = $1
+        RETURNING
+            subscriber_id,
+            old_email_ciphertext,
+            old_email_index,
+            changed_at,
+            expires_at > now() AS "unexpired!"
+        "#,
+        sha256_hex(token),
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+    .context("failed to consume email change revert token.")?
+    .ok_or(EmailChangeError::InvalidToken)?;
+    let current_index = sqlx::query_scalar!(
+        r#"
+        SELECT email_index AS "email_index!"
+        FROM subscription
+        WHERE id = $1 AND pii_key_id IS NOT NULL
+        FOR UPDATE
+        "#,
+        revert.subscriber_id,
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+    .context("failed to retrieve subscriber.")?;
+    let outcome = match current_index {
+        Some(current_index) if revert.unexpired => {
+            if current_index != revert.old_email_index
+                && has_list_conflict(
+                    transaction.as_mut(),
+                    &current_index,
+                    &revert.old_email_index,
+                )
+                .await?
+            {
+                Err(EmailChangeError::PreviousAddressTaken)
+            } else {
+                let email = pii_cipher.decrypt(&revert.old_email_ciphertext)?;
+                let preferences_nonce =
+                    replace_email(transaction.as_mut(), pii_cipher, &current_index, &email).await?;
+                sqlx::query!(
+                    r#"
+                    DELETE FROM email_change_revert
+                    WHERE subscriber_id = $1 AND changed_at >= $2
+                    "#,
+                    revert.subscriber_id,
+                    revert.changed_at,
+                )
+                .execute(transaction.as_mut())
+                .await
+                .context("failed to delete later email change revert tokens.")?;
+                Ok(EmailRevert {
+                    subscriber_id: revert.subscriber_id,
+                    email,
+                    preferences_nonce,
+                })
+            }
+        }
+        _ => Err(EmailChangeError::InvalidToken),
+    };
+    // 失败时也提交，令牌只能使用一次
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")?;
+
+    outcome
+}
+
+/// 新地址是否已订阅了旧地址所在的某个列表
+async fn has_list_conflict(
+    executor: &mut PgConnection,
+    old_index: &str,
+    new_index: &str,
+) -> anyhow::Result<bool> {
+    let conflict = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM subscription o
+            JOIN subscription n ON n.list_id = o.list_id
+            WHERE o.email_index = $1 AND n.email_index = $2
+        ) AS "conflict!"
+        "#,
+        old_index,
+        new_index,
+    )
+    .fetch_one(executor)
+    .await
+    .context("failed to check subscriptions of the new email address.")?;
+
+    Ok(conflict)
+}
+
+/// 替换旧地址全部订阅的邮箱地址，姓名及邮箱地址使用当前密钥重新加密
+///
+/// 同时更换偏好设置令牌的随机值，返回新的随机值
+async fn replace_email(
+    executor: &mut PgConnection,
+    pii_cipher: &PiiCipher,
+    old_index: &str,
+    new_email: &str,
+) -> anyhow::Result<String> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, name_ciphertext AS "name_ciphertext!"
+        FROM subscription
+        WHERE email_index = $1 AND pii_key_id IS NOT NULL
+        FOR UPDATE
+        "#,
+        old_index,
+    )
+    .fetch_all(&mut *executor)
+    .await
+    .context("failed to retrieve subscriptions of the old email address.")?;
+    let subscriber_ids: Vec<Uuid> = rows.iter().map(|r| r.id).collect();
+
+    // 等待正在发送的投递任务完成
+    sqlx::query!(
+        "SELECT newsletter_issue_id FROM issue_delivery_queue WHERE subscriber_id = ANY($1) FOR UPDATE",
+        &subscriber_ids,
+    )
+    .fetch_all(&mut *executor)
+    .await
+    .context("failed to lock pending deliveries.")?;
+    // 其他订阅的修改请求随旧地址一同失效
+    sqlx::query!(
+        "DELETE FROM email_change_request WHERE subscriber_id = ANY($1)",
+        &subscriber_ids,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to delete other email change requests.")?;
+
+    let preferences_nonce = random_token();
+    for row in rows {
+        let name = pii_cipher.decrypt(&row.name_ciphertext)?;
+        sqlx::query!(
+            r#"
+            UPDATE subscription
+            SET
+                name_ciphertext = $2,
+                email_ciphertext = $3,
+                email_index = $4,
+                pii_key_id = $5,
+                search_trigrams = $6,
+                email_index_scheme = $7,
+                preferences_nonce = $8
+            WHERE id = $1
+            "#,
+            row.id,
+            pii_cipher.encrypt(&name)?,
+            pii_cipher.encrypt(new_email)?,
+            pii_cipher.blind_index(new_email),
+            pii_cipher.active_key_id(),
+            &pii_cipher.search_trigrams([name.as_str(), new_email]),
+            pii_cipher.email_index_scheme(),
+            preferences_nonce,
+        )
+        .execute(&mut *executor)
+        .await
+        .context("failed to replace subscriber email.")?;
+    }
+
+    Ok(preferences_nonce)
+}
+
+/// 发送确认链接到新地址
+pub async fn send_email_change_confirmation(
+    email_client: &EmailCient,
+    new_email: &SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> reqwest::Result<()> {
+    let confirm_link = format!("{base_url}/preferences/email/confirm?token={token}");
+    let text_body = format!(
+        "Visit {confirm_link} to confirm your new email address.\n\
+        The link expires in {TOKEN_TTL_HOURS} hours."
+    );
+    let html_body = format!(
+        "Click <a href=\"{confirm_link}\">here</a> to confirm your new email address.<br />\
+        The link expires in {TOKEN_TTL_HOURS} hours."
+    );
+
+    email_client
+        .send(
+            new_email,
+            "Confirm your new email address",
+            &text_body,
+            &html_body,
+        )
+        .await
+}
+
+/// 通知旧地址邮箱地址已修改，附带撤销链接
+pub async fn send_email_changed_notice(
+    email_client: &EmailCient,
+    old_email: &SubscriberEmail,
+    base_url: &str,
+    revert_token: &str,
+) -> reqwest::Result<()> {
+    let revert_link = format!("{base_url}/preferences/email/revert?token={revert_token}");
+    let text_body = format!(
+        "The email address of your subscription has been changed. \
+        Future issues will be sent to the new address.\n\
+        If you did not request this change, visit {revert_link} to restore this address.\n\
+        The link expires in {REVERT_TTL_DAYS} days."
+    );
+    let html_body = format!(
+        "The email address of your subscription has been changed. \
+        Future issues will be sent to the new address.<br />\
+        If you did not request this change, click <a href=\"{revert_link}\">here</a> \
+        to restore this address.<br />\
+        The link expires in {REVERT_TTL_DAYS} days."
+    );
+
+    email_client
+        .send(
+            old_email,
+            "Your subscription email address has changed",
+            &text_body,
+            &html_body,
+        )
+        .await
+}
+
+fn random_token() -> String {
+    Alphanumeric.sample_string(&mut rand::thread_rng(), 43)
+}
+
+fn sha256_hex(value: &str) -> String {
+    hex::encode(Sha256::digest(value.as_bytes()))
+}
@@ -46,17 +46,32 @@ impl EmailCient {
         )
     }
 
-    #[tracing::instrument(name = "sending email", skip_all)]
     pub async fn send(
         &self,
         receiver: &SubscriberEmail,
         subject: &str,
         text_body: &str,
         html_body: &str,
+    ) -> reqwest::Result<()> {
+        self.send_from(None, receiver, subject, text_body, Some(html_body))
+            .await
+    }
+
+    /// 以指定发件人发送邮件，`sender`为`None`时使用配置中的发件人
+    ///
+    /// `html_body`为`None`时只发送纯文本
+    #[tracing::instrument(name = "sending email", skip_all)]
+    pub async fn send_from(
+        &self,
+        sender: Option<&SubscriberEmail>,
+        receiver: &SubscriberEmail,
+        subject: &str,
+        text_body: &str,
+        html_body: Option<&str>,
     ) -> reqwest::Result<()> {
         let url = self.base_url.join("/email").unwrap();
         let body = EmailRequestBody {
-            from: self.sender.as_ref(),
+            from: sender.unwrap_or(&self.sender).as_ref(),
             to: receiver.as_ref(),
             subject,
             text_body,
@@ -85,7 +100,8 @@ struct EmailRequestBody<'a> {
     to: &'a str,
     subject: &'a str,
     text_body: &'a str,
-    html_body: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html_body: Option<&'a str>,
 }
 
 #[cfg(test)]
@@ -92,7 +92,8 @@ async fn get_saved_response(
 }
 
 pub enum NextAction {
-    StartProcessing(Transaction<'static, Postgres>),
+    // 事务较大，装箱以免整个枚举都占用事务的大小
+    StartProcessing(Box<Transaction<'static, Postgres>>),
     ReturnSavedResponse(HttpResponse),
 }
 
@@ -121,7 +122,7 @@ pub async fn try_processing(
     .rows_affected();
 
     if n_insert_rows > 0 {
-        Ok(NextAction::StartProcessing(transaction))
+        Ok(NextAction::StartProcessing(Box::new(transaction)))
     } else {
         let saved_response = get_saved_response(pool, user_id, idempotency_key)
             .await?
@@ -5,14 +5,38 @@ use sqlx::{PgConnection, PgPool};
 use tracing::field::{display, Empty};
 use uuid::Uuid;
 
-use crate::{domain::SubscriberEmail, email_client::EmailCient};
+use crate::{
+    attributes::{self, personalise, Attributes},
+    domain::{EmailFormat, SubscriberEmail},
+    email_client::EmailCient,
+    mailing_lists::get_list,
+    pii::PiiCipher,
+    preferences::PreferenceLinks,
+    routes::send_confirm_email,
+};
 
 struct IssueDeliveryTask {
     issue_id: Uuid,
-    email: String,
+    subscriber_id: Uuid,
+    email_ciphertext: String,
+    email_format: String,
+    attributes: Attributes,
+    preferences_nonce: Option<String>,
+    /// 订阅者当前是否暂停接收
+    paused: bool,
+}
+
+struct ConfirmationEmailTask {
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    subscription_token: String,
+    email_ciphertext: String,
+    preferences_nonce: Option<String>,
 }
 
 struct NewsletterIssue {
+    /// 所属列表的发件人，为空时使用配置中的发件人
+    sender: Option<String>,
     subject: String,
     text_body: String,
     html_body: String,
@@ -23,12 +47,39 @@ pub enum ExecutionOutcome {
     EmptyQueue,
 }
 
-pub async fn run(pool: web::Data<PgPool>, email_client: web::Data<EmailCient>) {
+/// 依次处理邮件简报及确认邮件两个队列，两者都为空时休眠
+pub async fn run(
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailCient>,
+    pii_cipher: web::Data<PiiCipher>,
+    preference_links: PreferenceLinks,
+    base_url: String,
+) {
     loop {
-        match try_execute_task(pool.as_ref(), email_client.as_ref()).await {
-            Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(Duration::from_secs(10)).await,
-            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
-            Ok(ExecutionOutcome::TaskCompleted) => {}
+        let outcomes = [
+            try_execute_task(
+                pool.as_ref(),
+                email_client.as_ref(),
+                pii_cipher.as_ref(),
+                &preference_links,
+            )
+            .await,
+            try_execute_confirmation_task(
+                pool.as_ref(),
+                email_client.as_ref(),
+                pii_cipher.as_ref(),
+                &preference_links,
+                &base_url,
+            )
+            .await,
+        ];
+        if outcomes.iter().any(Result::is_err) {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        } else if outcomes
+            .iter()
+            .all(|o| matches!(o, Ok(ExecutionOutcome::EmptyQueue)))
+        {
+            tokio::time::sleep(Duration::from_secs(10)).await;
         }
     }
 }
@@ -37,14 +88,19 @@ pub async fn run(pool: web::Data<PgPool>, email_client: web::Data<EmailCient>) {
     skip_all,
     fields(
         newsletter_issue_id = Empty,
-        subscriber_email = Empty,
+        subscriber_id = Empty,
     ),
     err
 )]
 /// 尝试执行邮件简报发送任务
+///
+/// 简报中的`{{ key }}`替换为订阅者的属性值，每封简报末尾附上订阅者的偏好设置链接
+/// 发布后才暂停接收的订阅者，其尚未发送的简报直接删除，与发布时跳过暂停的订阅者一致
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailCient,
+    pii_cipher: &PiiCipher,
+    preference_links: &PreferenceLinks,
 ) -> anyhow::Result<ExecutionOutcome> {
     let mut transaction = pool.begin().await?;
 
@@ -56,22 +112,26 @@ pub async fn try_execute_task(
     let issue_task = task.unwrap();
     tracing::Span::current()
         .record("newsletter_issue_id", display(&issue_task.issue_id))
-        .record("subscriber_email", display(&issue_task.email));
+        .record("subscriber_id", display(&issue_task.subscriber_id));
+    if issue_task.paused {
+        dequeue_task(&mut transaction, &issue_task).await?;
+        transaction.commit().await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
 
-    // 验证邮箱的有效性
+    // 解密并验证邮箱的有效性
     // 若无效，删除该任务
-    let subscriber_email = match SubscriberEmail::parse(&issue_task.email) {
+    let email = pii_cipher.decrypt(&issue_task.email_ciphertext)?;
+    let subscriber_email = match SubscriberEmail::parse(&email) {
         Ok(email) => email,
-        Err(e) => {
-            tracing::error!(
-                error.cause_chain = ?e,
-                error.message = %e,
-                "The email address is no longer valid, subscriber_email = {}",
-                &issue_task.email,
-            );
+        // 错误信息包含邮箱地址，不写入日志
+        Err(_) => {
             dequeue_task(&mut transaction, &issue_task).await?;
             transaction.commit().await?;
-            return Err(anyhow::anyhow!(e));
+            return Err(anyhow::anyhow!(
+                "the email address is no longer valid, subscriber_id = {}",
+                &issue_task.subscriber_id,
+            ));
         }
     };
 
@@ -93,13 +153,37 @@ pub async fn try_execute_task(
         }
     };
 
-    // 发送邮件简报
+    // 以所属列表的发件人发送邮件简报，选择纯文本的订阅者不发送HTML
+    let sender = issue
+        .sender
+        .as_deref()
+        .and_then(|sender| SubscriberEmail::parse(sender).ok());
+    let link = preference_links.link(
+        issue_task.subscriber_id,
+        issue_task.preferences_nonce.as_deref(),
+    );
+    let subject = personalise(&issue.subject, &issue_task.attributes, false);
+    let text_body = format!(
+        "{}\n\n--\nManage your subscription: {}",
+        personalise(&issue.text_body, &issue_task.attributes, false),
+        link
+    );
+    let html_body = format!(
+        "{}<p><a href=\"{}\">Manage your subscription</a></p>",
+        personalise(&issue.html_body, &issue_task.attributes, true),
+        link
+    );
+    let html_body = match EmailFormat::parse(&issue_task.email_format) {
+        Ok(EmailFormat::Text) => None,
+        _ => Some(html_body.as_str()),
+    };
     email_client
-        .send(
+        .send_from(
+            sender.as_ref(),
             &subscriber_email,
-            &issue.subject,
-            &issue.text_body,
-            &issue.html_body,
+            &subject,
+            &text_body,
+            html_body,
         )
         .await?;
 
@@ -111,16 +195,114 @@ pub async fn try_execute_task(
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
+#[tracing::instrument(skip_all, fields(subscriber_id = Empty), err)]
+/// 尝试发送一封导入订阅者的确认邮件或确认提醒，附上订阅者的偏好设置链接
+pub async fn try_execute_confirmation_task(
+    pool: &PgPool,
+    email_client: &EmailCient,
+    pii_cipher: &PiiCipher,
+    preference_links: &PreferenceLinks,
+    base_url: &str,
+) -> anyhow::Result<ExecutionOutcome> {
+    let mut transaction = pool.begin().await?;
+
+    let Some(task) = get_and_lock_confirmation_task(&mut transaction).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    tracing::Span::current().record("subscriber_id", display(&task.subscriber_id));
+
+    // 导入时已校验过邮箱地址，仍无效时删除该任务
+    let email = pii_cipher.decrypt(&task.email_ciphertext)?;
+    let subscriber_email = match SubscriberEmail::parse(&email) {
+        Ok(email) => email,
+        // 错误信息包含邮箱地址，不写入日志
+        Err(_) => {
+            dequeue_confirmation_task(&mut transaction, &task.subscriber_id).await?;
+            transaction.commit().await?;
+            return Err(anyhow::anyhow!(
+                "the email address is no longer valid, subscriber_id = {}",
+                &task.subscriber_id,
+            ));
+        }
+    };
+
+    // 订阅者所属的列表决定确认邮件的发件人及文案
+    let list = get_list(transaction.as_mut(), task.list_id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("mailing list {} not found.", task.list_id))?;
+    send_confirm_email(
+        &subscriber_email,
+        &list,
+        email_client,
+        base_url,
+        &task.subscription_token,
+        &preference_links.link(task.subscriber_id, task.preferences_nonce.as_deref()),
+    )
+    .await?;
+
+    dequeue_confirmation_task(&mut transaction, &task.subscriber_id).await?;
+    transaction.commit().await?;
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_and_lock_confirmation_task(
+    executor: &mut PgConnection,
+) -> sqlx::Result<Option<ConfirmationEmailTask>> {
+    sqlx::query_as!(
+        ConfirmationEmailTask,
+        r#"
+        SELECT
+            q.subscriber_id,
+            s.list_id,
+            q.subscription_token,
+            s.email_ciphertext AS "email_ciphertext!",
+            s.preferences_nonce
+        FROM
+            confirmation_email_queue q
+        JOIN subscription s ON s.id = q.subscriber_id
+        ORDER BY q.enqueued_at
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_confirmation_task(
+    executor: &mut PgConnection,
+    subscriber_id: &Uuid,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        "DELETE FROM confirmation_email_queue WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
 #[tracing::instrument(skip_all)]
 async fn get_and_lock_task(executor: &mut PgConnection) -> sqlx::Result<Option<IssueDeliveryTask>> {
     let row = sqlx::query!(
         r#"
         SELECT
-            newsletter_issue_id,
-            subscriber_email
+            q.newsletter_issue_id,
+            q.subscriber_id,
+            s.email_ciphertext AS "email_ciphertext!",
+            s.email_format,
+            s.attributes,
+            s.preferences_nonce,
+            COALESCE(s.paused_until > now(), false) AS "paused!"
         FROM
-            issue_delivery_queue
-        FOR UPDATE
+            issue_delivery_queue q
+        JOIN subscription s ON s.id = q.subscriber_id
+        FOR UPDATE OF q
         SKIP LOCKED
         LIMIT 1
         "#
@@ -131,7 +313,12 @@ async fn get_and_lock_task(executor: &mut PgConnection) -> sqlx::Result<Option<I
     if let Some(row) = row {
         Ok(Some(IssueDeliveryTask {
             issue_id: row.newsletter_issue_id,
-            email: row.subscriber_email,
+            subscriber_id: row.subscriber_id,
+            email_ciphertext: row.email_ciphertext,
+            email_format: row.email_format,
+            attributes: attributes::from_json(row.attributes),
+            preferences_nonce: row.preferences_nonce,
+            paused: row.paused,
         }))
     } else {
         Ok(None)
@@ -148,10 +335,10 @@ async fn dequeue_task(
         DELETE FROM issue_delivery_queue
         WHERE
             newsletter_issue_id = $1 AND
-            subscriber_email = $2
+            subscriber_id = $2
         "#,
         issue_task.issue_id,
-        issue_task.email,
+        issue_task.subscriber_id,
     )
     .execute(executor)
     .await?;
@@ -184,11 +371,12 @@ async fn get_issue(pool: &PgPool, issue_id: &Uuid) -> sqlx::Result<NewsletterIss
         NewsletterIssue,
         r#"
         SELECT
-            subject, text_body, html_body
+            l.sender, n.subject, n.text_body, n.html_body
         FROM
-            newsletter_issue
+            newsletter_issue n
+        JOIN mailing_list l ON l.list_id = n.list_id
         WHERE
-            newsletter_issue_id = $1
+            n.newsletter_issue_id = $1
         "#,
         issue_id,
     )
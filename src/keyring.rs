@@ -0,0 +1,247 @@
+use actix_web::{
+    body::MessageBody,
+    cookie::{Cookie, CookieJar, Key},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderValue, COOKIE},
+    middleware::Next,
+    web,
+};
+use hmac::Mac;
+use secrecy::{ExposeSecret, SecretString};
+
+use crate::{
+    config::WebConfig,
+    util::{e500, hmac_sign},
+};
+
+/// 会话cookie的名称，加密储存
+pub const SESSION_COOKIE_NAME: &str = "id";
+/// 闪现消息cookie的名称，签名储存
+pub const FLASH_COOKIE_NAME: &str = "_flash";
+
+/// HMAC密钥环
+///
+/// 第一个密钥为当前密钥，用于签名及加密
+/// 其余为轮换前的旧密钥，仅用于校验，由旧密钥签名的数据在过期前仍然有效
+#[derive(Clone)]
+pub struct Keyring {
+    secrets: Vec<SecretString>,
+}
+
+impl Keyring {
+    pub fn new(current: SecretString, previous: Vec<SecretString>) -> Self {
+        let mut secrets = vec![current];
+        secrets.extend(previous);
+        Self { secrets }
+    }
+
+    pub fn from_config(config: &WebConfig) -> Self {
+        Self::new(
+            config.hmac_secret.clone(),
+            config.previous_hmac_secrets.clone(),
+        )
+    }
+
+    /// 使用当前密钥签名，返回hex编码的签名
+    pub fn sign(&self, payload: &str) -> String {
+        hex::encode(hmac_sign(&self.secrets[0], payload).finalize().into_bytes())
+    }
+
+    /// 使用密钥环中的任一密钥校验签名
+    pub fn verify(&self, payload: &str, signature: &[u8]) -> bool {
+        self.secrets
+            .iter()
+            .any(|secret| hmac_sign(secret, payload).verify_slice(signature).is_ok())
+    }
+
+    /// 会话及闪现消息cookie使用的当前密钥
+    pub fn cookie_key(&self) -> Key {
+        Key::from(self.secrets[0].expose_secret().as_bytes())
+    }
+
+    /// 将由旧密钥签名或加密的cookie使用当前密钥重新处理
+    ///
+    /// 返回新的`Cookie`请求头，没有需要处理的cookie时返回`None`
+    fn reseal_cookie_header(&self, header: &str) -> Option<String> {
+        let current = self.cookie_key();
+        let previous: Vec<Key> = self.secrets[1..]
+            .iter()
+            .map(|s| Key::from(s.expose_secret().as_bytes()))
+            .collect();
+        let mut resealed = false;
+        let cookies: Vec<String> = header
+            .split(';')
+            .map(str::trim)
+            .filter(|raw| !raw.is_empty())
+            .map(|raw| {
+                Cookie::parse_encoded(raw.to_owned())
+                    .ok()
+                    .and_then(|cookie| reseal_cookie(cookie, &current, &previous))
+                    .map(|cookie| {
+                        resealed = true;
+                        cookie.encoded().stripped().to_string()
+                    })
+                    .unwrap_or_else(|| raw.to_owned())
+            })
+            .collect();
+
+        resealed.then(|| cookies.join("; "))
+    }
+}
+
+/// 当前密钥无法校验、但旧密钥可以校验时，返回使用当前密钥重新处理的cookie
+fn reseal_cookie(
+    cookie: Cookie<'static>,
+    current: &Key,
+    previous: &[Key],
+) -> Option<Cookie<'static>> {
+    let name = cookie.name().to_owned();
+    let mut jar = CookieJar::new();
+    jar.add_original(cookie);
+    let open = |key: &Key| match name.as_str() {
+        SESSION_COOKIE_NAME => jar.private(key).get(&name),
+        FLASH_COOKIE_NAME => jar.signed(key).get(&name),
+        _ => None,
+    };
+    if open(current).is_some() {
+        return None;
+    }
+    let value = previous.iter().find_map(open)?.value().to_owned();
+
+    let mut jar = CookieJar::new();
+    let cookie = Cookie::new(name.clone(), value);
+    match name.as_str() {
+        SESSION_COOKIE_NAME => jar.private_mut(current).add(cookie),
+        _ => jar.signed_mut(current).add(cookie),
+    }
+    jar.get(&name).cloned()
+}
+
+/// 使用当前密钥重新处理请求中由旧密钥签名的会话及闪现消息cookie
+///
+/// 轮换密钥后已登录的管理员无需重新登录，需注册在`SessionMiddleware`及`FlashMessagesFramework`之外
+pub async fn reseal_cookies(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let keyring = req
+        .app_data::<web::Data<Keyring>>()
+        .ok_or_else(|| e500("keyring is not configured."))?
+        .clone();
+    if keyring.secrets.len() > 1 {
+        let header = req
+            .headers()
+            .get_all(COOKIE)
+            .filter_map(|v| v.to_str().ok())
+            .collect::<Vec<_>>()
+            .join("; ");
+        if let Some(header) = keyring.reseal_cookie_header(&header) {
+            let value = HeaderValue::from_str(&header).map_err(e500)?;
+            req.headers_mut().insert(COOKIE, value);
+        }
+    }
+
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::cookie::{Cookie, CookieJar, Key};
+    use secrecy::SecretString;
+
+    use super::{Keyring, FLASH_COOKIE_NAME, SESSION_COOKIE_NAME};
+
+    const OLD: &str = "old-secret-old-secret-old-secret-old-secret-old-secret-old-secret";
+    const NEW: &str = "new-secret-new-secret-new-secret-new-secret-new-secret-new-secret";
+
+    fn keyring(current: &str, previous: &[&str]) -> Keyring {
+        Keyring::new(
+            SecretString::from(current),
+            previous.iter().map(|s| SecretString::from(*s)).collect(),
+        )
+    }
+
+    fn session_cookie(secret: &str, value: &str) -> String {
+        let mut jar = CookieJar::new();
+        jar.private_mut(&Key::from(secret.as_bytes()))
+            .add(Cookie::new(SESSION_COOKIE_NAME, value.to_owned()));
+        jar.get(SESSION_COOKIE_NAME)
+            .unwrap()
+            .encoded()
+            .stripped()
+            .to_string()
+    }
+
+    fn flash_cookie(secret: &str, value: &str) -> String {
+        let mut jar = CookieJar::new();
+        jar.signed_mut(&Key::from(secret.as_bytes()))
+            .add(Cookie::new(FLASH_COOKIE_NAME, value.to_owned()));
+        jar.get(FLASH_COOKIE_NAME)
+            .unwrap()
+            .encoded()
+            .stripped()
+            .to_string()
+    }
+
+    fn open(keyring: &Keyring, header: &str) -> (Option<String>, Option<String>) {
+        let key = keyring.cookie_key();
+        let mut jar = CookieJar::new();
+        for raw in header.split("; ") {
+            jar.add_original(Cookie::parse_encoded(raw.to_owned()).unwrap());
+        }
+        (
+            jar.private(&key)
+                .get(SESSION_COOKIE_NAME)
+                .map(|c| c.value().to_owned()),
+            jar.signed(&key)
+                .get(FLASH_COOKIE_NAME)
+                .map(|c| c.value().to_owned()),
+        )
+    }
+
+    #[test]
+    fn signature_from_previous_key_is_accepted() {
+        let old = keyring(OLD, &[]);
+        let rotated = keyring(NEW, &[OLD]);
+        let signature = hex::decode(old.sign("payload")).unwrap();
+
+        assert!(rotated.verify("payload", &signature));
+        assert!(!rotated.verify("tampered", &signature));
+        assert!(!keyring(NEW, &[]).verify("payload", &signature));
+        assert_ne!(rotated.sign("payload"), old.sign("payload"));
+    }
+
+    #[test]
+    fn cookies_sealed_with_previous_key_are_resealed() {
+        let rotated = keyring(NEW, &[OLD]);
+        let header = format!(
+            "{}; other=value; {}",
+            session_cookie(OLD, "session-key"),
+            flash_cookie(OLD, r#"[{"content":"hello; world"}]"#)
+        );
+
+        let resealed = rotated.reseal_cookie_header(&header).unwrap();
+
+        assert!(resealed.contains("other=value"));
+        assert_eq!(
+            open(&rotated, &resealed),
+            (
+                Some("session-key".to_owned()),
+                Some(r#"[{"content":"hello; world"}]"#.to_owned())
+            )
+        );
+    }
+
+    #[test]
+    fn cookies_sealed_with_current_or_unknown_key_are_left_alone() {
+        let rotated = keyring(NEW, &[OLD]);
+
+        let current = session_cookie(NEW, "session-key");
+        assert_eq!(rotated.reseal_cookie_header(&current), None);
+        let unknown = session_cookie(
+            "unknown-secret-unknown-secret-unknown-secret-unknown-secret-unknown",
+            "session-key",
+        );
+        assert_eq!(rotated.reseal_cookie_header(&unknown), None);
+    }
+}
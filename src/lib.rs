@@ -1,12 +1,30 @@
+mod attributes;
+mod audit;
 mod authentication;
+pub mod client_info;
 pub mod config;
+mod consent;
 mod domain;
+mod email_change;
 pub mod email_client;
 mod idempotency;
 mod issue_delivery_worker;
+mod keyring;
+pub mod mailing_lists;
+pub mod pending_retention;
+pub mod pii;
+pub mod preferences;
+mod proof_of_work;
 mod routes;
+mod security_headers;
+mod segments;
+mod session_registry;
 mod session_state;
 mod startup;
+pub mod subscriber_data;
+pub mod subscriber_import;
+mod subscribers;
+mod subscription_guard;
 pub mod telemetry;
 mod util;
 
@@ -0,0 +1,144 @@
+use anyhow::Context;
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::domain::{ListSlug, SubscriberEmail, SubscriberStatus};
+
+/// 迁移时创建的默认列表，订阅及发布未指定列表时使用
+pub const DEFAULT_LIST_SLUG: &str = "default";
+
+#[derive(Debug)]
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+    /// 为空时使用配置中的发件人
+    pub sender: Option<String>,
+    pub confirmation_subject: String,
+    pub confirmation_text: String,
+}
+
+impl MailingList {
+    /// 列表自己的发件人，未设置或无效时返回`None`
+    pub fn sender(&self) -> Option<SubscriberEmail> {
+        self.sender
+            .as_deref()
+            .and_then(|sender| SubscriberEmail::parse(sender).ok())
+    }
+}
+
+/// 新建列表的参数
+#[derive(Debug)]
+pub struct NewMailingList {
+    pub slug: ListSlug,
+    pub name: String,
+    pub sender: Option<SubscriberEmail>,
+    pub confirmation_subject: String,
+    pub confirmation_text: String,
+}
+
+pub struct MailingListSummary {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub sender: Option<String>,
+    /// 已确认的订阅者数
+    pub confirmed: i64,
+    /// 全部订阅者数
+    pub total: i64,
+}
+
+/// 新建列表，标识已存在时返回`None`
+#[tracing::instrument(name = "新建邮件列表", skip(executor))]
+pub async fn create_list(
+    executor: &mut PgConnection,
+    list: &NewMailingList,
+) -> anyhow::Result<Option<Uuid>> {
+    let list_id = Uuid::new_v4();
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO mailing_list (
+            list_id, slug, name, sender,
+            confirmation_subject, confirmation_text, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+        list_id,
+        list.slug.as_ref(),
+        list.name,
+        list.sender.as_ref().map(|s| s.as_ref()),
+        list.confirmation_subject,
+        list.confirmation_text,
+    )
+    .execute(executor)
+    .await
+    .context("failed to create mailing list.")?
+    .rows_affected();
+
+    Ok((inserted > 0).then_some(list_id))
+}
+
+/// 按标识查询列表
+#[tracing::instrument(name = "按标识查询邮件列表", skip(executor))]
+pub async fn get_list_by_slug(
+    executor: impl PgExecutor<'_>,
+    slug: &str,
+) -> sqlx::Result<Option<MailingList>> {
+    sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT list_id, slug, name, sender, confirmation_subject, confirmation_text
+        FROM mailing_list
+        WHERE slug = $1
+        "#,
+        slug,
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// 按id查询列表
+#[tracing::instrument(name = "按id查询邮件列表", skip(executor))]
+pub async fn get_list(
+    executor: impl PgExecutor<'_>,
+    list_id: Uuid,
+) -> sqlx::Result<Option<MailingList>> {
+    sqlx::query_as!(
+        MailingList,
+        r#"
+        SELECT list_id, slug, name, sender, confirmation_subject, confirmation_text
+        FROM mailing_list
+        WHERE list_id = $1
+        "#,
+        list_id,
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// 全部列表及其订阅者数，默认列表在前，其余按名称排序
+#[tracing::instrument(name = "查询邮件列表", skip(pool))]
+pub async fn list_mailing_lists(pool: &PgPool) -> anyhow::Result<Vec<MailingListSummary>> {
+    sqlx::query_as!(
+        MailingListSummary,
+        r#"
+        SELECT
+            l.list_id,
+            l.slug,
+            l.name,
+            l.sender,
+            count(s.id) FILTER (WHERE s.status = $2) AS "confirmed!",
+            count(s.id) AS "total!"
+        FROM mailing_list l
+        LEFT JOIN subscription s ON s.list_id = l.list_id AND s.pii_key_id IS NOT NULL
+        GROUP BY l.list_id
+        ORDER BY l.slug <> $1, l.name
+        "#,
+        DEFAULT_LIST_SLUG,
+        SubscriberStatus::Confirmed.as_str(),
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to retrieve mailing lists.")
+}
@@ -3,7 +3,15 @@ use std::{net::TcpListener, time::Duration};
 use actix_web::web;
 use sqlx::postgres::PgPoolOptions;
 use tokio::signal;
-use tutorial::{email_client::EmailCient, telemetry};
+use tutorial::{
+    email_client::EmailCient,
+    pii::{rotate_all_keys, PiiCipher},
+    preferences::PreferenceLinks,
+    telemetry,
+};
+
+/// 启动时重建订阅者索引，每个事务处理的行数
+const PII_ROTATION_BATCH_SIZE: i64 = 500;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -23,13 +31,48 @@ async fn main() -> anyhow::Result<()> {
     );
     // 初始化邮件客户端
     let email_client = web::Data::new(EmailCient::from_config(&config));
+    // 订阅者个人信息加解密
+    let pii_cipher = web::Data::new(PiiCipher::from_config(&config.pii_encryption)?);
+
+    let base_url = config.web.base_url.clone();
+    let pending_retention = config.pending_retention.clone();
+    // 邮件简报中的偏好设置链接
+    let preference_links = PreferenceLinks::from_config(&config);
 
     // web工作线程
-    let web_task = tutorial::web_run(config, listener, pool.clone(), email_client.clone()).await?;
+    let web_task = tutorial::web_run(
+        config,
+        listener,
+        pool.clone(),
+        email_client.clone(),
+        pii_cipher.clone(),
+    )
+    .await?;
     let web_handler = web_task.handle();
     let web_task = tokio::spawn(web_task);
-    // 发送邮件简报的工作线程
-    let worker_task = tutorial::worker_run(pool, email_client);
+    // 重建遗留订阅者的加密及盲索引，未完成前旧规则的行无法按邮箱地址查询
+    let rotation_task = {
+        let (pool, pii_cipher) = (pool.get_ref().clone(), pii_cipher.clone());
+        async move {
+            match rotate_all_keys(&pool, &pii_cipher, PII_ROTATION_BATCH_SIZE).await {
+                Ok(count) => tracing::info!(count, "subscriber pii is up to date."),
+                Err(e) => tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "failed to rotate subscriber pii."
+                ),
+            }
+        }
+    };
+    tokio::spawn(rotation_task);
+    // 清理从未确认的订阅者
+    let retention_task = tokio::spawn(tutorial::pending_retention::run(
+        pool.get_ref().clone(),
+        pending_retention,
+    ));
+    // 发送邮件简报及确认邮件的工作线程
+    let worker_task =
+        tutorial::worker_run(pool, email_client, pii_cipher, preference_links, base_url);
     let worker_task = tokio::spawn(worker_task);
 
     // 优雅停机
@@ -43,6 +86,7 @@ async fn main() -> anyhow::Result<()> {
     tokio::select! {
         _ = web_task => {},
         _ = worker_task => {},
+        _ = retention_task => {},
         _ = signal => {
             web_handler.stop(true).await;
         },
@@ -0,0 +1,183 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    PgConnection, PgPool,
+};
+use uuid::Uuid;
+
+use crate::{
+    config::PendingRetentionConfig, domain::SubscriberStatus,
+    subscriber_data::erase_subscriber_by_id,
+};
+
+/// 每个事务删除的订阅者数，避免长事务
+const PURGE_BATCH_SIZE: i64 = 500;
+
+/// 一次清理的结果
+#[derive(Debug, Default, PartialEq)]
+pub struct RetentionReport {
+    /// 加入发送队列的提醒邮件数
+    pub reminders_sent: i64,
+    /// 删除的订阅者数
+    pub purged: i64,
+}
+
+pub struct RetentionRun {
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub reminders_sent: i64,
+    pub purged: i64,
+}
+
+/// 定期清理从未确认的订阅者
+pub async fn run(pool: PgPool, config: PendingRetentionConfig) {
+    let period = Duration::from_secs(config.interval_seconds);
+    loop {
+        if let Err(e) = run_once(&pool, &config).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "failed to clean up pending subscribers."
+            );
+        }
+        tokio::time::sleep(period).await;
+    }
+}
+
+/// 执行一次清理并保存执行记录
+///
+/// 订阅后超过`reminder_after_hours`仍未确认的订阅者，以原令牌再发送一次确认邮件，
+/// 由后台工作线程发送；提醒后超过`purge_after_hours`仍未确认的订阅者及其令牌被删除
+#[tracing::instrument(name = "清理未确认的订阅者", skip_all)]
+pub async fn run_once(
+    pool: &PgPool,
+    config: &PendingRetentionConfig,
+) -> anyhow::Result<RetentionReport> {
+    let started_at = Utc::now();
+    let report = RetentionReport {
+        reminders_sent: enqueue_reminders(pool, config.reminder_after_hours).await?,
+        purged: purge_reminded(pool, config.purge_after_hours).await?,
+    };
+    sqlx::query!(
+        r#"
+        INSERT INTO pending_retention_run (id, started_at, finished_at, reminders_sent, purged)
+        VALUES ($1, $2, now(), $3, $4)
+        "#,
+        Uuid::new_v4(),
+        started_at,
+        report.reminders_sent,
+        report.purged,
+    )
+    .execute(pool)
+    .await
+    .context("failed to store pending retention run.")?;
+    tracing::info!(
+        reminders_sent = report.reminders_sent,
+        purged = report.purged,
+        "pending subscribers cleaned up."
+    );
+
+    Ok(report)
+}
+
+/// 将需要提醒的订阅者的确认邮件加入发送队列并标记，返回提醒的订阅者数
+///
+/// 每个订阅者只提醒一次，只有确认邮件实际加入队列的订阅者才会被标记并在之后删除
+/// 确认邮件已在队列中(尚未发送)的订阅者等到下一次运行，没有确认令牌的订阅者不会被提醒
+async fn enqueue_reminders(pool: &PgPool, reminder_after_hours: u64) -> anyhow::Result<i64> {
+    let reminded = sqlx::query!(
+        r#"
+        WITH queued AS (
+            INSERT INTO confirmation_email_queue (subscriber_id, subscription_token, enqueued_at)
+            SELECT DISTINCT ON (t.subscriber_id) t.subscriber_id, t.subscription_token, now()
+            FROM subscription s
+            JOIN subscription_token t ON t.subscriber_id = s.id
+            WHERE
+                s.status = $1 AND
+                s.confirmation_reminder_sent_at IS NULL AND
+                s.subscribed_at <= now() - make_interval(secs => $2)
+            ON CONFLICT (subscriber_id) DO NOTHING
+            RETURNING subscriber_id
+        )
+        UPDATE subscription
+        SET confirmation_reminder_sent_at = now()
+        FROM queued
+        WHERE subscription.id = queued.subscriber_id
+        "#,
+        SubscriberStatus::PendingConfirmation.as_str(),
+        (reminder_after_hours * 3600) as f64,
+    )
+    .execute(pool)
+    .await
+    .context("failed to enqueue confirmation reminders.")?
+    .rows_affected();
+
+    Ok(reminded as i64)
+}
+
+/// 分批删除提醒后仍未确认的订阅者，返回删除的订阅者数
+async fn purge_reminded(pool: &PgPool, purge_after_hours: u64) -> anyhow::Result<i64> {
+    let mut purged = 0;
+    loop {
+        let mut transaction = pool
+            .begin()
+            .await
+            .context("failed to open a transaction.")?;
+        let batch = lock_purgeable(transaction.as_mut(), purge_after_hours).await?;
+        for subscriber_id in &batch {
+            erase_subscriber_by_id(transaction.as_mut(), *subscriber_id).await?;
+        }
+        transaction
+            .commit()
+            .await
+            .context("failed to commit transaction.")?;
+        purged += batch.len() as i64;
+        if (batch.len() as i64) < PURGE_BATCH_SIZE {
+            return Ok(purged);
+        }
+    }
+}
+
+/// 锁定一批可删除的订阅者，正在确认的订阅者被跳过
+async fn lock_purgeable(
+    executor: &mut PgConnection,
+    purge_after_hours: u64,
+) -> anyhow::Result<Vec<Uuid>> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT id FROM subscription
+        WHERE
+            status = $1 AND
+            confirmation_reminder_sent_at <= now() - make_interval(secs => $2)
+        ORDER BY subscribed_at
+        LIMIT $3
+        FOR UPDATE SKIP LOCKED
+        "#,
+        SubscriberStatus::PendingConfirmation.as_str(),
+        (purge_after_hours * 3600) as f64,
+        PURGE_BATCH_SIZE,
+    )
+    .fetch_all(executor)
+    .await
+    .context("failed to retrieve pending subscribers to purge.")
+}
+
+/// 最近的清理记录
+#[tracing::instrument(name = "查询清理记录", skip(pool))]
+pub async fn recent_runs(pool: &PgPool, limit: i64) -> anyhow::Result<Vec<RetentionRun>> {
+    sqlx::query_as!(
+        RetentionRun,
+        r#"
+        SELECT started_at, finished_at, reminders_sent, purged
+        FROM pending_retention_run
+        ORDER BY started_at DESC
+        LIMIT $1
+        "#,
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to retrieve pending retention runs.")
+}
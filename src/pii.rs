@@ -0,0 +1,421 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use anyhow::Context;
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use hmac::Mac;
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::PgPool;
+
+use crate::{
+    config::PiiEncryptionConfig,
+    domain::{canonical_email, SubscriberEmail, SubscriberStatus},
+    subscriber_data::merge_subscriber,
+    util::hmac_sign,
+};
+
+/// 订阅者个人信息(姓名、邮箱地址)的字段级加密
+///
+/// 密文格式: `{key_id}:{hex nonce}:{hex ciphertext}`
+/// 加密始终使用当前密钥，解密根据密文中的密钥id选择密钥
+/// 邮箱地址另外储存一个HMAC盲索引，用于按邮箱查询及唯一性检查，
+/// 索引基于邮箱地址的规范形式，不区分大小写
+/// 姓名及邮箱地址的每个三元组也储存HMAC盲索引，用于子串搜索
+pub struct PiiCipher {
+    active_key_id: String,
+    keys: HashMap<String, XChaCha20Poly1305>,
+    blind_index_key: SecretString,
+    // 由`blind_index_key`派生，三元组索引与邮箱地址索引互不关联
+    trigram_key: SecretString,
+    provider_email_rules: bool,
+}
+
+impl PiiCipher {
+    pub fn from_config(config: &PiiEncryptionConfig) -> anyhow::Result<Self> {
+        let mut keys = HashMap::with_capacity(config.keys.len());
+        for (key_id, key) in &config.keys {
+            anyhow::ensure!(
+                !key_id.is_empty() && !key_id.contains(':'),
+                "invalid pii encryption key id `{key_id}`."
+            );
+            let key = hex::decode(key.expose_secret())
+                .with_context(|| format!("pii encryption key `{key_id}` is not valid hex."))?;
+            let cipher = XChaCha20Poly1305::new_from_slice(&key)
+                .map_err(|_| anyhow::anyhow!("pii encryption key `{key_id}` must be 32 bytes."))?;
+            keys.insert(key_id.clone(), cipher);
+        }
+        anyhow::ensure!(
+            keys.contains_key(&config.active_key_id),
+            "active pii encryption key `{}` is not configured.",
+            config.active_key_id
+        );
+
+        let trigram_key = hmac_sign(&config.blind_index_key, "pii:search-trigram-key")
+            .finalize()
+            .into_bytes();
+
+        Ok(Self {
+            active_key_id: config.active_key_id.clone(),
+            keys,
+            blind_index_key: config.blind_index_key.clone(),
+            trigram_key: SecretString::from(hex::encode(trigram_key)),
+            provider_email_rules: config.provider_email_rules,
+        })
+    }
+
+    pub fn active_key_id(&self) -> &str {
+        &self.active_key_id
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> anyhow::Result<String> {
+        let cipher = &self.keys[&self.active_key_id];
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| anyhow::anyhow!("failed to encrypt pii."))?;
+
+        Ok(format!(
+            "{}:{}:{}",
+            self.active_key_id,
+            hex::encode(nonce),
+            hex::encode(ciphertext)
+        ))
+    }
+
+    pub fn decrypt(&self, ciphertext: &str) -> anyhow::Result<String> {
+        let mut parts = ciphertext.splitn(3, ':');
+        let (Some(key_id), Some(nonce), Some(ciphertext)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            anyhow::bail!("malformed pii ciphertext.");
+        };
+        let cipher = self
+            .keys
+            .get(key_id)
+            .with_context(|| format!("pii encryption key `{key_id}` is not configured."))?;
+        let nonce = hex::decode(nonce).context("malformed pii ciphertext nonce.")?;
+        anyhow::ensure!(nonce.len() == 24, "malformed pii ciphertext nonce.");
+        let ciphertext = hex::decode(ciphertext).context("malformed pii ciphertext.")?;
+        let plaintext = cipher
+            .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| anyhow::anyhow!("failed to decrypt pii with key `{key_id}`."))?;
+
+        String::from_utf8(plaintext).context("decrypted pii is not valid utf-8.")
+    }
+
+    /// 邮箱地址规范形式的盲索引
+    pub fn blind_index(&self, email: &str) -> String {
+        let email = canonical_email(email, self.provider_email_rules);
+        hex::encode(
+            hmac_sign(&self.blind_index_key, &email)
+                .finalize()
+                .into_bytes(),
+        )
+    }
+
+    /// 当前盲索引使用的规范化规则，与`subscription.email_index_scheme`比较判断是否需要重建索引
+    pub fn email_index_scheme(&self) -> &'static str {
+        if self.provider_email_rules {
+            "canonical+provider"
+        } else {
+            "canonical"
+        }
+    }
+
+    /// 文本中所有三元组(不区分大小写)的盲索引，已去重并排序
+    ///
+    /// 查询词的三元组全部包含在订阅者的三元组中时即为候选结果，
+    /// 盲索引截断为8字节，候选结果仍需解密后确认
+    ///
+    /// 使用单独派生的密钥，不能与邮箱地址盲索引相互比对。取舍: 同一三元组在所有订阅者中
+    /// 的索引相同，能读取数据库的人可以统计各索引的出现频率，结合常见姓名及邮箱域名的
+    /// 三元组分布推测部分明文(如邮箱域名)，也能判断两个订阅者是否含有相同的子串。
+    /// 这是支持子串搜索的代价，不需要搜索时可以清空`search_trigrams`列
+    pub fn search_trigrams<'a>(&self, values: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+        let mut trigrams = BTreeSet::new();
+        for value in values {
+            let chars: Vec<char> = value.to_lowercase().chars().collect();
+            for window in chars.windows(3) {
+                let trigram: String = window.iter().collect();
+                let mac = hmac_sign(&self.trigram_key, &format!("trigram:{trigram}"))
+                    .finalize()
+                    .into_bytes();
+                trigrams.insert(hex::encode(&mac[..8]));
+            }
+        }
+
+        trigrams.into_iter().collect()
+    }
+}
+
+/// 重新加密并重建索引，直至所有订阅者都使用当前密钥及盲索引规则，返回处理的行数
+///
+/// 服务启动时在后台执行，升级盲索引规则或轮换密钥后无需手动执行`rotate_pii_keys`
+pub async fn rotate_all_keys(
+    pool: &PgPool,
+    cipher: &PiiCipher,
+    batch_size: i64,
+) -> anyhow::Result<u64> {
+    let mut total = 0;
+    loop {
+        let count = rotate_keys_batch(pool, cipher, batch_size).await?;
+        if count == 0 {
+            return Ok(total);
+        }
+        total += count;
+    }
+}
+
+/// 使用当前密钥重新加密一批订阅者，返回处理的行数
+///
+/// 未使用当前密钥加密的行(包括加密前遗留的明文行)、缺少搜索索引的行
+/// 及盲索引规则不是当前规则的行都会被处理，邮箱地址同时规范化
+/// 规范化后与同一列表中的其他订阅重复时合并，保留已确认或订阅较早的一条
+/// 每批在一个事务中完成，可与web服务同时运行
+#[tracing::instrument(name = "轮换订阅者加密密钥", skip(pool, cipher))]
+pub async fn rotate_keys_batch(
+    pool: &PgPool,
+    cipher: &PiiCipher,
+    batch_size: i64,
+) -> anyhow::Result<u64> {
+    let mut transaction = pool.begin().await?;
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            id, list_id, status, subscribed_at,
+            name, email, name_ciphertext, email_ciphertext
+        FROM subscription
+        WHERE
+            pii_key_id IS DISTINCT FROM $1 OR
+            cardinality(search_trigrams) = 0 OR
+            email_index_scheme IS DISTINCT FROM $3
+        LIMIT $2
+        FOR UPDATE SKIP LOCKED
+        "#,
+        cipher.active_key_id(),
+        batch_size,
+        cipher.email_index_scheme(),
+    )
+    .fetch_all(transaction.as_mut())
+    .await
+    .context("failed to retrieve subscribers to re-encrypt.")?;
+
+    // 本批中已被合并删除的订阅
+    let mut merged = HashSet::new();
+    for row in &rows {
+        if merged.contains(&row.id) {
+            continue;
+        }
+        let (name, email) = match (&row.name_ciphertext, &row.email_ciphertext) {
+            (Some(name), Some(email)) => (cipher.decrypt(name)?, cipher.decrypt(email)?),
+            _ => (
+                row.name.clone().context("subscriber name is missing.")?,
+                row.email.clone().context("subscriber email is missing.")?,
+            ),
+        };
+        let email = SubscriberEmail::parse(&email)
+            .map(|email| email.as_ref().to_owned())
+            .unwrap_or(email);
+        let email_index = cipher.blind_index(&email);
+
+        let duplicate = sqlx::query!(
+            r#"
+            SELECT id, status, subscribed_at FROM subscription
+            WHERE list_id = $1 AND email_index = $2 AND id <> $3
+            FOR UPDATE
+            "#,
+            row.list_id,
+            email_index,
+            row.id,
+        )
+        .fetch_optional(transaction.as_mut())
+        .await
+        .context("failed to look up duplicate subscription.")?;
+        if let Some(duplicate) = duplicate {
+            let confirmed = SubscriberStatus::Confirmed.as_str();
+            let keep_row = (row.status != confirmed, row.subscribed_at)
+                < (duplicate.status != confirmed, duplicate.subscribed_at);
+            if !keep_row {
+                merge_subscriber(transaction.as_mut(), row.id, duplicate.id).await?;
+                continue;
+            }
+            merge_subscriber(transaction.as_mut(), duplicate.id, row.id).await?;
+            merged.insert(duplicate.id);
+        }
+
+        sqlx::query!(
+            r#"
+            UPDATE subscription
+            SET
+                name = NULL,
+                email = NULL,
+                name_ciphertext = $2,
+                email_ciphertext = $3,
+                email_index = $4,
+                pii_key_id = $5,
+                search_trigrams = $6,
+                email_index_scheme = $7
+            WHERE id = $1
+            "#,
+            row.id,
+            cipher.encrypt(&name)?,
+            cipher.encrypt(&email)?,
+            email_index,
+            cipher.active_key_id(),
+            &cipher.search_trigrams([name.as_str(), email.as_str()]),
+            cipher.email_index_scheme(),
+        )
+        .execute(transaction.as_mut())
+        .await
+        .context("failed to re-encrypt subscriber.")?;
+    }
+    transaction.commit().await?;
+
+    Ok(rows.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use claim::{assert_err, assert_ok};
+    use hmac::Mac;
+    use secrecy::SecretString;
+
+    use super::PiiCipher;
+    use crate::{config::PiiEncryptionConfig, util::hmac_sign};
+
+    const KEY_1: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    const KEY_2: &str = "1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+
+    fn cipher(active_key_id: &str, keys: &[(&str, &str)]) -> PiiCipher {
+        PiiCipher::from_config(&PiiEncryptionConfig {
+            active_key_id: active_key_id.into(),
+            keys: keys
+                .iter()
+                .map(|(id, key)| (id.to_string(), SecretString::from(key.to_string())))
+                .collect::<HashMap<_, _>>(),
+            blind_index_key: SecretString::from("blind-index-key"),
+            provider_email_rules: false,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn ciphertext_round_trips_and_hides_plaintext() {
+        let cipher = cipher("k1", &[("k1", KEY_1)]);
+        let ciphertext = cipher.encrypt("git@github.com").unwrap();
+
+        assert!(ciphertext.starts_with("k1:"));
+        assert!(!ciphertext.contains("github"));
+        assert_eq!(cipher.decrypt(&ciphertext).unwrap(), "git@github.com");
+        // 每次加密使用随机nonce
+        assert_ne!(ciphertext, cipher.encrypt("git@github.com").unwrap());
+    }
+
+    #[test]
+    fn old_key_can_still_decrypt_after_rotation() {
+        let old = cipher("k1", &[("k1", KEY_1)]);
+        let rotated = cipher("k2", &[("k1", KEY_1), ("k2", KEY_2)]);
+        let ciphertext = old.encrypt("IceFruit huang").unwrap();
+
+        assert_eq!(rotated.decrypt(&ciphertext).unwrap(), "IceFruit huang");
+        assert!(rotated
+            .encrypt("IceFruit huang")
+            .unwrap()
+            .starts_with("k2:"));
+    }
+
+    #[test]
+    fn tampered_or_unknown_ciphertext_is_rejected() {
+        let cipher = cipher("k1", &[("k1", KEY_1)]);
+        let ciphertext = cipher.encrypt("git@github.com").unwrap();
+        let mut tampered = ciphertext.clone();
+        let last = if tampered.pop() == Some('0') {
+            '1'
+        } else {
+            '0'
+        };
+        tampered.push(last);
+
+        assert_err!(cipher.decrypt(&tampered));
+        assert_err!(cipher.decrypt(&ciphertext.replacen("k1", "k9", 1)));
+        assert_err!(cipher.decrypt("git@github.com"));
+    }
+
+    #[test]
+    fn blind_index_is_deterministic_and_keyed() {
+        let cipher = cipher("k1", &[("k1", KEY_1)]);
+
+        assert_eq!(
+            cipher.blind_index("git@github.com"),
+            cipher.blind_index("git@github.com")
+        );
+        assert_ne!(
+            cipher.blind_index("git@github.com"),
+            cipher.blind_index("other@github.com")
+        );
+    }
+
+    #[test]
+    fn search_trigrams_match_substrings_case_insensitively() {
+        let cipher = cipher("k1", &[("k1", KEY_1)]);
+        let subscriber = cipher.search_trigrams(["IceFruit huang", "git@github.com"]);
+        let contains = |query: &str| {
+            cipher
+                .search_trigrams([query])
+                .iter()
+                .all(|t| subscriber.binary_search(t).is_ok())
+        };
+
+        assert!(contains("fruit"));
+        assert!(contains("HUANG"));
+        assert!(contains("@github"));
+        assert!(!contains("gitlab"));
+        assert!(cipher.search_trigrams(["ab"]).is_empty());
+        // 不同的盲索引密钥得到不同的索引
+        let other = PiiCipher::from_config(&PiiEncryptionConfig {
+            active_key_id: "k1".into(),
+            keys: HashMap::from([("k1".to_string(), SecretString::from(KEY_1))]),
+            blind_index_key: SecretString::from("other-blind-index-key"),
+            provider_email_rules: false,
+        })
+        .unwrap();
+        assert_ne!(
+            other.search_trigrams(["fruit"]),
+            cipher.search_trigrams(["fruit"])
+        );
+        // 三元组索引不直接使用盲索引密钥
+        let mac = hmac_sign(&SecretString::from("blind-index-key"), "trigram:fru")
+            .finalize()
+            .into_bytes();
+        assert_eq!(cipher.search_trigrams(["fru"]).len(), 1);
+        assert!(!cipher
+            .search_trigrams(["fru"])
+            .contains(&hex::encode(&mac[..8])));
+    }
+
+    #[test]
+    fn active_key_must_be_configured() {
+        let config = PiiEncryptionConfig {
+            active_key_id: "k2".into(),
+            keys: HashMap::from([("k1".to_string(), SecretString::from(KEY_1))]),
+            blind_index_key: SecretString::from("blind-index-key"),
+            provider_email_rules: false,
+        };
+        assert!(PiiCipher::from_config(&config).is_err());
+
+        let config = PiiEncryptionConfig {
+            active_key_id: "k1".into(),
+            keys: HashMap::from([("k1".to_string(), SecretString::from("too-short"))]),
+            ..config
+        };
+        assert!(PiiCipher::from_config(&config).is_err());
+        assert_ok!(PiiCipher::from_config(&PiiEncryptionConfig {
+            keys: HashMap::from([("k1".to_string(), SecretString::from(KEY_1))]),
+            ..config
+        }));
+    }
+}
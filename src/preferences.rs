@@ -0,0 +1,426 @@
+use anyhow::Context;
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    PgConnection, PgPool,
+};
+use uuid::Uuid;
+
+use crate::{
+    client_info::ClientInfo,
+    config::Config,
+    consent::{record_confirmation, record_consent},
+    domain::{EmailFormat, SubscriberName, SubscriberStatus},
+    keyring::Keyring,
+    pii::PiiCipher,
+};
+
+/// 签名内容的前缀，与使用同一密钥环的其他签名区分
+const TOKEN_PURPOSE: &str = "preferences";
+
+/// 偏好设置页面中新订阅列表时记录的表单来源
+pub const PREFERENCES_FORM_ORIGIN: &str = "preferences";
+
+/// 签发订阅者的偏好设置令牌，格式为`{subscriber_id}:{signature}`
+///
+/// 签名包含订阅者的`preferences_nonce`，修改邮箱地址时更换，旧地址收到的令牌随之失效；
+/// 从未修改过邮箱地址的订阅者没有该值。令牌不会过期，订阅者被删除后无法再使用
+pub fn issue_preferences_token(
+    keyring: &Keyring,
+    subscriber_id: Uuid,
+    nonce: Option<&str>,
+) -> String {
+    let signature = keyring.sign(&token_payload(subscriber_id, nonce));
+
+    format!("{subscriber_id}:{signature}")
+}
+
+/// 令牌中的订阅者id，签名需再通过[`verify_preferences_token`]校验
+pub fn preferences_token_subscriber(token: &str) -> Option<Uuid> {
+    let (subscriber_id, _) = token.split_once(':')?;
+
+    Uuid::parse_str(subscriber_id).ok()
+}
+
+/// 以订阅者当前的`preferences_nonce`校验偏好设置令牌，返回订阅者id
+pub fn verify_preferences_token(
+    keyring: &Keyring,
+    token: &str,
+    nonce: Option<&str>,
+) -> Option<Uuid> {
+    let subscriber_id = preferences_token_subscriber(token)?;
+    let (_, signature) = token.split_once(':')?;
+    let signature = hex::decode(signature).ok()?;
+
+    keyring
+        .verify(&token_payload(subscriber_id, nonce), &signature)
+        .then_some(subscriber_id)
+}
+
+fn token_payload(subscriber_id: Uuid, nonce: Option<&str>) -> String {
+    match nonce {
+        Some(nonce) => format!("{TOKEN_PURPOSE}:{subscriber_id}:{nonce}"),
+        None => format!("{TOKEN_PURPOSE}:{subscriber_id}"),
+    }
+}
+
+/// 生成邮件中的偏好设置链接
+#[derive(Clone)]
+pub struct PreferenceLinks {
+    base_url: String,
+    keyring: Keyring,
+}
+
+impl PreferenceLinks {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            base_url: config.web.base_url.clone(),
+            keyring: Keyring::from_config(&config.web),
+        }
+    }
+
+    pub fn link(&self, subscriber_id: Uuid, nonce: Option<&str>) -> String {
+        format!(
+            "{}/preferences?token={}",
+            self.base_url,
+            issue_preferences_token(&self.keyring, subscriber_id, nonce)
+        )
+    }
+}
+
+pub struct SubscriberPreferences {
+    pub subscriber_id: Uuid,
+    pub list_name: String,
+    pub name: String,
+    pub email: String,
+    pub status: String,
+    pub email_format: String,
+    pub paused_until: Option<DateTime<Utc>>,
+    pub preferences_nonce: Option<String>,
+}
+
+impl SubscriberPreferences {
+    /// 当前是否暂停接收简报
+    pub fn is_paused(&self) -> bool {
+        self.paused_until.is_some_and(|until| until > Utc::now())
+    }
+
+    /// 暂停中返回恢复接收的日期
+    pub fn paused_until_date(&self) -> Option<String> {
+        self.paused_until
+            .filter(|_| self.is_paused())
+            .map(|until| until.format("%Y-%m-%d").to_string())
+    }
+}
+
+/// 同一邮箱地址在某个邮件列表中的订阅情况
+pub struct ListMembership {
+    pub list_id: Uuid,
+    pub name: String,
+    /// 从未订阅时为空
+    pub subscriber_id: Option<Uuid>,
+    pub status: Option<String>,
+}
+
+impl ListMembership {
+    pub fn is_subscribed(&self) -> bool {
+        self.status.as_deref() == Some(SubscriberStatus::Confirmed.as_str())
+    }
+}
+
+/// 订阅者修改的设置
+pub struct PreferencesUpdate {
+    pub name: SubscriberName,
+    pub email_format: EmailFormat,
+    /// `None`保持不变，`Some(0)`恢复接收，其余为从现在起暂停的天数
+    pub pause_days: Option<i32>,
+}
+
+/// 查询订阅者的偏好设置，订阅者不存在时返回`None`
+#[tracing::instrument(name = "查询偏好设置", skip(pool, pii_cipher))]
+pub async fn get_preferences(
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    subscriber_id: Uuid,
+) -> anyhow::Result<Option<SubscriberPreferences>> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            s.id,
+            l.name AS list_name,
+            s.name_ciphertext AS "name_ciphertext!",
+            s.email_ciphertext AS "email_ciphertext!",
+            s.status,
+            s.email_format,
+            s.paused_until,
+            s.preferences_nonce
+        FROM subscription s
+        JOIN mailing_list l ON l.list_id = s.list_id
+        WHERE s.id = $1 AND s.pii_key_id IS NOT NULL
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to retrieve subscriber preferences.")?;
+    let Some(r) = row else {
+        return Ok(None);
+    };
+
+    Ok(Some(SubscriberPreferences {
+        subscriber_id: r.id,
+        list_name: r.list_name,
+        name: pii_cipher.decrypt(&r.name_ciphertext)?,
+        email: pii_cipher.decrypt(&r.email_ciphertext)?,
+        status: r.status,
+        email_format: r.email_format,
+        paused_until: r.paused_until,
+        preferences_nonce: r.preferences_nonce,
+    }))
+}
+
+/// 保存订阅者修改的设置，姓名及邮箱地址使用当前密钥重新加密
+///
+/// 邮箱地址在事务中锁定后重新读取，不会覆盖期间确认的邮箱地址修改
+#[tracing::instrument(name = "保存偏好设置", skip(pool, pii_cipher, preferences, update))]
+pub async fn update_preferences(
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    preferences: &SubscriberPreferences,
+    update: &PreferencesUpdate,
+) -> anyhow::Result<()> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")?;
+    let email_ciphertext = sqlx::query_scalar!(
+        "SELECT email_ciphertext FROM subscription WHERE id = $1 FOR UPDATE",
+        preferences.subscriber_id,
+    )
+    .fetch_optional(transaction.as_mut())
+    .await
+    .context("failed to lock subscriber.")?
+    .flatten();
+    // 期间订阅者已被删除或擦除
+    let Some(email_ciphertext) = email_ciphertext else {
+        return Ok(());
+    };
+    let email = pii_cipher.decrypt(&email_ciphertext)?;
+    let name = update.name.as_ref();
+    sqlx::query!(
+        r#"
+        UPDATE subscription
+        SET
+            name_ciphertext = $2,
+            email_ciphertext = $3,
+            pii_key_id = $4,
+            search_trigrams = $5,
+            email_format = $6,
+            paused_until = CASE
+                WHEN $7::int IS NULL THEN paused_until
+                WHEN $7 = 0 THEN NULL
+                ELSE now() + make_interval(days => $7)
+            END
+        WHERE id = $1
+        "#,
+        preferences.subscriber_id,
+        pii_cipher.encrypt(name)?,
+        pii_cipher.encrypt(&email)?,
+        pii_cipher.active_key_id(),
+        &pii_cipher.search_trigrams([name, email.as_str()]),
+        update.email_format.as_str(),
+        update.pause_days,
+    )
+    .execute(transaction.as_mut())
+    .await
+    .context("failed to update subscriber preferences.")?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")?;
+
+    Ok(())
+}
+
+/// 邮箱地址在全部邮件列表中的订阅情况
+#[tracing::instrument(name = "查询订阅的邮件列表", skip(pool, pii_cipher, email))]
+pub async fn list_memberships(
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    email: &str,
+) -> anyhow::Result<Vec<ListMembership>> {
+    sqlx::query_as!(
+        ListMembership,
+        r#"
+        SELECT
+            l.list_id,
+            l.name,
+            s.id AS "subscriber_id?",
+            s.status AS "status?"
+        FROM mailing_list l
+        LEFT JOIN subscription s ON s.list_id = l.list_id AND s.email_index = $1
+        ORDER BY l.name
+        "#,
+        pii_cipher.blind_index(email),
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to retrieve list memberships.")
+}
+
+/// 订阅另一个邮件列表，需在同一事务中执行，返回该列表中的订阅者id
+///
+/// 通过偏好设置链接已证明邮箱地址的所有权，直接订阅为已确认，
+/// 新的订阅沿用当前订阅的姓名、设置及偏好设置令牌的随机值
+#[tracing::instrument(name = "订阅邮件列表", skip(executor, pii_cipher, preferences, client))]
+pub async fn join_list(
+    executor: &mut PgConnection,
+    pii_cipher: &PiiCipher,
+    preferences: &SubscriberPreferences,
+    list_id: Uuid,
+    client: &ClientInfo,
+) -> anyhow::Result<Uuid> {
+    let (name, email) = (preferences.name.as_str(), preferences.email.as_str());
+    let existing = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM subscription
+        WHERE list_id = $1 AND email_index = $2
+        FOR UPDATE
+        "#,
+        list_id,
+        pii_cipher.blind_index(email),
+    )
+    .fetch_optional(&mut *executor)
+    .await
+    .context("failed to retrieve subscription.")?;
+
+    let subscriber_id = match existing {
+        Some(subscriber_id) => {
+            sqlx::query!(
+                "UPDATE subscription SET status = $2 WHERE id = $1",
+                subscriber_id,
+                SubscriberStatus::Confirmed.as_str(),
+            )
+            .execute(&mut *executor)
+            .await
+            .context("failed to confirm subscription.")?;
+            subscriber_id
+        }
+        None => {
+            let subscriber_id = Uuid::new_v4();
+            sqlx::query!(
+                r#"
+                INSERT INTO subscription (
+                    id, list_id, name_ciphertext, email_ciphertext, email_index, pii_key_id,
+                    search_trigrams, subscribed_at, status, email_format, paused_until,
+                    email_index_scheme, preferences_nonce
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, now(), $8, $9, $10, $11, $12)
+                "#,
+                subscriber_id,
+                list_id,
+                pii_cipher.encrypt(name)?,
+                pii_cipher.encrypt(email)?,
+                pii_cipher.blind_index(email),
+                pii_cipher.active_key_id(),
+                &pii_cipher.search_trigrams([name, email]),
+                SubscriberStatus::Confirmed.as_str(),
+                preferences.email_format,
+                preferences.paused_until,
+                pii_cipher.email_index_scheme(),
+                preferences.preferences_nonce,
+            )
+            .execute(&mut *executor)
+            .await
+            .context("failed to add subscription.")?;
+            record_consent(executor, subscriber_id, PREFERENCES_FORM_ORIGIN, client)
+                .await
+                .context("failed to record consent.")?;
+            subscriber_id
+        }
+    };
+    record_confirmation(executor, subscriber_id, client)
+        .await
+        .context("failed to record consent confirmation.")?;
+
+    Ok(subscriber_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::SecretString;
+    use uuid::Uuid;
+
+    use super::{issue_preferences_token, verify_preferences_token};
+    use crate::keyring::Keyring;
+
+    fn keyring() -> Keyring {
+        Keyring::new(SecretString::from("secret"), vec![])
+    }
+
+    #[test]
+    fn token_identifies_the_subscriber() {
+        let subscriber_id = Uuid::new_v4();
+        let token = issue_preferences_token(&keyring(), subscriber_id, Some("nonce"));
+
+        assert_eq!(
+            verify_preferences_token(&keyring(), &token, Some("nonce")),
+            Some(subscriber_id)
+        );
+    }
+
+    #[test]
+    fn token_signed_before_rotation_is_accepted() {
+        let subscriber_id = Uuid::new_v4();
+        let token = issue_preferences_token(&keyring(), subscriber_id, None);
+        let rotated = Keyring::new(
+            SecretString::from("new-secret"),
+            vec![SecretString::from("secret")],
+        );
+
+        assert_eq!(
+            verify_preferences_token(&rotated, &token, None),
+            Some(subscriber_id)
+        );
+    }
+
+    #[test]
+    fn token_is_bound_to_the_nonce() {
+        let subscriber_id = Uuid::new_v4();
+        let legacy = issue_preferences_token(&keyring(), subscriber_id, None);
+        let token = issue_preferences_token(&keyring(), subscriber_id, Some("old"));
+
+        assert_eq!(
+            verify_preferences_token(&keyring(), &legacy, Some("new")),
+            None
+        );
+        assert_eq!(
+            verify_preferences_token(&keyring(), &token, Some("new")),
+            None
+        );
+        assert_eq!(verify_preferences_token(&keyring(), &token, None), None);
+    }
+
+    #[test]
+    fn forged_tokens_are_rejected() {
+        let token = issue_preferences_token(&keyring(), Uuid::new_v4(), None);
+        let (_, signature) = token.split_once(':').unwrap();
+
+        for forged in [
+            format!("{}:{signature}", Uuid::new_v4()),
+            issue_preferences_token(
+                &Keyring::new(SecretString::from("another-secret"), vec![]),
+                Uuid::new_v4(),
+                None,
+            ),
+            Uuid::new_v4().to_string(),
+            format!("not-a-uuid:{signature}"),
+            format!("{}:zz", Uuid::new_v4()),
+        ] {
+            assert_eq!(
+                verify_preferences_token(&keyring(), &forged, None),
+                None,
+                "{forged}"
+            );
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use anyhow::Context;
+use rand::distributions::{Alphanumeric, DistString};
+use redis::{aio::ConnectionManager, AsyncCommands, ExistenceCheck, SetExpiry, SetOptions};
+use sha2::{Digest, Sha256};
+use sqlx::types::chrono::Utc;
+
+use crate::{config::ProofOfWorkConfig, keyring::Keyring};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ProofOfWorkError {
+    #[error("proof of work is required.")]
+    Missing,
+    #[error("challenge is malformed.")]
+    Malformed,
+    #[error("challenge signature is invalid.")]
+    InvalidSignature,
+    #[error("challenge has expired.")]
+    Expired,
+    #[error("solution does not meet the required difficulty.")]
+    InsufficientWork,
+    #[error("challenge has already been used.")]
+    AlreadyUsed,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+/// 订阅表单的工作量证明(hashcash)
+///
+/// 挑战格式: `{issued_at}:{difficulty}:{salt}:{signature}`
+/// signature为前三段的HMAC-SHA256，服务端无需保存已签发的挑战
+/// 客户端需找到nonce，使`SHA-256("{challenge}:{nonce}")`至少有`difficulty`个前导0 bit
+/// 每个挑战只能使用一次，已使用的挑战记录在redis中直至过期
+#[derive(Clone)]
+pub struct ProofOfWork {
+    manager: ConnectionManager,
+    keyring: Keyring,
+    config: ProofOfWorkConfig,
+}
+
+impl ProofOfWork {
+    pub fn new(manager: ConnectionManager, keyring: Keyring, config: ProofOfWorkConfig) -> Self {
+        Self {
+            manager,
+            keyring,
+            config,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn difficulty(&self) -> u32 {
+        self.config.difficulty_bits
+    }
+
+    pub fn ttl_seconds(&self) -> u64 {
+        self.config.ttl_seconds
+    }
+
+    /// 签发新的挑战
+    pub fn issue(&self) -> String {
+        issue_challenge(
+            &self.keyring,
+            self.config.difficulty_bits,
+            Utc::now().timestamp(),
+        )
+    }
+
+    /// 校验挑战的解，校验通过后该挑战即失效
+    #[tracing::instrument(name = "校验工作量证明", skip_all)]
+    pub async fn verify(&self, challenge: &str, nonce: &str) -> Result<(), ProofOfWorkError> {
+        verify_solution(
+            &self.keyring,
+            challenge,
+            nonce,
+            Utc::now().timestamp(),
+            self.config.ttl_seconds,
+        )?;
+
+        let mut con = self.manager.clone();
+        let options = SetOptions::default()
+            .conditional_set(ExistenceCheck::NX)
+            .with_expiration(SetExpiry::EX(self.config.ttl_seconds));
+        let first_use: Option<String> = con
+            .set_options(format!("pow_challenge:{challenge}"), 1, options)
+            .await
+            .context("failed to mark challenge as used.")?;
+        if first_use.is_none() {
+            return Err(ProofOfWorkError::AlreadyUsed);
+        }
+
+        Ok(())
+    }
+}
+
+fn issue_challenge(keyring: &Keyring, difficulty: u32, now: i64) -> String {
+    let salt = Alphanumeric.sample_string(&mut rand::thread_rng(), 16);
+    let payload = format!("{now}:{difficulty}:{salt}");
+    let signature = keyring.sign(&payload);
+
+    format!("{payload}:{signature}")
+}
+
+fn verify_solution(
+    keyring: &Keyring,
+    challenge: &str,
+    nonce: &str,
+    now: i64,
+    ttl_seconds: u64,
+) -> Result<(), ProofOfWorkError> {
+    let (payload, signature) = challenge
+        .rsplit_once(':')
+        .ok_or(ProofOfWorkError::Malformed)?;
+    let mut parts = payload.splitn(3, ':');
+    let (Some(issued_at), Some(difficulty), Some(_salt)) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ProofOfWorkError::Malformed);
+    };
+    let issued_at: i64 = issued_at.parse().map_err(|_| ProofOfWorkError::Malformed)?;
+    let difficulty: u32 = difficulty
+        .parse()
+        .map_err(|_| ProofOfWorkError::Malformed)?;
+    let signature = hex::decode(signature).map_err(|_| ProofOfWorkError::Malformed)?;
+
+    // 先校验签名，防止客户端篡改难度或签发时间
+    if !keyring.verify(payload, &signature) {
+        return Err(ProofOfWorkError::InvalidSignature);
+    }
+    if now - issued_at > ttl_seconds as i64 || issued_at > now {
+        return Err(ProofOfWorkError::Expired);
+    }
+
+    let hash = Sha256::digest(format!("{challenge}:{nonce}").as_bytes());
+    if leading_zero_bits(&hash) < difficulty {
+        return Err(ProofOfWorkError::InsufficientWork);
+    }
+
+    Ok(())
+}
+
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::SecretString;
+    use sha2::{Digest, Sha256};
+
+    use super::{issue_challenge, leading_zero_bits, verify_solution, ProofOfWorkError};
+    use crate::keyring::Keyring;
+
+    const TTL: u64 = 300;
+
+    fn keyring() -> Keyring {
+        Keyring::new(SecretString::from("secret"), vec![])
+    }
+
+    fn solve(challenge: &str, difficulty: u32) -> String {
+        (0u64..)
+            .map(|n| n.to_string())
+            .find(|nonce| {
+                let hash = Sha256::digest(format!("{challenge}:{nonce}").as_bytes());
+                leading_zero_bits(&hash) >= difficulty
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn leading_zero_bits_are_counted_across_bytes() {
+        assert_eq!(leading_zero_bits(&[0xff]), 0);
+        assert_eq!(leading_zero_bits(&[0x00, 0x10]), 11);
+        assert_eq!(leading_zero_bits(&[0x00, 0x00]), 16);
+    }
+
+    #[test]
+    fn valid_solution_is_accepted() {
+        let challenge = issue_challenge(&keyring(), 8, 1000);
+        let nonce = solve(&challenge, 8);
+
+        assert!(verify_solution(&keyring(), &challenge, &nonce, 1010, TTL).is_ok());
+    }
+
+    #[test]
+    fn insufficient_work_is_rejected() {
+        let challenge = issue_challenge(&keyring(), 8, 1000);
+        let nonce = (0u64..)
+            .map(|n| n.to_string())
+            .find(|nonce| {
+                let hash = Sha256::digest(format!("{challenge}:{nonce}").as_bytes());
+                leading_zero_bits(&hash) < 8
+            })
+            .unwrap();
+
+        assert!(matches!(
+            verify_solution(&keyring(), &challenge, &nonce, 1010, TTL),
+            Err(ProofOfWorkError::InsufficientWork)
+        ));
+    }
+
+    #[test]
+    fn expired_challenge_is_rejected() {
+        let challenge = issue_challenge(&keyring(), 0, 1000);
+
+        assert!(matches!(
+            verify_solution(&keyring(), &challenge, "0", 1000 + TTL as i64 + 1, TTL),
+            Err(ProofOfWorkError::Expired)
+        ));
+    }
+
+    #[test]
+    fn tampered_difficulty_is_rejected() {
+        let challenge = issue_challenge(&keyring(), 20, 1000);
+        let tampered = challenge.replacen(":20:", ":0:", 1);
+
+        assert!(matches!(
+            verify_solution(&keyring(), &tampered, "0", 1010, TTL),
+            Err(ProofOfWorkError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn challenge_signed_with_another_secret_is_rejected() {
+        let challenge = issue_challenge(
+            &Keyring::new(SecretString::from("another-secret"), vec![]),
+            0,
+            1000,
+        );
+
+        assert!(matches!(
+            verify_solution(&keyring(), &challenge, "0", 1010, TTL),
+            Err(ProofOfWorkError::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn malformed_challenge_is_rejected() {
+        for challenge in ["", "abc", "1000:8:salt", "x:8:salt:00", "1000:8:salt:zz"] {
+            assert!(
+                matches!(
+                    verify_solution(&keyring(), challenge, "0", 1010, TTL),
+                    Err(ProofOfWorkError::Malformed)
+                ),
+                "{challenge}"
+            );
+        }
+    }
+}
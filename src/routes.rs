@@ -1,11 +1,15 @@
 mod admin;
 mod login;
+mod preferences;
 mod subscription;
+mod subscription_challenge;
 mod subscription_confirm;
 
 pub use admin::*;
 pub use login::*;
+pub use preferences::*;
 pub use subscription::*;
+pub use subscription_challenge::*;
 pub use subscription_confirm::*;
 
 use actix_web::{HttpResponse, Responder};
@@ -1,11 +1,34 @@
+mod attributes;
+mod audit;
+mod consents;
 mod dashboard;
+mod email;
+mod lists;
 mod logout;
 mod newsletter;
 mod password;
+mod segments;
+mod sessions;
+mod subscriber_data;
+mod subscriber_import;
+mod subscribers;
 
+pub use attributes::{attributes_page, create_attribute};
+pub use audit::{audit_log_page, export_audit_log};
+pub use consents::consents_page;
 pub use dashboard::admin_dashboard;
+pub use email::{confirm_email, confirm_email_page, email_page, request_email};
+pub use lists::{create_list, lists_page};
 pub use logout::logout;
 pub use newsletter::publish;
 pub use newsletter::publish_form;
 pub use password::change_password;
 pub use password::change_password_form;
+pub use segments::{create_segment, delete_segment, segments_page};
+pub use sessions::{revoke_all_sessions, revoke_session, sessions_page};
+pub use subscriber_data::{download_subscriber_data, erase_subscriber, subscriber_data_page};
+pub use subscriber_import::{download_import_report, import_subscribers, subscriber_import_page};
+pub use subscribers::{
+    add_tags, export_subscribers, manage_subscriber, remove_tag, subscriber_page, subscribers_page,
+    update_subscriber_attributes,
+};
@@ -0,0 +1,30 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::{
+    attributes::{list_attributes, AttributeDefinition, AttributeKind},
+    util::{collect_flash_messages, e500, render_html},
+};
+
+#[derive(Template)]
+#[template(path = "admin/attributes.html")]
+struct AttributesPage {
+    flash_messages: Vec<String>,
+    attributes: Vec<AttributeDefinition>,
+    kinds: [AttributeKind; 5],
+}
+
+pub async fn attributes_page(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let attributes = list_attributes(pool.get_ref()).await.map_err(e500)?;
+
+    render_html(&AttributesPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+        attributes,
+        kinds: AttributeKind::ALL,
+    })
+}
@@ -0,0 +1,109 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    attributes::{self, AttributeKind, NewAttribute},
+    audit::{record_audit_log, AuditAction},
+    authentication::UserId,
+    client_info::ClientInfo,
+    domain::AttributeKey,
+    util::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    key: String,
+    label: String,
+    kind: String,
+    // 复选框，勾选时为"yes"
+    required: Option<String>,
+    // 逗号分隔，仅`choice`类型使用
+    #[serde(default)]
+    choices: String,
+}
+
+impl TryFrom<&FormData> for NewAttribute {
+    type Error = String;
+
+    fn try_from(form: &FormData) -> Result<Self, Self::Error> {
+        let label = form.label.trim();
+        if label.is_empty() {
+            return Err("label is required.".into());
+        }
+        let kind = AttributeKind::parse(form.kind.trim())?;
+        let choices: Vec<String> = form
+            .choices
+            .split(',')
+            .map(str::trim)
+            .filter(|c| !c.is_empty())
+            .map(str::to_owned)
+            .collect();
+        match kind {
+            AttributeKind::Choice if choices.is_empty() => {
+                return Err("choice attributes need at least one choice.".into())
+            }
+            AttributeKind::Choice => {}
+            _ if !choices.is_empty() => {
+                return Err("only choice attributes can have choices.".into())
+            }
+            _ => {}
+        }
+
+        Ok(Self {
+            key: AttributeKey::parse(&form.key)?,
+            label: label.to_owned(),
+            kind,
+            required: form.required.is_some(),
+            choices,
+        })
+    }
+}
+
+/// 新建订阅者属性
+#[tracing::instrument(name = "新建订阅者属性", skip_all)]
+pub async fn create_attribute(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let attribute: NewAttribute = match (&form.0).try_into() {
+        Ok(attribute) => attribute,
+        Err(e) => {
+            FlashMessage::error(format!("属性无效: {e}")).send();
+            return Ok(see_other("/admin/attributes"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")
+        .map_err(e500)?;
+    if !attributes::create_attribute(transaction.as_mut(), &attribute)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("属性标识已存在.").send();
+        return Ok(see_other("/admin/attributes"));
+    }
+    record_audit_log(
+        transaction.as_mut(),
+        &user_id.into_inner(),
+        AuditAction::CreateAttribute,
+        Some(attribute.key.as_ref()),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")
+        .map_err(e500)?;
+
+    FlashMessage::info("属性已创建.").send();
+    Ok(see_other("/admin/attributes"))
+}
@@ -0,0 +1,52 @@
+mod export;
+mod get;
+
+pub use export::export_audit_log;
+pub use get::audit_log_page;
+
+use sqlx::types::chrono::{NaiveDate, NaiveTime};
+
+use crate::audit::AuditLogFilter;
+
+/// 审计日志页面及导出共用的查询参数，空字符串视为不限制
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    action: Option<String>,
+    actor: Option<String>,
+    // 日期格式: YYYY-MM-DD，包含当天
+    from: Option<String>,
+    to: Option<String>,
+}
+
+impl TryFrom<&QueryParams> for AuditLogFilter {
+    type Error = String;
+
+    fn try_from(params: &QueryParams) -> Result<Self, Self::Error> {
+        fn non_empty(s: &Option<String>) -> Option<String> {
+            s.as_deref()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_owned)
+        }
+        fn parse_date(s: &Option<String>) -> Result<Option<NaiveDate>, String> {
+            non_empty(s)
+                .map(|s| {
+                    NaiveDate::parse_from_str(&s, "%Y-%m-%d")
+                        .map_err(|_| format!("`{s}` is not a valid date."))
+                })
+                .transpose()
+        }
+
+        let from = parse_date(&params.from)?.map(|d| d.and_time(NaiveTime::MIN).and_utc());
+        let to = parse_date(&params.to)?
+            .and_then(|d| d.succ_opt())
+            .map(|d| d.and_time(NaiveTime::MIN).and_utc());
+
+        Ok(Self {
+            action: non_empty(&params.action),
+            actor: non_empty(&params.actor),
+            from,
+            to,
+        })
+    }
+}
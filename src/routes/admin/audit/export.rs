@@ -0,0 +1,57 @@
+use actix_web::{
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web, HttpResponse,
+};
+use sqlx::PgPool;
+
+use crate::{
+    audit::{search_audit_log, AuditLogFilter},
+    util::{csv_cell, e400, e500},
+};
+
+use super::QueryParams;
+
+#[tracing::instrument(name = "导出审计日志", skip_all)]
+pub async fn export_audit_log(
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter: AuditLogFilter = (&query.0).try_into().map_err(e400)?;
+    let entries = search_audit_log(&pool, &filter, None).await.map_err(e500)?;
+
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record([
+            "created_at",
+            "actor",
+            "action",
+            "target_id",
+            "ip",
+            "user_agent",
+        ])
+        .map_err(e500)?;
+    for entry in entries {
+        let created_at = entry.created_at.to_rfc3339();
+        let fields = [
+            created_at.as_str(),
+            &entry.actor,
+            &entry.action,
+            entry.target_id.as_deref().unwrap_or(""),
+            &entry.ip,
+            &entry.user_agent,
+        ]
+        .map(csv_cell);
+        writer
+            .write_record(fields.iter().map(|field| field.as_bytes()))
+            .map_err(e500)?;
+    }
+    let body = writer.into_inner().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("audit_log.csv".into())],
+        })
+        .body(body))
+}
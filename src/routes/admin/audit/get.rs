@@ -0,0 +1,46 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::{
+    audit::{search_audit_log, AuditAction, AuditLogEntry, AuditLogFilter},
+    util::{collect_flash_messages, e400, e500, render_html},
+};
+
+use super::QueryParams;
+
+/// 页面最多显示的记录数，完整记录可通过CSV导出
+const PAGE_LIMIT: i64 = 200;
+
+#[derive(Template)]
+#[template(path = "admin/audit.html")]
+struct AuditLogPage<'a> {
+    flash_messages: Vec<String>,
+    actions: &'a [AuditAction],
+    query: &'a QueryParams,
+    query_string: &'a str,
+    filter: &'a AuditLogFilter,
+    entries: Vec<AuditLogEntry>,
+}
+
+pub async fn audit_log_page(
+    request: HttpRequest,
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter: AuditLogFilter = (&query.0).try_into().map_err(e400)?;
+    let entries = search_audit_log(&pool, &filter, Some(PAGE_LIMIT))
+        .await
+        .map_err(e500)?;
+
+    render_html(&AuditLogPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+        actions: &AuditAction::ALL,
+        query: &query,
+        query_string: request.query_string(),
+        filter: &filter,
+        entries,
+    })
+}
@@ -0,0 +1,49 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::{
+    consent::{search_consent_records, ConsentRecord},
+    pii::PiiCipher,
+    util::{collect_flash_messages, e500, render_html},
+};
+
+/// 未指定邮箱时最多显示的记录数
+const PAGE_LIMIT: i64 = 100;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    email: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/consents.html")]
+struct ConsentsPage {
+    flash_messages: Vec<String>,
+    email: String,
+    records: Vec<ConsentRecord>,
+}
+
+pub async fn consents_page(
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+    pii_cipher: web::Data<PiiCipher>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = query.0.email.unwrap_or_default().trim().to_owned();
+    let records = search_consent_records(
+        &pool,
+        &pii_cipher,
+        Some(email.as_str()).filter(|e| !e.is_empty()),
+        PAGE_LIMIT,
+    )
+    .await
+    .map_err(e500)?;
+
+    render_html(&ConsentsPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+        email,
+        records,
+    })
+}
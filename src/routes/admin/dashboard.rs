@@ -1,27 +1,95 @@
-use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
 use sqlx::PgPool;
 
 use crate::{
     authentication::UserId,
-    util::{e500, format_flash_messages, get_username_by_user_id},
+    pending_retention::{recent_runs, RetentionRun},
+    segments::{list_segments, SegmentSummary},
+    subscription_guard::SubscriptionGuard,
+    util::{collect_flash_messages, e500, get_username_by_user_id, render_html},
 };
 
+/// 仪表盘显示的清理记录数
+const RETENTION_RUNS_SHOWN: i64 = 5;
+
+#[derive(Template)]
+#[template(path = "admin/dashboard.html")]
+struct DashboardPage {
+    flash_messages: Vec<String>,
+    username: String,
+    segments: Vec<SegmentSummary>,
+    rejected_subscriptions: Vec<(&'static str, i64)>,
+    retention_runs: Vec<RetentionRun>,
+}
+
 pub async fn admin_dashboard(
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
     flash_messages: IncomingFlashMessages,
+    guard: web::Data<SubscriptionGuard>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     let username = get_username_by_user_id(*user_id, &pool)
         .await
         .map_err(e500)?;
+    let segments = list_segments(&pool).await.map_err(e500)?;
+    let rejected_subscriptions = guard
+        .rejection_counts()
+        .await
+        .map_err(e500)?
+        .into_iter()
+        .map(|(reason, count)| (reason.as_str(), count))
+        .collect();
+    let retention_runs = recent_runs(&pool, RETENTION_RUNS_SHOWN)
+        .await
+        .map_err(e500)?;
+
+    render_html(&DashboardPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+        username,
+        segments,
+        rejected_subscriptions,
+        retention_runs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use askama::Template;
+
+    use super::DashboardPage;
+
+    #[test]
+    fn admin_pages_share_navigation() {
+        let page = DashboardPage {
+            flash_messages: vec![],
+            username: "admin".into(),
+            segments: vec![],
+            rejected_subscriptions: vec![],
+            retention_runs: vec![],
+        }
+        .render()
+        .unwrap();
+
+        assert!(page.contains("<title>Admin Dashboard</title>"));
+        assert!(page.contains("<nav>"));
+        assert!(page.contains(r#"<form name="logout_form" action="/admin/logout" method="post">"#));
+    }
+
+    #[test]
+    fn username_is_escaped() {
+        let page = DashboardPage {
+            flash_messages: vec![],
+            username: "<b>admin</b>".into(),
+            segments: vec![],
+            rejected_subscriptions: vec![],
+            retention_runs: vec![],
+        }
+        .render()
+        .unwrap();
 
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            include_str!("dashboard.html"),
-            format_flash_messages(flash_messages),
-            username,
-        )))
+        assert!(page.contains("<p>Welcome &lt;b&gt;admin&lt;/b&gt;!</p>"));
+    }
 }
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{confirm_email_page, email_page};
+pub use post::{confirm_email, request_email};
@@ -0,0 +1,54 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::{
+    authentication::{get_admin_email, UserId},
+    util::{collect_flash_messages, e500, render_html},
+};
+
+#[derive(Template)]
+#[template(path = "admin/email.html")]
+struct EmailPage {
+    flash_messages: Vec<String>,
+    email: Option<String>,
+}
+
+/// 管理员邮箱地址页面，免密登录链接发送到该地址
+pub async fn email_page(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = get_admin_email(&pool, *user_id.into_inner())
+        .await
+        .map_err(e500)?;
+
+    render_html(&EmailPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+        email,
+    })
+}
+
+#[derive(serde::Deserialize)]
+pub struct ConfirmParams {
+    token: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin/confirm_email.html")]
+struct ConfirmEmailPage<'a> {
+    flash_messages: Vec<String>,
+    token: &'a str,
+}
+
+/// 验证邮件中的链接，只显示确认页面，避免邮件客户端预取链接时消耗令牌
+pub async fn confirm_email_page(
+    params: web::Query<ConfirmParams>,
+) -> Result<HttpResponse, actix_web::Error> {
+    render_html(&ConfirmEmailPage {
+        flash_messages: Vec::new(),
+        token: &params.token,
+    })
+}
@@ -0,0 +1,116 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::{
+    audit::{record_audit_log, AuditAction},
+    authentication::{confirm_admin_email, request_admin_email, AdminEmailError, UserId},
+    client_info::ClientInfo,
+    config::Config,
+    domain::SubscriberEmail,
+    email_client::EmailCient,
+    util::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+/// 请求设置管理员邮箱地址，向新地址发送验证链接
+#[tracing::instrument(name = "请求设置管理员邮箱", skip_all)]
+pub async fn request_email(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    email_client: web::Data<EmailCient>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Ok(email) = SubscriberEmail::parse(form.0.email.trim()) else {
+        FlashMessage::error("邮箱地址无效.").send();
+        return Ok(see_other("/admin/email"));
+    };
+    let token = match request_admin_email(&pool, *user_id.into_inner(), &email).await {
+        Ok(token) => token,
+        Err(AdminEmailError::Taken) => {
+            FlashMessage::error("该邮箱地址已被其他管理员使用.").send();
+            return Ok(see_other("/admin/email"));
+        }
+        Err(e) => return Err(e500(e)),
+    };
+    send_verification_email(&email, &email_client, &config, &token)
+        .await
+        .map_err(e500)?;
+
+    FlashMessage::info("验证链接已发送，请登录后在新邮箱中打开.").send();
+    Ok(see_other("/admin/email"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ConfirmFormData {
+    token: String,
+}
+
+/// 验证并设置管理员邮箱地址
+#[tracing::instrument(name = "设置管理员邮箱", skip_all)]
+pub async fn confirm_email(
+    form: web::Form<ConfirmFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let email = match confirm_admin_email(&pool, *user_id, &form.token).await {
+        Ok(email) => email,
+        Err(AdminEmailError::Taken) => {
+            FlashMessage::error("该邮箱地址已被其他管理员使用.").send();
+            return Ok(see_other("/admin/email"));
+        }
+        Err(AdminEmailError::InvalidToken) => {
+            FlashMessage::error("验证链接无效或已过期.").send();
+            return Ok(see_other("/admin/email"));
+        }
+        Err(e) => return Err(e500(e)),
+    };
+    record_audit_log(
+        pool.get_ref(),
+        &user_id,
+        AuditAction::ChangeEmail,
+        Some(&email),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info("邮箱地址已更新.").send();
+    Ok(see_other("/admin/email"))
+}
+
+/// 发送邮箱地址验证邮件
+async fn send_verification_email(
+    recipient: &SubscriberEmail,
+    email_client: &EmailCient,
+    config: &Config,
+    token: &str,
+) -> reqwest::Result<()> {
+    let verification_link = format!(
+        "{}/admin/email/confirm?token={}",
+        config.web.base_url, token
+    );
+    let subject = "Verify your admin email address";
+    let text_body = format!(
+        "Visit {} while logged in to the admin dashboard to use this address for login links.\n\
+        If you did not request it, you can ignore this email.",
+        &verification_link
+    );
+    let html_body = format!(
+        "Click <a href=\"{}\">here</a> while logged in to the admin dashboard \
+        to use this address for login links.<br />\
+        If you did not request it, you can ignore this email.",
+        &verification_link
+    );
+
+    email_client
+        .send(recipient, subject, &text_body, &html_body)
+        .await
+}
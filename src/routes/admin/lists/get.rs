@@ -0,0 +1,28 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::{
+    mailing_lists::{list_mailing_lists, MailingListSummary},
+    util::{collect_flash_messages, e500, render_html},
+};
+
+#[derive(Template)]
+#[template(path = "admin/lists.html")]
+struct ListsPage {
+    flash_messages: Vec<String>,
+    lists: Vec<MailingListSummary>,
+}
+
+pub async fn lists_page(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = list_mailing_lists(&pool).await.map_err(e500)?;
+
+    render_html(&ListsPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+        lists,
+    })
+}
@@ -0,0 +1,97 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    audit::{record_audit_log, AuditAction},
+    authentication::UserId,
+    client_info::ClientInfo,
+    domain::{ListSlug, SubscriberEmail},
+    mailing_lists::{self, NewMailingList},
+    util::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    slug: String,
+    name: String,
+    // 为空时使用配置中的发件人
+    sender: String,
+    confirmation_subject: String,
+    confirmation_text: String,
+}
+
+impl TryFrom<&FormData> for NewMailingList {
+    type Error = String;
+
+    fn try_from(form: &FormData) -> Result<Self, Self::Error> {
+        fn required(field: &str, value: &str) -> Result<String, String> {
+            let value = value.trim();
+            if value.is_empty() {
+                return Err(format!("{field} is required."));
+            }
+            Ok(value.to_owned())
+        }
+
+        let sender = Some(form.sender.trim())
+            .filter(|s| !s.is_empty())
+            .map(SubscriberEmail::parse)
+            .transpose()?;
+
+        Ok(Self {
+            slug: ListSlug::parse(&form.slug)?,
+            name: required("name", &form.name)?,
+            sender,
+            confirmation_subject: required("confirmation subject", &form.confirmation_subject)?,
+            confirmation_text: required("confirmation text", &form.confirmation_text)?,
+        })
+    }
+}
+
+/// 新建邮件列表
+#[tracing::instrument(name = "新建邮件列表", skip_all)]
+pub async fn create_list(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let list: NewMailingList = match (&form.0).try_into() {
+        Ok(list) => list,
+        Err(e) => {
+            FlashMessage::error(format!("邮件列表无效: {e}")).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")
+        .map_err(e500)?;
+    let Some(list_id) = mailing_lists::create_list(transaction.as_mut(), &list)
+        .await
+        .map_err(e500)?
+    else {
+        FlashMessage::error("列表标识已存在.").send();
+        return Ok(see_other("/admin/lists"));
+    };
+    record_audit_log(
+        transaction.as_mut(),
+        &user_id.into_inner(),
+        AuditAction::CreateList,
+        Some(&list_id.to_string()),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")
+        .map_err(e500)?;
+
+    FlashMessage::info("邮件列表已创建.").send();
+    Ok(see_other("/admin/lists"))
+}
@@ -1,8 +1,38 @@
+use actix_web::web;
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
-use crate::{session_state::TypedSession, util::see_other};
+use crate::{
+    audit::{record_audit_log, AuditAction},
+    authentication::UserId,
+    client_info::ClientInfo,
+    session_registry::SessionRegistry,
+    session_state::TypedSession,
+    util::{e500, see_other},
+};
+
+pub async fn logout(
+    session: TypedSession,
+    user_id: web::ReqData<UserId>,
+    registry: web::Data<SessionRegistry>,
+    pool: web::Data<PgPool>,
+    client: ClientInfo,
+) -> Result<actix_web::HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let session_id = session.get_session_id().map_err(e500)?;
+    if let Some(session_id) = session_id {
+        registry.revoke(&user_id, &session_id).await.map_err(e500)?;
+    }
+    record_audit_log(
+        pool.get_ref(),
+        &user_id,
+        AuditAction::Logout,
+        session_id.map(|id| id.to_string()).as_deref(),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
 
-pub async fn logout(session: TypedSession) -> Result<actix_web::HttpResponse, actix_web::Error> {
     FlashMessage::info("注销成功.").send();
     do_logout(session)
 }
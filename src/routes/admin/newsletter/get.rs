@@ -1,17 +1,36 @@
-use actix_web::{http::header::ContentType, HttpResponse, Responder};
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::util::format_flash_messages;
+use crate::{
+    mailing_lists::{list_mailing_lists, MailingListSummary},
+    segments::{list_segments, SegmentSummary},
+    util::{collect_flash_messages, e500, render_html},
+};
+
+#[derive(Template)]
+#[template(path = "admin/newsletter.html")]
+struct PublishPage {
+    flash_messages: Vec<String>,
+    idempotency_key: Uuid,
+    lists: Vec<MailingListSummary>,
+    segments: Vec<SegmentSummary>,
+}
+
+pub async fn publish_form(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = list_mailing_lists(&pool).await.map_err(e500)?;
+    let segments = list_segments(&pool).await.map_err(e500)?;
 
-pub async fn publish_form(flash_messages: IncomingFlashMessages) -> impl Responder {
     // 表单中嵌入幂等键
-    let idempotency_key = Uuid::new_v4();
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            include_str!("newsletter.html"),
-            format_flash_messages(flash_messages),
-            idempotency_key,
-        ))
+    render_html(&PublishPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+        idempotency_key: Uuid::new_v4(),
+        lists,
+        segments,
+    })
 }
@@ -4,8 +4,12 @@ use sqlx::{PgConnection, PgPool};
 use uuid::Uuid;
 
 use crate::{
+    audit::{record_audit_log, AuditAction},
     authentication::UserId,
+    client_info::ClientInfo,
     idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    mailing_lists::{get_list, get_list_by_slug, DEFAULT_LIST_SLUG},
+    segments::lock_segment,
     util::{e400, e500, see_other},
     SubscriberStatus,
 };
@@ -15,6 +19,10 @@ pub struct FormData {
     subject: String,
     text_body: String,
     html_body: String,
+    // 所属邮件列表，为空时发布到默认列表
+    list_id: Option<String>,
+    // 目标分组，为空时发送给全部已确认的订阅者
+    segment_id: Option<String>,
     // 幂等键
     idempotency_key: String,
 }
@@ -28,13 +36,10 @@ pub async fn publish(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    client: ClientInfo,
 ) -> Result<impl Responder, actix_web::Error> {
     fn send_success_message() {
-        FlashMessage::info(
-            r#"简报已接收，邮件将很快发送给所有订阅用户，
-            可<a href="\#">点击此处</a>查看详情."#,
-        )
-        .send();
+        FlashMessage::info("简报已接收，邮件将很快发送给所有订阅用户.").send();
     }
 
     let user_id = user_id.into_inner();
@@ -42,16 +47,26 @@ pub async fn publish(
         subject,
         text_body,
         html_body,
+        list_id,
+        segment_id,
         idempotency_key,
     } = form.0;
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let list_id = match list_id.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(id) => Some(Uuid::parse_str(id).map_err(e400)?),
+    };
+    let segment_id = match segment_id.as_deref().map(str::trim) {
+        None | Some("") => None,
+        Some(id) => Some(Uuid::parse_str(id).map_err(e400)?),
+    };
 
     let mut transaction = match try_processing(&pool, &user_id, &idempotency_key)
         .await
         .map_err(e500)?
     {
         // 第一次请求，执行全部流程
-        NextAction::StartProcessing(t) => t,
+        NextAction::StartProcessing(t) => *t,
         // 第二次请求
         // 等待第一次请求执行完成，响应写入数据库
         // 获取响应并返回
@@ -61,14 +76,47 @@ pub async fn publish(
         }
     };
 
+    // 简报只发送给所属列表的订阅者
+    let list = match list_id {
+        Some(list_id) => get_list(transaction.as_mut(), list_id).await,
+        None => get_list_by_slug(transaction.as_mut(), DEFAULT_LIST_SLUG).await,
+    }
+    .map_err(e500)?
+    .ok_or_else(|| e400("the mailing list does not exist."))?;
+    // 锁定目标分组，在同一事务中按分组规则确定收件人
+    if let Some(segment_id) = segment_id {
+        if !lock_segment(&mut transaction, segment_id)
+            .await
+            .map_err(e500)?
+        {
+            return Err(e400("the target segment does not exist."));
+        }
+    }
     // 存储邮件简报
-    let issue_id = insert_newsletter_issue(&mut transaction, &subject, &text_body, &html_body)
-        .await
-        .map_err(e500)?;
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &subject,
+        &text_body,
+        &html_body,
+        list.list_id,
+        segment_id,
+    )
+    .await
+    .map_err(e500)?;
     // 新增简报发布队列
-    enqueue_delivery_task(&mut transaction, &issue_id)
+    enqueue_delivery_task(&mut transaction, &issue_id, list.list_id, segment_id)
         .await
         .map_err(e500)?;
+    // 记录审计日志，与发布操作在同一事务中
+    record_audit_log(
+        transaction.as_mut(),
+        &user_id,
+        AuditAction::PublishIssue,
+        Some(&issue_id.to_string()),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
     // 存储响应
     let res = see_other("/admin/dashboard");
     let res = save_response(&mut transaction, &user_id, &idempotency_key, res)
@@ -86,6 +134,8 @@ async fn insert_newsletter_issue(
     subject: &str,
     text_body: &str,
     html_body: &str,
+    list_id: Uuid,
+    segment_id: Option<Uuid>,
 ) -> sqlx::Result<Uuid> {
     let newsletter_issue_id = Uuid::new_v4();
     sqlx::query!(
@@ -95,15 +145,19 @@ async fn insert_newsletter_issue(
             subject,
             text_body,
             html_body,
+            list_id,
+            segment_id,
             published_at
         ) VALUES (
-            $1, $2, $3, $4, now()
+            $1, $2, $3, $4, $5, $6, now()
         )
         "#,
         newsletter_issue_id,
         subject,
         text_body,
         html_body,
+        list_id,
+        segment_id,
     )
     .execute(executor)
     .await?;
@@ -115,19 +169,30 @@ async fn insert_newsletter_issue(
 async fn enqueue_delivery_task(
     executor: &mut PgConnection,
     newsletter_issue_id: &Uuid,
+    list_id: Uuid,
+    segment_id: Option<Uuid>,
 ) -> sqlx::Result<()> {
+    // 只发送给列表中已确认且未暂停的订阅者，指定分组时再按分组规则筛选
     sqlx::query!(
         r#"
         INSERT INTO issue_delivery_queue (
             newsletter_issue_id,
-            subscriber_email
+            subscriber_id
         )
-        SELECT $1, email
-        FROM subscription
-        WHERE status = $2
+        SELECT $1, s.id
+        FROM subscription s
+        LEFT JOIN segment g ON g.segment_id = $3
+        WHERE
+            s.list_id = $4 AND
+            s.status = $2 AND
+            s.pii_key_id IS NOT NULL AND
+            (s.paused_until IS NULL OR s.paused_until <= now()) AND
+            ($3::uuid IS NULL OR subscriber_in_segment(s, g))
         "#,
         newsletter_issue_id,
-        SubscriberStatus::Confirmed.as_str()
+        SubscriberStatus::Confirmed.as_str(),
+        segment_id,
+        list_id,
     )
     .execute(executor)
     .await?;
@@ -1,15 +1,19 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::HttpResponse;
 use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
 
-use crate::util::format_flash_messages;
+use crate::util::{collect_flash_messages, render_html};
+
+#[derive(Template)]
+#[template(path = "admin/change_password_form.html")]
+struct ChangePasswordPage {
+    flash_messages: Vec<String>,
+}
 
 pub async fn change_password_form(
     flash_messages: IncomingFlashMessages,
 ) -> Result<HttpResponse, actix_web::Error> {
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            include_str!("change_password_form.html"),
-            format_flash_messages(flash_messages)
-        )))
+    render_html(&ChangePasswordPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+    })
 }
@@ -4,8 +4,12 @@ use secrecy::{ExposeSecret, SecretString};
 use sqlx::PgPool;
 
 use crate::{
-    authentication::{validate_credential, AuthError, Credential, UserId},
+    audit::{record_audit_log, AuditAction},
+    authentication::{validate_credential, AuthError, Credential, PasswordPolicy, UserId},
+    client_info::ClientInfo,
+    config::Config,
     routes::admin::logout::do_logout,
+    session_registry::SessionRegistry,
     session_state::TypedSession,
     util::{e500, get_username_by_user_id, see_other},
 };
@@ -17,12 +21,18 @@ pub struct FormData {
     new_password_check: SecretString,
 }
 
-#[tracing::instrument(name = "更改管理员密码", skip(form, pool, session))]
+#[tracing::instrument(
+    name = "更改管理员密码",
+    skip(form, pool, config, session, registry, client)
+)]
 pub async fn change_password(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
+    config: web::Data<Config>,
     session: TypedSession,
     user_id: web::ReqData<UserId>,
+    registry: web::Data<SessionRegistry>,
+    client: ClientInfo,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
 
@@ -32,17 +42,27 @@ pub async fn change_password(
         FlashMessage::error("两次输入的密码不一致.").send();
         return Ok(see_other("/admin/password"));
     }
-    // 2. 新密码不符合规则
-
-    // 3. 当前密码比对不一致
+    // 2. 新密码不符合规则，逐条提示
     let username = get_username_by_user_id(*user_id, &pool)
         .await
         .map_err(e500)?;
+    if let Err(violations) = PasswordPolicy::new(&config.password_policy).check(
+        &form.new_password,
+        &username,
+        Some(&form.current_password),
+    ) {
+        for violation in violations {
+            FlashMessage::error(violation.to_string()).send();
+        }
+        return Ok(see_other("/admin/password"));
+    }
+
+    // 3. 当前密码比对不一致
     let credential = Credential {
         username,
         password: form.0.current_password,
     };
-    if let Err(e) = validate_credential(credential, &pool).await {
+    if let Err(e) = validate_credential(credential, &pool, &config.password_hash).await {
         match e {
             AuthError::InvalidCredential(_) => {
                 FlashMessage::error("密码不正确.").send();
@@ -53,9 +73,27 @@ pub async fn change_password(
     };
 
     // 更新密码
-    crate::authentication::change_password(*user_id, form.0.new_password, &pool)
-        .await
-        .map_err(e500)?;
+    crate::authentication::change_password(
+        *user_id,
+        form.0.new_password,
+        &pool,
+        &config.password_hash,
+    )
+    .await
+    .map_err(e500)?;
+
+    record_audit_log(
+        pool.get_ref(),
+        &user_id,
+        AuditAction::ChangePassword,
+        None,
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+
+    // 吊销该管理员的所有会话(包括当前会话)
+    registry.revoke_all(&user_id).await.map_err(e500)?;
 
     // 登出
     FlashMessage::info("密码修改成功.").send();
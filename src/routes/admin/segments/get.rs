@@ -0,0 +1,31 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::{
+    domain::SubscriberStatus,
+    segments::{list_segments, SegmentSummary},
+    util::{collect_flash_messages, e500, render_html},
+};
+
+#[derive(Template)]
+#[template(path = "admin/segments.html")]
+struct SegmentsPage<'a> {
+    flash_messages: Vec<String>,
+    statuses: &'a [SubscriberStatus],
+    segments: Vec<SegmentSummary>,
+}
+
+pub async fn segments_page(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let segments = list_segments(&pool).await.map_err(e500)?;
+
+    render_html(&SegmentsPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+        statuses: &SubscriberStatus::ALL,
+        segments,
+    })
+}
@@ -0,0 +1,167 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{
+    types::chrono::{NaiveDate, NaiveTime},
+    PgPool,
+};
+use uuid::Uuid;
+
+use crate::{
+    attributes::{list_attributes, parse_attribute_conditions},
+    audit::{record_audit_log, AuditAction},
+    authentication::UserId,
+    client_info::ClientInfo,
+    domain::{SubscriberStatus, SubscriberTag},
+    segments::{self, SegmentRules},
+    util::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    // 逗号分隔的标签
+    required_tags: String,
+    excluded_tags: String,
+    // 空字符串表示不限制
+    status: String,
+    // 日期格式: YYYY-MM-DD，包含当天
+    from: String,
+    to: String,
+    // 逗号分隔的属性条件，格式: key=value
+    #[serde(default)]
+    attributes: String,
+}
+
+impl TryFrom<&FormData> for SegmentRules {
+    type Error = String;
+
+    fn try_from(form: &FormData) -> Result<Self, Self::Error> {
+        fn parse_date(s: &str) -> Result<Option<NaiveDate>, String> {
+            let s = s.trim();
+            if s.is_empty() {
+                return Ok(None);
+            }
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .map(Some)
+                .map_err(|_| format!("`{s}` is not a valid date."))
+        }
+
+        let status = Some(form.status.trim()).filter(|s| !s.is_empty());
+        if let Some(status) = status {
+            if !SubscriberStatus::ALL.iter().any(|s| s.as_str() == status) {
+                return Err(format!("`{status}` is not a valid status."));
+            }
+        }
+
+        Ok(Self {
+            required_tags: SubscriberTag::parse_list(&form.required_tags)?,
+            excluded_tags: SubscriberTag::parse_list(&form.excluded_tags)?,
+            status: status.map(str::to_owned),
+            subscribed_from: parse_date(&form.from)?.map(|d| d.and_time(NaiveTime::MIN).and_utc()),
+            subscribed_to: parse_date(&form.to)?
+                .and_then(|d| d.succ_opt())
+                .map(|d| d.and_time(NaiveTime::MIN).and_utc()),
+            // 需要属性定义，由调用方校验后填入
+            attributes: Default::default(),
+        })
+    }
+}
+
+/// 新建分组
+#[tracing::instrument(name = "新建订阅者分组", skip_all)]
+pub async fn create_segment(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.name.trim();
+    if name.is_empty() {
+        FlashMessage::error("请输入分组名称.").send();
+        return Ok(see_other("/admin/segments"));
+    }
+    let definitions = list_attributes(pool.get_ref()).await.map_err(e500)?;
+    let rules = SegmentRules::try_from(&form.0).and_then(|rules| {
+        Ok(SegmentRules {
+            attributes: parse_attribute_conditions(&form.attributes, &definitions)?,
+            ..rules
+        })
+    });
+    let rules = match rules {
+        Ok(rules) => rules,
+        Err(e) => {
+            FlashMessage::error(format!("分组规则无效: {e}")).send();
+            return Ok(see_other("/admin/segments"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")
+        .map_err(e500)?;
+    let Some(segment_id) = segments::create_segment(transaction.as_mut(), name, &rules)
+        .await
+        .map_err(e500)?
+    else {
+        FlashMessage::error("分组名称已存在.").send();
+        return Ok(see_other("/admin/segments"));
+    };
+    record_audit_log(
+        transaction.as_mut(),
+        &user_id.into_inner(),
+        AuditAction::CreateSegment,
+        Some(&segment_id.to_string()),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")
+        .map_err(e500)?;
+
+    FlashMessage::info("分组已创建.").send();
+    Ok(see_other("/admin/segments"))
+}
+
+/// 删除分组
+#[tracing::instrument(name = "删除订阅者分组", skip_all, fields(segment_id = %segment_id))]
+pub async fn delete_segment(
+    segment_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let segment_id = segment_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")
+        .map_err(e500)?;
+    if !segments::delete_segment(transaction.as_mut(), segment_id)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    record_audit_log(
+        transaction.as_mut(),
+        &user_id.into_inner(),
+        AuditAction::DeleteSegment,
+        Some(&segment_id.to_string()),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")
+        .map_err(e500)?;
+
+    FlashMessage::info("分组已删除.").send();
+    Ok(see_other("/admin/segments"))
+}
@@ -0,0 +1,56 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::types::chrono::DateTime;
+use uuid::Uuid;
+
+use crate::{
+    authentication::UserId,
+    session_registry::SessionRegistry,
+    session_state::TypedSession,
+    util::{collect_flash_messages, e500, render_html},
+};
+
+struct SessionRow {
+    session_id: Uuid,
+    created_at: String,
+    ip: String,
+    user_agent: String,
+    // 当前会话不提供吊销按钮，使用注销功能即可
+    is_current: bool,
+}
+
+#[derive(Template)]
+#[template(path = "admin/sessions.html")]
+struct SessionsPage {
+    flash_messages: Vec<String>,
+    sessions: Vec<SessionRow>,
+}
+
+pub async fn sessions_page(
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    registry: web::Data<SessionRegistry>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    let records = registry.list(&user_id.into_inner()).await.map_err(e500)?;
+
+    let sessions = records
+        .into_iter()
+        .map(|record| SessionRow {
+            session_id: record.session_id,
+            created_at: DateTime::from_timestamp(record.created_at, 0)
+                .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_default(),
+            ip: record.ip,
+            user_agent: record.user_agent,
+            is_current: Some(record.session_id) == current_session_id,
+        })
+        .collect();
+
+    render_html(&SessionsPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+        sessions,
+    })
+}
@@ -0,0 +1,74 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_log, AuditAction},
+    authentication::UserId,
+    client_info::ClientInfo,
+    routes::admin::logout::do_logout,
+    session_registry::SessionRegistry,
+    session_state::TypedSession,
+    util::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    session_id: Uuid,
+}
+
+#[tracing::instrument(
+    name = "吊销管理员会话",
+    skip(form, registry, pool, client),
+    fields(session_id = %form.session_id)
+)]
+pub async fn revoke_session(
+    form: web::Form<FormData>,
+    user_id: web::ReqData<UserId>,
+    registry: web::Data<SessionRegistry>,
+    pool: web::Data<PgPool>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    registry
+        .revoke(&user_id, &form.session_id)
+        .await
+        .map_err(e500)?;
+    record_audit_log(
+        pool.get_ref(),
+        &user_id,
+        AuditAction::RevokeSession,
+        Some(&form.session_id.to_string()),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info("会话已吊销.").send();
+    Ok(see_other("/admin/sessions"))
+}
+
+#[tracing::instrument(name = "注销所有会话", skip(session, registry, pool, client))]
+pub async fn revoke_all_sessions(
+    session: TypedSession,
+    user_id: web::ReqData<UserId>,
+    registry: web::Data<SessionRegistry>,
+    pool: web::Data<PgPool>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    registry.revoke_all(&user_id).await.map_err(e500)?;
+    record_audit_log(
+        pool.get_ref(),
+        &user_id,
+        AuditAction::RevokeAllSessions,
+        None,
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info("已在所有设备上注销.").send();
+    do_logout(session)
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::subscriber_data_page;
+pub use post::{download_subscriber_data, erase_subscriber};
@@ -0,0 +1,19 @@
+use actix_web::HttpResponse;
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+
+use crate::util::{collect_flash_messages, render_html};
+
+#[derive(Template)]
+#[template(path = "admin/subscriber_data.html")]
+struct SubscriberDataPage {
+    flash_messages: Vec<String>,
+}
+
+pub async fn subscriber_data_page(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    render_html(&SubscriberDataPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+    })
+}
@@ -0,0 +1,124 @@
+use actix_web::{
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web, HttpResponse,
+};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_log, AuditAction},
+    authentication::UserId,
+    client_info::ClientInfo,
+    pii::PiiCipher,
+    subscriber_data::{erase_subscriber_data, export_subscriber_data},
+    util::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct ExportFormData {
+    email: String,
+}
+
+/// 以JSON文件的形式下载邮箱地址相关的全部数据
+///
+/// 邮箱地址通过表单提交，不出现在URL中，避免被访问日志及浏览器历史记录
+#[tracing::instrument(name = "下载订阅者数据", skip_all)]
+pub async fn download_subscriber_data(
+    form: web::Form<ExportFormData>,
+    pool: web::Data<PgPool>,
+    pii_cipher: web::Data<PiiCipher>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = form.email.trim();
+    let bundle = export_subscriber_data(&pool, &pii_cipher, email)
+        .await
+        .map_err(e500)?;
+    // 审计日志中不记录邮箱地址，避免删除数据后仍残留个人信息
+    // 多个列表中的订阅以逗号分隔
+    let target_id = (!bundle.is_empty()).then(|| {
+        bundle
+            .subscriptions
+            .iter()
+            .map(|s| s.id.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    });
+    record_audit_log(
+        pool.get_ref(),
+        &user_id.into_inner(),
+        AuditAction::ExportSubscriberData,
+        target_id.as_deref(),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("subscriber_data.json".into())],
+        })
+        .json(bundle))
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+    // 再次输入邮箱地址，防止误删
+    email_check: String,
+}
+
+#[tracing::instrument(name = "删除订阅者", skip_all)]
+pub async fn erase_subscriber(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    pii_cipher: web::Data<PiiCipher>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = form.email.trim();
+    if email != form.email_check.trim() {
+        FlashMessage::error("两次输入的邮箱地址不一致.").send();
+        return Ok(see_other("/admin/subscriber_data"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")
+        .map_err(e500)?;
+    let subscriber_ids = erase_subscriber_data(transaction.as_mut(), &pii_cipher, email)
+        .await
+        .map_err(e500)?;
+    let target_id = (!subscriber_ids.is_empty()).then(|| {
+        subscriber_ids
+            .iter()
+            .map(Uuid::to_string)
+            .collect::<Vec<_>>()
+            .join(",")
+    });
+    record_audit_log(
+        transaction.as_mut(),
+        &user_id.into_inner(),
+        AuditAction::EraseSubscriberData,
+        target_id.as_deref(),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")
+        .map_err(e500)?;
+
+    if subscriber_ids.is_empty() {
+        FlashMessage::info("未找到该邮箱地址的订阅者.").send();
+    } else {
+        FlashMessage::info("订阅者数据已删除.").send();
+    }
+    Ok(see_other("/admin/subscriber_data"))
+}
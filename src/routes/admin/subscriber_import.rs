@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{download_import_report, subscriber_import_page};
+pub use post::import_subscribers;
@@ -0,0 +1,81 @@
+use actix_web::{
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web, HttpResponse,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_log, AuditAction},
+    authentication::UserId,
+    client_info::ClientInfo,
+    mailing_lists::{list_mailing_lists, MailingListSummary},
+    pii::PiiCipher,
+    subscriber_import::{get_import_report, list_imports, ImportSummary},
+    util::{collect_flash_messages, e500, render_html},
+};
+
+/// 显示的最近导入记录数
+const RECENT_IMPORTS: i64 = 20;
+
+#[derive(Template)]
+#[template(path = "admin/subscriber_import.html")]
+struct SubscriberImportPage {
+    flash_messages: Vec<String>,
+    lists: Vec<MailingListSummary>,
+    imports: Vec<ImportSummary>,
+}
+
+pub async fn subscriber_import_page(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let lists = list_mailing_lists(&pool).await.map_err(e500)?;
+    let imports = list_imports(&pool, RECENT_IMPORTS).await.map_err(e500)?;
+
+    render_html(&SubscriberImportPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+        lists,
+        imports,
+    })
+}
+
+/// 下载导入报告，列出所有被拒绝的行及原因
+#[tracing::instrument(name = "下载导入报告", skip_all, fields(import_id = %import_id))]
+pub async fn download_import_report(
+    import_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    pii_cipher: web::Data<PiiCipher>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let import_id = import_id.into_inner();
+    let Some(report) = get_import_report(&pool, &pii_cipher, import_id)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    // 报告包含被拒绝行的个人信息，下载需记录审计日志
+    record_audit_log(
+        pool.get_ref(),
+        &user_id.into_inner(),
+        AuditAction::DownloadImportReport,
+        Some(&import_id.to_string()),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "import_report_{import_id}.csv"
+            ))],
+        })
+        .body(report))
+}
@@ -0,0 +1,110 @@
+use actix_multipart::form::{tempfile::TempFile, text::Text, MultipartForm};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_log, AuditAction},
+    authentication::UserId,
+    client_info::ClientInfo,
+    mailing_lists::{get_list, get_list_by_slug, DEFAULT_LIST_SLUG},
+    pii::PiiCipher,
+    subscriber_import::{
+        self, finish_import, start_import, ImportError, ImportMode, ImportReport, ImportStatus,
+    },
+    util::{e400, e500, see_other},
+};
+
+#[derive(MultipartForm)]
+pub struct UploadForm {
+    #[multipart(limit = "20MiB")]
+    file: TempFile,
+    // 导入到的邮件列表，为空时导入默认列表
+    list_id: Option<Text<String>>,
+    // 勾选时管理员确认已取得同意，导入为已确认，否则导入为待确认并发送确认邮件
+    consent_attested: Option<Text<String>>,
+}
+
+/// 从上传的CSV文件中导入订阅者，导入结果及报告保存在导入记录中
+///
+/// 上传的文件先写入临时文件，再逐行读取
+/// 导入记录及审计日志在导入前保存，导入中途出错时记录为失败
+#[tracing::instrument(name = "上传导入订阅者", skip_all)]
+pub async fn import_subscribers(
+    MultipartForm(form): MultipartForm<UploadForm>,
+    pool: web::Data<PgPool>,
+    pii_cipher: web::Data<PiiCipher>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filename = form
+        .file
+        .file_name
+        .clone()
+        .unwrap_or_else(|| "upload.csv".into());
+    let list_id = match form.list_id.as_ref().map(|id| id.trim()) {
+        None | Some("") => None,
+        Some(id) => Some(Uuid::parse_str(id).map_err(e400)?),
+    };
+    let list = match list_id {
+        Some(list_id) => get_list(pool.get_ref(), list_id).await,
+        None => get_list_by_slug(pool.get_ref(), DEFAULT_LIST_SLUG).await,
+    }
+    .map_err(e500)?
+    .ok_or_else(|| e400("the mailing list does not exist."))?;
+    let mode = match form.consent_attested {
+        Some(_) => ImportMode::Confirmed,
+        None => ImportMode::Pending,
+    };
+
+    let user_id = user_id.into_inner();
+    let import_id = start_import(&pool, Some(*user_id), &filename, mode)
+        .await
+        .map_err(e500)?;
+    record_audit_log(
+        pool.get_ref(),
+        &user_id,
+        AuditAction::ImportSubscribers,
+        Some(&import_id.to_string()),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+
+    let mut report = ImportReport::default();
+    let outcome = subscriber_import::import_subscribers(
+        &pool,
+        &pii_cipher,
+        form.file.file.into_file(),
+        list.list_id,
+        mode,
+        &format!("import:{filename}"),
+        &client,
+        &mut report,
+    )
+    .await;
+    let status = match outcome {
+        Ok(()) => ImportStatus::Completed,
+        Err(_) => ImportStatus::Failed,
+    };
+    finish_import(&pool, &pii_cipher, import_id, status, &report)
+        .await
+        .map_err(e500)?;
+    match outcome {
+        Ok(()) => {}
+        Err(ImportError::InvalidFile(reason)) => {
+            FlashMessage::error(format!("无法读取CSV文件: {reason}")).send();
+            return Ok(see_other("/admin/subscribers/import"));
+        }
+        Err(e) => return Err(e500(e)),
+    }
+
+    FlashMessage::info(format!(
+        "已导入{}个订阅者，拒绝{}行.",
+        report.imported,
+        report.rejected.len()
+    ))
+    .send();
+    Ok(see_other("/admin/subscribers/import"))
+}
@@ -0,0 +1,77 @@
+mod attributes;
+mod export;
+mod get;
+mod post;
+mod tags;
+
+pub use attributes::update_subscriber_attributes;
+pub use export::export_subscribers;
+pub use get::{subscriber_page, subscribers_page};
+pub use post::manage_subscriber;
+pub use tags::{add_tags, remove_tag};
+
+use sqlx::types::chrono::{NaiveDate, NaiveTime};
+use uuid::Uuid;
+
+use crate::subscribers::{SubscriberFilter, MIN_QUERY_CHARS};
+
+/// 订阅者列表的查询参数，空字符串视为不限制
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    // 邮件列表id，为空时显示全部列表
+    list: Option<String>,
+    status: Option<String>,
+    // 日期格式: YYYY-MM-DD，包含当天
+    from: Option<String>,
+    to: Option<String>,
+    // 姓名或邮箱地址包含的子串
+    q: Option<String>,
+    // 从1开始
+    page: Option<i64>,
+}
+
+impl TryFrom<&QueryParams> for SubscriberFilter {
+    type Error = String;
+
+    fn try_from(params: &QueryParams) -> Result<Self, Self::Error> {
+        fn non_empty(s: &Option<String>) -> Option<String> {
+            s.as_deref()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_owned)
+        }
+        fn parse_date(s: &Option<String>) -> Result<Option<NaiveDate>, String> {
+            non_empty(s)
+                .map(|s| {
+                    NaiveDate::parse_from_str(&s, "%Y-%m-%d")
+                        .map_err(|_| format!("`{s}` is not a valid date."))
+                })
+                .transpose()
+        }
+
+        let from = parse_date(&params.from)?.map(|d| d.and_time(NaiveTime::MIN).and_utc());
+        let to = parse_date(&params.to)?
+            .and_then(|d| d.succ_opt())
+            .map(|d| d.and_time(NaiveTime::MIN).and_utc());
+        let list_id = non_empty(&params.list)
+            .map(|id| Uuid::parse_str(&id).map_err(|_| format!("`{id}` is not a valid list id.")))
+            .transpose()?;
+        let query = non_empty(&params.q);
+        if query
+            .as_deref()
+            .is_some_and(|q| q.chars().count() < MIN_QUERY_CHARS)
+        {
+            return Err(format!(
+                "search query must have at least {MIN_QUERY_CHARS} characters."
+            ));
+        }
+
+        Ok(Self {
+            list_id,
+            status: non_empty(&params.status),
+            from,
+            to,
+            query,
+        })
+    }
+}
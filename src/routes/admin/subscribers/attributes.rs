@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    attributes::{list_attributes, set_subscriber_attributes, validate_form_attributes},
+    audit::{record_audit_log, AuditAction},
+    authentication::UserId,
+    client_info::ClientInfo,
+    subscribers::lock_subscriber,
+    util::{e500, see_other},
+};
+
+/// 修改订阅者的属性值，表单字段名为`attr_{key}`，未提交或为空的属性被清除
+#[tracing::instrument(name = "修改订阅者属性", skip_all, fields(subscriber_id = %subscriber_id))]
+pub async fn update_subscriber_attributes(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<HashMap<String, String>>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let detail_page = format!("/admin/subscribers/{subscriber_id}");
+    let definitions = list_attributes(pool.get_ref()).await.map_err(e500)?;
+    let attributes = match validate_form_attributes(&definitions, &form) {
+        Ok(attributes) => attributes,
+        Err(e) => {
+            FlashMessage::error(format!("属性值无效: {e}")).send();
+            return Ok(see_other(&detail_page));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")
+        .map_err(e500)?;
+    if !lock_subscriber(transaction.as_mut(), subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    set_subscriber_attributes(transaction.as_mut(), subscriber_id, &attributes)
+        .await
+        .map_err(e500)?;
+    record_audit_log(
+        transaction.as_mut(),
+        &user_id.into_inner(),
+        AuditAction::UpdateSubscriberAttributes,
+        Some(&subscriber_id.to_string()),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")
+        .map_err(e500)?;
+
+    FlashMessage::info("属性已保存.").send();
+    Ok(see_other(&detail_page))
+}
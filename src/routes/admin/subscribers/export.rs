@@ -0,0 +1,123 @@
+use actix_web::{
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web, HttpResponse,
+};
+use sqlx::PgPool;
+
+use crate::{
+    audit::{record_audit_log, AuditAction},
+    authentication::UserId,
+    client_info::ClientInfo,
+    pii::PiiCipher,
+    subscribers::{self, ExportColumn, ExportFormat, SubscriberFilter},
+    util::{e400, e500},
+};
+
+use super::QueryParams;
+
+/// 导出参数，筛选条件与订阅者列表相同，见[`QueryParams`]
+#[derive(serde::Deserialize)]
+pub struct ExportParams {
+    // csv或ndjson，默认为csv
+    format: Option<String>,
+    // 逗号分隔的列名，默认导出全部列
+    columns: Option<String>,
+}
+
+impl ExportParams {
+    fn format(&self) -> Result<ExportFormat, String> {
+        match self.format.as_deref().map(str::trim) {
+            None | Some("") => Ok(ExportFormat::Csv),
+            Some(format) => ExportFormat::parse(format)
+                .ok_or_else(|| format!("unknown export format `{format}`.")),
+        }
+    }
+
+    fn columns(&self) -> Result<Vec<ExportColumn>, String> {
+        let Some(columns) = self.columns.as_deref().filter(|c| !c.trim().is_empty()) else {
+            return Ok(ExportColumn::ALL.to_vec());
+        };
+        let mut selected = Vec::new();
+        for column in columns.split(',').map(str::trim) {
+            let column =
+                ExportColumn::parse(column).ok_or_else(|| format!("unknown column `{column}`."))?;
+            if !selected.contains(&column) {
+                selected.push(column);
+            }
+        }
+
+        Ok(selected)
+    }
+}
+
+/// 以流的形式导出订阅者，导出的列及筛选条件记录在审计日志中
+#[tracing::instrument(name = "导出订阅者列表", skip_all)]
+pub async fn export_subscribers(
+    query: web::Query<QueryParams>,
+    params: web::Query<ExportParams>,
+    pool: web::Data<PgPool>,
+    pii_cipher: web::Data<PiiCipher>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter: SubscriberFilter = (&query.0).try_into().map_err(e400)?;
+    let format = params.format().map_err(e400)?;
+    let columns = params.columns().map_err(e400)?;
+
+    record_audit_log(
+        pool.get_ref(),
+        &user_id.into_inner(),
+        AuditAction::ExportSubscribers,
+        Some(&audit_target(&columns, &filter)),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+
+    let stream = subscribers::export_subscribers(
+        pool.get_ref().clone(),
+        pii_cipher.into_inner(),
+        filter,
+        columns,
+        format,
+    );
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "subscribers.{}",
+                format.extension()
+            ))],
+        })
+        .streaming(stream))
+}
+
+/// 审计日志中的导出描述，如`columns=id,email;status=confirmed;search`
+///
+/// 搜索词可能包含邮箱地址，只记录是否搜索
+fn audit_target(columns: &[ExportColumn], filter: &SubscriberFilter) -> String {
+    let columns = columns
+        .iter()
+        .map(ExportColumn::as_str)
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut target = vec![format!("columns={columns}")];
+    if let Some(list_id) = filter.list_id {
+        target.push(format!("list={list_id}"));
+    }
+    if let Some(status) = &filter.status {
+        target.push(format!("status={status}"));
+    }
+    if let Some(from) = filter.from {
+        target.push(format!("from={}", from.to_rfc3339()));
+    }
+    if let Some(to) = filter.to {
+        target.push(format!("to={}", to.to_rfc3339()));
+    }
+    if filter.query.is_some() {
+        target.push("search".into());
+    }
+
+    target.join(";")
+}
@@ -0,0 +1,102 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    attributes::{attribute_text, list_attributes, AttributeDefinition},
+    domain::SubscriberStatus,
+    mailing_lists::{list_mailing_lists, MailingListSummary},
+    pii::PiiCipher,
+    subscribers::{
+        get_subscriber, list_subscribers, SubscriberDetail, SubscriberFilter, SubscriberSummary,
+    },
+    util::{collect_flash_messages, e400, e500, render_html},
+};
+
+use super::QueryParams;
+
+/// 每页显示的订阅者数
+const PAGE_SIZE: i64 = 50;
+
+#[derive(Template)]
+#[template(path = "admin/subscribers.html")]
+struct SubscribersPage<'a> {
+    flash_messages: Vec<String>,
+    lists: Vec<MailingListSummary>,
+    statuses: &'a [SubscriberStatus],
+    query: &'a QueryParams,
+    filter: &'a SubscriberFilter,
+    subscribers: Vec<SubscriberSummary>,
+    total: i64,
+    page: i64,
+    last_page: i64,
+}
+
+pub async fn subscribers_page(
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+    pii_cipher: web::Data<PiiCipher>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filter: SubscriberFilter = (&query.0).try_into().map_err(e400)?;
+    let page = query.page.unwrap_or(1).max(1);
+    let lists = list_mailing_lists(&pool).await.map_err(e500)?;
+    let list = list_subscribers(
+        &pool,
+        &pii_cipher,
+        &filter,
+        PAGE_SIZE,
+        (page - 1) * PAGE_SIZE,
+    )
+    .await
+    .map_err(e500)?;
+
+    render_html(&SubscribersPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+        lists,
+        statuses: &SubscriberStatus::ALL,
+        query: &query,
+        filter: &filter,
+        subscribers: list.subscribers,
+        total: list.total,
+        page,
+        last_page: (list.total + PAGE_SIZE - 1) / PAGE_SIZE,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "admin/subscriber.html")]
+struct SubscriberPage {
+    flash_messages: Vec<String>,
+    detail: SubscriberDetail,
+    attributes: Vec<AttributeDefinition>,
+}
+
+impl SubscriberPage {
+    fn attribute_value(&self, key: &str) -> String {
+        attribute_text(&self.detail.attributes, key)
+    }
+}
+
+pub async fn subscriber_page(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    pii_cipher: web::Data<PiiCipher>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(detail) = get_subscriber(&pool, &pii_cipher, subscriber_id.into_inner())
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let attributes = list_attributes(pool.get_ref()).await.map_err(e500)?;
+
+    render_html(&SubscriberPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+        detail,
+        attributes,
+    })
+}
@@ -0,0 +1,123 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_log, AuditAction},
+    authentication::UserId,
+    client_info::ClientInfo,
+    pii::PiiCipher,
+    subscriber_data::erase_subscriber_by_id,
+    subscribers::{self, get_subscriber},
+    util::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    // 确认框，未勾选时不执行操作
+    confirm: Option<String>,
+    // 删除时需再次输入订阅者的邮箱地址，防止误删
+    email_check: Option<String>,
+}
+
+enum Action {
+    Confirm,
+    Unsubscribe,
+    Delete,
+}
+
+impl Action {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "confirm" => Some(Action::Confirm),
+            "unsubscribe" => Some(Action::Unsubscribe),
+            "delete" => Some(Action::Delete),
+            _ => None,
+        }
+    }
+}
+
+/// 校验确认步骤后执行操作，操作与审计日志在同一事务中完成
+///
+/// 路径: `/admin/subscribers/{id}/{confirm|unsubscribe|delete}`
+#[tracing::instrument(name = "管理订阅者", skip_all, fields(subscriber_id = %path.0))]
+pub async fn manage_subscriber(
+    path: web::Path<(Uuid, String)>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    pii_cipher: web::Data<PiiCipher>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (subscriber_id, action) = path.into_inner();
+    let Some(action) = Action::parse(&action) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let detail_page = format!("/admin/subscribers/{subscriber_id}");
+    if form.confirm.is_none() {
+        FlashMessage::error("请勾选确认框后再提交.").send();
+        return Ok(see_other(&detail_page));
+    }
+    if let Action::Delete = action {
+        let Some(detail) = get_subscriber(&pool, &pii_cipher, subscriber_id)
+            .await
+            .map_err(e500)?
+        else {
+            return Ok(HttpResponse::NotFound().finish());
+        };
+        let email_check = form.email_check.as_deref().unwrap_or_default().trim();
+        if !detail.subscriber.email.eq_ignore_ascii_case(email_check) {
+            FlashMessage::error("输入的邮箱地址与订阅者不一致.").send();
+            return Ok(see_other(&detail_page));
+        }
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")
+        .map_err(e500)?;
+    let (found, audit_action) = match action {
+        Action::Confirm => (
+            subscribers::confirm_subscriber(transaction.as_mut(), subscriber_id).await,
+            AuditAction::ConfirmSubscriber,
+        ),
+        Action::Unsubscribe => (
+            subscribers::unsubscribe_subscriber(transaction.as_mut(), subscriber_id).await,
+            AuditAction::UnsubscribeSubscriber,
+        ),
+        Action::Delete => (
+            erase_subscriber_by_id(transaction.as_mut(), subscriber_id).await,
+            AuditAction::DeleteSubscriber,
+        ),
+    };
+    if !found.map_err(e500)? {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    record_audit_log(
+        transaction.as_mut(),
+        &user_id.into_inner(),
+        audit_action,
+        Some(&subscriber_id.to_string()),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")
+        .map_err(e500)?;
+
+    match action {
+        Action::Confirm => FlashMessage::info("已确认订阅.").send(),
+        Action::Unsubscribe => FlashMessage::info("已退订.").send(),
+        Action::Delete => {
+            FlashMessage::info("订阅者已删除.").send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    }
+    Ok(see_other(&detail_page))
+}
@@ -0,0 +1,121 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_log, AuditAction},
+    authentication::UserId,
+    client_info::ClientInfo,
+    domain::SubscriberTag,
+    subscribers::{add_subscriber_tags, lock_subscriber, remove_subscriber_tag},
+    util::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct AddTagsFormData {
+    // 逗号分隔的标签
+    tags: String,
+}
+
+/// 为订阅者添加标签
+#[tracing::instrument(name = "添加标签", skip_all, fields(subscriber_id = %subscriber_id))]
+pub async fn add_tags(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<AddTagsFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let detail_page = format!("/admin/subscribers/{subscriber_id}");
+    let tags = match SubscriberTag::parse_list(&form.tags) {
+        Ok(tags) if !tags.is_empty() => tags,
+        Ok(_) => {
+            FlashMessage::error("请输入至少一个标签.").send();
+            return Ok(see_other(&detail_page));
+        }
+        Err(e) => {
+            FlashMessage::error(format!("标签无效: {e}")).send();
+            return Ok(see_other(&detail_page));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")
+        .map_err(e500)?;
+    if !lock_subscriber(transaction.as_mut(), subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    add_subscriber_tags(transaction.as_mut(), subscriber_id, &tags)
+        .await
+        .map_err(e500)?;
+    record_audit_log(
+        transaction.as_mut(),
+        &user_id.into_inner(),
+        AuditAction::UpdateSubscriberTags,
+        Some(&subscriber_id.to_string()),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")
+        .map_err(e500)?;
+
+    FlashMessage::info("标签已添加.").send();
+    Ok(see_other(&detail_page))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveTagFormData {
+    tag: String,
+}
+
+/// 移除订阅者的标签
+#[tracing::instrument(name = "移除标签", skip_all, fields(subscriber_id = %subscriber_id))]
+pub async fn remove_tag(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<RemoveTagFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")
+        .map_err(e500)?;
+    if !remove_subscriber_tag(transaction.as_mut(), subscriber_id, &form.tag)
+        .await
+        .map_err(e500)?
+    {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    record_audit_log(
+        transaction.as_mut(),
+        &user_id.into_inner(),
+        AuditAction::UpdateSubscriberTags,
+        Some(&subscriber_id.to_string()),
+        &client,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")
+        .map_err(e500)?;
+
+    FlashMessage::info("标签已移除.").send();
+    Ok(see_other(&format!("/admin/subscribers/{subscriber_id}")))
+}
@@ -1,5 +1,9 @@
 mod get;
+mod magic_link;
+mod oidc;
 mod post;
 
 pub use get::login_form;
+pub use magic_link::{magic_link_login, magic_link_page, request_magic_link};
+pub use oidc::{oidc_callback, oidc_login};
 pub use post::login;
@@ -1,13 +1,90 @@
-use actix_web::{http::header::ContentType, HttpResponse, Responder};
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
 
-use crate::util::format_flash_messages;
+use crate::{
+    config::Config,
+    util::{collect_flash_messages, render_html},
+};
 
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> impl Responder {
-    HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            include_str!("login.html"),
-            format_flash_messages(flash_messages)
-        ))
+#[derive(Template)]
+#[template(path = "login.html")]
+struct LoginPage {
+    flash_messages: Vec<String>,
+    oidc_enabled: bool,
+    password_login_enabled: bool,
+}
+
+pub async fn login_form(
+    flash_messages: IncomingFlashMessages,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let oidc = config.oidc.as_ref();
+    render_html(&LoginPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+        oidc_enabled: oidc.is_some(),
+        password_login_enabled: !oidc.is_some_and(|oidc| oidc.disable_password_login),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use askama::Template;
+
+    use super::LoginPage;
+
+    #[test]
+    fn flash_messages_are_escaped() {
+        let page = LoginPage {
+            flash_messages: vec![r#"<script>alert("xss")</script>"#.into()],
+            oidc_enabled: false,
+            password_login_enabled: true,
+        }
+        .render()
+        .unwrap();
+
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("<p><i>&lt;script&gt;alert(&quot;xss&quot;)&lt;/script&gt;</i></p>"));
+    }
+
+    #[test]
+    fn public_pages_have_no_admin_navigation() {
+        let page = LoginPage {
+            flash_messages: vec![],
+            oidc_enabled: false,
+            password_login_enabled: true,
+        }
+        .render()
+        .unwrap();
+
+        assert!(page.contains("<title>Login</title>"));
+        assert!(!page.contains("/admin/dashboard"));
+    }
+
+    #[test]
+    fn sso_link_and_password_form_follow_config() {
+        let render = |oidc_enabled, password_login_enabled| {
+            LoginPage {
+                flash_messages: vec![],
+                oidc_enabled,
+                password_login_enabled,
+            }
+            .render()
+            .unwrap()
+        };
+
+        let page = render(false, true);
+        assert!(page.contains(r#"name="password""#));
+        assert!(page.contains(r#"action="/login/magic_link""#));
+        assert!(!page.contains("/login/oidc"));
+
+        let page = render(true, true);
+        assert!(page.contains(r#"name="password""#));
+        assert!(page.contains(r#"href="/login/oidc""#));
+
+        let page = render(true, false);
+        assert!(!page.contains(r#"name="password""#));
+        assert!(!page.contains(r#"action="/login/magic_link""#));
+        assert!(page.contains(r#"href="/login/oidc""#));
+    }
 }
@@ -0,0 +1,165 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+use tracing::Instrument;
+
+use super::post::{password_login_disabled, start_admin_session, LoginError};
+use crate::{
+    authentication::{
+        consume_login_token, find_user_id_by_email, store_login_token, AuthError, MagicLink,
+    },
+    client_info::ClientInfo,
+    config::Config,
+    domain::SubscriberEmail,
+    email_client::EmailCient,
+    session_registry::SessionRegistry,
+    session_state::TypedSession,
+    util::{render_html, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct RequestFormData {
+    email: String,
+}
+
+/// 请求免密登录链接
+///
+/// 无论邮箱地址是否属于管理员都返回相同的提示，避免枚举管理员邮箱
+/// 查找管理员及发送邮件在后台任务中进行，响应时间与邮箱地址无关
+#[tracing::instrument(skip_all)]
+pub async fn request_magic_link(
+    form: web::Form<RequestFormData>,
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    email_client: web::Data<EmailCient>,
+    session: TypedSession,
+) -> Result<HttpResponse, LoginError> {
+    if password_login_disabled(&config) {
+        return Err(LoginError::PasswordLoginDisabled);
+    }
+
+    let link = MagicLink::generate();
+    session
+        .insert_magic_link_binding(&link.browser_binding)
+        .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+    let email = form.0.email.trim().to_owned();
+    tokio::spawn(
+        async move {
+            if let Err(e) = send_magic_link(&pool, &email_client, &config, &email, &link).await {
+                tracing::error!(error.cause_chain = ?e, error.message = %e, "failed to send magic link.");
+            }
+        }
+        .in_current_span(),
+    );
+
+    FlashMessage::info(
+        "If the address belongs to an admin, a login link has been sent to it. \
+        Open it in this browser.",
+    )
+    .send();
+    Ok(see_other("/login"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct MagicLinkParams {
+    token: String,
+}
+
+#[derive(Template)]
+#[template(path = "magic_link_login.html")]
+struct MagicLinkPage<'a> {
+    flash_messages: Vec<String>,
+    token: &'a str,
+}
+
+/// 邮件中的免密登录链接，只显示确认页面，避免邮件客户端预取链接时消耗令牌
+pub async fn magic_link_page(
+    params: web::Query<MagicLinkParams>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if password_login_disabled(&config) {
+        return Ok(see_other("/login"));
+    }
+
+    render_html(&MagicLinkPage {
+        flash_messages: Vec::new(),
+        token: &params.token,
+    })
+}
+
+/// 使用免密登录链接登录
+#[tracing::instrument(skip_all, fields(user_id=tracing::field::Empty))]
+pub async fn magic_link_login(
+    client: ClientInfo,
+    form: web::Form<MagicLinkParams>,
+    pool: web::Data<PgPool>,
+    config: web::Data<Config>,
+    session: TypedSession,
+    registry: web::Data<SessionRegistry>,
+) -> Result<HttpResponse, LoginError> {
+    if password_login_disabled(&config) {
+        return Err(LoginError::PasswordLoginDisabled);
+    }
+    let browser_binding = session
+        .get_magic_link_binding()
+        .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+    let user_id = consume_login_token(&pool, &form.token, browser_binding.as_deref())
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredential(_) => LoginError::AuthError(e.into()),
+            AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
+        })?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    session.remove_magic_link_binding();
+
+    start_admin_session(user_id, &client, &pool, &session, &registry).await
+}
+
+/// 邮箱地址属于管理员时保存登录令牌并发送邮件
+#[tracing::instrument(skip_all, fields(user_id=tracing::field::Empty))]
+async fn send_magic_link(
+    pool: &PgPool,
+    email_client: &EmailCient,
+    config: &Config,
+    email: &str,
+    link: &MagicLink,
+) -> anyhow::Result<()> {
+    let Some(user_id) = find_user_id_by_email(pool, email).await? else {
+        return Ok(());
+    };
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+    let recipient = SubscriberEmail::parse(email).map_err(|e| anyhow::anyhow!(e))?;
+    store_login_token(pool, user_id, link, config.web.magic_link_ttl_seconds).await?;
+    send_magic_link_email(&recipient, email_client, config, &link.token)
+        .await
+        .context("failed to send magic link email.")
+}
+
+/// 发送免密登录邮件
+async fn send_magic_link_email(
+    recipient: &SubscriberEmail,
+    email_client: &EmailCient,
+    config: &Config,
+    token: &str,
+) -> reqwest::Result<()> {
+    let login_link = format!("{}/login/magic_link?token={}", config.web.base_url, token);
+    let expires_in_minutes = config.web.magic_link_ttl_seconds / 60;
+    let subject = "Your login link";
+    let text_body = format!(
+        "Visit {} to log in. The link can be used once and expires in {} minutes.\n\
+        If you did not request it, you can ignore this email.",
+        &login_link, expires_in_minutes
+    );
+    let html_body = format!(
+        "Click <a href=\"{}\">here</a> to log in. \
+        The link can be used once and expires in {} minutes.<br />\
+        If you did not request it, you can ignore this email.",
+        &login_link, expires_in_minutes
+    );
+
+    email_client
+        .send(recipient, subject, &text_body, &html_body)
+        .await
+}
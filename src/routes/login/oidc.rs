@@ -0,0 +1,82 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+use super::post::{start_admin_session, LoginError};
+use crate::{
+    authentication::{find_or_create_oidc_user, OidcClient, OidcError},
+    client_info::ClientInfo,
+    session_registry::SessionRegistry,
+    session_state::TypedSession,
+    util::{e500, see_other},
+};
+
+/// 跳转到身份提供方登录，未配置单点登录时返回404
+pub async fn oidc_login(
+    oidc: Option<web::Data<OidcClient>>,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(oidc) = oidc else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let (url, pending) = oidc.authorization_request().await.map_err(e500)?;
+    session.insert_oidc_login(&pending).map_err(e500)?;
+
+    Ok(see_other(&url))
+}
+
+#[derive(serde::Deserialize)]
+pub struct CallbackParams {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// 身份提供方登录完成后的回调
+#[tracing::instrument(skip_all, fields(user_id=tracing::field::Empty))]
+pub async fn oidc_callback(
+    client: ClientInfo,
+    params: web::Query<CallbackParams>,
+    oidc: Option<web::Data<OidcClient>>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    registry: web::Data<SessionRegistry>,
+) -> Result<HttpResponse, LoginError> {
+    let Some(oidc) = oidc else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let rejected = |reason: &str| LoginError::AuthError(anyhow::anyhow!("{reason}"));
+
+    // 授权请求只能使用一次，无论成功与否都从会话中移除
+    let pending = session
+        .remove_oidc_login()
+        .ok_or_else(|| rejected("no oidc login in progress."))?
+        .map_err(|e| LoginError::UnexpectedError(anyhow::anyhow!(e)))?;
+    if let Some(error) = &params.error {
+        return Err(rejected(&format!("identity provider returned `{error}`.")));
+    }
+    if params.state.as_deref() != Some(pending.state.as_str()) {
+        return Err(rejected("oidc state does not match."));
+    }
+    let code = params
+        .code
+        .as_deref()
+        .ok_or_else(|| rejected("oidc callback is missing the code."))?;
+
+    let identity = oidc
+        .exchange_code(code, &pending)
+        .await
+        .map_err(into_login_error)?;
+    let user_id = find_or_create_oidc_user(&pool, &identity, oidc.auto_create_users())
+        .await
+        .map_err(into_login_error)?;
+    tracing::Span::current().record("user_id", tracing::field::display(&user_id));
+
+    start_admin_session(user_id, &client, &pool, &session, &registry).await
+}
+
+fn into_login_error(e: OidcError) -> LoginError {
+    match e {
+        OidcError::Rejected(_) => LoginError::AuthError(e.into()),
+        OidcError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
+    }
+}
@@ -1,12 +1,18 @@
 use std::fmt::Debug;
 
-use actix_web::{body::BoxBody, web, HttpResponse, Responder, ResponseError};
+use actix_web::{body::BoxBody, web, HttpResponse, ResponseError};
 use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
 use secrecy::SecretString;
-use sqlx::PgPool;
+use sqlx::{types::chrono::Utc, PgPool};
+use uuid::Uuid;
 
 use crate::{
+    audit::{record_audit_log, AuditAction},
     authentication::{validate_credential, AuthError, Credential},
+    client_info::ClientInfo,
+    config::Config,
+    session_registry::{SessionRecord, SessionRegistry},
     session_state::TypedSession,
     util::error_chain_fmt,
     util::see_other,
@@ -23,17 +29,24 @@ pub struct FormData {
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn login(
+    client: ClientInfo,
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
+    config: web::Data<Config>,
     session: TypedSession,
-) -> Result<impl Responder, LoginError> {
+    registry: web::Data<SessionRegistry>,
+) -> Result<HttpResponse, LoginError> {
+    if password_login_disabled(&config) {
+        return Err(LoginError::PasswordLoginDisabled);
+    }
+
     let credential = Credential {
         username: form.0.username,
         password: form.0.password,
     };
     tracing::Span::current().record("username", tracing::field::display(&credential.username));
 
-    let user_id = validate_credential(credential, &pool)
+    let user_id = validate_credential(credential, &pool, &config.password_hash)
         .await
         .map_err(|e| match e {
             AuthError::InvalidCredential(_) => LoginError::AuthError(e.into()),
@@ -41,10 +54,54 @@ pub async fn login(
         })?;
     tracing::Span::current().record("user_id", tracing::field::display(&user_id));
 
+    start_admin_session(user_id, &client, &pool, &session, &registry).await
+}
+
+/// 只允许单点登录时，密码登录及免密登录链接均被禁用
+pub(super) fn password_login_disabled(config: &Config) -> bool {
+    config
+        .oidc
+        .as_ref()
+        .is_some_and(|oidc| oidc.disable_password_login)
+}
+
+/// 登录成功后建立管理员会话并记录审计日志，密码登录及单点登录共用
+pub(super) async fn start_admin_session(
+    user_id: Uuid,
+    client: &ClientInfo,
+    pool: &PgPool,
+    session: &TypedSession,
+    registry: &SessionRegistry,
+) -> Result<HttpResponse, LoginError> {
     session.renew();
     session
         .insert_user_id(user_id)
         .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+    // 记录登录时间及活动时间，用于会话超时校验
+    let now = Utc::now().timestamp();
+    session
+        .insert_login_at(now)
+        .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+    session
+        .insert_last_activity_at(now)
+        .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+    // 登记会话，用于会话列表展示及远程吊销
+    let record = SessionRecord::new(client);
+    session
+        .insert_session_id(record.session_id)
+        .map_err(|e| LoginError::UnexpectedError(e.into()))?;
+    registry.register(&user_id, &record).await?;
+
+    let session_id = record.session_id.to_string();
+    record_audit_log(
+        pool,
+        &user_id,
+        AuditAction::Login,
+        Some(&session_id),
+        client,
+    )
+    .await
+    .context("failed to record audit log.")?;
 
     Ok(see_other("/admin/dashboard"))
 }
@@ -53,6 +110,8 @@ pub async fn login(
 pub enum LoginError {
     #[error("Authentication failed.")]
     AuthError(#[source] anyhow::Error),
+    #[error("Password login is disabled, please use single sign-on.")]
+    PasswordLoginDisabled,
     #[error("Something went wrong.")]
     UnexpectedError(#[from] anyhow::Error),
 }
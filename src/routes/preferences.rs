@@ -0,0 +1,368 @@
+use actix_web::{
+    error::{ErrorConflict, ErrorNotFound, ErrorUnauthorized},
+    web, HttpResponse,
+};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    client_info::ClientInfo,
+    config::Config,
+    consent::CONSENT_TEXT,
+    domain::{EmailFormat, SubscriberEmail, SubscriberName},
+    email_change::{
+        confirm_email_change, request_email_change, revert_email_change,
+        send_email_change_confirmation, send_email_changed_notice, EmailChangeError,
+    },
+    email_client::EmailCient,
+    keyring::Keyring,
+    pii::PiiCipher,
+    preferences::{
+        get_preferences, issue_preferences_token, join_list, list_memberships,
+        preferences_token_subscriber, update_preferences, verify_preferences_token, ListMembership,
+        PreferencesUpdate, SubscriberPreferences,
+    },
+    subscribers::unsubscribe_subscriber,
+    util::{collect_flash_messages, e400, e500, render_html, see_other},
+};
+
+/// 可选的暂停天数
+const PAUSE_DAYS: [i32; 3] = [7, 30, 90];
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesQuery {
+    token: String,
+}
+
+#[derive(Template)]
+#[template(path = "preferences.html")]
+struct PreferencesPage<'a> {
+    flash_messages: Vec<String>,
+    token: &'a str,
+    preferences: SubscriberPreferences,
+    formats: &'a [EmailFormat],
+    pause_days: &'a [i32],
+    lists: Vec<ListMembership>,
+    consent_text: &'static str,
+}
+
+/// 偏好设置页面，通过邮件中的签名链接访问，无需登录
+pub async fn preferences_page(
+    query: web::Query<PreferencesQuery>,
+    pool: web::Data<PgPool>,
+    pii_cipher: web::Data<PiiCipher>,
+    keyring: web::Data<Keyring>,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let preferences = load_preferences(&query.token, &pool, &pii_cipher, &keyring).await?;
+    let lists = list_memberships(&pool, &pii_cipher, &preferences.email)
+        .await
+        .map_err(e500)?;
+
+    render_html(&PreferencesPage {
+        flash_messages: collect_flash_messages(&flash_messages),
+        token: &query.token,
+        preferences,
+        formats: &EmailFormat::ALL,
+        pause_days: &PAUSE_DAYS,
+        lists,
+        consent_text: CONSENT_TEXT,
+    })
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreferencesFormData {
+    token: String,
+    name: String,
+    email_format: String,
+    // 空字符串保持不变，`0`恢复接收，其余为暂停的天数
+    pause: String,
+}
+
+/// 保存姓名、邮件格式及暂停设置
+#[tracing::instrument(name = "订阅者修改偏好设置", skip_all)]
+pub async fn update_subscriber_preferences(
+    form: web::Form<PreferencesFormData>,
+    pool: web::Data<PgPool>,
+    pii_cipher: web::Data<PiiCipher>,
+    keyring: web::Data<Keyring>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let preferences = load_preferences(&form.token, &pool, &pii_cipher, &keyring).await?;
+    let location = format!("/preferences?token={}", form.token);
+    let name = match SubscriberName::parse(form.name.trim()) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(format!("姓名无效: {e}")).send();
+            return Ok(see_other(&location));
+        }
+    };
+    let email_format = EmailFormat::parse(&form.email_format).map_err(e400)?;
+    let pause_days = match form.pause.trim() {
+        "" => None,
+        "0" => Some(0),
+        days => Some(
+            days.parse()
+                .ok()
+                .filter(|days| PAUSE_DAYS.contains(days))
+                .ok_or_else(|| e400(format!("`{days}` is not a valid pause.")))?,
+        ),
+    };
+
+    update_preferences(
+        &pool,
+        &pii_cipher,
+        &preferences,
+        &PreferencesUpdate {
+            name,
+            email_format,
+            pause_days,
+        },
+    )
+    .await
+    .map_err(e500)?;
+
+    FlashMessage::info("偏好设置已保存.").send();
+    Ok(see_other(&location))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ListFormData {
+    token: String,
+    list_id: Uuid,
+    // `join`或`leave`
+    action: String,
+}
+
+/// 订阅或退订同一邮箱地址的某个邮件列表
+#[tracing::instrument(name = "订阅者修改订阅的邮件列表", skip_all, fields(list_id = %form.list_id))]
+pub async fn update_subscriber_lists(
+    form: web::Form<ListFormData>,
+    pool: web::Data<PgPool>,
+    pii_cipher: web::Data<PiiCipher>,
+    keyring: web::Data<Keyring>,
+    client: ClientInfo,
+) -> Result<HttpResponse, actix_web::Error> {
+    let preferences = load_preferences(&form.token, &pool, &pii_cipher, &keyring).await?;
+    let lists = list_memberships(&pool, &pii_cipher, &preferences.email)
+        .await
+        .map_err(e500)?;
+    let Some(list) = lists.iter().find(|l| l.list_id == form.list_id) else {
+        return Err(e400("the mailing list does not exist."));
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")
+        .map_err(e500)?;
+    let message = match (form.action.as_str(), list.subscriber_id) {
+        ("join", _) => {
+            join_list(
+                transaction.as_mut(),
+                &pii_cipher,
+                &preferences,
+                list.list_id,
+                &client,
+            )
+            .await
+            .map_err(e500)?;
+            format!("已订阅{}.", list.name)
+        }
+        ("leave", Some(subscriber_id)) => {
+            unsubscribe_subscriber(transaction.as_mut(), subscriber_id)
+                .await
+                .map_err(e500)?;
+            format!("已退订{}.", list.name)
+        }
+        ("leave", None) => format!("未订阅{}.", list.name),
+        (action, _) => return Err(e400(format!("`{action}` is not a valid action."))),
+    };
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")
+        .map_err(e500)?;
+
+    FlashMessage::info(message).send();
+    Ok(see_other(&format!("/preferences?token={}", form.token)))
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailChangeFormData {
+    token: String,
+    email: String,
+}
+
+/// 修改邮箱地址，确认链接发送到新地址，确认前仍使用旧地址
+#[tracing::instrument(name = "订阅者请求修改邮箱地址", skip_all)]
+pub async fn request_subscriber_email_change(
+    form: web::Form<EmailChangeFormData>,
+    pool: web::Data<PgPool>,
+    pii_cipher: web::Data<PiiCipher>,
+    keyring: web::Data<Keyring>,
+    email_client: web::Data<EmailCient>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let preferences = load_preferences(&form.token, &pool, &pii_cipher, &keyring).await?;
+    let location = format!("/preferences?token={}", form.token);
+    let new_email = match SubscriberEmail::parse(form.email.trim()) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(format!("邮箱地址无效: {e}")).send();
+            return Ok(see_other(&location));
+        }
+    };
+
+    let token = match request_email_change(
+        &pool,
+        &pii_cipher,
+        preferences.subscriber_id,
+        &preferences.email,
+        &new_email,
+    )
+    .await
+    {
+        Ok(token) => token,
+        Err(EmailChangeError::SameAddress) => {
+            FlashMessage::error("新邮箱地址与当前地址相同.").send();
+            return Ok(see_other(&location));
+        }
+        Err(EmailChangeError::AlreadySubscribed) => {
+            FlashMessage::error("新邮箱地址已订阅相同的邮件列表.").send();
+            return Ok(see_other(&location));
+        }
+        Err(e) => return Err(e500(e)),
+    };
+    send_email_change_confirmation(&email_client, &new_email, &config.web.base_url, &token)
+        .await
+        .context("failed to send email change confirmation.")
+        .map_err(e500)?;
+
+    FlashMessage::info("确认邮件已发送至新邮箱地址.").send();
+    Ok(see_other(&location))
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailChangeQuery {
+    token: String,
+}
+
+/// 新地址中的确认链接，替换邮箱地址后通知旧地址，通知中附带撤销链接
+#[tracing::instrument(name = "订阅者确认修改邮箱地址", skip_all)]
+pub async fn confirm_subscriber_email_change(
+    query: web::Query<EmailChangeQuery>,
+    pool: web::Data<PgPool>,
+    pii_cipher: web::Data<PiiCipher>,
+    keyring: web::Data<Keyring>,
+    email_client: web::Data<EmailCient>,
+    config: web::Data<Config>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let change = match confirm_email_change(&pool, &pii_cipher, &query.token).await {
+        Ok(change) => change,
+        Err(e @ EmailChangeError::InvalidToken) => return Err(ErrorUnauthorized(e.to_string())),
+        Err(e @ EmailChangeError::AlreadySubscribed) => return Err(ErrorConflict(e.to_string())),
+        Err(e) => return Err(e500(e)),
+    };
+    // 邮箱地址已修改，通知失败不影响结果
+    match SubscriberEmail::parse(&change.old_email) {
+        Ok(old_email) => {
+            if let Err(e) = send_email_changed_notice(
+                &email_client,
+                &old_email,
+                &config.web.base_url,
+                &change.revert_token,
+            )
+            .await
+            {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "failed to notify the old email address."
+                );
+            }
+        }
+        // 错误信息包含邮箱地址，不写入日志
+        Err(_) => tracing::warn!("the old email address is invalid."),
+    }
+
+    FlashMessage::info(format!("邮箱地址已修改为{}.", change.new_email)).send();
+    Ok(see_other(&format!(
+        "/preferences?token={}",
+        issue_preferences_token(
+            &keyring,
+            change.subscriber_id,
+            Some(&change.preferences_nonce)
+        )
+    )))
+}
+
+#[derive(Template)]
+#[template(path = "email_revert.html")]
+struct EmailRevertPage<'a> {
+    flash_messages: Vec<String>,
+    token: &'a str,
+}
+
+/// 旧地址中的撤销链接，只显示确认页面，避免邮件客户端预取链接时误撤销
+pub async fn email_revert_page(
+    query: web::Query<EmailChangeQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    render_html(&EmailRevertPage {
+        flash_messages: Vec::new(),
+        token: &query.token,
+    })
+}
+
+/// 恢复为旧地址，修改后的地址收到的偏好设置链接全部失效
+#[tracing::instrument(name = "订阅者撤销修改邮箱地址", skip_all)]
+pub async fn revert_subscriber_email_change(
+    form: web::Form<EmailChangeQuery>,
+    pool: web::Data<PgPool>,
+    pii_cipher: web::Data<PiiCipher>,
+    keyring: web::Data<Keyring>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let revert = match revert_email_change(&pool, &pii_cipher, &form.token).await {
+        Ok(revert) => revert,
+        Err(e @ EmailChangeError::InvalidToken) => return Err(ErrorUnauthorized(e.to_string())),
+        Err(e @ EmailChangeError::PreviousAddressTaken) => {
+            return Err(ErrorConflict(e.to_string()))
+        }
+        Err(e) => return Err(e500(e)),
+    };
+
+    FlashMessage::info(format!("邮箱地址已恢复为{}.", revert.email)).send();
+    Ok(see_other(&format!(
+        "/preferences?token={}",
+        issue_preferences_token(
+            &keyring,
+            revert.subscriber_id,
+            Some(&revert.preferences_nonce)
+        )
+    )))
+}
+
+/// 校验令牌并查询订阅者的偏好设置
+///
+/// 令牌无法解析时返回401，订阅者已被删除时返回404，
+/// 签名无效或随机值已更换(邮箱地址修改过)时返回401
+async fn load_preferences(
+    token: &str,
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    keyring: &Keyring,
+) -> Result<SubscriberPreferences, actix_web::Error> {
+    let subscriber_id = preferences_token_subscriber(token)
+        .ok_or_else(|| ErrorUnauthorized("invalid preferences link."))?;
+    let preferences = get_preferences(pool, pii_cipher, subscriber_id)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| ErrorNotFound("subscriber not found."))?;
+    verify_preferences_token(keyring, token, preferences.preferences_nonce.as_deref())
+        .filter(|id| *id == subscriber_id)
+        .ok_or_else(|| ErrorUnauthorized("invalid preferences link."))?;
+
+    Ok(preferences)
+}
@@ -1,43 +1,180 @@
-use std::fmt::Debug;
+use std::{collections::HashMap, fmt::Debug};
 
-use actix_web::{http::StatusCode, web, HttpResponse, Responder, ResponseError};
+use actix_web::{
+    http::{
+        header::{ORIGIN, REFERER},
+        StatusCode,
+    },
+    web, HttpRequest, HttpResponse, Responder, ResponseError,
+};
+use actix_web_flash_messages::IncomingFlashMessages;
 use anyhow::Context;
+use askama::{Html, MarkupDisplay, Template};
 use rand::distributions::{Alphanumeric, DistString};
 use sqlx::{types::chrono::Utc, PgConnection, PgPool};
 use uuid::Uuid;
 
-use crate::{config::Config, domain::Subscriber, email_client::EmailCient, util::error_chain_fmt};
+use crate::{
+    attributes::{list_attributes, validate_form_attributes, AttributeDefinition, Attributes},
+    client_info::ClientInfo,
+    config::Config,
+    consent::{record_consent, CONSENT_TEXT},
+    domain::{Subscriber, SubscriberEmail},
+    email_client::EmailCient,
+    mailing_lists::{get_list_by_slug, MailingList, DEFAULT_LIST_SLUG},
+    pii::PiiCipher,
+    preferences::PreferenceLinks,
+    proof_of_work::{ProofOfWork, ProofOfWorkError},
+    security_headers::CspNonce,
+    subscription_guard::SubscriptionGuard,
+    util::{collect_flash_messages, e500, error_chain_fmt, render_html},
+};
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     pub name: String,
     pub email: String,
+    // 邮件列表标识，为空时订阅默认列表
+    pub list: Option<String>,
+    // 启用工作量证明时必填，见`GET /subscribe/challenge`
+    pub pow_challenge: Option<String>,
+    pub pow_nonce: Option<String>,
+    // 蜜罐字段，正常用户不可见，必须为空
+    pub website: Option<String>,
+    // 表单渲染时签发，用于检查表单填写时间
+    pub form_token: Option<String>,
+    // 管理员定义的订阅者属性，字段名为`attr_{key}`
+    #[serde(flatten)]
+    pub attributes: HashMap<String, String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormQuery {
+    list: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "subscribe.html")]
+struct SubscribePage {
+    flash_messages: Vec<String>,
+    list: MailingList,
+    form_token: String,
+    consent_text: &'static str,
+    attributes: Vec<AttributeDefinition>,
+    csp_nonce: CspNonce,
+    // 启用工作量证明时签发的挑战，由页面中的脚本求解后随表单提交
+    proof_of_work: Option<PowChallenge>,
+}
+
+struct PowChallenge {
+    challenge: String,
+    difficulty: u32,
+    ttl_seconds: u64,
+}
+
+/// 订阅表单，`?list=`指定订阅的邮件列表
+pub async fn subscribe_form(
+    query: web::Query<FormQuery>,
+    pool: web::Data<PgPool>,
+    guard: web::Data<SubscriptionGuard>,
+    proof_of_work: web::Data<ProofOfWork>,
+    csp_nonce: CspNonce,
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(list) = find_list(&pool, query.list.as_deref())
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let attributes = list_attributes(pool.get_ref()).await.map_err(e500)?;
+
+    render_html(&SubscribePage {
+        flash_messages: collect_flash_messages(&flash_messages),
+        list,
+        form_token: guard.issue_form_token(),
+        consent_text: CONSENT_TEXT,
+        attributes,
+        csp_nonce,
+        proof_of_work: proof_of_work.is_enabled().then(|| PowChallenge {
+            challenge: proof_of_work.issue(),
+            difficulty: proof_of_work.difficulty(),
+            ttl_seconds: proof_of_work.ttl_seconds(),
+        }),
+    })
 }
 
 #[tracing::instrument(
     name = "新增订阅者",
-    skip(form, pool, email_client, config),
-    fields(
-        %form.name,
-        %form.email
-    )
+    skip(request, form, pool, email_client, config, proof_of_work, guard, pii_cipher, preference_links),
+    fields(subscriber_id = tracing::field::Empty)
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
+    request: HttpRequest,
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailCient>,
     config: web::Data<Config>,
+    proof_of_work: web::Data<ProofOfWork>,
+    guard: web::Data<SubscriptionGuard>,
+    pii_cipher: web::Data<PiiCipher>,
+    preference_links: web::Data<PreferenceLinks>,
 ) -> Result<impl Responder, SubscribeError> {
+    // 在任何数据库或邮件操作之前校验工作量证明
+    if proof_of_work.is_enabled() {
+        let (Some(challenge), Some(nonce)) = (&form.pow_challenge, &form.pow_nonce) else {
+            return Err(SubscribeError::InvalidProofOfWork(
+                ProofOfWorkError::Missing,
+            ));
+        };
+        proof_of_work
+            .verify(challenge, nonce)
+            .await
+            .map_err(SubscribeError::InvalidProofOfWork)?;
+    }
+    // 疑似机器人的订阅返回与成功相同的响应，不透露拒绝原因
+    if let Err(reason) = guard.check_form(form.website.as_deref(), form.form_token.as_deref()) {
+        guard.record_rejection(reason).await;
+        return Ok(HttpResponse::Ok());
+    }
+    let list = find_list(&pool, form.list.as_deref())
+        .await
+        .context("failed to retrieve the mailing list.")?
+        .ok_or_else(|| SubscribeError::ValidationError("unknown mailing list.".into()))?;
+    let definitions = list_attributes(pool.get_ref()).await?;
+    let attributes = validate_form_attributes(&definitions, &form.attributes)
+        .map_err(SubscribeError::ValidationError)?;
     let subscriber: Subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    if let Err(reason) = guard.check_email(&subscriber.email) {
+        guard.record_rejection(reason).await;
+        return Ok(HttpResponse::Ok());
+    }
     // 开启事务
     let mut transaction = pool
         .begin()
         .await
         .context("failed to open a transaction.")?;
     // 新增订阅者
-    let subscriber_id = add_subscriber(transaction.as_mut(), &subscriber)
-        .await
-        .context("failed to add new subscriber in the database.")?;
+    let subscriber_id = add_subscriber(
+        transaction.as_mut(),
+        list.list_id,
+        &subscriber,
+        &attributes,
+        &pii_cipher,
+    )
+    .await
+    .context("failed to add new subscriber in the database.")?;
+    tracing::Span::current().record("subscriber_id", tracing::field::display(&subscriber_id));
+    // 记录同意证据
+    record_consent(
+        transaction.as_mut(),
+        subscriber_id,
+        &form_origin(&request),
+        &ClientInfo::from_http_request(&request),
+    )
+    .await
+    .context("failed to record consent for a new subscriber.")?;
     // 生成订阅令牌
     let subscription_token = generate_subscription_token();
     // 储存订阅令牌
@@ -50,30 +187,67 @@ pub async fn subscribe(
         .await
         .context("failed to commit transaction.")?;
     // 发送确认订阅邮件
-    send_confirm_email(&subscriber, &email_client, &config, &subscription_token)
-        .await
-        .context("failed to send a confimation email.")?;
+    send_confirm_email(
+        &subscriber.email,
+        &list,
+        &email_client,
+        &config.web.base_url,
+        &subscription_token,
+        &preference_links.link(subscriber_id, None),
+    )
+    .await
+    .context("failed to send a confimation email.")?;
 
     Ok(HttpResponse::Ok())
 }
 
-/// 新增订阅者
+/// 订阅请求的来源页面，优先使用`Origin`，其次`Referer`
+fn form_origin(request: &HttpRequest) -> String {
+    [ORIGIN, REFERER]
+        .iter()
+        .find_map(|name| request.headers().get(name)?.to_str().ok())
+        .unwrap_or("unknown")
+        .to_owned()
+}
+
+/// 表单中的列表标识对应的邮件列表，未指定时使用默认列表
+async fn find_list(pool: &PgPool, slug: Option<&str>) -> sqlx::Result<Option<MailingList>> {
+    let slug = slug
+        .map(str::trim)
+        .filter(|slug| !slug.is_empty())
+        .unwrap_or(DEFAULT_LIST_SLUG);
+    get_list_by_slug(pool, &slug.to_ascii_lowercase()).await
+}
+
+/// 新增订阅者，姓名及邮箱地址加密后储存
 async fn add_subscriber(
     executor: &mut PgConnection,
+    list_id: Uuid,
     subscriber: &Subscriber,
-) -> sqlx::Result<Uuid> {
+    attributes: &Attributes,
+    pii_cipher: &PiiCipher,
+) -> anyhow::Result<Uuid> {
     let subscriber_id = Uuid::new_v4();
 
     sqlx::query!(
         r#"
-        INSERT INTO subscription (id, name, email, subscribed_at, status)
-        VALUES($1, $2, $3, $4, $5)
+        INSERT INTO subscription (
+            id, list_id, name_ciphertext, email_ciphertext, email_index, pii_key_id,
+            search_trigrams, subscribed_at, status, email_index_scheme, attributes
+        )
+        VALUES($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
         "#,
         subscriber_id,
-        subscriber.name.as_ref(),
-        subscriber.email.as_ref(),
+        list_id,
+        pii_cipher.encrypt(subscriber.name.as_ref())?,
+        pii_cipher.encrypt(subscriber.email.as_ref())?,
+        pii_cipher.blind_index(subscriber.email.as_ref()),
+        pii_cipher.active_key_id(),
+        &pii_cipher.search_trigrams([subscriber.name.as_ref(), subscriber.email.as_ref()]),
         Utc::now(),
-        subscriber.status.as_str()
+        subscriber.status.as_str(),
+        pii_cipher.email_index_scheme(),
+        serde_json::Value::Object(attributes.clone()),
     )
     .execute(executor)
     .await?;
@@ -82,7 +256,7 @@ async fn add_subscriber(
 }
 
 /// 储存订阅令牌
-async fn store_token(
+pub(crate) async fn store_token(
     executor: &mut PgConnection,
     subscriber_id: Uuid,
     subscription_token: &str,
@@ -101,35 +275,46 @@ async fn store_token(
     Ok(())
 }
 
-/// 发送确认订阅邮件
-async fn send_confirm_email(
-    subscriber: &Subscriber,
+/// 以列表的发件人及文案发送确认订阅邮件
+pub(crate) async fn send_confirm_email(
+    recipient: &SubscriberEmail,
+    list: &MailingList,
     email_client: &EmailCient,
-    config: &Config,
+    base_url: &str,
     subscription_token: &str,
+    preferences_link: &str,
 ) -> reqwest::Result<()> {
     let confirm_link = format!(
         "{}/subscription/confirm?subscription_token={}",
-        config.web.base_url, subscription_token
+        base_url, subscription_token
     );
-    let subject = "Welcome!";
     let text_body = format!(
-        "Welcome to our tutorial!\nVisit {} to confirm you subscribe.",
-        &confirm_link
+        "{}\nVisit {} to confirm you subscribe.\n\n--\nManage your subscription: {}",
+        list.confirmation_text, &confirm_link, preferences_link
     );
+    // 文案由管理员填写，写入HTML前需要转义
     let html_body = format!(
-        "Welcome to our tutorial!<br />\
-        Click <a href=\"{}\">here</a> to confirm you subscribe.",
-        &confirm_link
+        "{}<br />\
+        Click <a href=\"{}\">here</a> to confirm you subscribe.\
+        <p><a href=\"{}\">Manage your subscription</a></p>",
+        MarkupDisplay::new_unsafe(&list.confirmation_text, Html),
+        &confirm_link,
+        preferences_link
     );
 
     email_client
-        .send(&subscriber.email, subject, &text_body, &html_body)
+        .send_from(
+            list.sender().as_ref(),
+            recipient,
+            &list.confirmation_subject,
+            &text_body,
+            Some(&html_body),
+        )
         .await
 }
 
 /// 生成25位随机(a-z, A-Z and 0-9)的订阅令牌
-fn generate_subscription_token() -> String {
+pub(crate) fn generate_subscription_token() -> String {
     Alphanumeric.sample_string(&mut rand::thread_rng(), 25)
 }
 
@@ -137,6 +322,8 @@ fn generate_subscription_token() -> String {
 pub enum SubscribeError {
     #[error("failed to validate form data when add a new subscriber: {0}")]
     ValidationError(String),
+    #[error("failed to verify proof of work.")]
+    InvalidProofOfWork(#[source] ProofOfWorkError),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -145,6 +332,10 @@ impl ResponseError for SubscribeError {
     fn status_code(&self) -> actix_web::http::StatusCode {
         match self {
             SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            SubscribeError::InvalidProofOfWork(ProofOfWorkError::UnexpectedError(_)) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            SubscribeError::InvalidProofOfWork(_) => StatusCode::BAD_REQUEST,
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
@@ -0,0 +1,21 @@
+use actix_web::{web, HttpResponse, Responder};
+
+use crate::proof_of_work::ProofOfWork;
+
+#[derive(serde::Serialize)]
+struct Challenge {
+    challenge: String,
+    difficulty: u32,
+}
+
+/// 签发订阅表单的工作量证明挑战，未启用时返回404
+pub async fn subscription_challenge(proof_of_work: web::Data<ProofOfWork>) -> impl Responder {
+    if !proof_of_work.is_enabled() {
+        return HttpResponse::NotFound().finish();
+    }
+
+    HttpResponse::Ok().json(Challenge {
+        challenge: proof_of_work.issue(),
+        difficulty: proof_of_work.difficulty(),
+    })
+}
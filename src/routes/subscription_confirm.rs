@@ -2,10 +2,12 @@ use std::fmt::Debug;
 
 use actix_web::{http::StatusCode, web, HttpResponse, Responder, ResponseError};
 use anyhow::Context;
-use sqlx::PgPool;
+use sqlx::{PgConnection, PgPool};
 use uuid::Uuid;
 
-use crate::{util::error_chain_fmt, SubscriberStatus};
+use crate::{
+    client_info::ClientInfo, consent::record_confirmation, util::error_chain_fmt, SubscriberStatus,
+};
 
 #[derive(serde::Deserialize)]
 pub struct Parameters {
@@ -14,27 +16,40 @@ pub struct Parameters {
 
 #[tracing::instrument(
     name = "用户点击邮件中的确认订阅链接...", 
-    skip(parameters, pool),
+    skip(parameters, pool, client),
     fields( %parameters.subscription_token )
 )]
 pub async fn subscription_confirm(
     parameters: web::Query<Parameters>,
     pool: web::Data<PgPool>,
+    client: ClientInfo,
 ) -> Result<impl Responder, SubscriptionConfirmError> {
     let subscriber_id = get_subscriber_id(&pool, &parameters.subscription_token)
         .await
         .context(
             "failed to query subscriber_id in table[subscription_token] with subscription_token.",
-        )?;
+        )?
+        .ok_or_else(|| {
+            SubscriptionConfirmError::AuthorizationError(
+                "cannot find record in table[subscription_token] with subscription_token.".into(),
+            )
+        })?;
 
-    match subscriber_id {
-        None => Err(SubscriptionConfirmError::AuthorizationError(
-            "cannot find record in table[subscription_token] with subscription_token.".into(),
-        ))?,
-        Some(id) => confirm_subscriber(&pool, id)
-            .await
-            .context("failed to update subscriber's status in table[subscription] with id.")?,
-    }
+    // 更新订阅状态与记录确认证据在同一事务中完成
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")?;
+    confirm_subscriber(transaction.as_mut(), subscriber_id)
+        .await
+        .context("failed to update subscriber's status in table[subscription] with id.")?;
+    record_confirmation(transaction.as_mut(), subscriber_id, &client)
+        .await
+        .context("failed to record consent confirmation.")?;
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")?;
 
     Ok(HttpResponse::Ok())
 }
@@ -58,7 +73,10 @@ async fn get_subscriber_id(
 }
 
 /// 更新用户状态为`SubscriberStatus::Confirmed`
-async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+async fn confirm_subscriber(
+    executor: &mut PgConnection,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"
         UPDATE subscription SET status = $1 
@@ -67,7 +85,7 @@ async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sq
         SubscriberStatus::Confirmed.as_str(),
         subscriber_id
     )
-    .execute(pool)
+    .execute(executor)
     .await?;
 
     Ok(())
@@ -0,0 +1,98 @@
+use std::fmt::Display;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    http::header::{
+        HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, REFERRER_POLICY,
+        STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+    },
+    middleware::Next,
+    web, FromRequest, HttpMessage, HttpRequest,
+};
+use rand::distributions::{Alphanumeric, DistString};
+
+use crate::{config::Config, util::e500};
+
+/// CSP中的nonce占位符
+const NONCE_PLACEHOLDER: &str = "{nonce}";
+
+/// 本次请求的CSP nonce
+///
+/// 页面中的内联脚本或样式需要带上`nonce="{}"`属性才能被浏览器执行
+#[derive(Clone)]
+pub struct CspNonce(String);
+
+impl CspNonce {
+    fn generate() -> Self {
+        Self(Alphanumeric.sample_string(&mut rand::thread_rng(), 22))
+    }
+}
+
+impl Display for CspNonce {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromRequest for CspNonce {
+    type Error = actix_web::Error;
+    type Future = std::future::Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        std::future::ready(
+            req.extensions()
+                .get::<CspNonce>()
+                .cloned()
+                .ok_or_else(|| e500("security headers middleware is not configured.")),
+        )
+    }
+}
+
+/// 为所有响应添加安全相关的响应头，具体策略由配置文件`security_headers`定义
+pub async fn security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let config = req
+        .app_data::<web::Data<Config>>()
+        .ok_or_else(|| e500("config is not configured."))?
+        .clone();
+    let nonce = CspNonce::generate();
+    req.extensions_mut().insert(nonce.clone());
+
+    let mut res = next.call(req).await?;
+
+    let config = &config.security_headers;
+    let csp = config
+        .content_security_policy
+        .replace(NONCE_PLACEHOLDER, &format!("'nonce-{nonce}'"));
+    let headers = [
+        (CONTENT_SECURITY_POLICY, Some(csp.as_str())),
+        (
+            STRICT_TRANSPORT_SECURITY,
+            config.strict_transport_security.as_deref(),
+        ),
+        (X_FRAME_OPTIONS, Some(config.frame_options.as_str())),
+        (X_CONTENT_TYPE_OPTIONS, Some("nosniff")),
+        (REFERRER_POLICY, Some(config.referrer_policy.as_str())),
+    ];
+    for (name, value) in headers {
+        if let Some(value) = value {
+            insert_header(&mut res, name, value)?;
+        }
+    }
+
+    Ok(res)
+}
+
+fn insert_header<B>(
+    res: &mut ServiceResponse<B>,
+    name: HeaderName,
+    value: &str,
+) -> Result<(), actix_web::Error> {
+    let value = HeaderValue::from_str(value).map_err(e500)?;
+    res.headers_mut().insert(name, value);
+
+    Ok(())
+}
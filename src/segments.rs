@@ -0,0 +1,144 @@
+use anyhow::Context;
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    PgConnection, PgPool,
+};
+use uuid::Uuid;
+
+use crate::{
+    attributes::{self, Attributes},
+    domain::{SubscriberStatus, SubscriberTag},
+};
+
+/// 分组规则，各项规则同时满足，`None`或空列表表示不限制
+///
+/// 判断逻辑在数据库函数`subscriber_in_segment`中，统计人数与发布简报共用
+#[derive(Debug, Default)]
+pub struct SegmentRules {
+    /// 必须包含全部标签
+    pub required_tags: Vec<SubscriberTag>,
+    /// 不能包含其中任何一个标签
+    pub excluded_tags: Vec<SubscriberTag>,
+    pub status: Option<String>,
+    pub subscribed_from: Option<DateTime<Utc>>,
+    pub subscribed_to: Option<DateTime<Utc>>,
+    /// 订阅者的属性需包含全部属性值
+    pub attributes: Attributes,
+}
+
+pub struct SegmentSummary {
+    pub segment_id: Uuid,
+    pub name: String,
+    pub required_tags: Vec<String>,
+    pub excluded_tags: Vec<String>,
+    pub status: Option<String>,
+    pub subscribed_from: Option<DateTime<Utc>>,
+    pub subscribed_to: Option<DateTime<Utc>>,
+    pub attributes: serde_json::Value,
+    /// 当前符合规则且会收到简报的订阅者数，即已确认且未暂停的订阅者
+    pub size: i64,
+}
+
+impl SegmentSummary {
+    /// 属性条件的`key=value`文本形式
+    pub fn attribute_conditions(&self) -> String {
+        attributes::format_attributes(&attributes::from_json(self.attributes.clone()))
+    }
+}
+
+/// 新建分组，名称已存在时返回`None`
+#[tracing::instrument(name = "新建分组", skip(executor))]
+pub async fn create_segment(
+    executor: &mut PgConnection,
+    name: &str,
+    rules: &SegmentRules,
+) -> anyhow::Result<Option<Uuid>> {
+    let segment_id = Uuid::new_v4();
+    let tags = |tags: &[SubscriberTag]| -> Vec<String> {
+        tags.iter().map(|t| t.as_ref().to_owned()).collect()
+    };
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO segment (
+            segment_id, name, required_tags, excluded_tags,
+            status, subscribed_from, subscribed_to, attributes, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, now())
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        segment_id,
+        name,
+        &tags(&rules.required_tags),
+        &tags(&rules.excluded_tags),
+        rules.status,
+        rules.subscribed_from,
+        rules.subscribed_to,
+        serde_json::Value::Object(rules.attributes.clone()),
+    )
+    .execute(executor)
+    .await
+    .context("failed to create segment.")?
+    .rows_affected();
+
+    Ok((inserted > 0).then_some(segment_id))
+}
+
+/// 删除分组，已发布的简报保留发送记录，返回分组是否存在
+#[tracing::instrument(name = "删除分组", skip(executor))]
+pub async fn delete_segment(executor: &mut PgConnection, segment_id: Uuid) -> anyhow::Result<bool> {
+    let deleted = sqlx::query!("DELETE FROM segment WHERE segment_id = $1", segment_id)
+        .execute(executor)
+        .await
+        .context("failed to delete segment.")?
+        .rows_affected();
+
+    Ok(deleted > 0)
+}
+
+/// 全部分组及其当前人数，按名称排序
+///
+/// 人数与发布时的筛选条件一致，只统计已确认且未暂停的订阅者
+#[tracing::instrument(name = "查询分组", skip(pool))]
+pub async fn list_segments(pool: &PgPool) -> anyhow::Result<Vec<SegmentSummary>> {
+    sqlx::query_as!(
+        SegmentSummary,
+        r#"
+        SELECT
+            g.segment_id,
+            g.name,
+            g.required_tags,
+            g.excluded_tags,
+            g.status,
+            g.subscribed_from,
+            g.subscribed_to,
+            g.attributes,
+            (
+                SELECT count(*) FROM subscription s
+                WHERE
+                    s.status = $1 AND
+                    s.pii_key_id IS NOT NULL AND
+                    (s.paused_until IS NULL OR s.paused_until <= now()) AND
+                    subscriber_in_segment(s, g)
+            ) AS "size!"
+        FROM segment g
+        ORDER BY g.name
+        "#,
+        SubscriberStatus::Confirmed.as_str(),
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to retrieve segments.")
+}
+
+/// 在事务中锁定分组，发布期间分组不会被删除，返回分组是否存在
+#[tracing::instrument(name = "锁定分组", skip(executor))]
+pub async fn lock_segment(executor: &mut PgConnection, segment_id: Uuid) -> sqlx::Result<bool> {
+    let segment = sqlx::query_scalar!(
+        "SELECT segment_id FROM segment WHERE segment_id = $1 FOR SHARE",
+        segment_id,
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(segment.is_some())
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use sqlx::types::chrono::Utc;
+use uuid::Uuid;
+
+use crate::client_info::ClientInfo;
+
+/// 会话元数据
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SessionRecord {
+    #[serde(skip)]
+    pub session_id: Uuid,
+    /// 会话创建时间(unix时间戳，单位秒)
+    pub created_at: i64,
+    pub ip: String,
+    pub user_agent: String,
+}
+
+impl SessionRecord {
+    /// 根据登录请求的客户端信息生成会话元数据
+    pub fn new(client: &ClientInfo) -> Self {
+        Self {
+            session_id: Uuid::new_v4(),
+            created_at: Utc::now().timestamp(),
+            ip: client.ip.clone(),
+            user_agent: client.user_agent.clone(),
+        }
+    }
+}
+
+/// 按管理员索引所有已登录的会话
+///
+/// 每个管理员对应一个redis hash: `user_sessions:{user_id}`
+/// field为会话id，value为JSON格式的[`SessionRecord`]
+/// 会话从索引中移除即视为被吊销
+#[derive(Clone)]
+pub struct SessionRegistry {
+    manager: ConnectionManager,
+    // 会话索引的过期时间，与会话的绝对超时时间保持一致
+    ttl_seconds: i64,
+}
+
+impl SessionRegistry {
+    pub fn new(manager: ConnectionManager, ttl_seconds: u64) -> Self {
+        Self {
+            manager,
+            ttl_seconds: ttl_seconds as i64,
+        }
+    }
+
+    fn index_key(user_id: &Uuid) -> String {
+        format!("user_sessions:{user_id}")
+    }
+
+    #[tracing::instrument(name = "登记会话", skip(self, record))]
+    pub async fn register(&self, user_id: &Uuid, record: &SessionRecord) -> anyhow::Result<()> {
+        let key = Self::index_key(user_id);
+        let value = serde_json::to_string(record).context("failed to serialize session record.")?;
+        let mut con = self.manager.clone();
+        let _: () = con
+            .hset(&key, record.session_id.to_string(), value)
+            .await
+            .context("failed to store session record.")?;
+        let _: () = con
+            .expire(&key, self.ttl_seconds)
+            .await
+            .context("failed to set expiration on session index.")?;
+
+        Ok(())
+    }
+
+    /// 查询管理员所有未被吊销的会话，按创建时间倒序排列
+    #[tracing::instrument(name = "查询会话列表", skip(self))]
+    pub async fn list(&self, user_id: &Uuid) -> anyhow::Result<Vec<SessionRecord>> {
+        let mut con = self.manager.clone();
+        let entries: HashMap<String, String> = con
+            .hgetall(Self::index_key(user_id))
+            .await
+            .context("failed to retrieve session records.")?;
+
+        let mut records = Vec::with_capacity(entries.len());
+        for (session_id, value) in entries {
+            let mut record: SessionRecord =
+                serde_json::from_str(&value).context("failed to deserialize session record.")?;
+            record.session_id =
+                Uuid::parse_str(&session_id).context("invalid session id in session index.")?;
+            records.push(record);
+        }
+        records.sort_by_key(|r| std::cmp::Reverse(r.created_at));
+
+        Ok(records)
+    }
+
+    pub async fn is_active(&self, user_id: &Uuid, session_id: &Uuid) -> anyhow::Result<bool> {
+        let mut con = self.manager.clone();
+        con.hexists(Self::index_key(user_id), session_id.to_string())
+            .await
+            .context("failed to look up session index.")
+    }
+
+    #[tracing::instrument(name = "吊销会话", skip(self))]
+    pub async fn revoke(&self, user_id: &Uuid, session_id: &Uuid) -> anyhow::Result<()> {
+        let mut con = self.manager.clone();
+        let _: () = con
+            .hdel(Self::index_key(user_id), session_id.to_string())
+            .await
+            .context("failed to revoke session.")?;
+
+        Ok(())
+    }
+
+    /// 吊销管理员的所有会话
+    #[tracing::instrument(name = "吊销全部会话", skip(self))]
+    pub async fn revoke_all(&self, user_id: &Uuid) -> anyhow::Result<()> {
+        let mut con = self.manager.clone();
+        let _: () = con
+            .del(Self::index_key(user_id))
+            .await
+            .context("failed to revoke all sessions.")?;
+
+        Ok(())
+    }
+}
@@ -4,10 +4,17 @@ use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
 use actix_web::{dev::Payload, FromRequest, HttpRequest};
 use uuid::Uuid;
 
+use crate::authentication::PendingOidcLogin;
+
 pub struct TypedSession(Session);
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_ID_KEY: &'static str = "session_id";
+    const LOGIN_AT_KEY: &'static str = "login_at";
+    const LAST_ACTIVITY_AT_KEY: &'static str = "last_activity_at";
+    const OIDC_LOGIN_KEY: &'static str = "oidc_login";
+    const MAGIC_LINK_BINDING_KEY: &'static str = "magic_link_binding";
 
     pub fn renew(&self) {
         self.0.renew();
@@ -21,6 +28,56 @@ impl TypedSession {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    pub fn insert_session_id(&self, session_id: Uuid) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::SESSION_ID_KEY, session_id)
+    }
+
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    /// 记录登录时间(unix时间戳，单位秒)
+    pub fn insert_login_at(&self, timestamp: i64) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::LOGIN_AT_KEY, timestamp)
+    }
+
+    pub fn get_login_at(&self) -> Result<Option<i64>, SessionGetError> {
+        self.0.get(Self::LOGIN_AT_KEY)
+    }
+
+    /// 记录最后一次活动时间(unix时间戳，单位秒)
+    pub fn insert_last_activity_at(&self, timestamp: i64) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::LAST_ACTIVITY_AT_KEY, timestamp)
+    }
+
+    pub fn get_last_activity_at(&self) -> Result<Option<i64>, SessionGetError> {
+        self.0.get(Self::LAST_ACTIVITY_AT_KEY)
+    }
+
+    /// 记录进行中的单点登录请求，回调时校验
+    pub fn insert_oidc_login(&self, pending: &PendingOidcLogin) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::OIDC_LOGIN_KEY, pending)
+    }
+
+    /// 取出进行中的单点登录请求，每个请求只能使用一次
+    pub fn remove_oidc_login(&self) -> Option<Result<PendingOidcLogin, String>> {
+        self.0.remove_as(Self::OIDC_LOGIN_KEY)
+    }
+
+    /// 记录请求免密登录链接的浏览器的绑定值
+    pub fn insert_magic_link_binding(&self, binding: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::MAGIC_LINK_BINDING_KEY, binding)
+    }
+
+    pub fn get_magic_link_binding(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::MAGIC_LINK_BINDING_KEY)
+    }
+
+    /// 登录成功后移除浏览器绑定值，每个登录链接只能使用一次
+    pub fn remove_magic_link_binding(&self) {
+        self.0.remove(Self::MAGIC_LINK_BINDING_KEY);
+    }
+
     pub fn logout(&self) {
         self.0.purge();
     }
@@ -4,8 +4,12 @@ use actix_extensible_rate_limit::{
     backend::{redis::RedisBackend, SimpleInputFunctionBuilder},
     RateLimiter,
 };
-use actix_session::{storage::RedisSessionStore, SessionMiddleware};
-use actix_web::{cookie::Key, dev::Server, middleware::from_fn, web, App, HttpServer};
+use actix_session::{
+    config::{BrowserSession, CookieContentSecurity},
+    storage::RedisSessionStore,
+    SessionMiddleware,
+};
+use actix_web::{cookie, dev::Server, middleware::from_fn, web, App, HttpServer};
 use actix_web_flash_messages::{storage::CookieMessageStore, FlashMessagesFramework};
 use redis::aio::ConnectionManager;
 use secrecy::ExposeSecret;
@@ -13,7 +17,17 @@ use sqlx::PgPool;
 use tracing_actix_web::TracingLogger;
 
 use crate::{
-    authentication::reject_anonymous_user, config::Config, email_client::EmailCient, routes,
+    authentication::{reject_anonymous_user, OidcClient},
+    config::Config,
+    email_client::EmailCient,
+    keyring::{reseal_cookies, Keyring, FLASH_COOKIE_NAME, SESSION_COOKIE_NAME},
+    pii::PiiCipher,
+    preferences::PreferenceLinks,
+    proof_of_work::ProofOfWork,
+    routes,
+    security_headers::security_headers,
+    session_registry::SessionRegistry,
+    subscription_guard::SubscriptionGuard,
 };
 
 pub async fn run(
@@ -21,53 +35,212 @@ pub async fn run(
     listener: TcpListener,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailCient>,
+    pii_cipher: web::Data<PiiCipher>,
 ) -> anyhow::Result<Server> {
-    let secret_key = Key::from(config.web.hmac_secret.expose_secret().as_bytes());
-    let cookie_msg_store = CookieMessageStore::builder(secret_key.clone()).build();
+    let keyring = web::Data::new(Keyring::from_config(&config.web));
+    let secret_key = keyring.cookie_key();
+    let cookie_msg_store = CookieMessageStore::builder(secret_key.clone())
+        .cookie_name(FLASH_COOKIE_NAME.into())
+        .build();
     let flash_msg_framework = FlashMessagesFramework::builder(cookie_msg_store).build();
     let redis_session_store = RedisSessionStore::new(config.redis_uri.expose_secret()).await?;
     let client = redis::Client::open(config.redis_uri.expose_secret()).unwrap();
     let manager = ConnectionManager::new(client).await.unwrap();
-    let backend = RedisBackend::builder(manager).build();
+    let session_registry = web::Data::new(SessionRegistry::new(
+        manager.clone(),
+        config.web.session_absolute_timeout_seconds,
+    ));
+    // 会话状态在redis中的有效期不超过绝对超时时间
+    // 空闲超时及绝对超时由`reject_anonymous_user`校验
+    let session_lifecycle = BrowserSession::default().state_ttl(cookie::time::Duration::seconds(
+        config.web.session_absolute_timeout_seconds as i64,
+    ));
+    let proof_of_work = web::Data::new(ProofOfWork::new(
+        manager.clone(),
+        keyring.get_ref().clone(),
+        config.proof_of_work.clone(),
+    ));
+    // 确认邮件中的偏好设置链接
+    let preference_links = web::Data::new(PreferenceLinks::from_config(&config));
+    let subscription_guard = web::Data::new(SubscriptionGuard::new(
+        manager.clone(),
+        keyring.get_ref().clone(),
+        config.subscription_guard.clone(),
+    )?);
+    tokio::spawn(subscription_guard.get_ref().clone().reload_periodically());
+    let backend = RedisBackend::builder(manager)
+        .key_prefix(config.rate_limit.key_prefix.as_deref())
+        .build();
+    let rate_limit = config.rate_limit.clone();
+    // 仅在配置了身份提供方时启用单点登录
+    let oidc_client = config
+        .oidc
+        .clone()
+        .map(|oidc| web::Data::new(OidcClient::new(oidc, &config.web.base_url)));
 
     let server = HttpServer::new(move || {
-        let input = SimpleInputFunctionBuilder::new(Duration::from_secs(60), 100)
-            .real_ip_key()
-            .build();
+        let input = SimpleInputFunctionBuilder::new(
+            Duration::from_secs(rate_limit.interval_seconds),
+            rate_limit.max_requests,
+        )
+        .real_ip_key()
+        .build();
         let middleware = RateLimiter::builder(backend.clone(), input)
             .add_headers()
             .build();
 
-        App::new()
+        let mut app = App::new()
             .wrap(flash_msg_framework.clone())
-            .wrap(SessionMiddleware::new(
-                redis_session_store.clone(),
-                secret_key.clone(),
-            ))
+            .wrap(
+                SessionMiddleware::builder(redis_session_store.clone(), secret_key.clone())
+                    .cookie_name(SESSION_COOKIE_NAME.into())
+                    .cookie_content_security(CookieContentSecurity::Private)
+                    .session_lifecycle(session_lifecycle.clone())
+                    .build(),
+            )
+            .wrap(from_fn(reseal_cookies))
             .wrap(middleware)
+            .wrap(from_fn(security_headers))
             .wrap(TracingLogger::default())
             .route("/", web::get().to(routes::home))
             .route("/health_check", web::get().to(routes::health_check))
             .route("/login", web::get().to(routes::login_form))
             .route("/login", web::post().to(routes::login))
+            .route("/login/magic_link", web::get().to(routes::magic_link_page))
+            .route(
+                "/login/magic_link",
+                web::post().to(routes::request_magic_link),
+            )
+            .route(
+                "/login/magic_link/confirm",
+                web::post().to(routes::magic_link_login),
+            )
+            .route("/login/oidc", web::get().to(routes::oidc_login))
+            .route("/login/oidc/callback", web::get().to(routes::oidc_callback))
+            .route("/subscribe", web::get().to(routes::subscribe_form))
             .route("/subscribe", web::post().to(routes::subscribe))
+            .route(
+                "/subscribe/challenge",
+                web::get().to(routes::subscription_challenge),
+            )
             .route(
                 "/subscription/confirm",
                 web::get().to(routes::subscription_confirm),
             )
+            .route("/preferences", web::get().to(routes::preferences_page))
+            .route(
+                "/preferences",
+                web::post().to(routes::update_subscriber_preferences),
+            )
+            .route(
+                "/preferences/lists",
+                web::post().to(routes::update_subscriber_lists),
+            )
+            .route(
+                "/preferences/email",
+                web::post().to(routes::request_subscriber_email_change),
+            )
+            .route(
+                "/preferences/email/confirm",
+                web::get().to(routes::confirm_subscriber_email_change),
+            )
+            .route(
+                "/preferences/email/revert",
+                web::get().to(routes::email_revert_page),
+            )
+            .route(
+                "/preferences/email/revert",
+                web::post().to(routes::revert_subscriber_email_change),
+            )
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_user))
                     .route("/dashboard", web::get().to(routes::admin_dashboard))
                     .route("/password", web::get().to(routes::change_password_form))
                     .route("/password", web::post().to(routes::change_password))
+                    .route("/email", web::get().to(routes::email_page))
+                    .route("/email", web::post().to(routes::request_email))
+                    .route("/email/confirm", web::get().to(routes::confirm_email_page))
+                    .route("/email/confirm", web::post().to(routes::confirm_email))
                     .route("/logout", web::post().to(routes::logout))
                     .route("/publish", web::get().to(routes::publish_form))
-                    .route("/publish", web::post().to(routes::publish)),
+                    .route("/publish", web::post().to(routes::publish))
+                    .route("/sessions", web::get().to(routes::sessions_page))
+                    .route("/sessions/revoke", web::post().to(routes::revoke_session))
+                    .route(
+                        "/sessions/revoke_all",
+                        web::post().to(routes::revoke_all_sessions),
+                    )
+                    .route("/audit", web::get().to(routes::audit_log_page))
+                    .route("/audit/export", web::get().to(routes::export_audit_log))
+                    .route("/consents", web::get().to(routes::consents_page))
+                    .route(
+                        "/subscriber_data",
+                        web::get().to(routes::subscriber_data_page),
+                    )
+                    .route(
+                        "/subscriber_data/export",
+                        web::post().to(routes::download_subscriber_data),
+                    )
+                    .route(
+                        "/subscriber_data/erase",
+                        web::post().to(routes::erase_subscriber),
+                    )
+                    .route("/subscribers", web::get().to(routes::subscribers_page))
+                    .route(
+                        "/subscribers/export",
+                        web::get().to(routes::export_subscribers),
+                    )
+                    .route(
+                        "/subscribers/import",
+                        web::get().to(routes::subscriber_import_page),
+                    )
+                    .route(
+                        "/subscribers/import",
+                        web::post().to(routes::import_subscribers),
+                    )
+                    .route(
+                        "/subscribers/import/{id}/report",
+                        web::get().to(routes::download_import_report),
+                    )
+                    .route("/subscribers/{id}", web::get().to(routes::subscriber_page))
+                    .route("/subscribers/{id}/tags", web::post().to(routes::add_tags))
+                    .route(
+                        "/subscribers/{id}/attributes",
+                        web::post().to(routes::update_subscriber_attributes),
+                    )
+                    .route(
+                        "/subscribers/{id}/tags/remove",
+                        web::post().to(routes::remove_tag),
+                    )
+                    .route(
+                        "/subscribers/{id}/{action}",
+                        web::post().to(routes::manage_subscriber),
+                    )
+                    .route("/lists", web::get().to(routes::lists_page))
+                    .route("/lists", web::post().to(routes::create_list))
+                    .route("/attributes", web::get().to(routes::attributes_page))
+                    .route("/attributes", web::post().to(routes::create_attribute))
+                    .route("/segments", web::get().to(routes::segments_page))
+                    .route("/segments", web::post().to(routes::create_segment))
+                    .route(
+                        "/segments/{id}/delete",
+                        web::post().to(routes::delete_segment),
+                    ),
             )
             .app_data(config.clone())
             .app_data(pool.clone())
             .app_data(email_client.clone())
+            .app_data(session_registry.clone())
+            .app_data(proof_of_work.clone())
+            .app_data(subscription_guard.clone())
+            .app_data(pii_cipher.clone())
+            .app_data(preference_links.clone())
+            .app_data(keyring.clone());
+        if let Some(oidc_client) = &oidc_client {
+            app = app.app_data(oidc_client.clone());
+        }
+        app
     })
     .listen(listener)
     .expect("failed to bind a TcpListener.")
@@ -0,0 +1,380 @@
+use anyhow::Context;
+use sqlx::{types::chrono::Utc, PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::pii::PiiCipher;
+
+/// 某个邮箱地址相关的全部数据，用于响应数据主体的访问请求
+#[derive(serde::Serialize)]
+pub struct SubscriberDataBundle {
+    pub email: String,
+    pub exported_at: String,
+    /// 同一邮箱地址在每个邮件列表中各有一条订阅
+    pub subscriptions: Vec<SubscriptionData>,
+}
+
+impl SubscriberDataBundle {
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriptionData {
+    pub id: Uuid,
+    pub list: String,
+    pub name: String,
+    pub email: String,
+    pub status: String,
+    pub subscribed_at: String,
+    pub email_format: String,
+    pub paused_until: Option<String>,
+    /// 管理员定义的自定义属性
+    pub attributes: serde_json::Value,
+    /// 尚未确认的新邮箱地址
+    pub pending_email_change: Option<String>,
+    pub subscription_tokens: Vec<String>,
+    pub tags: Vec<String>,
+    pub consent: Option<ConsentData>,
+    /// 已发送的邮件不会保留记录，只有尚未发送的邮件
+    pub pending_deliveries: Vec<PendingDelivery>,
+}
+
+#[derive(serde::Serialize)]
+pub struct ConsentData {
+    pub consent_text: String,
+    pub form_origin: String,
+    pub subscribe_ip: String,
+    pub subscribe_user_agent: String,
+    pub subscribed_at: String,
+    pub confirm_ip: Option<String>,
+    pub confirm_user_agent: Option<String>,
+    pub confirmed_at: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct PendingDelivery {
+    pub newsletter_issue_id: Uuid,
+    pub subject: String,
+    pub published_at: String,
+}
+
+/// 导出邮箱地址相关的全部数据
+#[tracing::instrument(name = "导出订阅者数据", skip(pool, pii_cipher, email))]
+pub async fn export_subscriber_data(
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    email: &str,
+) -> anyhow::Result<SubscriberDataBundle> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            s.id,
+            l.name AS list,
+            s.name_ciphertext AS "name_ciphertext!",
+            s.email_ciphertext AS "email_ciphertext!",
+            s.status,
+            s.subscribed_at,
+            s.email_format,
+            s.paused_until,
+            s.attributes
+        FROM subscription s
+        JOIN mailing_list l ON l.list_id = s.list_id
+        WHERE s.email_index = $1
+        ORDER BY s.subscribed_at
+        "#,
+        pii_cipher.blind_index(email),
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to retrieve subscriptions.")?;
+
+    let mut subscriptions = Vec::with_capacity(rows.len());
+    for r in rows {
+        let subscription_tokens = sqlx::query_scalar!(
+            r#"
+            SELECT subscription_token FROM subscription_token
+            WHERE subscriber_id = $1
+            "#,
+            r.id,
+        )
+        .fetch_all(pool)
+        .await
+        .context("failed to retrieve subscription tokens.")?;
+        let tags = sqlx::query_scalar!(
+            r#"
+            SELECT tag FROM subscription_tag
+            WHERE subscriber_id = $1
+            ORDER BY tag
+            "#,
+            r.id,
+        )
+        .fetch_all(pool)
+        .await
+        .context("failed to retrieve subscriber tags.")?;
+        let consent = sqlx::query!(
+            r#"
+            SELECT
+                consent_text, form_origin, subscribe_ip, subscribe_user_agent,
+                subscribed_at, confirm_ip, confirm_user_agent, confirmed_at
+            FROM subscription_consent
+            WHERE subscriber_id = $1
+            "#,
+            r.id,
+        )
+        .fetch_optional(pool)
+        .await
+        .context("failed to retrieve consent record.")?
+        .map(|c| ConsentData {
+            consent_text: c.consent_text,
+            form_origin: c.form_origin,
+            subscribe_ip: c.subscribe_ip,
+            subscribe_user_agent: c.subscribe_user_agent,
+            subscribed_at: c.subscribed_at.to_rfc3339(),
+            confirm_ip: c.confirm_ip,
+            confirm_user_agent: c.confirm_user_agent,
+            confirmed_at: c.confirmed_at.map(|t| t.to_rfc3339()),
+        });
+        let pending_email_change = sqlx::query_scalar!(
+            r#"
+            SELECT new_email_ciphertext FROM email_change_request
+            WHERE subscriber_id = $1
+            "#,
+            r.id,
+        )
+        .fetch_optional(pool)
+        .await
+        .context("failed to retrieve email change request.")?
+        .map(|email| pii_cipher.decrypt(&email))
+        .transpose()?;
+        let pending_deliveries = sqlx::query!(
+            r#"
+            SELECT n.newsletter_issue_id, n.subject, n.published_at
+            FROM issue_delivery_queue q
+            JOIN newsletter_issue n ON n.newsletter_issue_id = q.newsletter_issue_id
+            WHERE q.subscriber_id = $1
+            ORDER BY n.published_at
+            "#,
+            r.id,
+        )
+        .fetch_all(pool)
+        .await
+        .context("failed to retrieve pending deliveries.")?
+        .into_iter()
+        .map(|d| PendingDelivery {
+            newsletter_issue_id: d.newsletter_issue_id,
+            subject: d.subject,
+            published_at: d.published_at.to_rfc3339(),
+        })
+        .collect();
+
+        subscriptions.push(SubscriptionData {
+            id: r.id,
+            list: r.list,
+            name: pii_cipher.decrypt(&r.name_ciphertext)?,
+            email: pii_cipher.decrypt(&r.email_ciphertext)?,
+            status: r.status,
+            subscribed_at: r.subscribed_at.to_rfc3339(),
+            email_format: r.email_format,
+            paused_until: r.paused_until.map(|until| until.to_rfc3339()),
+            attributes: r.attributes,
+            pending_email_change,
+            subscription_tokens,
+            tags,
+            consent,
+            pending_deliveries,
+        });
+    }
+
+    Ok(SubscriberDataBundle {
+        email: email.to_owned(),
+        exported_at: Utc::now().to_rfc3339(),
+        subscriptions,
+    })
+}
+
+/// 删除邮箱地址在所有邮件列表中的全部数据，需在同一事务中执行
+///
+/// 返回被删除的订阅者id，邮箱不存在时为空
+#[tracing::instrument(name = "删除订阅者数据", skip(executor, pii_cipher, email))]
+pub async fn erase_subscriber_data(
+    executor: &mut PgConnection,
+    pii_cipher: &PiiCipher,
+    email: &str,
+) -> anyhow::Result<Vec<Uuid>> {
+    let subscriber_ids = sqlx::query_scalar!(
+        r#"
+        SELECT id FROM subscription
+        WHERE email_index = $1
+        ORDER BY id
+        FOR UPDATE
+        "#,
+        pii_cipher.blind_index(email),
+    )
+    .fetch_all(&mut *executor)
+    .await
+    .context("failed to retrieve subscriptions.")?;
+    for subscriber_id in &subscriber_ids {
+        erase_subscriber_by_id(executor, *subscriber_id).await?;
+    }
+
+    Ok(subscriber_ids)
+}
+
+/// 删除订阅者及其相关的全部数据，需在同一事务中执行
+///
+/// 先删除引用`subscription`的数据及待发送的邮件，再删除订阅者本身
+/// 返回订阅者是否存在
+#[tracing::instrument(name = "按id删除订阅者数据", skip(executor))]
+pub async fn erase_subscriber_by_id(
+    executor: &mut PgConnection,
+    subscriber_id: Uuid,
+) -> anyhow::Result<bool> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to delete pending deliveries.")?;
+    sqlx::query!(
+        r#"
+        DELETE FROM confirmation_email_queue
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to delete queued confirmation email.")?;
+    sqlx::query!(
+        r#"
+        DELETE FROM subscription_token
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to delete subscription tokens.")?;
+    sqlx::query!(
+        r#"
+        DELETE FROM subscription_tag
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to delete subscriber tags.")?;
+    sqlx::query!(
+        r#"
+        DELETE FROM subscription_consent
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to delete consent record.")?;
+    sqlx::query!(
+        r#"
+        DELETE FROM email_change_request
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to delete email change request.")?;
+    sqlx::query!(
+        r#"
+        DELETE FROM email_change_revert
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to delete email change revert tokens.")?;
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM subscription
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to delete subscription.")?
+    .rows_affected();
+
+    Ok(deleted > 0)
+}
+
+/// 合并重复的订阅，需在同一事务中执行
+///
+/// `from`的标签及尚未发送的简报转移到`into`，`into`没有同意证据时一并转移，
+/// `into`没有的属性值从`from`补充，之后删除`from`及其余的相关数据
+#[tracing::instrument(name = "合并重复的订阅", skip(executor))]
+pub async fn merge_subscriber(
+    executor: &mut PgConnection,
+    from: Uuid,
+    into: Uuid,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tag (subscriber_id, tag)
+        SELECT $2, tag FROM subscription_tag WHERE subscriber_id = $1
+        ON CONFLICT DO NOTHING
+        "#,
+        from,
+        into,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to merge subscriber tags.")?;
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_id)
+        SELECT newsletter_issue_id, $2 FROM issue_delivery_queue WHERE subscriber_id = $1
+        ON CONFLICT DO NOTHING
+        "#,
+        from,
+        into,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to merge pending deliveries.")?;
+    sqlx::query!(
+        r#"
+        UPDATE subscription_consent SET subscriber_id = $2
+        WHERE subscriber_id = $1 AND NOT EXISTS (
+            SELECT 1 FROM subscription_consent WHERE subscriber_id = $2
+        )
+        "#,
+        from,
+        into,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to merge consent record.")?;
+    sqlx::query!(
+        r#"
+        UPDATE subscription SET attributes = f.attributes || subscription.attributes
+        FROM subscription f
+        WHERE subscription.id = $2 AND f.id = $1
+        "#,
+        from,
+        into,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to merge subscriber attributes.")?;
+    erase_subscriber_by_id(executor, from).await?;
+
+    Ok(())
+}
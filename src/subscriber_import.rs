@@ -0,0 +1,671 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt::Debug,
+    io::Read,
+};
+
+use anyhow::Context;
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    PgConnection, PgPool,
+};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::{
+    attributes::{list_attributes, validate_attributes, AttributeDefinition, Attributes},
+    client_info::ClientInfo,
+    consent::record_import_consent,
+    domain::{SubscriberEmail, SubscriberName, SubscriberStatus},
+    pii::PiiCipher,
+    routes::{generate_subscription_token, store_token},
+    telemetry::spawn_blocking_with_tracing,
+    util::{csv_cell, error_chain_fmt},
+};
+
+/// 每个事务写入的行数，大文件分批提交，避免长事务
+const BATCH_SIZE: usize = 500;
+
+/// 导入方式
+#[derive(Debug, Clone, Copy)]
+pub enum ImportMode {
+    /// 管理员确认订阅者已同意接收邮件，直接导入为已确认
+    Confirmed,
+    /// 导入为待确认，并向订阅者发送确认邮件
+    Pending,
+}
+
+impl ImportMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportMode::Confirmed => "confirmed",
+            ImportMode::Pending => "pending",
+        }
+    }
+
+    fn status(&self) -> SubscriberStatus {
+        match self {
+            ImportMode::Confirmed => SubscriberStatus::Confirmed,
+            ImportMode::Pending => SubscriberStatus::PendingConfirmation,
+        }
+    }
+}
+
+/// 导入记录的状态
+#[derive(Debug, Clone, Copy)]
+pub enum ImportStatus {
+    Running,
+    Completed,
+    /// 导入中途出错，出错前的批次已写入
+    Failed,
+}
+
+impl ImportStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportStatus::Running => "running",
+            ImportStatus::Completed => "completed",
+            ImportStatus::Failed => "failed",
+        }
+    }
+}
+
+/// 被拒绝的行，`line`为CSV文件中的行号(表头为第1行)
+#[derive(Debug, PartialEq)]
+pub struct RejectedRow {
+    pub line: u64,
+    pub name: String,
+    pub email: String,
+    pub reason: String,
+}
+
+impl RejectedRow {
+    fn new(line: u64, name: &str, email: &str, reason: impl Into<String>) -> Self {
+        Self {
+            line,
+            name: name.to_owned(),
+            email: email.to_owned(),
+            reason: reason.into(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: u64,
+    pub rejected: Vec<RejectedRow>,
+}
+
+impl ImportReport {
+    /// 被拒绝行的CSV报告，姓名和邮箱来自上传的文件，可能是公式
+    pub fn rejected_csv(&self) -> anyhow::Result<String> {
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(["line", "name", "email", "reason"])?;
+        for row in &self.rejected {
+            let line = row.line.to_string();
+            let fields = [line.as_str(), &row.name, &row.email, &row.reason].map(csv_cell);
+            writer.write_record(fields.iter().map(|field| field.as_bytes()))?;
+        }
+        let csv = writer
+            .into_inner()
+            .context("failed to write import report.")?;
+
+        Ok(String::from_utf8(csv)?)
+    }
+
+    fn reject(&mut self, line: u64, name: &str, email: &str, reason: impl Into<String>) {
+        self.rejected
+            .push(RejectedRow::new(line, name, email, reason));
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum ImportError {
+    #[error("{0}")]
+    InvalidFile(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl Debug for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+struct ValidRow {
+    line: u64,
+    name: SubscriberName,
+    email: SubscriberEmail,
+    attributes: Attributes,
+}
+
+/// 从CSV中导入订阅者，结果写入`report`，出错时`report`中为已写入的部分
+///
+/// CSV需包含`name`和`email`两列(不区分大小写，可包含其他列)，逐行读取并校验，
+/// 与文件中之前的行或列表中已有订阅者重复的邮箱地址不会导入，均记录在报告中。
+/// 与订阅者属性标识同名的列按属性定义校验，属性值无效的行不会导入
+/// CSV的读取及校验在阻塞线程中执行，不占用异步工作线程，
+/// 每读取一批行即交给写入，内存中只保留少量批次
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(name = "导入订阅者", skip(pool, pii_cipher, reader, client, report))]
+pub async fn import_subscribers(
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    reader: impl Read + Send + 'static,
+    list_id: Uuid,
+    mode: ImportMode,
+    form_origin: &str,
+    client: &ClientInfo,
+    report: &mut ImportReport,
+) -> Result<(), ImportError> {
+    let definitions = list_attributes(pool).await?;
+    let (sender, mut receiver) = mpsc::channel(1);
+    let parser = spawn_blocking_with_tracing(move || parse_rows(reader, &definitions, sender));
+
+    // 文件中已出现的邮箱地址的盲索引及其行号
+    let mut seen = HashMap::new();
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    while let Some(rows) = receiver.recv().await {
+        for row in rows {
+            let row = match row {
+                Ok(row) => row,
+                Err(rejected) => {
+                    report.rejected.push(rejected);
+                    continue;
+                }
+            };
+            match seen.entry(pii_cipher.blind_index(row.email.as_ref())) {
+                Entry::Occupied(first) => {
+                    report.reject(
+                        row.line,
+                        row.name.as_ref(),
+                        row.email.as_ref(),
+                        format!("duplicate of line {}.", first.get()),
+                    );
+                    continue;
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(row.line);
+                }
+            }
+            batch.push(row);
+            if batch.len() == BATCH_SIZE {
+                insert_batch(
+                    pool,
+                    pii_cipher,
+                    &batch,
+                    list_id,
+                    mode,
+                    form_origin,
+                    client,
+                    report,
+                )
+                .await?;
+                batch.clear();
+            }
+        }
+    }
+    // 通道关闭后解析已结束，表头无效等错误在此返回
+    parser.await.context("failed to spawn blocking task.")??;
+    insert_batch(
+        pool,
+        pii_cipher,
+        &batch,
+        list_id,
+        mode,
+        form_origin,
+        client,
+        report,
+    )
+    .await?;
+    // 已订阅的行在写入时才被发现，按行号重新排序
+    report.rejected.sort_by_key(|row| row.line);
+
+    Ok(())
+}
+
+/// 读取并校验CSV中的行，每`BATCH_SIZE`行发送一次，格式或内容无效的行作为被拒绝的行发送
+///
+/// 写入出错时接收端关闭，停止读取
+fn parse_rows(
+    reader: impl Read,
+    definitions: &[AttributeDefinition],
+    sender: mpsc::Sender<Vec<Result<ValidRow, RejectedRow>>>,
+) -> Result<(), ImportError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+    let (name_column, email_column) = find_columns(&mut reader)?;
+    let attribute_columns = find_attribute_columns(&mut reader, definitions)?;
+
+    let mut rows = Vec::with_capacity(BATCH_SIZE);
+    for record in reader.records() {
+        if rows.len() == BATCH_SIZE {
+            let batch = std::mem::replace(&mut rows, Vec::with_capacity(BATCH_SIZE));
+            if sender.blocking_send(batch).is_err() {
+                return Ok(());
+            }
+        }
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => match e.kind() {
+                csv::ErrorKind::Io(_) => {
+                    return Err(anyhow::Error::new(e)
+                        .context("failed to read the CSV file.")
+                        .into())
+                }
+                _ => {
+                    let line = e.position().map_or(0, |p| p.line());
+                    rows.push(Err(RejectedRow::new(line, "", "", e.to_string())));
+                    continue;
+                }
+            },
+        };
+        let line = record.position().map_or(0, |p| p.line());
+        let name = record.get(name_column).unwrap_or_default();
+        let email = record.get(email_column).unwrap_or_default();
+
+        let attributes =
+            validate_attributes(definitions, |key| record.get(*attribute_columns.get(key)?));
+
+        rows.push(
+            match (
+                SubscriberName::parse(name),
+                SubscriberEmail::parse(email),
+                attributes,
+            ) {
+                (Ok(name), Ok(email), Ok(attributes)) => Ok(ValidRow {
+                    line,
+                    name,
+                    email,
+                    attributes,
+                }),
+                (Err(reason), _, _) | (_, Err(reason), _) | (_, _, Err(reason)) => {
+                    Err(RejectedRow::new(line, name, email, reason))
+                }
+            },
+        );
+    }
+    if !rows.is_empty() {
+        // 接收端已关闭时写入已出错，错误由接收端返回
+        let _ = sender.blocking_send(rows);
+    }
+
+    Ok(())
+}
+
+/// 表头中`name`和`email`列的位置
+fn find_columns<R: Read>(reader: &mut csv::Reader<R>) -> Result<(usize, usize), ImportError> {
+    let headers = reader
+        .headers()
+        .map_err(|e| ImportError::InvalidFile(format!("failed to read the CSV header: {e}")))?;
+    let position = |column: &str| {
+        headers
+            .iter()
+            .position(|h| h.eq_ignore_ascii_case(column))
+            .ok_or_else(|| {
+                ImportError::InvalidFile(format!("the CSV file has no `{column}` column."))
+            })
+    };
+
+    Ok((position("name")?, position("email")?))
+}
+
+/// 表头中与属性标识同名的列的位置
+fn find_attribute_columns<R: Read>(
+    reader: &mut csv::Reader<R>,
+    definitions: &[AttributeDefinition],
+) -> Result<HashMap<String, usize>, ImportError> {
+    let headers = reader
+        .headers()
+        .map_err(|e| ImportError::InvalidFile(format!("failed to read the CSV header: {e}")))?;
+
+    Ok(definitions
+        .iter()
+        .filter_map(|definition| {
+            let position = headers
+                .iter()
+                .position(|h| h.eq_ignore_ascii_case(&definition.key))?;
+            Some((definition.key.clone(), position))
+        })
+        .collect())
+}
+
+/// 在一个事务中写入一批订阅者，已订阅的邮箱地址记录在报告中
+#[allow(clippy::too_many_arguments)]
+async fn insert_batch(
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    batch: &[ValidRow],
+    list_id: Uuid,
+    mode: ImportMode,
+    form_origin: &str,
+    client: &ClientInfo,
+    report: &mut ImportReport,
+) -> anyhow::Result<()> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("failed to open a transaction.")?;
+    for row in batch {
+        let Some(subscriber_id) =
+            insert_subscriber(transaction.as_mut(), pii_cipher, list_id, row, mode)
+                .await
+                .context("failed to insert an imported subscriber.")?
+        else {
+            report.reject(
+                row.line,
+                row.name.as_ref(),
+                row.email.as_ref(),
+                "already subscribed.",
+            );
+            continue;
+        };
+        record_import_consent(
+            transaction.as_mut(),
+            subscriber_id,
+            form_origin,
+            client,
+            matches!(mode, ImportMode::Confirmed),
+        )
+        .await
+        .context("failed to record consent for an imported subscriber.")?;
+        if let ImportMode::Pending = mode {
+            let subscription_token = generate_subscription_token();
+            store_token(transaction.as_mut(), subscriber_id, &subscription_token)
+                .await
+                .context("failed to store the confirmation token for an imported subscriber.")?;
+            enqueue_confirmation_email(transaction.as_mut(), subscriber_id, &subscription_token)
+                .await
+                .context("failed to enqueue a confirmation email.")?;
+        }
+        report.imported += 1;
+    }
+    transaction
+        .commit()
+        .await
+        .context("failed to commit transaction.")?;
+
+    Ok(())
+}
+
+/// 新增订阅者，邮箱地址已在列表中时返回`None`
+async fn insert_subscriber(
+    executor: &mut PgConnection,
+    pii_cipher: &PiiCipher,
+    list_id: Uuid,
+    row: &ValidRow,
+    mode: ImportMode,
+) -> anyhow::Result<Option<Uuid>> {
+    let subscriber_id = Uuid::new_v4();
+    let (name, email) = (row.name.as_ref(), row.email.as_ref());
+    let status = mode.status();
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO subscription (
+            id, list_id, name_ciphertext, email_ciphertext, email_index, pii_key_id,
+            search_trigrams, subscribed_at, status, email_index_scheme, attributes
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, now(), $8, $9, $10)
+        ON CONFLICT (list_id, email_index) DO NOTHING
+        "#,
+        subscriber_id,
+        list_id,
+        pii_cipher.encrypt(name)?,
+        pii_cipher.encrypt(email)?,
+        pii_cipher.blind_index(email),
+        pii_cipher.active_key_id(),
+        &pii_cipher.search_trigrams([name, email]),
+        status.as_str(),
+        pii_cipher.email_index_scheme(),
+        serde_json::Value::Object(row.attributes.clone()),
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+
+    Ok((inserted > 0).then_some(subscriber_id))
+}
+
+/// 将确认邮件加入发送队列，由后台工作线程发送
+async fn enqueue_confirmation_email(
+    executor: &mut PgConnection,
+    subscriber_id: Uuid,
+    subscription_token: &str,
+) -> sqlx::Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO confirmation_email_queue (subscriber_id, subscription_token, enqueued_at)
+        VALUES ($1, $2, now())
+        "#,
+        subscriber_id,
+        subscription_token,
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+/// 开始导入前保存导入记录，状态为`running`，返回导入记录id
+///
+/// 每批订阅者单独提交，先保存记录，中途出错时已写入的订阅者也有记录可查
+#[tracing::instrument(name = "保存导入记录", skip(pool))]
+pub async fn start_import(
+    pool: &PgPool,
+    user_id: Option<Uuid>,
+    filename: &str,
+    mode: ImportMode,
+) -> anyhow::Result<Uuid> {
+    let import_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_import (
+            id, user_id, filename, mode, status, imported_count, rejected_count, created_at
+        )
+        VALUES ($1, $2, $3, $4, $5, 0, 0, now())
+        "#,
+        import_id,
+        user_id,
+        filename,
+        mode.as_str(),
+        ImportStatus::Running.as_str(),
+    )
+    .execute(pool)
+    .await
+    .context("failed to store subscriber import.")?;
+
+    Ok(import_id)
+}
+
+/// 导入结束后更新导入记录的状态及结果，报告包含个人信息，加密后储存
+#[tracing::instrument(name = "更新导入记录", skip(pool, pii_cipher, report))]
+pub async fn finish_import(
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    import_id: Uuid,
+    status: ImportStatus,
+    report: &ImportReport,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriber_import
+        SET status = $2, imported_count = $3, rejected_count = $4, report_ciphertext = $5
+        WHERE id = $1
+        "#,
+        import_id,
+        status.as_str(),
+        report.imported as i64,
+        report.rejected.len() as i64,
+        pii_cipher.encrypt(&report.rejected_csv()?)?,
+    )
+    .execute(pool)
+    .await
+    .context("failed to update subscriber import.")?;
+
+    Ok(())
+}
+
+pub struct ImportSummary {
+    pub id: Uuid,
+    pub filename: String,
+    pub mode: String,
+    pub status: String,
+    pub imported_count: i64,
+    pub rejected_count: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// 最近的导入记录
+#[tracing::instrument(name = "查询导入记录", skip(pool))]
+pub async fn list_imports(pool: &PgPool, limit: i64) -> anyhow::Result<Vec<ImportSummary>> {
+    sqlx::query_as!(
+        ImportSummary,
+        r#"
+        SELECT id, filename, mode, status, imported_count, rejected_count, created_at
+        FROM subscriber_import
+        ORDER BY created_at DESC
+        LIMIT $1
+        "#,
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to retrieve subscriber imports.")
+}
+
+/// 解密导入记录的报告，记录不存在或尚未完成时返回`None`
+#[tracing::instrument(name = "查询导入报告", skip(pool, pii_cipher))]
+pub async fn get_import_report(
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    import_id: Uuid,
+) -> anyhow::Result<Option<String>> {
+    sqlx::query_scalar!(
+        "SELECT report_ciphertext FROM subscriber_import WHERE id = $1",
+        import_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to retrieve import report.")?
+    .flatten()
+    .map(|ciphertext| pii_cipher.decrypt(&ciphertext))
+    .transpose()
+}
+
+/// 使用当前密钥重新加密所有导入报告，返回处理的行数
+#[tracing::instrument(name = "轮换导入报告加密密钥", skip(pool, pii_cipher))]
+pub async fn rotate_import_reports(pool: &PgPool, pii_cipher: &PiiCipher) -> anyhow::Result<u64> {
+    let mut transaction = pool.begin().await?;
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, report_ciphertext AS "report_ciphertext!" FROM subscriber_import
+        WHERE NOT starts_with(report_ciphertext, $1 || ':')
+        FOR UPDATE
+        "#,
+        pii_cipher.active_key_id(),
+    )
+    .fetch_all(transaction.as_mut())
+    .await
+    .context("failed to retrieve import reports to re-encrypt.")?;
+    for row in &rows {
+        sqlx::query!(
+            "UPDATE subscriber_import SET report_ciphertext = $2 WHERE id = $1",
+            row.id,
+            pii_cipher.encrypt(&pii_cipher.decrypt(&row.report_ciphertext)?)?,
+        )
+        .execute(transaction.as_mut())
+        .await
+        .context("failed to re-encrypt import report.")?;
+    }
+    transaction.commit().await?;
+
+    Ok(rows.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use claim::{assert_err, assert_ok};
+
+    use tokio::sync::mpsc;
+
+    use super::{find_columns, parse_rows, ImportReport, RejectedRow, BATCH_SIZE};
+
+    fn reader(csv: &str) -> csv::Reader<&[u8]> {
+        csv::ReaderBuilder::new()
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(csv.as_bytes())
+    }
+
+    #[test]
+    fn columns_are_found_case_insensitively_in_any_order() {
+        let columns = find_columns(&mut reader("Email,Country,NAME\n"));
+
+        assert_eq!(assert_ok!(columns), (2, 0));
+    }
+
+    #[test]
+    fn header_without_name_or_email_is_rejected() {
+        assert_err!(find_columns(&mut reader("name,address\n")));
+        assert_err!(find_columns(&mut reader("")));
+    }
+
+    #[test]
+    fn rows_are_sent_in_batches() {
+        let mut csv = String::from("name,email\n");
+        for i in 0..=BATCH_SIZE {
+            csv.push_str(&format!("Reader {i},reader{i}@example.com\n"));
+        }
+        let (sender, mut receiver) = mpsc::channel(2);
+
+        assert_ok!(parse_rows(csv.as_bytes(), &[], sender));
+
+        assert_eq!(receiver.blocking_recv().unwrap().len(), BATCH_SIZE);
+        assert_eq!(receiver.blocking_recv().unwrap().len(), 1);
+        assert!(receiver.blocking_recv().is_none());
+    }
+
+    #[test]
+    fn report_lists_rejected_rows_as_csv() {
+        let report = ImportReport {
+            imported: 2,
+            rejected: vec![RejectedRow {
+                line: 3,
+                name: "Le Guin, Ursula".into(),
+                email: "ursula".into(),
+                reason: "`ursula` is not a valid subscriber email.".into(),
+            }],
+        };
+
+        assert_eq!(
+            report.rejected_csv().unwrap(),
+            "line,name,email,reason\n\
+            3,\"Le Guin, Ursula\",ursula,`ursula` is not a valid subscriber email.\n"
+        );
+    }
+
+    #[test]
+    fn report_escapes_cells_that_would_run_as_formulas() {
+        let report = ImportReport {
+            imported: 0,
+            rejected: vec![RejectedRow {
+                line: 2,
+                name: "=1+1".into(),
+                email: "@evil".into(),
+                reason: "`@evil` is not a valid subscriber email.".into(),
+            }],
+        };
+
+        assert_eq!(
+            report.rejected_csv().unwrap(),
+            "line,name,email,reason\n\
+            2,'=1+1,'@evil,`@evil` is not a valid subscriber email.\n"
+        );
+    }
+}
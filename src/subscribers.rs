@@ -0,0 +1,547 @@
+use std::sync::Arc;
+
+use actix_web::web::Bytes;
+use anyhow::Context;
+use futures_util::Stream;
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    PgConnection, PgPool, Row,
+};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::{
+    attributes::{self, Attributes},
+    domain::{SubscriberStatus, SubscriberTag},
+    pii::PiiCipher,
+    util::csv_cell,
+};
+
+/// 搜索词的最短长度，短于三元组时无法使用搜索索引
+pub const MIN_QUERY_CHARS: usize = 3;
+
+pub struct SubscriberSummary {
+    pub id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+/// 订阅者查询条件，`None`表示不限制
+#[derive(Default)]
+pub struct SubscriberFilter {
+    pub list_id: Option<Uuid>,
+    pub status: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// 姓名或邮箱地址包含的子串，不区分大小写
+    pub query: Option<String>,
+}
+
+pub struct SubscriberList {
+    pub subscribers: Vec<SubscriberSummary>,
+    /// 符合条件的订阅者总数，用于分页
+    pub total: i64,
+}
+
+/// 按条件分页查询订阅者，按订阅时间倒序排列
+///
+/// 子串搜索先通过三元组盲索引筛选候选结果，解密后再确认是否包含搜索词，
+/// 总数及分页均基于确认后的结果。尚未建立三元组索引的行也作为候选结果
+#[tracing::instrument(name = "查询订阅者", skip(pool, pii_cipher, filter))]
+pub async fn list_subscribers(
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    filter: &SubscriberFilter,
+    limit: i64,
+    offset: i64,
+) -> anyhow::Result<SubscriberList> {
+    let query = filter.query.as_deref().map(str::to_lowercase);
+    let trigrams = query.as_deref().map(|q| pii_cipher.search_trigrams([q]));
+    // 搜索时需要解密全部候选结果后才能确定总数，在内存中分页
+    let (page_limit, page_offset) = match query {
+        Some(_) => (None, None),
+        None => (Some(limit), Some(offset)),
+    };
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            id,
+            name_ciphertext AS "name_ciphertext!",
+            email_ciphertext AS "email_ciphertext!",
+            status,
+            subscribed_at,
+            count(*) OVER () AS "total!"
+        FROM subscription
+        WHERE
+            pii_key_id IS NOT NULL AND
+            ($1::text IS NULL OR status = $1) AND
+            ($2::timestamptz IS NULL OR subscribed_at >= $2) AND
+            ($3::timestamptz IS NULL OR subscribed_at < $3) AND
+            (
+                $4::text[] IS NULL OR
+                search_trigrams @> $4 OR
+                cardinality(search_trigrams) = 0
+            ) AND
+            ($7::uuid IS NULL OR list_id = $7)
+        ORDER BY subscribed_at DESC, id
+        LIMIT $5 OFFSET $6
+        "#,
+        filter.status,
+        filter.from,
+        filter.to,
+        trigrams.as_deref(),
+        page_limit,
+        page_offset,
+        filter.list_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("failed to retrieve subscribers.")?;
+
+    let total = rows.first().map_or(0, |r| r.total);
+    let mut subscribers = Vec::with_capacity(rows.len());
+    for r in rows {
+        let subscriber = SubscriberSummary {
+            id: r.id,
+            name: pii_cipher.decrypt(&r.name_ciphertext)?,
+            email: pii_cipher.decrypt(&r.email_ciphertext)?,
+            status: r.status,
+            subscribed_at: r.subscribed_at,
+        };
+        let matches = query.as_deref().is_none_or(|q| {
+            subscriber.name.to_lowercase().contains(q)
+                || subscriber.email.to_lowercase().contains(q)
+        });
+        if matches {
+            subscribers.push(subscriber);
+        }
+    }
+    if query.is_none() {
+        return Ok(SubscriberList { subscribers, total });
+    }
+
+    Ok(SubscriberList {
+        total: subscribers.len() as i64,
+        subscribers: subscribers
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect(),
+    })
+}
+
+/// 导出时每次从游标中读取的行数
+const EXPORT_BATCH_SIZE: usize = 500;
+
+/// 可导出的列
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportColumn {
+    Id,
+    Name,
+    Email,
+    Status,
+    SubscribedAt,
+}
+
+impl ExportColumn {
+    pub const ALL: [ExportColumn; 5] = [
+        ExportColumn::Id,
+        ExportColumn::Name,
+        ExportColumn::Email,
+        ExportColumn::Status,
+        ExportColumn::SubscribedAt,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportColumn::Id => "id",
+            ExportColumn::Name => "name",
+            ExportColumn::Email => "email",
+            ExportColumn::Status => "status",
+            ExportColumn::SubscribedAt => "subscribed_at",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.as_str() == s)
+    }
+
+    /// 是否为需要解密的个人信息
+    pub fn is_pii(&self) -> bool {
+        matches!(self, ExportColumn::Name | ExportColumn::Email)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ExportFormat {
+    Csv,
+    /// 每行一个JSON对象
+    Ndjson,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "csv" => Some(ExportFormat::Csv),
+            "ndjson" => Some(ExportFormat::Ndjson),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+}
+
+/// 按条件导出订阅者，按订阅时间倒序排列
+///
+/// 在后台任务中通过服务端游标分批读取并序列化，每批作为一个数据块发送，
+/// 客户端断开时停止读取，任何时候内存中最多只有几批数据
+pub fn export_subscribers(
+    pool: PgPool,
+    pii_cipher: Arc<PiiCipher>,
+    filter: SubscriberFilter,
+    columns: Vec<ExportColumn>,
+    format: ExportFormat,
+) -> impl Stream<Item = anyhow::Result<Bytes>> {
+    let (sender, receiver) = mpsc::channel(2);
+    tokio::spawn(async move {
+        let result = write_export(&pool, &pii_cipher, &filter, &columns, format, &sender).await;
+        if let Err(e) = result {
+            tracing::error!(error.cause_chain = ?e, error.message = %e, "Failed to export subscribers");
+            // 响应已开始发送，只能中断连接，避免客户端误以为导出完整
+            let _ = sender.send(Err(e)).await;
+        }
+    });
+
+    futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    })
+}
+
+#[tracing::instrument(name = "导出订阅者", skip(pool, pii_cipher, filter, sender))]
+async fn write_export(
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    filter: &SubscriberFilter,
+    columns: &[ExportColumn],
+    format: ExportFormat,
+    sender: &mpsc::Sender<anyhow::Result<Bytes>>,
+) -> anyhow::Result<()> {
+    let query = filter.query.as_deref().map(str::to_lowercase);
+    let trigrams = query.as_deref().map(|q| pii_cipher.search_trigrams([q]));
+    // 搜索时需解密确认匹配，即使不导出个人信息
+    let decrypt = query.is_some() || columns.iter().any(ExportColumn::is_pii);
+
+    let mut transaction = pool.begin().await?;
+    sqlx::query(
+        r#"
+        DECLARE subscriber_export NO SCROLL CURSOR FOR
+        SELECT id, name_ciphertext, email_ciphertext, status, subscribed_at
+        FROM subscription
+        WHERE
+            pii_key_id IS NOT NULL AND
+            ($1::text IS NULL OR status = $1) AND
+            ($2::timestamptz IS NULL OR subscribed_at >= $2) AND
+            ($3::timestamptz IS NULL OR subscribed_at < $3) AND
+            (
+                $4::text[] IS NULL OR
+                search_trigrams @> $4 OR
+                cardinality(search_trigrams) = 0
+            ) AND
+            ($5::uuid IS NULL OR list_id = $5)
+        ORDER BY subscribed_at DESC, id
+        "#,
+    )
+    .bind(&filter.status)
+    .bind(filter.from)
+    .bind(filter.to)
+    .bind(trigrams.as_deref())
+    .bind(filter.list_id)
+    .execute(transaction.as_mut())
+    .await
+    .context("failed to declare subscriber export cursor.")?;
+
+    if let ExportFormat::Csv = format {
+        let header = columns.iter().map(ExportColumn::as_str);
+        if sender.send(Ok(csv_line(header)?.into())).await.is_err() {
+            return Ok(());
+        }
+    }
+    let fetch = format!("FETCH FORWARD {EXPORT_BATCH_SIZE} FROM subscriber_export");
+    loop {
+        let rows = sqlx::query(&fetch)
+            .fetch_all(transaction.as_mut())
+            .await
+            .context("failed to fetch subscribers from export cursor.")?;
+        if rows.is_empty() {
+            break;
+        }
+
+        let mut chunk = Vec::new();
+        for row in rows {
+            let (name, email) = if decrypt {
+                (
+                    pii_cipher.decrypt(row.try_get("name_ciphertext")?)?,
+                    pii_cipher.decrypt(row.try_get("email_ciphertext")?)?,
+                )
+            } else {
+                (String::new(), String::new())
+            };
+            let matches = query.as_deref().is_none_or(|q| {
+                name.to_lowercase().contains(q) || email.to_lowercase().contains(q)
+            });
+            if !matches {
+                continue;
+            }
+            let subscriber = SubscriberSummary {
+                id: row.try_get("id")?,
+                name,
+                email,
+                status: row.try_get("status")?,
+                subscribed_at: row.try_get("subscribed_at")?,
+            };
+            let values = columns.iter().map(|c| export_value(&subscriber, *c));
+            match format {
+                ExportFormat::Csv => chunk.extend(csv_line(values)?),
+                ExportFormat::Ndjson => {
+                    let object: serde_json::Map<_, _> = columns
+                        .iter()
+                        .map(|c| c.as_str().to_owned())
+                        .zip(values.map(serde_json::Value::String))
+                        .collect();
+                    serde_json::to_writer(&mut chunk, &object)?;
+                    chunk.push(b'\n');
+                }
+            }
+        }
+        // 客户端已断开
+        if sender.send(Ok(chunk.into())).await.is_err() {
+            return Ok(());
+        }
+    }
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+fn export_value(subscriber: &SubscriberSummary, column: ExportColumn) -> String {
+    match column {
+        ExportColumn::Id => subscriber.id.to_string(),
+        ExportColumn::Name => subscriber.name.clone(),
+        ExportColumn::Email => subscriber.email.clone(),
+        ExportColumn::Status => subscriber.status.clone(),
+        ExportColumn::SubscribedAt => subscriber.subscribed_at.to_rfc3339(),
+    }
+}
+
+fn csv_line<T: AsRef<str>>(values: impl IntoIterator<Item = T>) -> anyhow::Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for value in values {
+        writer.write_field(&*csv_cell(value.as_ref()))?;
+    }
+    writer.write_record(None::<&[u8]>)?;
+
+    writer.into_inner().context("failed to write csv line.")
+}
+
+pub struct SubscriberDetail {
+    pub subscriber: SubscriberSummary,
+    /// 所属邮件列表的名称
+    pub list_name: String,
+    pub confirmed_at: Option<DateTime<Utc>>,
+    pub pending_deliveries: i64,
+    pub tags: Vec<String>,
+    pub attributes: Attributes,
+}
+
+/// 查询单个订阅者，不存在时返回`None`
+#[tracing::instrument(name = "查询订阅者详情", skip(pool, pii_cipher))]
+pub async fn get_subscriber(
+    pool: &PgPool,
+    pii_cipher: &PiiCipher,
+    subscriber_id: Uuid,
+) -> anyhow::Result<Option<SubscriberDetail>> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            s.id,
+            s.name_ciphertext AS "name_ciphertext!",
+            s.email_ciphertext AS "email_ciphertext!",
+            s.status,
+            s.subscribed_at,
+            s.attributes,
+            l.name AS list_name,
+            c.confirmed_at AS "confirmed_at?",
+            (
+                SELECT count(*) FROM issue_delivery_queue q
+                WHERE q.subscriber_id = s.id
+            ) AS "pending_deliveries!",
+            ARRAY(
+                SELECT t.tag FROM subscription_tag t
+                WHERE t.subscriber_id = s.id
+                ORDER BY t.tag
+            ) AS "tags!"
+        FROM subscription s
+        JOIN mailing_list l ON l.list_id = s.list_id
+        LEFT JOIN subscription_consent c ON c.subscriber_id = s.id
+        WHERE s.id = $1 AND s.pii_key_id IS NOT NULL
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("failed to retrieve subscriber.")?;
+    let Some(r) = row else {
+        return Ok(None);
+    };
+
+    Ok(Some(SubscriberDetail {
+        subscriber: SubscriberSummary {
+            id: r.id,
+            name: pii_cipher.decrypt(&r.name_ciphertext)?,
+            email: pii_cipher.decrypt(&r.email_ciphertext)?,
+            status: r.status,
+            subscribed_at: r.subscribed_at,
+        },
+        list_name: r.list_name,
+        confirmed_at: r.confirmed_at,
+        pending_deliveries: r.pending_deliveries,
+        tags: r.tags,
+        attributes: attributes::from_json(r.attributes),
+    }))
+}
+
+/// 管理员手动确认订阅，返回订阅者是否存在
+#[tracing::instrument(name = "手动确认订阅者", skip(executor))]
+pub async fn confirm_subscriber(
+    executor: &mut PgConnection,
+    subscriber_id: Uuid,
+) -> anyhow::Result<bool> {
+    let updated = sqlx::query!(
+        "UPDATE subscription SET status = $2 WHERE id = $1",
+        subscriber_id,
+        SubscriberStatus::Confirmed.as_str(),
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to confirm subscriber.")?
+    .rows_affected();
+
+    Ok(updated > 0)
+}
+
+/// 管理员手动退订，返回订阅者是否存在
+///
+/// 同时删除确认令牌及尚未发送的邮件，旧的确认链接无法重新订阅
+#[tracing::instrument(name = "手动退订订阅者", skip(executor))]
+pub async fn unsubscribe_subscriber(
+    executor: &mut PgConnection,
+    subscriber_id: Uuid,
+) -> anyhow::Result<bool> {
+    let updated = sqlx::query!(
+        "UPDATE subscription SET status = $2 WHERE id = $1",
+        subscriber_id,
+        SubscriberStatus::Unsubscribed.as_str(),
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to unsubscribe subscriber.")?
+    .rows_affected();
+    sqlx::query!(
+        "DELETE FROM confirmation_email_queue WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to delete queued confirmation email.")?;
+    sqlx::query!(
+        "DELETE FROM subscription_token WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to delete subscription tokens.")?;
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .execute(&mut *executor)
+    .await
+    .context("failed to delete pending deliveries.")?;
+
+    Ok(updated > 0)
+}
+
+/// 在事务中锁定订阅者，返回订阅者是否存在
+#[tracing::instrument(name = "锁定订阅者", skip(executor))]
+pub async fn lock_subscriber(
+    executor: &mut PgConnection,
+    subscriber_id: Uuid,
+) -> anyhow::Result<bool> {
+    let subscriber = sqlx::query_scalar!(
+        "SELECT id FROM subscription WHERE id = $1 FOR UPDATE",
+        subscriber_id,
+    )
+    .fetch_optional(executor)
+    .await
+    .context("failed to lock subscriber.")?;
+
+    Ok(subscriber.is_some())
+}
+
+/// 为订阅者添加标签，已有的标签保持不变
+#[tracing::instrument(name = "添加订阅者标签", skip(executor))]
+pub async fn add_subscriber_tags(
+    executor: &mut PgConnection,
+    subscriber_id: Uuid,
+    tags: &[SubscriberTag],
+) -> anyhow::Result<()> {
+    let tags: Vec<String> = tags.iter().map(|t| t.as_ref().to_owned()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tag (subscriber_id, tag)
+        SELECT $1, unnest($2::text[])
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        &tags,
+    )
+    .execute(executor)
+    .await
+    .context("failed to add subscriber tags.")?;
+
+    Ok(())
+}
+
+/// 移除订阅者的标签，返回标签是否存在
+#[tracing::instrument(name = "移除订阅者标签", skip(executor))]
+pub async fn remove_subscriber_tag(
+    executor: &mut PgConnection,
+    subscriber_id: Uuid,
+    tag: &str,
+) -> anyhow::Result<bool> {
+    let deleted = sqlx::query!(
+        "DELETE FROM subscription_tag WHERE subscriber_id = $1 AND tag = $2",
+        subscriber_id,
+        tag,
+    )
+    .execute(executor)
+    .await
+    .context("failed to remove subscriber tag.")?
+    .rows_affected();
+
+    Ok(deleted > 0)
+}
@@ -0,0 +1,236 @@
+mod domain_filter;
+
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Context;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use sqlx::types::chrono::Utc;
+
+use domain_filter::DomainFilter;
+
+use crate::{config::SubscriptionGuardConfig, domain::SubscriberEmail, keyring::Keyring};
+
+/// 记录被拒绝订阅次数的redis hash
+const REJECTIONS_KEY: &str = "subscription_rejections";
+
+/// 订阅被拒绝的原因，仅用于日志及统计，不会返回给客户端
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RejectionReason {
+    Honeypot,
+    InvalidFormToken,
+    FilledTooFast,
+    BlockedDomain,
+}
+
+impl RejectionReason {
+    pub const ALL: [RejectionReason; 4] = [
+        RejectionReason::Honeypot,
+        RejectionReason::InvalidFormToken,
+        RejectionReason::FilledTooFast,
+        RejectionReason::BlockedDomain,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RejectionReason::Honeypot => "honeypot",
+            RejectionReason::InvalidFormToken => "invalid_form_token",
+            RejectionReason::FilledTooFast => "filled_too_fast",
+            RejectionReason::BlockedDomain => "blocked_domain",
+        }
+    }
+}
+
+/// 订阅表单的反垃圾检查: 蜜罐字段、最短填写时间及邮箱域名过滤
+///
+/// 表单令牌格式: `{rendered_at}:{signature}`，signature为渲染时间的HMAC-SHA256
+#[derive(Clone)]
+pub struct SubscriptionGuard {
+    manager: ConnectionManager,
+    keyring: Keyring,
+    config: SubscriptionGuardConfig,
+    domain_filter: DomainFilter,
+}
+
+impl SubscriptionGuard {
+    pub fn new(
+        manager: ConnectionManager,
+        keyring: Keyring,
+        config: SubscriptionGuardConfig,
+    ) -> anyhow::Result<Self> {
+        let domain_filter = DomainFilter::load(config.clone())?;
+
+        Ok(Self {
+            manager,
+            keyring,
+            config,
+            domain_filter,
+        })
+    }
+
+    /// 签发嵌入订阅表单的令牌，记录表单的渲染时间
+    pub fn issue_form_token(&self) -> String {
+        issue_form_token(&self.keyring, Utc::now().timestamp())
+    }
+
+    /// 检查蜜罐字段及表单填写时间
+    pub fn check_form(
+        &self,
+        honeypot: Option<&str>,
+        form_token: Option<&str>,
+    ) -> Result<(), RejectionReason> {
+        if honeypot.is_some_and(|v| !v.is_empty()) {
+            return Err(RejectionReason::Honeypot);
+        }
+        if self.config.min_fill_seconds == 0 {
+            return Ok(());
+        }
+
+        check_form_token(
+            &self.keyring,
+            form_token.ok_or(RejectionReason::InvalidFormToken)?,
+            Utc::now().timestamp(),
+            self.config.min_fill_seconds,
+        )
+    }
+
+    pub fn check_email(&self, email: &SubscriberEmail) -> Result<(), RejectionReason> {
+        if self.domain_filter.is_blocked(email.domain()) {
+            Err(RejectionReason::BlockedDomain)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// 记录被拒绝的订阅，统计失败不影响响应
+    pub async fn record_rejection(&self, reason: RejectionReason) {
+        tracing::warn!(reason = reason.as_str(), "subscription rejected.");
+
+        let mut con = self.manager.clone();
+        let result: redis::RedisResult<i64> = con.hincr(REJECTIONS_KEY, reason.as_str(), 1).await;
+        if let Err(e) = result {
+            tracing::error!(error.message = %e, "failed to count rejected subscription.");
+        }
+    }
+
+    /// 各拒绝原因的累计次数
+    pub async fn rejection_counts(&self) -> anyhow::Result<Vec<(RejectionReason, i64)>> {
+        let mut con = self.manager.clone();
+        let counts: HashMap<String, i64> = con
+            .hgetall(REJECTIONS_KEY)
+            .await
+            .context("failed to retrieve rejected subscription counts.")?;
+
+        Ok(RejectionReason::ALL
+            .into_iter()
+            .map(|r| (r, counts.get(r.as_str()).copied().unwrap_or_default()))
+            .collect())
+    }
+
+    /// 定期重新加载域名屏蔽列表文件，修改列表无需重启服务
+    pub async fn reload_periodically(self) {
+        let period = Duration::from_secs(self.config.reload_interval_seconds);
+        loop {
+            tokio::time::sleep(period).await;
+            if let Err(e) = self.domain_filter.reload() {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    "failed to reload domain blocklist."
+                );
+            }
+        }
+    }
+}
+
+fn issue_form_token(keyring: &Keyring, now: i64) -> String {
+    let signature = keyring.sign(&now.to_string());
+
+    format!("{now}:{signature}")
+}
+
+fn check_form_token(
+    keyring: &Keyring,
+    form_token: &str,
+    now: i64,
+    min_fill_seconds: u64,
+) -> Result<(), RejectionReason> {
+    let (rendered_at, signature) = form_token
+        .split_once(':')
+        .ok_or(RejectionReason::InvalidFormToken)?;
+    let signature = hex::decode(signature).map_err(|_| RejectionReason::InvalidFormToken)?;
+    if !keyring.verify(rendered_at, &signature) {
+        return Err(RejectionReason::InvalidFormToken);
+    }
+    let rendered_at: i64 = rendered_at
+        .parse()
+        .map_err(|_| RejectionReason::InvalidFormToken)?;
+
+    if now - rendered_at < min_fill_seconds as i64 {
+        return Err(RejectionReason::FilledTooFast);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::SecretString;
+
+    use super::{check_form_token, issue_form_token, RejectionReason};
+    use crate::keyring::Keyring;
+
+    fn secret() -> SecretString {
+        SecretString::from("secret")
+    }
+
+    fn keyring() -> Keyring {
+        Keyring::new(secret(), vec![])
+    }
+
+    #[test]
+    fn form_filled_after_minimum_time_is_accepted() {
+        let token = issue_form_token(&keyring(), 1000);
+
+        assert_eq!(check_form_token(&keyring(), &token, 1003, 3), Ok(()));
+    }
+
+    #[test]
+    fn form_filled_too_fast_is_rejected() {
+        let token = issue_form_token(&keyring(), 1000);
+
+        assert_eq!(
+            check_form_token(&keyring(), &token, 1002, 3),
+            Err(RejectionReason::FilledTooFast)
+        );
+    }
+
+    #[test]
+    fn form_token_signed_before_rotation_is_accepted() {
+        let token = issue_form_token(&keyring(), 1000);
+        let rotated = Keyring::new(SecretString::from("new-secret"), vec![secret()]);
+
+        assert_eq!(check_form_token(&rotated, &token, 1003, 3), Ok(()));
+    }
+
+    #[test]
+    fn forged_form_token_is_rejected() {
+        let token = issue_form_token(&keyring(), 1000);
+        let (_, signature) = token.split_once(':').unwrap();
+
+        for forged in [
+            format!("900:{signature}"),
+            issue_form_token(
+                &Keyring::new(SecretString::from("another-secret"), vec![]),
+                900,
+            ),
+            "900".into(),
+            "900:zz".into(),
+        ] {
+            assert_eq!(
+                check_form_token(&keyring(), &forged, 1003, 3),
+                Err(RejectionReason::InvalidFormToken),
+                "{forged}"
+            );
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use std::{
+    collections::HashSet,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Context;
+
+use crate::config::SubscriptionGuardConfig;
+
+/// 内置的一次性邮箱域名列表
+const DISPOSABLE_DOMAINS: &str = include_str!("disposable_domains.txt");
+
+#[derive(Default)]
+struct DomainLists {
+    blocklist: HashSet<String>,
+    allowlist: HashSet<String>,
+}
+
+/// 订阅邮箱的域名过滤
+///
+/// 屏蔽列表由内置的一次性邮箱域名、配置中的`blocklist`及`blocklist_path`文件合并而成
+/// `allowlist`优先于屏蔽列表，屏蔽某个域名时同时屏蔽其所有子域名
+#[derive(Clone)]
+pub struct DomainFilter {
+    lists: Arc<RwLock<DomainLists>>,
+    config: SubscriptionGuardConfig,
+}
+
+impl DomainFilter {
+    pub fn load(config: SubscriptionGuardConfig) -> anyhow::Result<Self> {
+        let filter = Self {
+            lists: Arc::new(RwLock::new(DomainLists::default())),
+            config,
+        };
+        filter.reload()?;
+
+        Ok(filter)
+    }
+
+    /// 重新加载屏蔽列表文件，加载失败时保留原有列表
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let mut blocklist: HashSet<String> = parse_domains(DISPOSABLE_DOMAINS)
+            .chain(self.config.blocklist.iter().map(|d| normalize(d)))
+            .collect();
+        if let Some(path) = &self.config.blocklist_path {
+            let content = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read domain blocklist `{path}`."))?;
+            blocklist.extend(parse_domains(&content));
+        }
+        let allowlist = self.config.allowlist.iter().map(|d| normalize(d)).collect();
+
+        let mut lists = self.lists.write().unwrap();
+        *lists = DomainLists {
+            blocklist,
+            allowlist,
+        };
+
+        Ok(())
+    }
+
+    pub fn is_blocked(&self, domain: &str) -> bool {
+        let domain = normalize(domain);
+        let lists = self.lists.read().unwrap();
+        // 依次匹配域名本身及其上级域名，如: a.mailinator.com => mailinator.com => com
+        let candidates =
+            || std::iter::successors(Some(domain.as_str()), |d| d.split_once('.').map(|(_, p)| p));
+
+        if candidates().any(|d| lists.allowlist.contains(d)) {
+            return false;
+        }
+        candidates().any(|d| lists.blocklist.contains(d))
+    }
+}
+
+fn normalize(domain: &str) -> String {
+    domain.trim().trim_end_matches('.').to_lowercase()
+}
+
+/// 每行一个域名，忽略空行及`#`开头的注释
+fn parse_domains(content: &str) -> impl Iterator<Item = String> + '_ {
+    content
+        .lines()
+        .map(normalize)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::DomainFilter;
+    use crate::config::SubscriptionGuardConfig;
+
+    fn config() -> SubscriptionGuardConfig {
+        SubscriptionGuardConfig {
+            blocklist: vec!["Spam.Example".into()],
+            allowlist: vec![],
+            blocklist_path: None,
+            reload_interval_seconds: 300,
+            min_fill_seconds: 0,
+        }
+    }
+
+    #[test]
+    fn bundled_disposable_domains_are_blocked() {
+        let filter = DomainFilter::load(config()).unwrap();
+
+        assert!(filter.is_blocked("mailinator.com"));
+        assert!(filter.is_blocked("YOPMAIL.COM"));
+        assert!(!filter.is_blocked("github.com"));
+    }
+
+    #[test]
+    fn subdomains_of_blocked_domains_are_blocked() {
+        let filter = DomainFilter::load(config()).unwrap();
+
+        assert!(filter.is_blocked("spam.example"));
+        assert!(filter.is_blocked("mx.spam.example"));
+        assert!(!filter.is_blocked("notspam.example"));
+    }
+
+    #[test]
+    fn allowlist_takes_precedence() {
+        let filter = DomainFilter::load(SubscriptionGuardConfig {
+            allowlist: vec!["mailinator.com".into()],
+            ..config()
+        })
+        .unwrap();
+
+        assert!(!filter.is_blocked("mailinator.com"));
+        assert!(filter.is_blocked("spam.example"));
+    }
+
+    #[test]
+    fn blocklist_file_is_reloaded() {
+        let path = std::env::temp_dir().join(format!("blocklist-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "# 注释\n\nfirst.example\n").unwrap();
+        let filter = DomainFilter::load(SubscriptionGuardConfig {
+            blocklist_path: Some(path.to_str().unwrap().into()),
+            ..config()
+        })
+        .unwrap();
+        assert!(filter.is_blocked("first.example"));
+        assert!(!filter.is_blocked("second.example"));
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        writeln!(file, "second.example").unwrap();
+        filter.reload().unwrap();
+        assert!(filter.is_blocked("second.example"));
+
+        // 文件读取失败时保留原有列表
+        std::fs::remove_file(&path).unwrap();
+        assert!(filter.reload().is_err());
+        assert!(filter.is_blocked("second.example"));
+    }
+}
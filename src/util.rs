@@ -1,8 +1,16 @@
-use actix_web::{http::header::LOCATION, HttpResponse};
+use std::borrow::Cow;
+
+use actix_web::{
+    http::header::{ContentType, LOCATION},
+    HttpResponse,
+};
 use actix_web_flash_messages::IncomingFlashMessages;
 use anyhow::Context;
+use askama::Template;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
 use sqlx::PgPool;
-use std::fmt::Write;
 use uuid::Uuid;
 
 pub fn e400<T>(e: T) -> actix_web::Error
@@ -25,13 +33,38 @@ pub fn see_other(location: &str) -> HttpResponse {
         .finish()
 }
 
-pub fn format_flash_messages(flash_messages: IncomingFlashMessages) -> String {
-    let mut error_html = String::new();
-    for m in flash_messages.iter() {
-        writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
-    }
+/// 取出本次请求的闪现消息内容，交由模板渲染(自动转义)
+pub fn collect_flash_messages(flash_messages: &IncomingFlashMessages) -> Vec<String> {
+    flash_messages
+        .iter()
+        .map(|m| m.content().to_owned())
+        .collect()
+}
 
-    error_html
+/// 渲染模板，返回HTML响应
+pub fn render_html(template: &impl Template) -> Result<HttpResponse, actix_web::Error> {
+    let body = template.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// 使用密钥计算HMAC-SHA256，用于签发无需服务端保存的令牌
+pub fn hmac_sign(secret: &SecretString, payload: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take key of any size.");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+/// 以`=`、`+`、`-`或`@`开头的单元格会被电子表格当作公式执行，写入CSV前加上`'`前缀
+pub fn csv_cell(value: &str) -> Cow<'_, str> {
+    if value.starts_with(['=', '+', '-', '@']) {
+        Cow::Owned(format!("'{value}"))
+    } else {
+        Cow::Borrowed(value)
+    }
 }
 
 /// tracing error log
@@ -0,0 +1,108 @@
+use crate::helper::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_sign_in_to_see_audit_log() {
+    let app = spawn_app().await;
+
+    let res = app.get_admin_audit("").await;
+    assert_is_redirect_to(&res, "/login");
+
+    let res = app.get_admin_audit_export("").await;
+    assert_is_redirect_to(&res, "/login");
+}
+
+#[tokio::test]
+async fn privileged_actions_are_recorded() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_revoke_all_sessions().await;
+
+    let actions: Vec<String> = sqlx::query_scalar!(
+        r#"
+        SELECT action FROM audit_log
+        ORDER BY created_at
+        "#
+    )
+    .fetch_all(app.pool.get_ref())
+    .await
+    .unwrap();
+
+    assert_eq!(actions, vec!["login", "revoke_all_sessions"]);
+}
+
+#[tokio::test]
+async fn audit_log_cannot_be_modified() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let update = sqlx::query!("UPDATE audit_log SET action = 'tampered'")
+        .execute(app.pool.get_ref())
+        .await;
+    assert!(update.is_err());
+
+    let delete = sqlx::query!("DELETE FROM audit_log")
+        .execute(app.pool.get_ref())
+        .await;
+    assert!(delete.is_err());
+}
+
+#[tokio::test]
+async fn audit_log_can_be_filtered_by_action() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_logout().await;
+    app.test_user.login(&app).await;
+
+    let html_page = app
+        .get_admin_audit("action=login")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert_eq!(html_page.matches("<td>login</td>").count(), 2);
+    assert!(html_page.contains(&app.test_user.username));
+
+    let html_page = app
+        .get_admin_audit("action=logout")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(html_page.matches("<td>logout</td>").count(), 1);
+    assert!(!html_page.contains("<td>login</td>"));
+}
+
+#[tokio::test]
+async fn invalid_date_filter_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let res = app.get_admin_audit("from=yesterday").await;
+
+    assert_eq!(res.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn audit_log_can_be_exported_as_csv() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let res = app.get_admin_audit_export("action=login").await;
+
+    assert_eq!(res.status().as_u16(), 200);
+    assert!(res.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/csv"));
+    let body = res.text().await.unwrap();
+    let mut lines = body.lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        "created_at,actor,action,target_id,ip,user_agent"
+    );
+    let row = lines.next().unwrap();
+    assert!(row.contains(&app.test_user.username));
+    assert!(row.contains(",login,"));
+    assert!(lines.next().is_none());
+}
@@ -0,0 +1,108 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helper::{assert_is_redirect_to, build_api_client, spawn_app, TestApp, TestUser};
+
+async fn mount_email_server(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn email_is_set_only_after_the_link_is_confirmed() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    app.test_user.login(&app).await;
+    assert!(app
+        .get_admin_email_html()
+        .await
+        .contains("No email address is set"));
+
+    let res = app.post_admin_email("admin@example.com").await;
+
+    assert_is_redirect_to(&res, "/admin/email");
+    let html = app.get_admin_email_html().await;
+    assert!(html.contains("验证链接已发送"));
+    assert!(html.contains("No email address is set"));
+
+    let link = app.get_confirmation_link().await;
+    let res = app.confirm_admin_email(link).await;
+
+    assert_is_redirect_to(&res, "/admin/email");
+    let html = app.get_admin_email_html().await;
+    assert!(html.contains("邮箱地址已更新."));
+    assert!(html.contains("Login links are sent to admin@example.com."));
+    let targets = sqlx::query_scalar!(
+        "SELECT target_id FROM audit_log WHERE action = 'change_email' AND actor_user_id = $1",
+        app.test_user.user_id,
+    )
+    .fetch_all(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_eq!(targets, [Some("admin@example.com".to_owned())]);
+}
+
+#[tokio::test]
+async fn verification_link_only_works_for_the_requesting_admin() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    app.test_user.login(&app).await;
+    app.post_admin_email("admin@example.com").await;
+    let link = app.get_confirmation_link().await;
+    app.post_logout().await;
+
+    let other_admin = TestUser::generate();
+    other_admin.store(&app.pool).await;
+    other_admin.login(&app).await;
+    let res = app.confirm_admin_email(link.clone()).await;
+
+    assert_is_redirect_to(&res, "/admin/email");
+    assert!(app
+        .get_admin_email_html()
+        .await
+        .contains("验证链接无效或已过期."));
+    let emails = sqlx::query_scalar!("SELECT email FROM users WHERE email IS NOT NULL")
+        .fetch_all(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert!(emails.is_empty());
+
+    // 未登录时打开链接需要先登录
+    let res = build_api_client().get(link).send().await.unwrap();
+    assert_is_redirect_to(&res, "/login");
+}
+
+#[tokio::test]
+async fn email_of_another_admin_is_rejected() {
+    let app = spawn_app().await;
+    mount_email_server(&app).await;
+    let other_admin = TestUser::generate();
+    other_admin.store(&app.pool).await;
+    sqlx::query!(
+        "UPDATE users SET email = 'taken@example.com' WHERE user_id = $1",
+        other_admin.user_id,
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    let res = app.post_admin_email("taken@example.com").await;
+
+    assert_is_redirect_to(&res, "/admin/email");
+    assert!(app
+        .get_admin_email_html()
+        .await
+        .contains("该邮箱地址已被其他管理员使用."));
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+}
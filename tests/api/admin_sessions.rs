@@ -0,0 +1,98 @@
+use crate::helper::{assert_is_redirect_to, build_api_client, spawn_app, TestApp};
+
+/// 使用另一个浏览器登录测试管理员
+async fn login_in_another_browser(app: &TestApp) -> reqwest::Client {
+    let client = build_api_client();
+    let res = client
+        .post(app.web_base_url.join("/login").unwrap())
+        .header("User-Agent", "another-browser")
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&res, "/admin/dashboard");
+
+    client
+}
+
+async fn get_dashboard(app: &TestApp, client: &reqwest::Client) -> reqwest::Response {
+    client
+        .get(app.web_base_url.join("/admin/dashboard").unwrap())
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_sign_in_to_see_sessions() {
+    let app = spawn_app().await;
+
+    let res = app
+        .api_client
+        .get(app.web_base_url.join("/admin/sessions").unwrap())
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&res, "/login");
+}
+
+#[tokio::test]
+async fn sessions_page_lists_all_active_sessions() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    login_in_another_browser(&app).await;
+
+    let html_page = app.get_admin_sessions_html().await;
+
+    assert!(html_page.contains("(current)"));
+    assert!(html_page.contains("another-browser"));
+}
+
+#[tokio::test]
+async fn revoked_session_must_sign_in_again() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let another_browser = login_in_another_browser(&app).await;
+
+    // 1. 查询另一个浏览器的会话id
+    let html_page = app.get_admin_sessions_html().await;
+    let session_id = html_page
+        .split(r#"name="session_id" value=""#)
+        .nth(1)
+        .and_then(|s| s.split('"').next())
+        .unwrap()
+        .to_owned();
+
+    // 2. 吊销该会话
+    let res = app
+        .post_revoke_session(&serde_json::json!({ "session_id": session_id }))
+        .await;
+    assert_is_redirect_to(&res, "/admin/sessions");
+
+    // 3. 另一个浏览器需要重新登录
+    let res = get_dashboard(&app, &another_browser).await;
+    assert_is_redirect_to(&res, "/login");
+
+    // 4. 当前会话不受影响
+    let res = app.get_admin_dashboard().await;
+    assert_eq!(200, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn log_out_everywhere_revokes_all_sessions() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let another_browser = login_in_another_browser(&app).await;
+
+    let res = app.post_revoke_all_sessions().await;
+    assert_is_redirect_to(&res, "/login");
+
+    let res = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&res, "/login");
+    let res = get_dashboard(&app, &another_browser).await;
+    assert_is_redirect_to(&res, "/login");
+}
@@ -0,0 +1,460 @@
+use reqwest::header::CONTENT_TYPE;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// 直接写入一个加密的订阅者，订阅时间为`days_ago`天前
+async fn insert_subscriber(
+    app: &TestApp,
+    name: &str,
+    email: &str,
+    status: &str,
+    days_ago: i32,
+) -> Uuid {
+    let id = Uuid::new_v4();
+    let cipher = &app.pii_cipher;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription (
+            id, list_id, name_ciphertext, email_ciphertext, email_index, pii_key_id,
+            search_trigrams, subscribed_at, status
+        )
+        VALUES (
+            $1, (SELECT list_id FROM mailing_list WHERE slug = 'default'),
+            $2, $3, $4, $5, $6, now() - make_interval(days => $7), $8
+        )
+        "#,
+        id,
+        cipher.encrypt(name).unwrap(),
+        cipher.encrypt(email).unwrap(),
+        cipher.blind_index(email),
+        cipher.active_key_id(),
+        &cipher.search_trigrams([name, email]),
+        days_ago,
+        status,
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+
+    id
+}
+
+async fn seed_subscribers(app: &TestApp) -> [Uuid; 3] {
+    [
+        insert_subscriber(app, "IceFruit huang", "git@github.com", "confirmed", 0).await,
+        insert_subscriber(
+            app,
+            "Ursula Le Guin",
+            "ursula@example.com",
+            "pending_confirmation",
+            10,
+        )
+        .await,
+        insert_subscriber(app, "Frank Herbert", "frank@dune.org", "unsubscribed", 30).await,
+    ]
+}
+
+async fn listed_emails(app: &TestApp, query: &[(&str, &str)]) -> Vec<&'static str> {
+    let res = app.get_admin_subscribers(query).await;
+    assert_eq!(200, res.status().as_u16());
+    let html = res.text().await.unwrap();
+    ["git@github.com", "ursula@example.com", "frank@dune.org"]
+        .into_iter()
+        .filter(|email| html.contains(email))
+        .collect()
+}
+
+/// `days`天前的日期，格式: YYYY-MM-DD
+async fn date_days_ago(app: &TestApp, days: i32) -> String {
+    sqlx::query_scalar!(r#"SELECT (current_date - $1::int)::text AS "date!""#, days)
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap()
+}
+
+async fn subscriber_status(app: &TestApp, id: Uuid) -> Option<String> {
+    sqlx::query_scalar!("SELECT status FROM subscription WHERE id = $1", id)
+        .fetch_optional(app.pool.get_ref())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_login_to_manage_subscribers() {
+    let app = spawn_app().await;
+    let id = Uuid::new_v4();
+
+    assert_is_redirect_to(&app.get_admin_subscribers(&[]).await, "/login");
+    assert_is_redirect_to(&app.get_export_subscribers(&[]).await, "/login");
+    assert_is_redirect_to(&app.get_admin_subscriber(&id).await, "/login");
+    let res = app
+        .post_manage_subscriber(&id, "delete", &json!({ "confirm": "yes" }))
+        .await;
+    assert_is_redirect_to(&res, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_and_filtered() {
+    let app = spawn_app().await;
+    seed_subscribers(&app).await;
+    app.test_user.login(&app).await;
+
+    assert_eq!(listed_emails(&app, &[]).await.len(), 3);
+    // 姓名或邮箱地址子串，不区分大小写
+    assert_eq!(
+        listed_emails(&app, &[("q", "FRUIT")]).await,
+        ["git@github.com"]
+    );
+    assert_eq!(
+        listed_emails(&app, &[("q", "@dune")]).await,
+        ["frank@dune.org"]
+    );
+    assert!(listed_emails(&app, &[("q", "nobody")]).await.is_empty());
+    assert_eq!(
+        listed_emails(&app, &[("status", "pending_confirmation")]).await,
+        ["ursula@example.com"]
+    );
+    assert_eq!(
+        listed_emails(&app, &[("to", &date_days_ago(&app, 5).await)]).await,
+        ["ursula@example.com", "frank@dune.org"]
+    );
+    assert_eq!(
+        listed_emails(
+            &app,
+            &[("from", &date_days_ago(&app, 20).await), ("q", "example")]
+        )
+        .await,
+        ["ursula@example.com"]
+    );
+}
+
+#[tokio::test]
+async fn search_query_must_be_at_least_three_characters() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let res = app.get_admin_subscribers(&[("q", "ab")]).await;
+
+    assert_eq!(400, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated() {
+    let app = spawn_app().await;
+    for i in 0..55 {
+        insert_subscriber(
+            &app,
+            &format!("Reader {i}"),
+            &format!("reader{i}@example.com"),
+            "confirmed",
+            0,
+        )
+        .await;
+    }
+    app.test_user.login(&app).await;
+
+    let first = app.get_admin_subscribers(&[]).await.text().await.unwrap();
+    let second = app
+        .get_admin_subscribers(&[("page", "2")])
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert!(first.contains("55 subscribers"));
+    assert!(first.contains("Page 1 of 2"));
+    assert_eq!(first.matches("@example.com").count(), 50);
+    assert_eq!(second.matches("@example.com").count(), 5);
+}
+
+#[tokio::test]
+async fn search_totals_and_pages_only_count_confirmed_matches() {
+    let app = spawn_app().await;
+    for i in 0..50 {
+        insert_subscriber(
+            &app,
+            &format!("Aaab {i}"),
+            &format!("match{i}@example.com"),
+            "confirmed",
+            0,
+        )
+        .await;
+    }
+    // 含有搜索词的全部三元组，但不包含搜索词
+    for i in 0..5 {
+        insert_subscriber(
+            &app,
+            "Aaa Baab",
+            &format!("decoy{i}@example.org"),
+            "confirmed",
+            0,
+        )
+        .await;
+    }
+    // 尚未建立三元组索引的订阅者
+    let legacy = insert_subscriber(&app, "Aaab Legacy", "legacy@example.net", "confirmed", 1).await;
+    sqlx::query!(
+        "UPDATE subscription SET search_trigrams = '{}' WHERE id = $1",
+        legacy,
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    let first = app
+        .get_admin_subscribers(&[("q", "aaab")])
+        .await
+        .text()
+        .await
+        .unwrap();
+    let second = app
+        .get_admin_subscribers(&[("q", "aaab"), ("page", "2")])
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert!(first.contains("51 subscribers"));
+    assert!(first.contains("Page 1 of 2"));
+    assert_eq!(first.matches("@example.com").count(), 50);
+    assert!(second.contains("legacy@example.net"));
+    assert!(!first.contains("@example.org") && !second.contains("@example.org"));
+}
+
+#[tokio::test]
+async fn subscribers_are_exported_as_csv_and_recorded_in_the_audit_log() {
+    let app = spawn_app().await;
+    let [confirmed, pending, unsubscribed] = seed_subscribers(&app).await;
+    app.test_user.login(&app).await;
+
+    let res = app.get_export_subscribers(&[]).await;
+
+    assert_eq!(200, res.status().as_u16());
+    assert_eq!(res.headers()[CONTENT_TYPE], "text/csv; charset=utf-8");
+    let csv = res.text().await.unwrap();
+    let rows: Vec<_> = csv.lines().collect();
+    assert_eq!(rows[0], "id,name,email,status,subscribed_at");
+    assert!(rows[1].starts_with(&format!(
+        "{confirmed},IceFruit huang,git@github.com,confirmed,"
+    )));
+    assert!(rows[2].starts_with(&format!("{pending},Ursula Le Guin,ursula@example.com,")));
+    assert!(rows[3].starts_with(&format!("{unsubscribed},Frank Herbert,frank@dune.org,")));
+    assert_eq!(rows.len(), 4);
+
+    let targets =
+        sqlx::query_scalar!("SELECT target_id FROM audit_log WHERE action = 'export_subscribers'")
+            .fetch_all(app.pool.get_ref())
+            .await
+            .unwrap();
+    assert_eq!(
+        targets,
+        [Some(
+            "columns=id,name,email,status,subscribed_at".to_owned()
+        )]
+    );
+}
+
+#[tokio::test]
+async fn export_records_the_filters_but_not_the_search_text() {
+    let app = spawn_app().await;
+    seed_subscribers(&app).await;
+    app.test_user.login(&app).await;
+
+    let res = app
+        .get_export_subscribers(&[("columns", "id"), ("status", "confirmed"), ("q", "github")])
+        .await;
+    assert_eq!(200, res.status().as_u16());
+
+    let targets =
+        sqlx::query_scalar!("SELECT target_id FROM audit_log WHERE action = 'export_subscribers'")
+            .fetch_all(app.pool.get_ref())
+            .await
+            .unwrap();
+    assert_eq!(
+        targets,
+        [Some("columns=id;status=confirmed;search".to_owned())]
+    );
+}
+
+#[tokio::test]
+async fn csv_export_escapes_cells_that_would_run_as_formulas() {
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app,
+        "=HYPERLINK(\"http://evil.example\")",
+        "formula@example.com",
+        "confirmed",
+        0,
+    )
+    .await;
+    insert_subscriber(&app, "-2+3", "minus@example.com", "confirmed", 1).await;
+    app.test_user.login(&app).await;
+
+    let res = app.get_export_subscribers(&[("columns", "name")]).await;
+
+    assert_eq!(200, res.status().as_u16());
+    let csv = res.text().await.unwrap();
+    let rows: Vec<_> = csv.lines().collect();
+    assert_eq!(
+        rows,
+        [
+            "name",
+            "\"'=HYPERLINK(\"\"http://evil.example\"\")\"",
+            "'-2+3"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn export_can_leave_out_pii_and_uses_the_list_filters() {
+    let app = spawn_app().await;
+    let [_, pending, _] = seed_subscribers(&app).await;
+    app.test_user.login(&app).await;
+
+    let res = app
+        .get_export_subscribers(&[
+            ("format", "ndjson"),
+            ("columns", "id,status"),
+            ("q", "example"),
+        ])
+        .await;
+
+    assert_eq!(200, res.status().as_u16());
+    let body = res.text().await.unwrap();
+    let lines: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(
+        lines,
+        [json!({ "id": pending.to_string(), "status": "pending_confirmation" })]
+    );
+    assert!(!body.contains("ursula"));
+}
+
+#[tokio::test]
+async fn export_rejects_unknown_columns_and_formats() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let res = app
+        .get_export_subscribers(&[("columns", "id,password")])
+        .await;
+    assert_eq!(400, res.status().as_u16());
+    let res = app.get_export_subscribers(&[("format", "xml")]).await;
+    assert_eq!(400, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn actions_require_the_confirmation_step() {
+    let app = spawn_app().await;
+    let [confirmed, pending, _] = seed_subscribers(&app).await;
+    app.test_user.login(&app).await;
+
+    let res = app
+        .post_manage_subscriber(&pending, "confirm", &json!({}))
+        .await;
+    assert_is_redirect_to(&res, &format!("/admin/subscribers/{pending}"));
+    assert_eq!(
+        subscriber_status(&app, pending).await.unwrap(),
+        "pending_confirmation"
+    );
+
+    let res = app
+        .post_manage_subscriber(
+            &confirmed,
+            "delete",
+            &json!({ "confirm": "yes", "email_check": "other@github.com" }),
+        )
+        .await;
+    assert_is_redirect_to(&res, &format!("/admin/subscribers/{confirmed}"));
+    assert!(subscriber_status(&app, confirmed).await.is_some());
+    let html = app
+        .get_admin_subscriber(&confirmed)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("输入的邮箱地址与订阅者不一致."));
+}
+
+#[tokio::test]
+async fn admin_can_confirm_unsubscribe_and_delete_a_subscriber() {
+    let app = spawn_app().await;
+    let [confirmed, pending, _] = seed_subscribers(&app).await;
+    app.test_user.login(&app).await;
+
+    let html = app
+        .get_admin_subscriber(&pending)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("Ursula Le Guin"));
+    let res = app
+        .post_manage_subscriber(&pending, "confirm", &json!({ "confirm": "yes" }))
+        .await;
+    assert_is_redirect_to(&res, &format!("/admin/subscribers/{pending}"));
+    assert_eq!(subscriber_status(&app, pending).await.unwrap(), "confirmed");
+
+    let res = app
+        .post_manage_subscriber(&pending, "unsubscribe", &json!({ "confirm": "yes" }))
+        .await;
+    assert_is_redirect_to(&res, &format!("/admin/subscribers/{pending}"));
+    assert_eq!(
+        subscriber_status(&app, pending).await.unwrap(),
+        "unsubscribed"
+    );
+
+    let res = app
+        .post_manage_subscriber(
+            &confirmed,
+            "delete",
+            &json!({ "confirm": "yes", "email_check": "git@github.com" }),
+        )
+        .await;
+    assert_is_redirect_to(&res, "/admin/subscribers");
+    assert!(subscriber_status(&app, confirmed).await.is_none());
+    assert_eq!(
+        404,
+        app.get_admin_subscriber(&confirmed).await.status().as_u16()
+    );
+
+    let actions = sqlx::query_scalar!(
+        "SELECT action FROM audit_log WHERE action <> 'login' ORDER BY created_at"
+    )
+    .fetch_all(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_eq!(
+        actions,
+        [
+            "confirm_subscriber",
+            "unsubscribe_subscriber",
+            "delete_subscriber"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_pending_issues() {
+    let app = spawn_app().await;
+    let [confirmed, ..] = seed_subscribers(&app).await;
+    app.test_user.login(&app).await;
+    app.post_publish_with_default_issue(None).await;
+
+    app.post_manage_subscriber(&confirmed, "unsubscribe", &json!({ "confirm": "yes" }))
+        .await;
+
+    let pending = sqlx::query_scalar!(
+        "SELECT count(*) FROM issue_delivery_queue WHERE subscriber_id = $1",
+        confirmed,
+    )
+    .fetch_one(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_eq!(pending, Some(0));
+}
@@ -0,0 +1,320 @@
+use serde_json::{json, Value};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// 新建公司(文本)、套餐(可选值)及席位(数字)三个属性
+async fn create_attributes(app: &TestApp) {
+    for attribute in [
+        json!({"key": "company", "label": "Company", "kind": "text"}),
+        json!({"key": "plan", "label": "Plan", "kind": "choice", "choices": "free, pro"}),
+        json!({"key": "seats", "label": "Seats", "kind": "number"}),
+    ] {
+        let res = app.post_create_attribute(&attribute).await;
+        assert_is_redirect_to(&res, "/admin/attributes");
+    }
+}
+
+async fn subscriber_attributes(app: &TestApp, email: &str) -> Value {
+    sqlx::query_scalar!(
+        "SELECT attributes FROM subscription WHERE email_index = $1",
+        app.pii_cipher.blind_index(email),
+    )
+    .fetch_one(app.pool.get_ref())
+    .await
+    .unwrap()
+}
+
+async fn subscriber_id(app: &TestApp, email: &str) -> Uuid {
+    sqlx::query_scalar!(
+        "SELECT id FROM subscription WHERE email_index = $1",
+        app.pii_cipher.blind_index(email),
+    )
+    .fetch_one(app.pool.get_ref())
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_login_to_manage_attributes() {
+    let app = spawn_app().await;
+
+    let res = app
+        .post_create_attribute(&json!({"key": "company", "label": "Company", "kind": "text"}))
+        .await;
+    assert_is_redirect_to(&res, "/login");
+    let res = app
+        .post_subscriber_attributes(&Uuid::new_v4(), &json!({"attr_company": "Acme"}))
+        .await;
+    assert_is_redirect_to(&res, "/login");
+}
+
+#[tokio::test]
+async fn invalid_attribute_definitions_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_attributes(&app).await;
+
+    let cases = [
+        (
+            json!({"key": "email", "label": "Email", "kind": "text"}),
+            "属性无效: `email` is not a valid attribute key.",
+        ),
+        (
+            json!({"key": "country", "label": "Country", "kind": "list"}),
+            "属性无效: `list` is not a valid attribute type.",
+        ),
+        (
+            json!({"key": "tier", "label": "Tier", "kind": "choice"}),
+            "属性无效: choice attributes need at least one choice.",
+        ),
+        (
+            json!({"key": "Company", "label": "Company", "kind": "text"}),
+            "属性标识已存在.",
+        ),
+    ];
+    for (body, message) in cases {
+        let res = app.post_create_attribute(&body).await;
+        assert_is_redirect_to(&res, "/admin/attributes");
+        assert!(
+            app.get_attributes_html().await.contains(message),
+            "{message}"
+        );
+    }
+
+    let actions = sqlx::query_scalar!("SELECT action FROM audit_log WHERE action <> 'login'")
+        .fetch_all(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(actions, ["create_attribute"; 3]);
+}
+
+#[tokio::test]
+async fn subscribe_form_stores_validated_attributes() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_attributes(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let html = app.get_subscribe_html().await;
+    assert!(html.contains(r#"name="attr_company""#));
+    assert!(html.contains(r#"<option value="pro">pro</option>"#));
+    assert!(html.contains(r#"<input type="number" name="attr_seats""#));
+
+    let res = app
+        .post_subscribe("name=Ferris&email=ferris%40example.com&attr_plan=enterprise")
+        .await;
+    assert_eq!(400, res.status().as_u16());
+
+    let res = app
+        .post_subscribe(
+            "name=Ferris&email=ferris%40example.com&attr_company=Acme&attr_plan=pro&attr_seats=3",
+        )
+        .await;
+    assert_eq!(200, res.status().as_u16());
+    assert_eq!(
+        subscriber_attributes(&app, "ferris@example.com").await,
+        json!({"company": "Acme", "plan": "pro", "seats": 3})
+    );
+}
+
+#[tokio::test]
+async fn import_validates_attribute_columns() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_attributes(&app).await;
+    let csv = "name,email,plan,seats\n\
+        Ferris,ferris@example.com,pro,12\n\
+        Crab,crab@example.com,enterprise,1\n";
+
+    let res = app.post_import_subscribers(csv, true).await;
+
+    assert_is_redirect_to(&res, "/admin/subscribers/import");
+    assert!(app
+        .get_subscriber_import_html()
+        .await
+        .contains("已导入1个订阅者，拒绝1行."));
+    assert_eq!(
+        subscriber_attributes(&app, "ferris@example.com").await,
+        json!({"plan": "pro", "seats": 12})
+    );
+}
+
+#[tokio::test]
+async fn admin_can_edit_attributes_and_segment_by_them() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_attributes(&app).await;
+    let csv = "name,email,plan\n\
+        Ferris,ferris@example.com,free\n\
+        Crab,crab@example.com,free\n";
+    app.post_import_subscribers(csv, true).await;
+    let ferris = subscriber_id(&app, "ferris@example.com").await;
+
+    let res = app
+        .post_subscriber_attributes(&ferris, &json!({"attr_plan": "gold"}))
+        .await;
+    assert_is_redirect_to(&res, &format!("/admin/subscribers/{ferris}"));
+    let html = app
+        .get_admin_subscriber(&ferris)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("属性值无效: `gold` is not a valid Plan."));
+    let res = app
+        .post_subscriber_attributes(
+            &ferris,
+            &json!({"attr_plan": "pro", "attr_company": "Acme"}),
+        )
+        .await;
+    assert_is_redirect_to(&res, &format!("/admin/subscribers/{ferris}"));
+    let html = app
+        .get_admin_subscriber(&ferris)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains(r#"<option value="pro" selected>pro</option>"#));
+    assert!(html.contains(r#"name="attr_company" value="Acme""#));
+
+    let mut segment = json!({
+        "name": "Pro",
+        "required_tags": "",
+        "excluded_tags": "",
+        "status": "",
+        "from": "",
+        "to": "",
+        "attributes": "plan=gold",
+    });
+    let res = app.post_create_segment(&segment).await;
+    assert_is_redirect_to(&res, "/admin/segments");
+    let html = app.get_segments_html().await;
+    assert!(html.contains("分组规则无效: `gold` is not a valid Plan."));
+    segment["attributes"] = json!("plan=pro");
+    let res = app.post_create_segment(&segment).await;
+    assert_is_redirect_to(&res, "/admin/segments");
+    let html = app.get_segments_html().await;
+    assert!(html.contains("<td>plan=pro</td>"));
+    assert!(app.get_admin_dashboard_html().await.contains("Pro: 1"));
+}
+
+#[tokio::test]
+async fn issues_are_personalised_with_subscriber_attributes() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_attributes(&app).await;
+    app.post_import_subscribers(
+        "name,email,company\nFerris,ferris@example.com,<Crustaceans>\nCrab,crab@example.com,\n",
+        true,
+    )
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let res = app
+        .post_publish(&json!({
+            "subject": "News for {{ company }}",
+            "text_body": "Hello {{company}} team.",
+            "html_body": "<p>Hello {{ company }} team.</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&res, "/admin/dashboard");
+    app.dispatch_all_pending_emails().await;
+
+    let bodies: Vec<Value> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| serde_json::from_slice(&r.body).unwrap())
+        .collect();
+    let to = |email: &str| bodies.iter().find(|b| b["To"] == email).unwrap();
+    let ferris = to("ferris@example.com");
+    assert_eq!(ferris["Subject"], "News for <Crustaceans>");
+    assert!(ferris["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Hello <Crustaceans> team."));
+    assert!(ferris["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("<p>Hello &lt;Crustaceans&gt; team.</p>"));
+    assert!(to("crab@example.com")["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Hello  team."));
+}
+
+#[tokio::test]
+async fn numbers_from_the_subscribe_form_are_rendered_as_entered() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_attributes(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscribe("name=Ferris&email=ferris%40example.com&attr_seats=12")
+        .await
+        .error_for_status()
+        .unwrap();
+    let link = app.get_confirmation_link().await;
+    reqwest::get(link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.post_subscribe("name=Crab&email=crab%40example.com&attr_seats=2.5")
+        .await
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("UPDATE subscription SET status = 'confirmed'")
+        .execute(app.pool.get_ref())
+        .await
+        .unwrap();
+
+    let res = app
+        .post_publish(&json!({
+            "subject": "Seats",
+            "text_body": "You have {{ seats }} seats.",
+            "html_body": "<p>You have {{ seats }} seats.</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&res, "/admin/dashboard");
+    app.dispatch_all_pending_emails().await;
+
+    let bodies: Vec<Value> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| serde_json::from_slice(&r.body).unwrap())
+        .filter(|b: &Value| b["Subject"] == "Seats")
+        .collect();
+    let text = |email: &str| {
+        bodies.iter().find(|b| b["To"] == email).unwrap()["TextBody"]
+            .as_str()
+            .unwrap()
+            .to_owned()
+    };
+    assert!(text("ferris@example.com").starts_with("You have 12 seats."));
+    assert!(text("crab@example.com").starts_with("You have 2.5 seats."));
+}
@@ -1,6 +1,6 @@
 use uuid::Uuid;
 
-use crate::helper::{assert_is_redirect_to, spawn_app};
+use crate::helper::{assert_is_redirect_to, build_api_client, spawn_app};
 
 #[tokio::test]
 async fn changing_password_success() {
@@ -122,3 +122,87 @@ async fn current_password_must_be_valid() {
     let html_page = app.get_change_password_html().await;
     assert!(html_page.contains("<p><i>密码不正确.</i></p>"));
 }
+
+#[tokio::test]
+async fn changing_password_revokes_other_sessions() {
+    let app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // 1. 在两个浏览器中登录
+    app.test_user.login(&app).await;
+    let another_browser = build_api_client();
+    another_browser
+        .post(app.web_base_url.join("/login").unwrap())
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // 2. 修改密码
+    let res = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&res, "/login");
+
+    // 3. 另一个浏览器的会话已失效
+    let res = another_browser
+        .get(app.web_base_url.join("/admin/dashboard").unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&res, "/login");
+}
+
+#[tokio::test]
+async fn new_password_must_satisfy_policy() {
+    let app = spawn_app().await;
+
+    // 1. sign in
+    app.test_user.login(&app).await;
+
+    // 2. try to change password to a short, common one
+    let new_password = "password";
+    let res = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": new_password,
+            "new_password_check": new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&res, "/admin/password");
+
+    // 3. follow the redirect, every violation is listed
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("<p><i>密码长度不能少于12个字符.</i></p>"));
+    assert!(html_page.contains("<p><i>密码过于常见或已被泄露.</i></p>"));
+    assert!(html_page.contains("<p><i>密码强度不足"));
+}
+
+#[tokio::test]
+async fn new_password_must_differ_from_current() {
+    let app = spawn_app().await;
+
+    // 1. sign in
+    app.test_user.login(&app).await;
+
+    // 2. try to reuse the current password
+    let res = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &app.test_user.password,
+            "new_password_check": &app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&res, "/admin/password");
+
+    // 3. follow the redirect
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains("<p><i>新密码不能与当前密码相同.</i></p>"));
+}
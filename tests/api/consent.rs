@@ -0,0 +1,157 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn subscribe_from_form(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let res = app
+        .api_client
+        .post(app.web_base_url.join("/subscribe").unwrap())
+        .header("Origin", "https://blog.example")
+        .header("User-Agent", "subscribe-browser")
+        .form(&serde_json::json!({
+            "name": "IceFruit huang",
+            "email": "git@github.com",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_form_shows_consent_text() {
+    let app = spawn_app().await;
+
+    let html_page = app.get_subscribe_html().await;
+
+    assert!(html_page.contains("unsubscribe at any time"));
+}
+
+#[tokio::test]
+async fn consent_is_recorded_on_subscribe() {
+    let app = spawn_app().await;
+
+    subscribe_from_form(&app).await;
+
+    let record = sqlx::query!(
+        r#"
+        SELECT consent_text, form_origin, subscribe_user_agent, confirmed_at
+        FROM subscription_consent
+        "#
+    )
+    .fetch_one(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert!(record.consent_text.contains("unsubscribe at any time"));
+    assert_eq!(record.form_origin, "https://blog.example");
+    assert_eq!(record.subscribe_user_agent, "subscribe-browser");
+    assert!(record.confirmed_at.is_none());
+}
+
+#[tokio::test]
+async fn confirmation_is_recorded_on_confirm_click() {
+    let app = spawn_app().await;
+    subscribe_from_form(&app).await;
+    let confirm_link = app.get_confirmation_link().await;
+
+    for user_agent in ["mail-client", "another-mail-client"] {
+        let res = app
+            .api_client
+            .get(confirm_link.clone())
+            .header("User-Agent", user_agent)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(200, res.status().as_u16());
+    }
+
+    let record = sqlx::query!(
+        r#"
+        SELECT confirm_user_agent, confirmed_at, subscribed_at
+        FROM subscription_consent
+        "#
+    )
+    .fetch_one(app.pool.get_ref())
+    .await
+    .unwrap();
+    // 保留首次确认的记录
+    assert_eq!(record.confirm_user_agent.as_deref(), Some("mail-client"));
+    assert!(record.confirmed_at.unwrap() >= record.subscribed_at);
+}
+
+#[tokio::test]
+async fn consent_is_not_recorded_when_subscribe_fails() {
+    let app = spawn_app().await;
+
+    // sabotage the database
+    sqlx::query!("ALTER TABLE subscription_token DROP COLUMN subscription_token;")
+        .execute(app.pool.get_ref())
+        .await
+        .unwrap();
+    let res = app
+        .post_subscribe("name=IceFruit%20huang&email=git%40github.com")
+        .await;
+    assert_eq!(500, res.status().as_u16());
+
+    let count = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM subscription_consent"#)
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(count, 0);
+}
+
+#[tokio::test]
+async fn you_must_sign_in_to_see_consent_records() {
+    let app = spawn_app().await;
+
+    let res = app
+        .api_client
+        .get(app.web_base_url.join("/admin/consents").unwrap())
+        .send()
+        .await
+        .unwrap();
+
+    assert_is_redirect_to(&res, "/login");
+}
+
+#[tokio::test]
+async fn consent_records_can_be_viewed_by_email() {
+    let app = spawn_app().await;
+    subscribe_from_form(&app).await;
+    app.test_user.login(&app).await;
+
+    let get_html = |query: &'static str| {
+        let app = &app;
+        async move {
+            app.api_client
+                .get(
+                    app.web_base_url
+                        .join(&format!("/admin/consents?{query}"))
+                        .unwrap(),
+                )
+                .send()
+                .await
+                .unwrap()
+                .text()
+                .await
+                .unwrap()
+        }
+    };
+
+    let html_page = get_html("email=git%40github.com").await;
+    assert!(html_page.contains("<td>git@github.com</td>"));
+    assert!(html_page.contains("<td>https://blog.example</td>"));
+    assert!(html_page.contains("<td>(pending)</td>"));
+
+    let html_page = get_html("email=other%40github.com").await;
+    assert!(!html_page.contains("<td>git@github.com</td>"));
+}
@@ -3,14 +3,21 @@ use std::{net::TcpListener, time::Duration};
 use actix_web::web;
 use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
 use once_cell::sync::Lazy;
-use reqwest::{Response, Url};
+use reqwest::{
+    multipart::{Form, Part},
+    Response, Url,
+};
 use serde_json::Value;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use tutorial::{
-    config::Config, email_client::EmailCient, telemetry, try_execute_task, ExecutionOutcome,
+    config::Config, email_client::EmailCient, pii::PiiCipher, preferences::PreferenceLinks,
+    telemetry, try_execute_confirmation_task, try_execute_task, ExecutionOutcome,
 };
 use uuid::Uuid;
-use wiremock::MockServer;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
 
 static TRACING: Lazy<()> = Lazy::new(|| telemetry::init_subscriber("test"));
 
@@ -19,6 +26,8 @@ pub struct TestApp {
     pub pool: web::Data<PgPool>,
     pub email_server: MockServer,
     pub email_client: web::Data<EmailCient>,
+    pub pii_cipher: web::Data<PiiCipher>,
+    pub preference_links: PreferenceLinks,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
 }
@@ -34,6 +43,25 @@ impl TestApp {
             .unwrap()
     }
 
+    pub async fn get_subscribe_html(&self) -> String {
+        self.api_client
+            .get(self.web_base_url.join("/subscribe").unwrap())
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_subscription_challenge(&self) -> Response {
+        self.api_client
+            .get(self.web_base_url.join("/subscribe/challenge").unwrap())
+            .send()
+            .await
+            .unwrap()
+    }
+
     pub async fn post_login(&self, body: &Value) -> Response {
         self.api_client
             .post(self.web_base_url.join("/login").unwrap())
@@ -54,6 +82,32 @@ impl TestApp {
             .unwrap()
     }
 
+    pub async fn post_request_magic_link(&self, body: &Value) -> Response {
+        self.api_client
+            .post(self.web_base_url.join("/login/magic_link").unwrap())
+            .form(body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_oidc_login(&self) -> Response {
+        self.api_client
+            .get(self.web_base_url.join("/login/oidc").unwrap())
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_oidc_callback(&self, query: &[(&str, &str)]) -> Response {
+        self.api_client
+            .get(self.web_base_url.join("/login/oidc/callback").unwrap())
+            .query(query)
+            .send()
+            .await
+            .unwrap()
+    }
+
     pub async fn post_logout(&self) -> Response {
         self.api_client
             .post(self.web_base_url.join("/admin/logout").unwrap())
@@ -95,6 +149,386 @@ impl TestApp {
             .unwrap()
     }
 
+    pub async fn get_admin_email_html(&self) -> String {
+        self.api_client
+            .get(self.web_base_url.join("/admin/email").unwrap())
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_admin_email(&self, email: &str) -> Response {
+        self.api_client
+            .post(self.web_base_url.join("/admin/email").unwrap())
+            .form(&serde_json::json!({ "email": email }))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    /// 打开验证链接的确认页面并提交
+    pub async fn confirm_admin_email(&self, link: Url) -> Response {
+        let html = self
+            .api_client
+            .get(link.clone())
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+        assert!(html.contains(r#"action="/admin/email/confirm" method="post""#));
+        let token = link
+            .query_pairs()
+            .find(|(key, _)| key == "token")
+            .map(|(_, token)| token.into_owned())
+            .unwrap();
+        self.api_client
+            .post(self.web_base_url.join("/admin/email/confirm").unwrap())
+            .form(&[("token", token)])
+            .send()
+            .await
+            .unwrap()
+    }
+
+    /// 通过管理页面设置测试管理员的邮箱地址，完成后登出
+    pub async fn set_test_user_email(&self, email: &str) {
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&self.email_server)
+            .await;
+        self.test_user.login(self).await;
+        let res = self.post_admin_email(email).await;
+        assert_is_redirect_to(&res, "/admin/email");
+        let link = self.get_confirmation_link().await;
+        let res = self.confirm_admin_email(link).await;
+        assert_is_redirect_to(&res, "/admin/email");
+        self.post_logout().await;
+    }
+
+    pub async fn get_admin_sessions_html(&self) -> String {
+        self.api_client
+            .get(self.web_base_url.join("/admin/sessions").unwrap())
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_revoke_session(&self, body: &Value) -> Response {
+        self.api_client
+            .post(self.web_base_url.join("/admin/sessions/revoke").unwrap())
+            .form(body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_revoke_all_sessions(&self) -> Response {
+        self.api_client
+            .post(
+                self.web_base_url
+                    .join("/admin/sessions/revoke_all")
+                    .unwrap(),
+            )
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_audit(&self, query: &str) -> Response {
+        self.api_client
+            .get(
+                self.web_base_url
+                    .join(&format!("/admin/audit?{query}"))
+                    .unwrap(),
+            )
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_audit_export(&self, query: &str) -> Response {
+        self.api_client
+            .get(
+                self.web_base_url
+                    .join(&format!("/admin/audit/export?{query}"))
+                    .unwrap(),
+            )
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_subscriber_data_export(&self, email: &str) -> Response {
+        self.api_client
+            .post(
+                self.web_base_url
+                    .join("/admin/subscriber_data/export")
+                    .unwrap(),
+            )
+            .form(&[("email", email)])
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_erase_subscriber(&self, body: &Value) -> Response {
+        self.api_client
+            .post(
+                self.web_base_url
+                    .join("/admin/subscriber_data/erase")
+                    .unwrap(),
+            )
+            .form(&body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_subscribers(&self, query: &[(&str, &str)]) -> Response {
+        self.api_client
+            .get(self.web_base_url.join("/admin/subscribers").unwrap())
+            .query(query)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_export_subscribers(&self, query: &[(&str, &str)]) -> Response {
+        self.api_client
+            .get(self.web_base_url.join("/admin/subscribers/export").unwrap())
+            .query(query)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_subscriber(&self, subscriber_id: &Uuid) -> Response {
+        self.api_client
+            .get(
+                self.web_base_url
+                    .join(&format!("/admin/subscribers/{subscriber_id}"))
+                    .unwrap(),
+            )
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_subscriber_import_html(&self) -> String {
+        self.api_client
+            .get(self.web_base_url.join("/admin/subscribers/import").unwrap())
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_import_subscribers(&self, csv: &str, consent_attested: bool) -> Response {
+        let file = Part::text(csv.to_owned())
+            .file_name("subscribers.csv")
+            .mime_str("text/csv")
+            .unwrap();
+        let mut form = Form::new().part("file", file);
+        if consent_attested {
+            form = form.text("consent_attested", "yes");
+        }
+        self.api_client
+            .post(self.web_base_url.join("/admin/subscribers/import").unwrap())
+            .multipart(form)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_import_report(&self, import_id: &Uuid) -> Response {
+        self.api_client
+            .get(
+                self.web_base_url
+                    .join(&format!("/admin/subscribers/import/{import_id}/report"))
+                    .unwrap(),
+            )
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_manage_subscriber(
+        &self,
+        subscriber_id: &Uuid,
+        action: &str,
+        body: &Value,
+    ) -> Response {
+        self.api_client
+            .post(
+                self.web_base_url
+                    .join(&format!("/admin/subscribers/{subscriber_id}/{action}"))
+                    .unwrap(),
+            )
+            .form(body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_add_tags(&self, subscriber_id: &Uuid, body: &Value) -> Response {
+        self.api_client
+            .post(
+                self.web_base_url
+                    .join(&format!("/admin/subscribers/{subscriber_id}/tags"))
+                    .unwrap(),
+            )
+            .form(body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_remove_tag(&self, subscriber_id: &Uuid, body: &Value) -> Response {
+        self.api_client
+            .post(
+                self.web_base_url
+                    .join(&format!("/admin/subscribers/{subscriber_id}/tags/remove"))
+                    .unwrap(),
+            )
+            .form(body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_lists_html(&self) -> String {
+        self.api_client
+            .get(self.web_base_url.join("/admin/lists").unwrap())
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_list(&self, body: &Value) -> Response {
+        self.api_client
+            .post(self.web_base_url.join("/admin/lists").unwrap())
+            .form(body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_attributes_html(&self) -> String {
+        self.api_client
+            .get(self.web_base_url.join("/admin/attributes").unwrap())
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_attribute(&self, body: &Value) -> Response {
+        self.api_client
+            .post(self.web_base_url.join("/admin/attributes").unwrap())
+            .form(body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_subscriber_attributes(&self, subscriber_id: &Uuid, body: &Value) -> Response {
+        self.api_client
+            .post(
+                self.web_base_url
+                    .join(&format!("/admin/subscribers/{subscriber_id}/attributes"))
+                    .unwrap(),
+            )
+            .form(body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_preferences(&self, token: &str) -> Response {
+        self.api_client
+            .get(self.web_base_url.join("/preferences").unwrap())
+            .query(&[("token", token)])
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_preferences(&self, body: &Value) -> Response {
+        self.api_client
+            .post(self.web_base_url.join("/preferences").unwrap())
+            .form(body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_preference_lists(&self, body: &Value) -> Response {
+        self.api_client
+            .post(self.web_base_url.join("/preferences/lists").unwrap())
+            .form(body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_preferences_email(&self, body: &Value) -> Response {
+        self.api_client
+            .post(self.web_base_url.join("/preferences/email").unwrap())
+            .form(body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_segments_html(&self) -> String {
+        self.api_client
+            .get(self.web_base_url.join("/admin/segments").unwrap())
+            .send()
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_segment(&self, body: &Value) -> Response {
+        self.api_client
+            .post(self.web_base_url.join("/admin/segments").unwrap())
+            .form(body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_delete_segment(&self, segment_id: &Uuid) -> Response {
+        self.api_client
+            .post(
+                self.web_base_url
+                    .join(&format!("/admin/segments/{segment_id}/delete"))
+                    .unwrap(),
+            )
+            .send()
+            .await
+            .unwrap()
+    }
+
     pub async fn post_publish(&self, body: &Value) -> Response {
         self.api_client
             .post(self.web_base_url.join("/admin/publish").unwrap())
@@ -119,7 +553,7 @@ impl TestApp {
         self.post_publish(&issue).await
     }
 
-    /// 从发送订阅确认邮件的请求中提取确认链接
+    /// 从发送订阅确认邮件的请求中提取确认链接，忽略偏好设置链接
     pub async fn get_confirmation_link(&self) -> Url {
         let email_request = &self
             .email_server
@@ -133,6 +567,7 @@ impl TestApp {
             let links: Vec<_> = linkify::LinkFinder::new()
                 .links(s)
                 .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .filter(|l| !l.as_str().contains("/preferences?"))
                 .collect();
             assert_eq!(links.len(), 1);
             let raw_link = links[0].as_str().to_owned();
@@ -141,16 +576,53 @@ impl TestApp {
             confirmation_link
         };
 
-        let text_link = get_link(&body["TextBody"].as_str().unwrap());
-        let html_link = get_link(&body["HtmlBody"].as_str().unwrap());
+        let text_link = get_link(body["TextBody"].as_str().unwrap());
+        let html_link = get_link(body["HtmlBody"].as_str().unwrap());
         assert_eq!(text_link, html_link);
 
         text_link
     }
 
+    /// 等待邮件服务器收到指定数量的邮件，用于后台任务发送的邮件
+    pub async fn wait_for_emails(&self, count: usize) {
+        for _ in 0..100 {
+            if self.email_server.received_requests().await.unwrap().len() >= count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("expected {count} emails to be sent.");
+    }
+
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            match try_execute_task(&self.pool, &self.email_client).await {
+            match try_execute_task(
+                &self.pool,
+                &self.email_client,
+                &self.pii_cipher,
+                &self.preference_links,
+            )
+            .await
+            {
+                Ok(ExecutionOutcome::EmptyQueue) => break,
+                Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+                Ok(ExecutionOutcome::TaskCompleted) => {}
+            }
+        }
+    }
+
+    pub async fn dispatch_all_confirmation_emails(&self) {
+        let base_url = self.web_base_url.as_str().trim_end_matches('/');
+        loop {
+            match try_execute_confirmation_task(
+                &self.pool,
+                &self.email_client,
+                &self.pii_cipher,
+                &self.preference_links,
+                base_url,
+            )
+            .await
+            {
                 Ok(ExecutionOutcome::EmptyQueue) => break,
                 Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
                 Ok(ExecutionOutcome::TaskCompleted) => {}
@@ -160,7 +632,7 @@ impl TestApp {
 }
 
 pub struct TestUser {
-    user_id: Uuid,
+    pub user_id: Uuid,
     pub username: String,
     pub password: String,
 }
@@ -174,7 +646,7 @@ impl TestUser {
         .await
     }
 
-    fn generate() -> Self {
+    pub fn generate() -> Self {
         Self {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
@@ -182,10 +654,10 @@ impl TestUser {
         }
     }
 
-    async fn store(&self, pool: &PgPool) {
+    pub async fn store(&self, pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
         let password_hash = Argon2::default()
-            .hash_password(&self.password.as_bytes(), &salt)
+            .hash_password(self.password.as_bytes(), &salt)
             .unwrap()
             .to_string();
 
@@ -205,9 +677,17 @@ impl TestUser {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with_config(|_| {}).await
+}
+
+/// 启动测试应用，启动前可自定义配置
+pub async fn spawn_app_with_config(customise: impl FnOnce(&mut Config)) -> TestApp {
     Lazy::force(&TRACING);
 
     let mut config = tutorial::config::config();
+    // 所有测试共用同一redis及客户端IP，每个应用使用独立的限流计数
+    config.rate_limit.key_prefix = Some(format!("rate_limit:{}:", Uuid::new_v4()));
+    customise(&mut config);
 
     // 绑定随机端口
     let address = format!("{}:{}", &config.web.host, 0);
@@ -230,6 +710,8 @@ pub async fn spawn_app() -> TestApp {
 
     // 邮件客户端
     let email_client = web::Data::new(EmailCient::from_config(&config));
+    let pii_cipher = web::Data::new(PiiCipher::from_config(&config.pii_encryption).unwrap());
+    let preference_links = PreferenceLinks::from_config(&config);
 
     // 启动web工作线程
     tokio::spawn(
@@ -238,6 +720,7 @@ pub async fn spawn_app() -> TestApp {
             listener,
             pool.clone(),
             email_client.clone(),
+            pii_cipher.clone(),
         )
         .await
         .unwrap(),
@@ -245,12 +728,7 @@ pub async fn spawn_app() -> TestApp {
 
     let web_base_url = Url::parse(&web_base_url).unwrap();
     // API客户端模拟对web服务的调用
-    let api_client = reqwest::Client::builder()
-        // 设置不自动重定向
-        .redirect(reqwest::redirect::Policy::none())
-        .cookie_store(true)
-        .build()
-        .unwrap();
+    let api_client = build_api_client();
     // 测试管理员
     let test_user = TestUser::generate();
 
@@ -259,6 +737,8 @@ pub async fn spawn_app() -> TestApp {
         pool,
         email_server,
         email_client,
+        pii_cipher,
+        preference_links,
         test_user,
         api_client,
     };
@@ -269,6 +749,16 @@ pub async fn spawn_app() -> TestApp {
     app
 }
 
+/// 构建一个独立的API客户端，拥有自己的cookie存储，相当于一个新的浏览器
+pub fn build_api_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        // 设置不自动重定向
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap()
+}
+
 async fn connect_random_database(config: &mut Config) -> PgPool {
     // 连接postgres实例
     let mut connection = PgConnection::connect_with(&config.database.without_db())
@@ -0,0 +1,74 @@
+use actix_web::cookie::{Cookie, CookieJar, Key};
+use secrecy::SecretString;
+
+use crate::helper::{assert_is_redirect_to, spawn_app_with_config, TestApp};
+
+const OLD_SECRET: &str = "old-secret-old-secret-old-secret-old-secret-old-secret-old-secret";
+const NEW_SECRET: &str = "new-secret-new-secret-new-secret-new-secret-new-secret-new-secret";
+
+/// 登录后取出会话cookie，并使用旧密钥重新加密，模拟轮换密钥前签发的会话
+async fn session_cookie_sealed_with_old_secret(app: &TestApp) -> String {
+    let res = app.test_user.login(app).await;
+    assert_is_redirect_to(&res, "/admin/dashboard");
+    let session_cookie = res
+        .headers()
+        .get_all("Set-Cookie")
+        .iter()
+        .filter_map(|v| Cookie::parse_encoded(v.to_str().unwrap().to_owned()).ok())
+        .find(|c| c.name() == "id")
+        .unwrap();
+
+    let mut jar = CookieJar::new();
+    jar.add_original(session_cookie);
+    let session_key = jar
+        .private(&Key::from(NEW_SECRET.as_bytes()))
+        .get("id")
+        .unwrap()
+        .value()
+        .to_owned();
+    let mut jar = CookieJar::new();
+    jar.private_mut(&Key::from(OLD_SECRET.as_bytes()))
+        .add(Cookie::new("id", session_key));
+
+    jar.get("id").unwrap().encoded().stripped().to_string()
+}
+
+async fn get_dashboard_with_cookie(app: &TestApp, cookie: &str) -> reqwest::Response {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(app.web_base_url.join("/admin/dashboard").unwrap())
+        .header("Cookie", cookie)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn session_signed_with_previous_secret_stays_logged_in() {
+    let app = spawn_app_with_config(|config| {
+        config.web.hmac_secret = SecretString::from(NEW_SECRET);
+        config.web.previous_hmac_secrets = vec![SecretString::from(OLD_SECRET)];
+    })
+    .await;
+    let cookie = session_cookie_sealed_with_old_secret(&app).await;
+
+    let res = get_dashboard_with_cookie(&app, &cookie).await;
+
+    assert_eq!(200, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn session_signed_with_retired_secret_is_rejected() {
+    let app = spawn_app_with_config(|config| {
+        config.web.hmac_secret = SecretString::from(NEW_SECRET);
+        config.web.previous_hmac_secrets = vec![];
+    })
+    .await;
+    let cookie = session_cookie_sealed_with_old_secret(&app).await;
+
+    let res = get_dashboard_with_cookie(&app, &cookie).await;
+
+    assert_is_redirect_to(&res, "/login");
+}
@@ -1,3 +1,5 @@
+use argon2::{password_hash::SaltString, Algorithm, Argon2, Params, PasswordHasher, Version};
+
 use crate::helper::{assert_is_redirect_to, spawn_app};
 
 #[tokio::test]
@@ -37,3 +39,49 @@ async fn redirect_to_admin_dashboard_after_login_success() {
     let html_page = app.get_admin_dashboard_html().await;
     assert!(html_page.contains(&format!("Welcome {}!", &app.test_user.username)));
 }
+
+#[tokio::test]
+async fn weak_password_hash_is_upgraded_after_login() {
+    let app = spawn_app().await;
+
+    // 使用较弱的参数存储测试管理员的密码哈希
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let weak_password_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(15000, 1, 1, None).unwrap(),
+    )
+    .hash_password(app.test_user.password.as_bytes(), &salt)
+    .unwrap()
+    .to_string();
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE username = $2",
+        weak_password_hash,
+        &app.test_user.username,
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+
+    // 登录成功
+    let res = app.test_user.login(&app).await;
+    assert_is_redirect_to(&res, "/admin/dashboard");
+
+    // 密码哈希已使用配置中的参数重新计算
+    let record = sqlx::query!(
+        "SELECT password_hash FROM users WHERE username = $1",
+        &app.test_user.username,
+    )
+    .fetch_one(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert!(record
+        .password_hash
+        .unwrap()
+        .starts_with("$argon2id$v=19$m=19456,t=2,p=1$"));
+
+    // 仍可使用原密码登录
+    app.post_logout().await;
+    let res = app.test_user.login(&app).await;
+    assert_is_redirect_to(&res, "/admin/dashboard");
+}
@@ -0,0 +1,175 @@
+use reqwest::Url;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helper::{
+    assert_is_redirect_to, build_api_client, spawn_app, spawn_app_with_config, TestApp,
+};
+
+const ADMIN_EMAIL: &str = "admin@example.com";
+
+/// 请求登录链接并从邮件中取出链接
+async fn request_magic_link(app: &TestApp) -> Url {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let sent = app.email_server.received_requests().await.unwrap().len();
+
+    let res = app
+        .post_request_magic_link(&serde_json::json!({ "email": ADMIN_EMAIL }))
+        .await;
+    assert_is_redirect_to(&res, "/login");
+
+    // 邮件在后台任务中发送
+    app.wait_for_emails(sent + 1).await;
+    app.get_confirmation_link().await
+}
+
+/// 打开登录链接并提交确认页面中的表单
+async fn use_magic_link(app: &TestApp, client: &reqwest::Client, link: Url) -> reqwest::Response {
+    let html = client
+        .get(link.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains(r#"action="/login/magic_link/confirm" method="post""#));
+    let token = link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, token)| token.into_owned())
+        .unwrap();
+    client
+        .post(app.web_base_url.join("/login/magic_link/confirm").unwrap())
+        .form(&[("token", token)])
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn magic_link_logs_in_the_requesting_browser() {
+    let app = spawn_app().await;
+    app.set_test_user_email(ADMIN_EMAIL).await;
+
+    let link = request_magic_link(&app).await;
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("If the address belongs to an admin, a login link has been sent to it."));
+    let res = use_magic_link(&app, &app.api_client, link).await;
+
+    assert_is_redirect_to(&res, "/admin/dashboard");
+    assert!(app
+        .get_admin_dashboard_html()
+        .await
+        .contains(&app.test_user.username));
+    let actions = sqlx::query_scalar!(
+        "SELECT action FROM audit_log WHERE actor_user_id = $1 ORDER BY created_at",
+        app.test_user.user_id,
+    )
+    .fetch_all(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_eq!(actions, ["login", "change_email", "logout", "login"]);
+}
+
+#[tokio::test]
+async fn magic_link_can_only_be_used_once() {
+    let app = spawn_app().await;
+    app.set_test_user_email(ADMIN_EMAIL).await;
+    let link = request_magic_link(&app).await;
+    let res = use_magic_link(&app, &app.api_client, link.clone()).await;
+    assert_is_redirect_to(&res, "/admin/dashboard");
+    app.post_logout().await;
+
+    let res = use_magic_link(&app, &app.api_client, link).await;
+
+    assert_is_redirect_to(&res, "/login");
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn forwarded_magic_link_cannot_be_used_in_another_browser() {
+    let app = spawn_app().await;
+    app.set_test_user_email(ADMIN_EMAIL).await;
+    let link = request_magic_link(&app).await;
+
+    let other_browser = build_api_client();
+    let res = use_magic_link(&app, &other_browser, link.clone()).await;
+    assert_is_redirect_to(&res, "/login");
+    let res = other_browser
+        .get(app.web_base_url.join("/admin/dashboard").unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&res, "/login");
+
+    // 其他浏览器的尝试不会使链接失效，请求链接的浏览器仍可登录
+    let res = use_magic_link(&app, &app.api_client, link).await;
+    assert_is_redirect_to(&res, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn opening_the_magic_link_does_not_consume_it() {
+    let app = spawn_app().await;
+    app.set_test_user_email(ADMIN_EMAIL).await;
+    let link = request_magic_link(&app).await;
+
+    // 邮件客户端预取链接时只会打开确认页面
+    build_api_client().get(link.clone()).send().await.unwrap();
+    app.api_client.get(link.clone()).send().await.unwrap();
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+
+    let res = use_magic_link(&app, &app.api_client, link).await;
+    assert_is_redirect_to(&res, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn expired_magic_link_is_rejected() {
+    let app = spawn_app_with_config(|config| config.web.magic_link_ttl_seconds = 0).await;
+    app.set_test_user_email(ADMIN_EMAIL).await;
+    let link = request_magic_link(&app).await;
+
+    let res = use_magic_link(&app, &app.api_client, link).await;
+
+    assert_is_redirect_to(&res, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Authentication failed."));
+}
+
+#[tokio::test]
+async fn unknown_email_gets_the_same_response_without_an_email() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let res = app
+        .post_request_magic_link(&serde_json::json!({ "email": "nobody@example.com" }))
+        .await;
+
+    assert_is_redirect_to(&res, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("If the address belongs to an admin, a login link has been sent to it."));
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    let tokens = sqlx::query_scalar!("SELECT count(*) FROM login_token")
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(tokens, Some(0));
+}
@@ -0,0 +1,276 @@
+use serde_json::json;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// 新建"Rust Weekly"列表，使用独立的发件人及确认邮件文案
+async fn create_weekly_list(app: &TestApp) {
+    let res = app
+        .post_create_list(&json!({
+            "slug": "rust-weekly",
+            "name": "Rust Weekly",
+            "sender": "weekly@example.com",
+            "confirmation_subject": "Confirm your Rust Weekly subscription",
+            "confirmation_text": "Thanks for joining <Rust Weekly>!",
+        }))
+        .await;
+    assert_is_redirect_to(&res, "/admin/lists");
+}
+
+/// 订阅并点击确认链接
+async fn subscribe_and_confirm(app: &TestApp, body: &str) {
+    app.post_subscribe(body).await.error_for_status().unwrap();
+    let link = app.get_confirmation_link().await;
+    reqwest::get(link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn you_must_login_to_manage_lists() {
+    let app = spawn_app().await;
+
+    let res = app
+        .api_client
+        .get(app.web_base_url.join("/admin/lists").unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&res, "/login");
+}
+
+#[tokio::test]
+async fn new_lists_are_listed_and_audited() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    create_weekly_list(&app).await;
+
+    let html = app.get_lists_html().await;
+    assert!(html.contains("邮件列表已创建."));
+    assert!(html.contains("<td>Rust Weekly</td>"));
+    assert!(html.contains("<td>weekly@example.com</td>"));
+    let actions = sqlx::query_scalar!("SELECT action FROM audit_log WHERE action = 'create_list'")
+        .fetch_all(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(actions.len(), 1);
+
+    // 标识不能重复
+    create_weekly_list(&app).await;
+    assert!(app.get_lists_html().await.contains("列表标识已存在."));
+}
+
+#[tokio::test]
+async fn invalid_lists_are_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let res = app
+        .post_create_list(&json!({
+            "slug": "rust weekly",
+            "name": "Rust Weekly",
+            "sender": "",
+            "confirmation_subject": "Welcome",
+            "confirmation_text": "Welcome",
+        }))
+        .await;
+    assert_is_redirect_to(&res, "/admin/lists");
+    assert!(app.get_lists_html().await.contains("邮件列表无效"));
+
+    let count = sqlx::query_scalar!("SELECT count(*) FROM mailing_list")
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(count, Some(1));
+}
+
+#[tokio::test]
+async fn subscribe_form_shows_the_requested_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_weekly_list(&app).await;
+
+    let get = |list: &'static str| {
+        app.api_client
+            .get(app.web_base_url.join("/subscribe").unwrap())
+            .query(&[("list", list)])
+            .send()
+    };
+    let html = get("rust-weekly").await.unwrap().text().await.unwrap();
+    assert!(html.contains("<h1>Rust Weekly</h1>"));
+    assert!(html.contains(r#"name="list" value="rust-weekly""#));
+
+    assert_eq!(get("unknown").await.unwrap().status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn confirmation_email_uses_the_list_sender_and_wording() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_weekly_list(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscribe("name=Ferris&email=ferris%40example.com&list=rust-weekly")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(body["From"], "weekly@example.com");
+    assert_eq!(body["Subject"], "Confirm your Rust Weekly subscription");
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Thanks for joining <Rust Weekly>!"));
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Thanks for joining &lt;Rust Weekly&gt;!"));
+    app.get_confirmation_link().await;
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    let app = spawn_app().await;
+
+    let res = app
+        .post_subscribe("name=Ferris&email=ferris%40example.com&list=unknown")
+        .await;
+
+    assert_eq!(res.status().as_u16(), 400);
+    let count = sqlx::query_scalar!("SELECT count(*) FROM subscription")
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(count, Some(0));
+}
+
+#[tokio::test]
+async fn the_same_email_can_subscribe_to_several_lists() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_weekly_list(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    subscribe_and_confirm(&app, "name=Ferris&email=ferris%40example.com").await;
+    subscribe_and_confirm(
+        &app,
+        "name=Ferris&email=ferris%40example.com&list=rust-weekly",
+    )
+    .await;
+
+    let lists = sqlx::query_scalar!(
+        r#"
+        SELECT l.slug FROM subscription s
+        JOIN mailing_list l ON l.list_id = s.list_id
+        WHERE s.status = 'confirmed'
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_eq!(lists, ["default", "rust-weekly"]);
+}
+
+#[tokio::test]
+async fn issues_are_delivered_only_to_subscribers_of_their_list() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_weekly_list(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    subscribe_and_confirm(&app, "name=Default&email=default%40example.com").await;
+    subscribe_and_confirm(
+        &app,
+        "name=Ferris&email=ferris%40example.com&list=rust-weekly",
+    )
+    .await;
+    let list_id =
+        sqlx::query_scalar!("SELECT list_id FROM mailing_list WHERE slug = 'rust-weekly'")
+            .fetch_one(app.pool.get_ref())
+            .await
+            .unwrap();
+    let sent_before = app.email_server.received_requests().await.unwrap().len();
+
+    let res = app
+        .post_publish(&json!({
+            "subject": "This week in Rust",
+            "text_body": "Text",
+            "html_body": "<p>Html</p>",
+            "list_id": list_id.to_string(),
+            "segment_id": "",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&res, "/admin/dashboard");
+    app.dispatch_all_pending_emails().await;
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let issues: Vec<serde_json::Value> = requests[sent_before..]
+        .iter()
+        .map(|r| serde_json::from_slice(&r.body).unwrap())
+        .collect();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0]["To"], "ferris@example.com");
+    assert_eq!(issues[0]["From"], "weekly@example.com");
+}
+
+#[tokio::test]
+async fn admin_subscriber_list_can_switch_between_lists() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    create_weekly_list(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscribe("name=Default&email=default%40example.com")
+        .await
+        .error_for_status()
+        .unwrap();
+    app.post_subscribe("name=Ferris&email=ferris%40example.com&list=rust-weekly")
+        .await
+        .error_for_status()
+        .unwrap();
+    let list_id =
+        sqlx::query_scalar!("SELECT list_id FROM mailing_list WHERE slug = 'rust-weekly'")
+            .fetch_one(app.pool.get_ref())
+            .await
+            .unwrap()
+            .to_string();
+
+    let html = app
+        .get_admin_subscribers(&[("list", &list_id)])
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("ferris@example.com"));
+    assert!(!html.contains("default@example.com"));
+
+    let html = app.get_admin_subscribers(&[]).await.text().await.unwrap();
+    assert!(html.contains("ferris@example.com"));
+    assert!(html.contains("default@example.com"));
+}
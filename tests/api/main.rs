@@ -1,9 +1,29 @@
 mod helper;
 
+mod admin_audit;
 mod admin_dashboard;
+mod admin_email;
+mod admin_sessions;
+mod admin_subscribers;
+mod attributes;
 mod change_password;
+mod consent;
 mod health_check;
+mod hmac_rotation;
 mod login;
+mod magic_link;
+mod mailing_lists;
 mod newsletter;
+mod oidc;
+mod pending_retention;
+mod pii_encryption;
+mod preferences;
+mod security_headers;
+mod segments;
+mod session_timeout;
+mod subscriber_data;
+mod subscriber_import;
 mod subscription;
 mod subscription_confirm;
+mod subscription_guard;
+mod subscription_pow;
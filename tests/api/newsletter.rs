@@ -163,7 +163,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> reqwest::Url {
 
     let name: String = Name().fake();
     let email: String = SafeEmail().fake();
-    let body = serde_urlencoded::to_string(&serde_json::json!({
+    let body = serde_urlencoded::to_string(serde_json::json!({
         "name": name,
         "email": email,
     }))
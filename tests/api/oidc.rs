@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use reqwest::Url;
+use secrecy::SecretString;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use sqlx::types::chrono::Utc;
+use tutorial::config::OidcConfig;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::helper::{assert_is_redirect_to, spawn_app, spawn_app_with_config, TestApp};
+
+const CLIENT_ID: &str = "tutorial";
+
+/// 模拟身份提供方
+struct MockIdp {
+    server: MockServer,
+}
+
+impl MockIdp {
+    async fn start() -> Self {
+        let idp = Self {
+            server: MockServer::start().await,
+        };
+        idp.mount_discovery().await;
+
+        idp
+    }
+
+    async fn mount_discovery(&self) {
+        Mock::given(method("GET"))
+            .and(path("/.well-known/openid-configuration"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "issuer": self.server.uri(),
+                "authorization_endpoint": format!("{}/authorize", self.server.uri()),
+                "token_endpoint": format!("{}/token", self.server.uri()),
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    /// 清除已签发的ID Token及收到的请求
+    async fn reset(&self) {
+        self.server.reset().await;
+        self.mount_discovery().await;
+    }
+
+    fn config(&self, auto_create_users: bool, disable_password_login: bool) -> OidcConfig {
+        OidcConfig {
+            issuer_url: self.server.uri(),
+            client_id: CLIENT_ID.into(),
+            client_secret: SecretString::from("client-secret"),
+            auto_create_users,
+            disable_password_login,
+        }
+    }
+
+    /// token端点返回包含指定claims的ID Token
+    async fn issue_id_token(&self, claims: Value) {
+        let id_token = format!(
+            "{}.{}.signature",
+            URL_SAFE_NO_PAD.encode(r#"{"alg":"RS256"}"#),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "access_token": "access-token",
+                "token_type": "Bearer",
+                "id_token": id_token,
+            })))
+            .mount(&self.server)
+            .await;
+    }
+
+    fn claims(&self, authorize: &HashMap<String, String>, sub: &str, email: &str) -> Value {
+        json!({
+            "iss": self.server.uri(),
+            "aud": CLIENT_ID,
+            "exp": Utc::now().timestamp() + 300,
+            "nonce": authorize["nonce"],
+            "sub": sub,
+            "email": email,
+            "email_verified": true,
+        })
+    }
+
+    async fn token_request(&self) -> HashMap<String, String> {
+        let requests = self.server.received_requests().await.unwrap();
+        let request = requests.iter().find(|r| r.url.path() == "/token").unwrap();
+        serde_urlencoded::from_bytes(&request.body).unwrap()
+    }
+}
+
+async fn spawn_app_with_idp(
+    auto_create_users: bool,
+    disable_password_login: bool,
+) -> (TestApp, MockIdp) {
+    let idp = MockIdp::start().await;
+    let oidc = idp.config(auto_create_users, disable_password_login);
+    let app = spawn_app_with_config(|config| config.oidc = Some(oidc)).await;
+
+    (app, idp)
+}
+
+/// 发起单点登录，返回授权请求的参数
+async fn start_oidc_login(app: &TestApp, idp: &MockIdp) -> HashMap<String, String> {
+    let res = app.get_oidc_login().await;
+    assert_eq!(303, res.status().as_u16());
+    let location = Url::parse(res.headers()["Location"].to_str().unwrap()).unwrap();
+    assert!(location
+        .as_str()
+        .starts_with(&format!("{}/authorize?", idp.server.uri())));
+
+    location.query_pairs().into_owned().collect()
+}
+
+async fn link_test_user_email(app: &TestApp, email: &str) {
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE user_id = $2",
+        email,
+        app.test_user.user_id,
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn oidc_login_is_not_found_when_not_configured() {
+    let app = spawn_app().await;
+
+    assert_eq!(404, app.get_oidc_login().await.status().as_u16());
+    assert!(!app.get_login_html().await.contains("/login/oidc"));
+}
+
+#[tokio::test]
+async fn oidc_login_redirects_to_idp_with_pkce() {
+    let (app, idp) = spawn_app_with_idp(false, false).await;
+
+    assert!(app.get_login_html().await.contains(r#"href="/login/oidc""#));
+    let authorize = start_oidc_login(&app, &idp).await;
+
+    assert_eq!(authorize["response_type"], "code");
+    assert_eq!(authorize["client_id"], CLIENT_ID);
+    assert_eq!(
+        authorize["redirect_uri"],
+        format!("{}login/oidc/callback", app.web_base_url)
+    );
+    assert!(authorize["scope"].split(' ').any(|s| s == "openid"));
+    assert_eq!(authorize["code_challenge_method"], "S256");
+    assert!(!authorize["code_challenge"].is_empty());
+    assert!(!authorize["state"].is_empty());
+    assert!(!authorize["nonce"].is_empty());
+}
+
+#[tokio::test]
+async fn verified_email_is_linked_to_existing_admin() {
+    let (app, idp) = spawn_app_with_idp(false, false).await;
+    link_test_user_email(&app, "admin@example.com").await;
+    let authorize = start_oidc_login(&app, &idp).await;
+    idp.issue_id_token(idp.claims(&authorize, "subject-1", "admin@example.com"))
+        .await;
+
+    let res = app
+        .get_oidc_callback(&[("code", "auth-code"), ("state", &authorize["state"])])
+        .await;
+
+    assert_is_redirect_to(&res, "/admin/dashboard");
+    assert!(app
+        .get_admin_dashboard_html()
+        .await
+        .contains(&app.test_user.username));
+    // token请求携带了与code_challenge匹配的code_verifier
+    let token_request = idp.token_request().await;
+    assert_eq!(token_request["code"], "auth-code");
+    assert_eq!(
+        URL_SAFE_NO_PAD.encode(Sha256::digest(token_request["code_verifier"].as_bytes())),
+        authorize["code_challenge"]
+    );
+    let subject = sqlx::query_scalar!(
+        "SELECT oidc_subject FROM users WHERE user_id = $1",
+        app.test_user.user_id,
+    )
+    .fetch_one(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_eq!(subject.as_deref(), Some("subject-1"));
+    let actions = sqlx::query_scalar!(
+        "SELECT action FROM audit_log WHERE actor_user_id = $1",
+        app.test_user.user_id,
+    )
+    .fetch_all(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_eq!(actions, vec!["login"]);
+}
+
+#[tokio::test]
+async fn unknown_identity_is_rejected_without_auto_create() {
+    let (app, idp) = spawn_app_with_idp(false, false).await;
+    let authorize = start_oidc_login(&app, &idp).await;
+    idp.issue_id_token(idp.claims(&authorize, "subject-1", "new@example.com"))
+        .await;
+
+    let res = app
+        .get_oidc_callback(&[("code", "auth-code"), ("state", &authorize["state"])])
+        .await;
+
+    assert_is_redirect_to(&res, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Authentication failed."));
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn unknown_identity_is_created_with_auto_create() {
+    let (app, idp) = spawn_app_with_idp(true, false).await;
+    let authorize = start_oidc_login(&app, &idp).await;
+    idp.issue_id_token(idp.claims(&authorize, "subject-1", "new@example.com"))
+        .await;
+
+    let res = app
+        .get_oidc_callback(&[("code", "auth-code"), ("state", &authorize["state"])])
+        .await;
+
+    assert_is_redirect_to(&res, "/admin/dashboard");
+    let user =
+        sqlx::query!("SELECT username, password_hash FROM users WHERE oidc_subject = 'subject-1'")
+            .fetch_one(app.pool.get_ref())
+            .await
+            .unwrap();
+    assert_eq!(user.username, "new@example.com");
+    assert!(user.password_hash.is_none());
+
+    // 再次登录按subject匹配，不会重复创建
+    app.post_logout().await;
+    idp.reset().await;
+    let authorize = start_oidc_login(&app, &idp).await;
+    idp.issue_id_token(idp.claims(&authorize, "subject-1", "changed@example.com"))
+        .await;
+    let res = app
+        .get_oidc_callback(&[("code", "auth-code"), ("state", &authorize["state"])])
+        .await;
+    assert_is_redirect_to(&res, "/admin/dashboard");
+    let count = sqlx::query_scalar!("SELECT count(*) FROM users WHERE oidc_subject IS NOT NULL")
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(count, Some(1));
+}
+
+#[tokio::test]
+async fn callback_with_mismatched_state_is_rejected() {
+    let (app, idp) = spawn_app_with_idp(true, false).await;
+    let authorize = start_oidc_login(&app, &idp).await;
+    idp.issue_id_token(idp.claims(&authorize, "subject-1", "new@example.com"))
+        .await;
+
+    let res = app
+        .get_oidc_callback(&[("code", "auth-code"), ("state", "forged-state")])
+        .await;
+
+    assert_is_redirect_to(&res, "/login");
+    // 授权码未被使用
+    assert!(idp
+        .server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .all(|r| r.url.path() != "/token"));
+    // 授权请求只能使用一次
+    let res = app
+        .get_oidc_callback(&[("code", "auth-code"), ("state", &authorize["state"])])
+        .await;
+    assert_is_redirect_to(&res, "/login");
+}
+
+#[tokio::test]
+async fn id_token_with_mismatched_nonce_is_rejected() {
+    let (app, idp) = spawn_app_with_idp(true, false).await;
+    let authorize = start_oidc_login(&app, &idp).await;
+    let mut claims = idp.claims(&authorize, "subject-1", "new@example.com");
+    claims["nonce"] = json!("replayed-nonce");
+    idp.issue_id_token(claims).await;
+
+    let res = app
+        .get_oidc_callback(&[("code", "auth-code"), ("state", &authorize["state"])])
+        .await;
+
+    assert_is_redirect_to(&res, "/login");
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn password_login_can_be_disabled() {
+    let (app, _idp) = spawn_app_with_idp(false, true).await;
+
+    let html = app.get_login_html().await;
+    assert!(!html.contains(r#"name="password""#));
+    assert!(html.contains(r#"href="/login/oidc""#));
+
+    let res = app.test_user.login(&app).await;
+    assert_is_redirect_to(&res, "/login");
+    assert!(app
+        .get_login_html()
+        .await
+        .contains("Password login is disabled, please use single sign-on."));
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+}
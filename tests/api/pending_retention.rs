@@ -0,0 +1,218 @@
+use tutorial::{
+    config::PendingRetentionConfig,
+    pending_retention::{run_once, RetentionReport},
+};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helper::{spawn_app, TestApp};
+
+fn retention_config() -> PendingRetentionConfig {
+    PendingRetentionConfig {
+        reminder_after_hours: 24,
+        purge_after_hours: 168,
+        interval_seconds: 3600,
+    }
+}
+
+/// 订阅并返回确认链接
+async fn subscribe(app: &TestApp, email: &str) -> reqwest::Url {
+    app.post_subscribe(&format!("name=Reader&email={}", email.replace('@', "%40")))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.get_confirmation_link().await
+}
+
+/// 将订阅时间提前指定的小时数
+async fn backdate_subscription(app: &TestApp, email: &str, hours: i32) {
+    sqlx::query!(
+        r#"
+        UPDATE subscription
+        SET subscribed_at = subscribed_at - make_interval(hours => $2)
+        WHERE email_index = $1
+        "#,
+        app.pii_cipher.blind_index(email),
+        hours,
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+}
+
+async fn subscriber_count(app: &TestApp, email: &str) -> i64 {
+    sqlx::query_scalar!(
+        r#"SELECT count(*) AS "count!" FROM subscription WHERE email_index = $1"#,
+        app.pii_cipher.blind_index(email),
+    )
+    .fetch_one(app.pool.get_ref())
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn unconfirmed_subscribers_are_reminded_once_then_purged() {
+    let app = spawn_app().await;
+    let config = retention_config();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let pending_link = subscribe(&app, "pending@example.com").await;
+    let confirmed_link = subscribe(&app, "confirmed@example.com").await;
+    reqwest::get(confirmed_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    subscribe(&app, "recent@example.com").await;
+    backdate_subscription(&app, "pending@example.com", 48).await;
+    backdate_subscription(&app, "confirmed@example.com", 48).await;
+
+    // 只提醒超过24小时仍未确认的订阅者，提醒使用原确认链接
+    let report = run_once(app.pool.get_ref(), &config).await.unwrap();
+    assert_eq!(
+        report,
+        RetentionReport {
+            reminders_sent: 1,
+            purged: 0
+        }
+    );
+    let sent_before = app.email_server.received_requests().await.unwrap().len();
+    app.dispatch_all_confirmation_emails().await;
+    assert_eq!(
+        app.email_server.received_requests().await.unwrap().len(),
+        sent_before + 1
+    );
+    assert_eq!(app.get_confirmation_link().await, pending_link);
+    let reminder = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let reminder: serde_json::Value = serde_json::from_slice(&reminder.body).unwrap();
+    assert!(reminder["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Manage your subscription: http://127.0.0.1"));
+
+    // 每个订阅者只提醒一次，提醒后7天内不会删除
+    let report = run_once(app.pool.get_ref(), &config).await.unwrap();
+    assert_eq!(report, RetentionReport::default());
+
+    sqlx::query!(
+        r#"
+        UPDATE subscription
+        SET confirmation_reminder_sent_at = confirmation_reminder_sent_at - interval '8 days'
+        "#
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+    let report = run_once(app.pool.get_ref(), &config).await.unwrap();
+    assert_eq!(
+        report,
+        RetentionReport {
+            reminders_sent: 0,
+            purged: 1
+        }
+    );
+    assert_eq!(subscriber_count(&app, "pending@example.com").await, 0);
+    assert_eq!(subscriber_count(&app, "confirmed@example.com").await, 1);
+    assert_eq!(subscriber_count(&app, "recent@example.com").await, 1);
+    let tokens = sqlx::query_scalar!("SELECT count(*) FROM subscription_token")
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(tokens, Some(2));
+
+    // 删除后原确认链接失效
+    let res = reqwest::get(pending_link).await.unwrap();
+    assert_eq!(401, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn only_subscribers_whose_reminder_was_queued_are_marked() {
+    let app = spawn_app().await;
+    let config = retention_config();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    subscribe(&app, "queued@example.com").await;
+    subscribe(&app, "tokenless@example.com").await;
+    backdate_subscription(&app, "queued@example.com", 48).await;
+    backdate_subscription(&app, "tokenless@example.com", 48).await;
+    // 一个的确认邮件仍在队列中，另一个没有确认令牌
+    let id = |email: &str| {
+        sqlx::query_scalar!(
+            "SELECT id FROM subscription WHERE email_index = $1",
+            app.pii_cipher.blind_index(email),
+        )
+        .fetch_one(app.pool.get_ref())
+    };
+    let queued = id("queued@example.com").await.unwrap();
+    let tokenless = id("tokenless@example.com").await.unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO confirmation_email_queue (subscriber_id, subscription_token, enqueued_at)
+        SELECT subscriber_id, subscription_token, now() FROM subscription_token
+        WHERE subscriber_id = $1
+        "#,
+        queued,
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+    sqlx::query!(
+        "DELETE FROM subscription_token WHERE subscriber_id = $1",
+        tokenless
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+
+    let report = run_once(app.pool.get_ref(), &config).await.unwrap();
+    assert_eq!(report, RetentionReport::default());
+    let marked = sqlx::query_scalar!(
+        "SELECT count(*) FROM subscription WHERE confirmation_reminder_sent_at IS NOT NULL"
+    )
+    .fetch_one(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_eq!(marked, Some(0));
+
+    // 队列中的确认邮件发出后，下一次运行再提醒
+    app.dispatch_all_confirmation_emails().await;
+    let report = run_once(app.pool.get_ref(), &config).await.unwrap();
+    assert_eq!(
+        report,
+        RetentionReport {
+            reminders_sent: 1,
+            purged: 0
+        }
+    );
+}
+
+#[tokio::test]
+async fn retention_runs_are_shown_on_the_dashboard() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    assert!(app
+        .get_admin_dashboard_html()
+        .await
+        .contains("(not run yet)"));
+
+    run_once(app.pool.get_ref(), &retention_config())
+        .await
+        .unwrap();
+
+    let html = app.get_admin_dashboard_html().await;
+    assert!(html.contains("0 reminders sent, 0 purged"));
+}
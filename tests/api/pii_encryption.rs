@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use secrecy::SecretString;
+use tutorial::pii::{rotate_all_keys, PiiCipher};
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helper::{spawn_app, TestApp};
+
+async fn subscribe(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscribe("name=IceFruit%20huang&email=git%40github.com")
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+async fn rotate_all(app: &TestApp, cipher: &PiiCipher) {
+    rotate_all_keys(&app.pool, cipher, 1).await.unwrap();
+}
+
+#[tokio::test]
+async fn subscriber_pii_is_not_stored_in_plaintext() {
+    let app = spawn_app().await;
+
+    subscribe(&app).await;
+
+    let row = sqlx::query_scalar!(r#"SELECT row_to_json(s)::text AS "row!" FROM subscription s"#)
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert!(!row.contains("github"));
+    assert!(!row.contains("IceFruit"));
+}
+
+#[tokio::test]
+async fn email_uniqueness_is_enforced_on_encrypted_rows() {
+    let app = spawn_app().await;
+    subscribe(&app).await;
+
+    app.post_subscribe("name=Someone%20else&email=git%40github.com")
+        .await;
+
+    let count = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM subscription"#)
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn legacy_plaintext_subscribers_are_encrypted_by_rotation() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription (id, list_id, name, email, subscribed_at, status)
+        SELECT $1, list_id, 'IceFruit huang', 'git@github.com', now(), 'confirmed'
+        FROM mailing_list WHERE slug = 'default'
+        "#,
+        Uuid::new_v4(),
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+
+    rotate_all(&app, &app.pii_cipher).await;
+
+    let row = sqlx::query!(
+        r#"
+        SELECT name, email, email_ciphertext AS "email_ciphertext!", email_index, pii_key_id
+        FROM subscription
+        "#
+    )
+    .fetch_one(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert!(row.name.is_none());
+    assert!(row.email.is_none());
+    assert_eq!(
+        app.pii_cipher.decrypt(&row.email_ciphertext).unwrap(),
+        "git@github.com"
+    );
+    assert_eq!(
+        row.email_index.unwrap(),
+        app.pii_cipher.blind_index("git@github.com")
+    );
+    assert_eq!(row.pii_key_id.unwrap(), app.pii_cipher.active_key_id());
+
+    // 加密后的订阅者能正常收到邮件简报
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.test_user.login(&app).await;
+    app.post_publish_with_default_issue(None).await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn rotation_re_encrypts_rows_with_the_new_key() {
+    let app = spawn_app().await;
+    subscribe(&app).await;
+
+    let mut config = tutorial::config::config().pii_encryption;
+    config.keys.insert(
+        "next".into(),
+        SecretString::from("1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100"),
+    );
+    config.active_key_id = "next".into();
+    let rotated = PiiCipher::from_config(&config).unwrap();
+
+    rotate_all(&app, &rotated).await;
+
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            name_ciphertext AS "name_ciphertext!",
+            email_ciphertext AS "email_ciphertext!",
+            email_index AS "email_index!",
+            pii_key_id AS "pii_key_id!"
+        FROM subscription
+        "#
+    )
+    .fetch_one(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_eq!(row.pii_key_id, "next");
+    assert!(row.email_ciphertext.starts_with("next:"));
+    assert_eq!(
+        rotated.decrypt(&row.name_ciphertext).unwrap(),
+        "IceFruit huang"
+    );
+    // 旧密钥移除后仍可解密，盲索引不受影响
+    let next_only = PiiCipher::from_config(&tutorial::config::PiiEncryptionConfig {
+        keys: HashMap::from([("next".into(), config.keys["next"].clone())]),
+        ..config
+    })
+    .unwrap();
+    assert_eq!(
+        next_only.decrypt(&row.email_ciphertext).unwrap(),
+        "git@github.com"
+    );
+    assert_eq!(
+        row.email_index,
+        app.pii_cipher.blind_index("git@github.com")
+    );
+}
+
+#[tokio::test]
+async fn email_uniqueness_ignores_case() {
+    let app = spawn_app().await;
+    subscribe(&app).await;
+
+    app.post_subscribe("name=Someone%20else&email=%20Git%40GitHub.COM")
+        .await;
+
+    let count = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM subscription"#)
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn subscriber_email_domain_is_normalised() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscribe("name=Ferris&email=Ferris%40B%C3%BCcher.Example")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email = sqlx::query_scalar!(r#"SELECT email_ciphertext AS "email!" FROM subscription"#)
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(
+        app.pii_cipher.decrypt(&email).unwrap(),
+        "Ferris@xn--bcher-kva.example"
+    );
+}
+
+#[tokio::test]
+async fn rotation_merges_subscriptions_that_differ_only_in_case() {
+    let app = spawn_app().await;
+    let (pending_id, confirmed_id) = (Uuid::new_v4(), Uuid::new_v4());
+    for (id, email, status, subscribed_at) in [
+        (
+            pending_id,
+            "Git@GitHub.com",
+            "pending_confirmation",
+            "2024-01-01",
+        ),
+        (confirmed_id, "git@github.com", "confirmed", "2024-06-01"),
+    ] {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscription (id, list_id, name, email, subscribed_at, status)
+            SELECT $1, list_id, 'IceFruit huang', $2, $3::text::timestamptz, $4
+            FROM mailing_list WHERE slug = 'default'
+            "#,
+            id,
+            email,
+            subscribed_at,
+            status,
+        )
+        .execute(app.pool.get_ref())
+        .await
+        .unwrap();
+    }
+    sqlx::query!(
+        "INSERT INTO subscription_tag (subscriber_id, tag) VALUES ($1, 'vip')",
+        pending_id,
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+
+    rotate_all(&app, &app.pii_cipher).await;
+
+    // 保留已确认的订阅，标签合并到保留的订阅
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, status, email_ciphertext AS "email_ciphertext!", email_index_scheme
+        FROM subscription
+        "#
+    )
+    .fetch_all(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].id, confirmed_id);
+    assert_eq!(rows[0].status, "confirmed");
+    assert_eq!(
+        app.pii_cipher.decrypt(&rows[0].email_ciphertext).unwrap(),
+        "git@github.com"
+    );
+    assert_eq!(rows[0].email_index_scheme.as_deref(), Some("canonical"));
+    let tags = sqlx::query_scalar!(
+        "SELECT tag FROM subscription_tag WHERE subscriber_id = $1",
+        confirmed_id,
+    )
+    .fetch_all(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_eq!(tags, ["vip"]);
+}
@@ -0,0 +1,549 @@
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// 订阅并确认，返回偏好设置令牌
+async fn confirmed_subscriber(app: &TestApp) -> String {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscribe("name=Ferris&email=ferris%40example.com")
+        .await
+        .error_for_status()
+        .unwrap();
+    let link = app.get_confirmation_link().await;
+    reqwest::get(link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let issue = publish_issue(app).await;
+    let link = extract_preferences_link(issue["TextBody"].as_str().unwrap());
+    let url = reqwest::Url::parse(&link).unwrap();
+    url.query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, token)| token.into_owned())
+        .unwrap()
+}
+
+/// 发布简报并返回发出的简报邮件，未发出时返回`Value::Null`
+async fn publish_issue(app: &TestApp) -> Value {
+    app.test_user.login(app).await;
+    let sent_before = app.email_server.received_requests().await.unwrap().len();
+    let res = app.post_publish_with_default_issue(None).await;
+    assert_is_redirect_to(&res, "/admin/dashboard");
+    app.dispatch_all_pending_emails().await;
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    requests[sent_before..]
+        .first()
+        .map(|r| serde_json::from_slice(&r.body).unwrap())
+        .unwrap_or(Value::Null)
+}
+
+fn extract_preferences_link(s: &str) -> String {
+    let links: Vec<_> = linkify::LinkFinder::new()
+        .links(s)
+        .filter(|l| *l.kind() == linkify::LinkKind::Url && l.as_str().contains("/preferences"))
+        .collect();
+    assert_eq!(links.len(), 1);
+    links[0].as_str().to_owned()
+}
+
+async fn get_preferences_html(app: &TestApp, token: &str) -> String {
+    app.get_preferences(token).await.text().await.unwrap()
+}
+
+#[tokio::test]
+async fn issues_link_to_the_preferences_page() {
+    let app = spawn_app().await;
+    let token = confirmed_subscriber(&app).await;
+
+    let html = get_preferences_html(&app, &token).await;
+    assert!(html.contains("ferris@example.com"));
+    assert!(html.contains(r#"name="name" value="Ferris""#));
+}
+
+#[tokio::test]
+async fn html_issues_include_the_preferences_link() {
+    let app = spawn_app().await;
+    confirmed_subscriber(&app).await;
+
+    let issue = publish_issue(&app).await;
+    let html = issue["HtmlBody"].as_str().unwrap();
+    assert_eq!(
+        extract_preferences_link(html),
+        extract_preferences_link(issue["TextBody"].as_str().unwrap())
+    );
+}
+
+#[tokio::test]
+async fn forged_tokens_are_rejected() {
+    let app = spawn_app().await;
+    let token = confirmed_subscriber(&app).await;
+    let (subscriber_id, _) = token.split_once(':').unwrap();
+
+    let res = app.get_preferences(&format!("{subscriber_id}:00ff")).await;
+    assert_eq!(res.status().as_u16(), 401);
+
+    let res = app
+        .post_preferences(&json!({
+            "token": format!("{subscriber_id}:00ff"),
+            "name": "Mallory",
+            "email_format": "html",
+            "pause": "",
+        }))
+        .await;
+    assert_eq!(res.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name() {
+    let app = spawn_app().await;
+    let token = confirmed_subscriber(&app).await;
+    let location = format!("/preferences?token={token}");
+
+    let res = app
+        .post_preferences(&json!({
+            "token": token,
+            "name": "Ferris the Crab",
+            "email_format": "html",
+            "pause": "",
+        }))
+        .await;
+    assert_is_redirect_to(&res, &location);
+    let html = get_preferences_html(&app, &token).await;
+    assert!(html.contains("偏好设置已保存."));
+    assert!(html.contains(r#"name="name" value="Ferris the Crab""#));
+
+    let res = app
+        .post_preferences(&json!({
+            "token": token,
+            "name": "",
+            "email_format": "html",
+            "pause": "",
+        }))
+        .await;
+    assert_is_redirect_to(&res, &location);
+    let html = get_preferences_html(&app, &token).await;
+    assert!(html.contains("姓名无效"));
+    assert!(html.contains(r#"name="name" value="Ferris the Crab""#));
+}
+
+#[tokio::test]
+async fn text_only_subscribers_receive_no_html_body() {
+    let app = spawn_app().await;
+    let token = confirmed_subscriber(&app).await;
+
+    app.post_preferences(&json!({
+        "token": token,
+        "name": "Ferris",
+        "email_format": "text",
+        "pause": "",
+    }))
+    .await;
+
+    let issue = publish_issue(&app).await;
+    assert!(issue.get("HtmlBody").is_none());
+    assert!(issue["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Manage your subscription"));
+}
+
+#[tokio::test]
+async fn paused_subscribers_are_skipped_until_resumed() {
+    let app = spawn_app().await;
+    let token = confirmed_subscriber(&app).await;
+
+    app.post_preferences(&json!({
+        "token": token,
+        "name": "Ferris",
+        "email_format": "html",
+        "pause": "30",
+    }))
+    .await;
+    assert!(get_preferences_html(&app, &token)
+        .await
+        .contains("Paused until"));
+    assert_eq!(publish_issue(&app).await, Value::Null);
+
+    app.post_preferences(&json!({
+        "token": token,
+        "name": "Ferris",
+        "email_format": "html",
+        "pause": "0",
+    }))
+    .await;
+    assert_eq!(publish_issue(&app).await["To"], "ferris@example.com");
+}
+
+#[tokio::test]
+async fn queued_issues_are_dropped_when_the_subscriber_pauses() {
+    let app = spawn_app().await;
+    let token = confirmed_subscriber(&app).await;
+    let res = app.post_publish_with_default_issue(None).await;
+    assert_is_redirect_to(&res, "/admin/dashboard");
+
+    app.post_preferences(&json!({
+        "token": token,
+        "name": "Ferris",
+        "email_format": "html",
+        "pause": "7",
+    }))
+    .await;
+    let sent_before = app.email_server.received_requests().await.unwrap().len();
+    app.dispatch_all_pending_emails().await;
+
+    assert_eq!(
+        app.email_server.received_requests().await.unwrap().len(),
+        sent_before
+    );
+    let queued = sqlx::query_scalar!("SELECT count(*) FROM issue_delivery_queue")
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(queued, Some(0));
+}
+
+#[tokio::test]
+async fn confirmation_emails_link_to_the_preferences_page() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscribe("name=Ferris&email=ferris%40example.com")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email = emails_to(&app, "ferris@example.com").await.pop().unwrap();
+    let link = extract_preferences_link(email["TextBody"].as_str().unwrap());
+    assert!(email["HtmlBody"].as_str().unwrap().contains(&link));
+    let res = app.api_client.get(link).send().await.unwrap();
+    assert_eq!(res.status().as_u16(), 200);
+    assert!(res
+        .text()
+        .await
+        .unwrap()
+        .contains("Preferences for ferris@example.com"));
+}
+
+#[tokio::test]
+async fn invalid_pause_durations_are_rejected() {
+    let app = spawn_app().await;
+    let token = confirmed_subscriber(&app).await;
+
+    let res = app
+        .post_preferences(&json!({
+            "token": token,
+            "name": "Ferris",
+            "email_format": "html",
+            "pause": "3650",
+        }))
+        .await;
+    assert_eq!(res.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn subscribers_can_join_and_leave_other_lists() {
+    let app = spawn_app().await;
+    let token = confirmed_subscriber(&app).await;
+    app.post_create_list(&json!({
+        "slug": "rust-weekly",
+        "name": "Rust Weekly",
+        "sender": "",
+        "confirmation_subject": "Welcome",
+        "confirmation_text": "Welcome",
+    }))
+    .await;
+    let list_id =
+        sqlx::query_scalar!("SELECT list_id FROM mailing_list WHERE slug = 'rust-weekly'")
+            .fetch_one(app.pool.get_ref())
+            .await
+            .unwrap()
+            .to_string();
+    let location = format!("/preferences?token={token}");
+
+    let res = app
+        .post_preference_lists(&json!({"token": token, "list_id": list_id, "action": "join"}))
+        .await;
+    assert_is_redirect_to(&res, &location);
+    assert!(get_preferences_html(&app, &token)
+        .await
+        .contains("已订阅Rust Weekly."));
+    let joined = sqlx::query!(
+        r#"
+        SELECT s.status, c.form_origin
+        FROM subscription s
+        JOIN mailing_list l ON l.list_id = s.list_id
+        JOIN subscription_consent c ON c.subscriber_id = s.id
+        WHERE l.slug = 'rust-weekly'
+        "#
+    )
+    .fetch_one(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_eq!(joined.status, "confirmed");
+    assert_eq!(joined.form_origin, "preferences");
+
+    let res = app
+        .post_preference_lists(&json!({"token": token, "list_id": list_id, "action": "leave"}))
+        .await;
+    assert_is_redirect_to(&res, &location);
+    assert!(get_preferences_html(&app, &token)
+        .await
+        .contains("已退订Rust Weekly."));
+    let status = sqlx::query_scalar!(
+        r#"
+        SELECT s.status FROM subscription s
+        JOIN mailing_list l ON l.list_id = s.list_id
+        WHERE l.slug = 'rust-weekly'
+        "#
+    )
+    .fetch_one(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_ne!(status, "confirmed");
+}
+
+/// 发往指定地址的全部邮件
+async fn emails_to(app: &TestApp, to: &str) -> Vec<Value> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| serde_json::from_slice::<Value>(&r.body).unwrap())
+        .filter(|body| body["To"] == to)
+        .collect()
+}
+
+/// 请求修改为新地址，返回发送到新地址的确认链接
+async fn request_email_change(app: &TestApp, token: &str, new_email: &str) -> reqwest::Url {
+    let res = app
+        .post_preferences_email(&json!({"token": token, "email": new_email}))
+        .await;
+    assert_is_redirect_to(&res, &format!("/preferences?token={token}"));
+    let emails = emails_to(app, new_email).await;
+    assert_eq!(emails.len(), 1);
+    let links: Vec<_> = linkify::LinkFinder::new()
+        .links(emails[0]["TextBody"].as_str().unwrap())
+        .filter(|l| *l.kind() == linkify::LinkKind::Url)
+        .collect();
+    assert_eq!(links.len(), 1);
+    reqwest::Url::parse(links[0].as_str()).unwrap()
+}
+
+#[tokio::test]
+async fn email_is_changed_only_after_the_new_address_confirms() {
+    let app = spawn_app().await;
+    let token = confirmed_subscriber(&app).await;
+
+    let link = request_email_change(&app, &token, "crab@example.com").await;
+    assert!(get_preferences_html(&app, &token)
+        .await
+        .contains("确认邮件已发送至新邮箱地址."));
+    assert_eq!(publish_issue(&app).await["To"], "ferris@example.com");
+
+    let res = app.api_client.get(link.clone()).send().await.unwrap();
+    assert_eq!(res.status().as_u16(), 303);
+    let location = res.headers().get("Location").unwrap().to_str().unwrap();
+    let html = app
+        .api_client
+        .get(app.web_base_url.join(location).unwrap())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("邮箱地址已修改为crab@example.com."));
+    assert!(html.contains("Preferences for crab@example.com"));
+
+    // 旧地址收到通知，之后的简报发送到新地址
+    let notices = emails_to(&app, "ferris@example.com").await;
+    assert_eq!(
+        notices.last().unwrap()["Subject"],
+        "Your subscription email address has changed"
+    );
+    assert_eq!(publish_issue(&app).await["To"], "crab@example.com");
+
+    // 确认链接只能使用一次
+    let res = app.api_client.get(link).send().await.unwrap();
+    assert_eq!(res.status().as_u16(), 401);
+}
+
+/// 确认修改邮箱地址，返回跳转到的偏好设置令牌
+async fn confirm_email_change(app: &TestApp, link: reqwest::Url) -> String {
+    let res = app.api_client.get(link).send().await.unwrap();
+    assert_eq!(res.status().as_u16(), 303);
+    let location = res.headers().get("Location").unwrap().to_str().unwrap();
+    location
+        .strip_prefix("/preferences?token=")
+        .unwrap()
+        .to_owned()
+}
+
+/// 旧地址收到的通知中的撤销链接
+async fn revert_link(app: &TestApp, old_email: &str) -> reqwest::Url {
+    let notices = emails_to(app, old_email).await;
+    let links: Vec<_> = linkify::LinkFinder::new()
+        .links(notices.last().unwrap()["TextBody"].as_str().unwrap())
+        .filter(|l| *l.kind() == linkify::LinkKind::Url)
+        .collect();
+    assert_eq!(links.len(), 1);
+    reqwest::Url::parse(links[0].as_str()).unwrap()
+}
+
+#[tokio::test]
+async fn preferences_links_of_the_old_address_stop_working_after_the_change() {
+    let app = spawn_app().await;
+    let old_token = confirmed_subscriber(&app).await;
+    let link = request_email_change(&app, &old_token, "crab@example.com").await;
+
+    let new_token = confirm_email_change(&app, link).await;
+    assert_ne!(new_token, old_token);
+
+    assert_eq!(app.get_preferences(&old_token).await.status().as_u16(), 401);
+    let res = app
+        .post_preferences_email(&json!({"token": old_token, "email": "evil@example.com"}))
+        .await;
+    assert_eq!(res.status().as_u16(), 401);
+    assert!(get_preferences_html(&app, &new_token)
+        .await
+        .contains("Preferences for crab@example.com"));
+    // 新地址收到的简报中的链接同样有效
+    let issue = publish_issue(&app).await;
+    let link = extract_preferences_link(issue["TextBody"].as_str().unwrap());
+    assert!(link.ends_with(&format!("token={new_token}")));
+}
+
+#[tokio::test]
+async fn the_old_address_can_revert_the_change() {
+    let app = spawn_app().await;
+    let old_token = confirmed_subscriber(&app).await;
+    let link = request_email_change(&app, &old_token, "crab@example.com").await;
+    let new_token = confirm_email_change(&app, link).await;
+
+    // 撤销链接只显示确认页面，不修改数据
+    let link = revert_link(&app, "ferris@example.com").await;
+    assert_eq!(link.path(), "/preferences/email/revert");
+    let html = app
+        .api_client
+        .get(link.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains(r#"action="/preferences/email/revert" method="post""#));
+    assert!(get_preferences_html(&app, &new_token)
+        .await
+        .contains("Preferences for crab@example.com"));
+
+    let revert_token = link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .map(|(_, token)| token.into_owned())
+        .unwrap();
+    let res = app
+        .api_client
+        .post(app.web_base_url.join("/preferences/email/revert").unwrap())
+        .form(&json!({"token": revert_token}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 303);
+    let location = res.headers().get("Location").unwrap().to_str().unwrap();
+    let reverted_token = location.strip_prefix("/preferences?token=").unwrap();
+    let html = get_preferences_html(&app, reverted_token).await;
+    assert!(html.contains("邮箱地址已恢复为ferris@example.com."));
+    assert!(html.contains("Preferences for ferris@example.com"));
+
+    // 修改后的地址收到的链接失效，简报发送回旧地址
+    assert_eq!(app.get_preferences(&new_token).await.status().as_u16(), 401);
+    assert_eq!(publish_issue(&app).await["To"], "ferris@example.com");
+
+    // 撤销链接只能使用一次
+    let res = app
+        .api_client
+        .post(app.web_base_url.join("/preferences/email/revert").unwrap())
+        .form(&json!({"token": revert_token}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn queued_deliveries_follow_the_changed_email() {
+    let app = spawn_app().await;
+    let token = confirmed_subscriber(&app).await;
+    let link = request_email_change(&app, &token, "crab@example.com").await;
+    let res = app.post_publish_with_default_issue(None).await;
+    assert_is_redirect_to(&res, "/admin/dashboard");
+
+    app.api_client
+        .get(link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    let issues: Vec<_> = emails_to(&app, "crab@example.com")
+        .await
+        .into_iter()
+        .filter(|body| body["Subject"] == "Publish Newsletter Test")
+        .collect();
+    assert_eq!(issues.len(), 1);
+}
+
+#[tokio::test]
+async fn email_change_to_an_address_on_the_same_list_is_rejected() {
+    let app = spawn_app().await;
+    let token = confirmed_subscriber(&app).await;
+    app.post_subscribe("name=Crab&email=crab%40example.com")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let res = app
+        .post_preferences_email(&json!({"token": token, "email": "crab@example.com"}))
+        .await;
+    assert_is_redirect_to(&res, &format!("/preferences?token={token}"));
+    assert!(get_preferences_html(&app, &token)
+        .await
+        .contains("新邮箱地址已订阅相同的邮件列表."));
+    // 只收到订阅确认邮件
+    assert_eq!(emails_to(&app, "crab@example.com").await.len(), 1);
+}
+
+#[tokio::test]
+async fn expired_email_change_links_are_rejected() {
+    let app = spawn_app().await;
+    let token = confirmed_subscriber(&app).await;
+    let link = request_email_change(&app, &token, "crab@example.com").await;
+    sqlx::query!("UPDATE email_change_request SET expires_at = now() - interval '1 minute'")
+        .execute(app.pool.get_ref())
+        .await
+        .unwrap();
+
+    let res = app.api_client.get(link).send().await.unwrap();
+    assert_eq!(res.status().as_u16(), 401);
+    assert!(get_preferences_html(&app, &token)
+        .await
+        .contains("Preferences for ferris@example.com"));
+}
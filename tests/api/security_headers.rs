@@ -0,0 +1,81 @@
+use crate::helper::{spawn_app, spawn_app_with_config};
+
+#[tokio::test]
+async fn html_pages_are_served_with_security_headers() {
+    let app = spawn_app().await;
+
+    let res = app
+        .api_client
+        .get(app.web_base_url.join("/login").unwrap())
+        .send()
+        .await
+        .unwrap();
+
+    let headers = res.headers();
+    let csp = headers["Content-Security-Policy"].to_str().unwrap();
+    assert!(csp.contains("default-src 'self'"));
+    assert!(csp.contains("'nonce-"));
+    assert!(!csp.contains("{nonce}"));
+    assert_eq!(headers["X-Frame-Options"], "DENY");
+    assert_eq!(headers["X-Content-Type-Options"], "nosniff");
+    assert_eq!(
+        headers["Referrer-Policy"],
+        "strict-origin-when-cross-origin"
+    );
+    // 本地环境不启用HSTS
+    assert!(headers.get("Strict-Transport-Security").is_none());
+}
+
+#[tokio::test]
+async fn each_response_gets_a_fresh_nonce() {
+    let app = spawn_app().await;
+
+    let mut policies = Vec::new();
+    for _ in 0..2 {
+        let res = app
+            .api_client
+            .get(app.web_base_url.join("/login").unwrap())
+            .send()
+            .await
+            .unwrap();
+        policies.push(
+            res.headers()["Content-Security-Policy"]
+                .to_str()
+                .unwrap()
+                .to_owned(),
+        );
+    }
+
+    assert_ne!(policies[0], policies[1]);
+}
+
+#[tokio::test]
+async fn redirects_are_served_with_security_headers() {
+    let app = spawn_app().await;
+
+    let res = app.get_admin_dashboard().await;
+
+    assert_eq!(res.status().as_u16(), 303);
+    assert!(res.headers().get("Content-Security-Policy").is_some());
+    assert_eq!(res.headers()["X-Frame-Options"], "DENY");
+}
+
+#[tokio::test]
+async fn hsts_is_sent_when_configured() {
+    let app = spawn_app_with_config(|c| {
+        c.security_headers.strict_transport_security = Some("max-age=31536000".into());
+    })
+    .await;
+
+    let res = app
+        .api_client
+        .get(app.web_base_url.join("/login").unwrap())
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(
+        res.headers()["Strict-Transport-Security"],
+        "max-age=31536000"
+    );
+}
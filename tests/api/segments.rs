@@ -0,0 +1,218 @@
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// 直接写入一个已确认的订阅者并打上标签
+async fn insert_tagged_subscriber(app: &TestApp, email: &str, tags: &[&str]) -> Uuid {
+    let id = Uuid::new_v4();
+    let cipher = &app.pii_cipher;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription (
+            id, list_id, name_ciphertext, email_ciphertext, email_index, pii_key_id,
+            subscribed_at, status
+        )
+        VALUES (
+            $1, (SELECT list_id FROM mailing_list WHERE slug = 'default'),
+            $2, $3, $4, $5, now(), 'confirmed'
+        )
+        "#,
+        id,
+        cipher.encrypt("Reader").unwrap(),
+        cipher.encrypt(email).unwrap(),
+        cipher.blind_index(email),
+        cipher.active_key_id(),
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+    for tag in tags {
+        sqlx::query!(
+            "INSERT INTO subscription_tag (subscriber_id, tag) VALUES ($1, $2)",
+            id,
+            tag,
+        )
+        .execute(app.pool.get_ref())
+        .await
+        .unwrap();
+    }
+
+    id
+}
+
+/// 新建"VIPs"分组: 包含`vip`标签且不包含`churned`标签
+async fn create_vip_segment(app: &TestApp) -> Uuid {
+    let res = app
+        .post_create_segment(&json!({
+            "name": "VIPs",
+            "required_tags": "VIP",
+            "excluded_tags": "churned",
+            "status": "",
+            "from": "",
+            "to": "",
+        }))
+        .await;
+    assert_is_redirect_to(&res, "/admin/segments");
+
+    sqlx::query_scalar!("SELECT segment_id FROM segment WHERE name = 'VIPs'")
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_login_to_manage_tags_and_segments() {
+    let app = spawn_app().await;
+    let id = Uuid::new_v4();
+
+    let res = app.post_add_tags(&id, &json!({ "tags": "vip" })).await;
+    assert_is_redirect_to(&res, "/login");
+    let res = app.post_create_segment(&json!({ "name": "VIPs" })).await;
+    assert_is_redirect_to(&res, "/login");
+    assert_is_redirect_to(&app.post_delete_segment(&id).await, "/login");
+}
+
+#[tokio::test]
+async fn tags_can_be_added_and_removed() {
+    let app = spawn_app().await;
+    let id = insert_tagged_subscriber(&app, "git@github.com", &[]).await;
+    app.test_user.login(&app).await;
+
+    let res = app
+        .post_add_tags(&id, &json!({ "tags": "VIP, beta" }))
+        .await;
+    assert_is_redirect_to(&res, &format!("/admin/subscribers/{id}"));
+    let res = app
+        .post_add_tags(&id, &json!({ "tags": "not valid" }))
+        .await;
+    assert_is_redirect_to(&res, &format!("/admin/subscribers/{id}"));
+    let html = app.get_admin_subscriber(&id).await.text().await.unwrap();
+    assert!(html.contains("标签无效: `not valid` is not a valid tag."));
+
+    let res = app.post_remove_tag(&id, &json!({ "tag": "vip" })).await;
+    assert_is_redirect_to(&res, &format!("/admin/subscribers/{id}"));
+
+    let tags = sqlx::query_scalar!(
+        "SELECT tag FROM subscription_tag WHERE subscriber_id = $1",
+        id
+    )
+    .fetch_all(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_eq!(tags, ["beta"]);
+    let actions = sqlx::query_scalar!(
+        "SELECT action FROM audit_log WHERE action <> 'login' ORDER BY created_at"
+    )
+    .fetch_all(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_eq!(
+        actions,
+        ["update_subscriber_tags", "update_subscriber_tags"]
+    );
+}
+
+#[tokio::test]
+async fn segment_sizes_are_shown_on_the_dashboard() {
+    let app = spawn_app().await;
+    insert_tagged_subscriber(&app, "vip@example.com", &["vip"]).await;
+    insert_tagged_subscriber(&app, "churned@example.com", &["vip", "churned"]).await;
+    insert_tagged_subscriber(&app, "reader@example.com", &[]).await;
+    // 待确认及暂停的订阅者不会收到简报，不计入人数
+    let pending = insert_tagged_subscriber(&app, "pending@example.com", &["vip"]).await;
+    let paused = insert_tagged_subscriber(&app, "paused@example.com", &["vip"]).await;
+    sqlx::query!(
+        "UPDATE subscription SET status = 'pending_confirmation' WHERE id = $1",
+        pending,
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+    sqlx::query!(
+        "UPDATE subscription SET paused_until = now() + interval '7 days' WHERE id = $1",
+        paused,
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+    app.test_user.login(&app).await;
+
+    create_vip_segment(&app).await;
+
+    assert!(app.get_segments_html().await.contains("分组已创建."));
+    assert!(app.get_admin_dashboard_html().await.contains("VIPs: 1"));
+
+    // 名称不能重复
+    let res = app
+        .post_create_segment(&json!({
+            "name": "VIPs",
+            "required_tags": "",
+            "excluded_tags": "",
+            "status": "confirmed",
+            "from": "",
+            "to": "",
+        }))
+        .await;
+    assert_is_redirect_to(&res, "/admin/segments");
+    assert!(app.get_segments_html().await.contains("分组名称已存在."));
+}
+
+#[tokio::test]
+async fn issue_targeted_at_a_segment_is_only_queued_for_its_members() {
+    let app = spawn_app().await;
+    let member = insert_tagged_subscriber(&app, "vip@example.com", &["vip"]).await;
+    insert_tagged_subscriber(&app, "churned@example.com", &["vip", "churned"]).await;
+    insert_tagged_subscriber(&app, "reader@example.com", &[]).await;
+    app.test_user.login(&app).await;
+    let segment_id = create_vip_segment(&app).await;
+
+    let res = app
+        .post_publish(&json!({
+            "subject": "VIP news",
+            "text_body": "Just for you.",
+            "html_body": "<p>Just for you.</p>",
+            "segment_id": segment_id.to_string(),
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_is_redirect_to(&res, "/admin/dashboard");
+
+    let queued = sqlx::query_scalar!("SELECT subscriber_id FROM issue_delivery_queue")
+        .fetch_all(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(queued, [member]);
+    let issue_segment = sqlx::query_scalar!("SELECT segment_id FROM newsletter_issue")
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(issue_segment, Some(segment_id));
+
+    // 删除分组后已发布简报的记录仍保留
+    let res = app.post_delete_segment(&segment_id).await;
+    assert_is_redirect_to(&res, "/admin/segments");
+    let issues = sqlx::query_scalar!("SELECT count(*) FROM newsletter_issue")
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(issues, Some(1));
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_segment_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let res = app
+        .post_publish(&json!({
+            "subject": "VIP news",
+            "text_body": "Just for you.",
+            "html_body": "<p>Just for you.</p>",
+            "segment_id": Uuid::new_v4().to_string(),
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    assert_eq!(400, res.status().as_u16());
+}
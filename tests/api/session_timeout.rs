@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+use crate::helper::{assert_is_redirect_to, spawn_app_with_config};
+
+#[tokio::test]
+async fn idle_session_must_sign_in_again() {
+    let app = spawn_app_with_config(|config| {
+        config.web.session_idle_timeout_seconds = 1;
+    })
+    .await;
+    app.test_user.login(&app).await;
+
+    // 1. 超过空闲超时时间后访问
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    let res = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&res, "/login");
+
+    // 2. 跟随重定向
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("<p><i>会话已过期，请重新登录.</i></p>"));
+}
+
+#[tokio::test]
+async fn activity_keeps_session_alive() {
+    let app = spawn_app_with_config(|config| {
+        config.web.session_idle_timeout_seconds = 3;
+    })
+    .await;
+    app.test_user.login(&app).await;
+
+    // 每次访问间隔均小于空闲超时时间
+    for _ in 0..3 {
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let res = app.get_admin_dashboard().await;
+        assert_eq!(200, res.status().as_u16());
+    }
+}
+
+#[tokio::test]
+async fn session_expires_after_absolute_timeout() {
+    let app = spawn_app_with_config(|config| {
+        config.web.session_absolute_timeout_seconds = 3;
+    })
+    .await;
+    app.test_user.login(&app).await;
+
+    // 1. 绝对超时前仍可访问
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let res = app.get_admin_dashboard().await;
+    assert_eq!(200, res.status().as_u16());
+
+    // 2. 即使持续活动，超过绝对超时时间后也需要重新登录
+    tokio::time::sleep(Duration::from_secs(4)).await;
+    let res = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&res, "/login");
+}
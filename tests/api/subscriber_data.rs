@@ -0,0 +1,192 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+
+const EMAIL: &str = "git@github.com";
+
+/// 订阅并确认，再发布一期简报，使待发送队列中存在该订阅者的邮件
+async fn subscribe_with_pending_delivery(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscribe(&format!("name=IceFruit%20huang&email={EMAIL}"))
+        .await
+        .error_for_status()
+        .unwrap();
+    let link = app.get_confirmation_link().await;
+    reqwest::get(link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    app.test_user.login(app).await;
+    app.post_publish_with_default_issue(None).await;
+}
+
+#[tokio::test]
+async fn you_must_login_to_export_or_erase_subscriber_data() {
+    let app = spawn_app().await;
+
+    let res = app.post_subscriber_data_export(EMAIL).await;
+    assert_is_redirect_to(&res, "/login");
+
+    let res = app
+        .post_erase_subscriber(&serde_json::json!({
+            "email": EMAIL,
+            "email_check": EMAIL,
+        }))
+        .await;
+    assert_is_redirect_to(&res, "/login");
+}
+
+#[tokio::test]
+async fn export_does_not_accept_the_email_in_the_url() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let html = app
+        .api_client
+        .get(app.web_base_url.join("/admin/subscriber_data").unwrap())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains(r#"action="/admin/subscriber_data/export" method="post""#));
+
+    let res = app
+        .api_client
+        .get(
+            app.web_base_url
+                .join("/admin/subscriber_data/export")
+                .unwrap(),
+        )
+        .query(&[("email", EMAIL)])
+        .send()
+        .await
+        .unwrap();
+    assert_ne!(200, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn export_contains_everything_held_about_the_email() {
+    let app = spawn_app().await;
+    subscribe_with_pending_delivery(&app).await;
+
+    let res = app.post_subscriber_data_export(EMAIL).await;
+
+    assert_eq!(200, res.status().as_u16());
+    assert!(res
+        .headers()
+        .get("Content-Disposition")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .contains("subscriber_data.json"));
+    let bundle: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(bundle["email"], EMAIL);
+    let subscription = &bundle["subscriptions"][0];
+    assert_eq!(subscription["list"], "Newsletter");
+    assert_eq!(subscription["status"], "confirmed");
+    assert_eq!(
+        subscription["subscription_tokens"]
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(subscription["consent"]["confirmed_at"].is_string());
+    assert_eq!(
+        subscription["pending_deliveries"].as_array().unwrap().len(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn export_is_recorded_in_audit_log_without_the_email() {
+    let app = spawn_app().await;
+    subscribe_with_pending_delivery(&app).await;
+
+    app.post_subscriber_data_export(EMAIL).await;
+
+    let target_id = sqlx::query_scalar!(
+        "SELECT target_id FROM audit_log WHERE action = 'export_subscriber_data'"
+    )
+    .fetch_one(app.pool.get_ref())
+    .await
+    .unwrap()
+    .unwrap();
+    let subscriber_id = sqlx::query_scalar!("SELECT id FROM subscription")
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(target_id, subscriber_id.to_string());
+}
+
+#[tokio::test]
+async fn erase_removes_all_rows_for_the_email() {
+    let app = spawn_app().await;
+    subscribe_with_pending_delivery(&app).await;
+
+    let res = app
+        .post_erase_subscriber(&serde_json::json!({
+            "email": EMAIL,
+            "email_check": EMAIL,
+        }))
+        .await;
+    assert_is_redirect_to(&res, "/admin/subscriber_data");
+
+    let pool = app.pool.get_ref();
+    let count = |table: &'static str| async move {
+        sqlx::query_scalar::<_, i64>(&format!("SELECT count(*) FROM {table}"))
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    };
+    assert_eq!(count("subscription").await, 0);
+    assert_eq!(count("subscription_token").await, 0);
+    assert_eq!(count("subscription_consent").await, 0);
+    assert_eq!(count("issue_delivery_queue").await, 0);
+    let actions =
+        sqlx::query_scalar!("SELECT action FROM audit_log WHERE action = 'erase_subscriber_data'")
+            .fetch_all(pool)
+            .await
+            .unwrap();
+    assert_eq!(actions.len(), 1);
+
+    let bundle: serde_json::Value = app
+        .post_subscriber_data_export(EMAIL)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(bundle["subscriptions"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn erase_requires_the_email_to_be_typed_twice() {
+    let app = spawn_app().await;
+    subscribe_with_pending_delivery(&app).await;
+
+    let res = app
+        .post_erase_subscriber(&serde_json::json!({
+            "email": EMAIL,
+            "email_check": "other@github.com",
+        }))
+        .await;
+    assert_is_redirect_to(&res, "/admin/subscriber_data");
+
+    let count = sqlx::query_scalar!("SELECT count(*) FROM subscription")
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(count, Some(1));
+}
@@ -0,0 +1,212 @@
+use reqwest::header::CONTENT_TYPE;
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// 直接写入一个已确认的订阅者
+async fn insert_existing_subscriber(app: &TestApp, name: &str, email: &str) {
+    let cipher = &app.pii_cipher;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription (
+            id, list_id, name_ciphertext, email_ciphertext, email_index, pii_key_id,
+            subscribed_at, status
+        )
+        VALUES (
+            $1, (SELECT list_id FROM mailing_list WHERE slug = 'default'),
+            $2, $3, $4, $5, now(), 'confirmed'
+        )
+        "#,
+        Uuid::new_v4(),
+        cipher.encrypt(name).unwrap(),
+        cipher.encrypt(email).unwrap(),
+        cipher.blind_index(email),
+        cipher.active_key_id(),
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+}
+
+async fn imported_statuses(app: &TestApp) -> Vec<String> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT s.status FROM subscription s
+        JOIN subscription_consent c ON c.subscriber_id = s.id
+        WHERE c.form_origin = 'import:subscribers.csv'
+        "#
+    )
+    .fetch_all(app.pool.get_ref())
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn you_must_login_to_import_subscribers() {
+    let app = spawn_app().await;
+
+    let res = app.post_import_subscribers("name,email\n", true).await;
+    assert_is_redirect_to(&res, "/login");
+    assert_is_redirect_to(&app.get_import_report(&Uuid::new_v4()).await, "/login");
+}
+
+#[tokio::test]
+async fn attested_import_confirms_valid_rows_and_reports_rejected_ones() {
+    let app = spawn_app().await;
+    insert_existing_subscriber(&app, "Ursula Le Guin", "ursula@example.com").await;
+    app.test_user.login(&app).await;
+    let csv = "Name,Email,Country\n\
+        IceFruit huang,git@github.com,CN\n\
+        Ursula Le Guin,ursula@example.com,US\n\
+        ,nobody@example.com,US\n\
+        Frank Herbert,not-an-email,US\n\
+        Frank Herbert,git@github.com,US\n\
+        \"Le Guin, Ursula\",le.guin@example.com\n";
+
+    let res = app.post_import_subscribers(csv, true).await;
+
+    assert_is_redirect_to(&res, "/admin/subscribers/import");
+    let html = app.get_subscriber_import_html().await;
+    assert!(html.contains("已导入2个订阅者，拒绝4行."));
+    assert_eq!(imported_statuses(&app).await, ["confirmed", "confirmed"]);
+    let unconfirmed =
+        sqlx::query_scalar!("SELECT count(*) FROM subscription_consent WHERE confirmed_at IS NULL")
+            .fetch_one(app.pool.get_ref())
+            .await
+            .unwrap();
+    assert_eq!(unconfirmed, Some(0));
+
+    let import_id = sqlx::query_scalar!("SELECT id FROM subscriber_import")
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert!(html.contains(&format!("/admin/subscribers/import/{import_id}/report")));
+    let res = app.get_import_report(&import_id).await;
+    assert_eq!(200, res.status().as_u16());
+    assert_eq!(res.headers()[CONTENT_TYPE], "text/csv; charset=utf-8");
+    let report = res.text().await.unwrap();
+    let lines: Vec<_> = report.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "line,name,email,reason",
+            "3,Ursula Le Guin,ursula@example.com,already subscribed.",
+            "4,,nobody@example.com,`` is not a valid subscriber name.",
+            "5,Frank Herbert,not-an-email,`not-an-email` is not a valid subscriber email.",
+            "6,Frank Herbert,git@github.com,duplicate of line 2.",
+        ]
+    );
+
+    let actions = sqlx::query_scalar!(
+        "SELECT action FROM audit_log WHERE action <> 'login' ORDER BY created_at"
+    )
+    .fetch_all(app.pool.get_ref())
+    .await
+    .unwrap();
+    assert_eq!(actions, ["import_subscribers", "download_import_report"]);
+}
+
+#[tokio::test]
+async fn pending_import_sends_confirmation_emails() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let csv = "name,email\n\
+        IceFruit huang,git@github.com\n\
+        Ursula Le Guin,ursula@example.com\n";
+    app.post_import_subscribers(csv, false).await;
+    assert_eq!(
+        imported_statuses(&app).await,
+        ["pending_confirmation", "pending_confirmation"]
+    );
+
+    app.dispatch_all_confirmation_emails().await;
+    let link = app.get_confirmation_link().await;
+    let res = reqwest::get(link).await.unwrap();
+
+    assert_eq!(200, res.status().as_u16());
+    let mut statuses = imported_statuses(&app).await;
+    statuses.sort();
+    assert_eq!(statuses, ["confirmed", "pending_confirmation"]);
+}
+
+#[tokio::test]
+async fn file_without_required_columns_is_rejected() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let res = app
+        .post_import_subscribers("name,address\nUrsula,Earthsea\n", true)
+        .await;
+
+    assert_is_redirect_to(&res, "/admin/subscribers/import");
+    let html = app.get_subscriber_import_html().await;
+    assert!(html.contains("the CSV file has no `email` column."));
+    let import = sqlx::query!("SELECT status, imported_count FROM subscriber_import")
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(import.status, "failed");
+    assert_eq!(import.imported_count, 0);
+}
+
+#[tokio::test]
+async fn import_failing_midway_is_recorded_with_the_committed_rows() {
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    // 第一批500行写入后，之后的写入全部失败
+    sqlx::query(
+        r#"
+        CREATE FUNCTION fail_after_first_batch() RETURNS trigger AS $$
+        BEGIN
+            IF (SELECT count(*) FROM subscription) >= 500 THEN
+                RAISE EXCEPTION 'disk full';
+            END IF;
+            RETURN NEW;
+        END;
+        $$ LANGUAGE plpgsql
+        "#,
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+    sqlx::query(
+        r#"
+        CREATE TRIGGER fail_after_first_batch BEFORE INSERT ON subscription
+        FOR EACH ROW EXECUTE FUNCTION fail_after_first_batch()
+        "#,
+    )
+    .execute(app.pool.get_ref())
+    .await
+    .unwrap();
+    let mut csv = String::from("name,email\n");
+    for i in 0..600 {
+        csv.push_str(&format!("Subscriber {i},subscriber{i}@example.com\n"));
+    }
+
+    let res = app.post_import_subscribers(&csv, true).await;
+
+    assert_eq!(500, res.status().as_u16());
+    let import = sqlx::query!("SELECT id, status, imported_count FROM subscriber_import")
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(import.status, "failed");
+    assert_eq!(import.imported_count, 500);
+    let target_ids =
+        sqlx::query_scalar!("SELECT target_id FROM audit_log WHERE action = 'import_subscribers'")
+            .fetch_all(app.pool.get_ref())
+            .await
+            .unwrap();
+    assert_eq!(target_ids, [Some(import.id.to_string())]);
+}
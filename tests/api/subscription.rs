@@ -20,12 +20,23 @@ async fn valid_subscribe() {
     let res = app.post_subscribe(body).await;
     assert_eq!(200, res.status().as_u16());
 
-    let record = sqlx::query!("select name, email, status from subscription")
-        .fetch_one(app.pool.get_ref())
-        .await
-        .expect("failed to execute query.");
-    assert_eq!("IceFruit huang", record.name);
-    assert_eq!("git@github.com", record.email);
+    let record = sqlx::query!(
+        r#"
+        SELECT name_ciphertext AS "name_ciphertext!", email_ciphertext AS "email_ciphertext!", status
+        FROM subscription
+        "#
+    )
+    .fetch_one(app.pool.get_ref())
+    .await
+    .expect("failed to execute query.");
+    assert_eq!(
+        "IceFruit huang",
+        app.pii_cipher.decrypt(&record.name_ciphertext).unwrap()
+    );
+    assert_eq!(
+        "git@github.com",
+        app.pii_cipher.decrypt(&record.email_ciphertext).unwrap()
+    );
     assert_eq!(
         SubscriberStatus::PendingConfirmation.as_str(),
         record.status
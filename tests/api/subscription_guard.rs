@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helper::{spawn_app, spawn_app_with_config, TestApp};
+
+async fn count_subscribers(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM subscription"#)
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap()
+}
+
+/// 被拒绝的订阅不发送确认邮件
+async fn expect_no_email(app: &TestApp) {
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+}
+
+/// 从订阅表单页面中提取表单令牌
+async fn get_form_token(app: &TestApp) -> String {
+    let html_page = app.get_subscribe_html().await;
+    let start = html_page.find(r#"name="form_token" value=""#).unwrap() + 25;
+    let end = start + html_page[start..].find('"').unwrap();
+
+    html_page[start..end].to_owned()
+}
+
+#[tokio::test]
+async fn subscribe_form_contains_honeypot_and_form_token() {
+    let app = spawn_app().await;
+
+    let html_page = app.get_subscribe_html().await;
+
+    assert!(html_page.contains(r#"name="website""#));
+    assert!(!get_form_token(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn disposable_email_domain_is_silently_rejected() {
+    let app = spawn_app().await;
+    expect_no_email(&app).await;
+
+    let res = app
+        .post_subscribe("name=IceFruit%20huang&email=bot%40Mailinator.com")
+        .await;
+
+    // 不透露拒绝原因
+    assert_eq!(200, res.status().as_u16());
+    assert_eq!(count_subscribers(&app).await, 0);
+}
+
+#[tokio::test]
+async fn configured_domains_are_blocked_or_allowed() {
+    let app = spawn_app_with_config(|c| {
+        c.subscription_guard.blocklist = vec!["blocked.example".into()];
+        c.subscription_guard.allowlist = vec!["yopmail.com".into()];
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscribe("name=IceFruit%20huang&email=a%40mx.blocked.example")
+        .await;
+    app.post_subscribe("name=IceFruit%20huang&email=b%40yopmail.com")
+        .await;
+
+    let email = sqlx::query_scalar!(r#"SELECT email_ciphertext AS "email!" FROM subscription"#)
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap();
+    assert_eq!(app.pii_cipher.decrypt(&email).unwrap(), "b@yopmail.com");
+}
+
+#[tokio::test]
+async fn filled_honeypot_is_silently_rejected() {
+    let app = spawn_app().await;
+    expect_no_email(&app).await;
+
+    let res = app
+        .post_subscribe(
+            "name=IceFruit%20huang&email=git%40github.com&website=http%3A%2F%2Fspam.com",
+        )
+        .await;
+
+    assert_eq!(200, res.status().as_u16());
+    assert_eq!(count_subscribers(&app).await, 0);
+}
+
+#[tokio::test]
+async fn form_submitted_too_fast_is_silently_rejected() {
+    let app = spawn_app_with_config(|c| c.subscription_guard.min_fill_seconds = 60).await;
+    expect_no_email(&app).await;
+
+    let form_token = get_form_token(&app).await;
+    for body in [
+        format!("name=IceFruit%20huang&email=git%40github.com&form_token={form_token}"),
+        "name=IceFruit%20huang&email=git%40github.com".into(),
+    ] {
+        let res = app.post_subscribe(&body).await;
+        assert_eq!(200, res.status().as_u16());
+    }
+
+    assert_eq!(count_subscribers(&app).await, 0);
+}
+
+#[tokio::test]
+async fn form_submitted_after_minimum_time_is_accepted() {
+    let app = spawn_app_with_config(|c| c.subscription_guard.min_fill_seconds = 1).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let form_token = get_form_token(&app).await;
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+    let res = app
+        .post_subscribe(&format!(
+            "name=IceFruit%20huang&email=git%40github.com&form_token={form_token}"
+        ))
+        .await;
+
+    assert_eq!(200, res.status().as_u16());
+    assert_eq!(count_subscribers(&app).await, 1);
+}
+
+#[tokio::test]
+async fn blocklist_file_is_reloaded_without_restart() {
+    let blocklist = std::env::temp_dir().join(format!("blocklist-{}.txt", uuid::Uuid::new_v4()));
+    std::fs::write(&blocklist, "").unwrap();
+    let blocklist_path = blocklist.to_str().unwrap().to_owned();
+    let app = spawn_app_with_config(|c| {
+        c.subscription_guard.blocklist_path = Some(blocklist_path);
+        c.subscription_guard.reload_interval_seconds = 1;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscribe("name=IceFruit%20huang&email=a%40new-spam.example")
+        .await;
+    std::fs::write(&blocklist, "new-spam.example\n").unwrap();
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    app.post_subscribe("name=IceFruit%20huang&email=b%40new-spam.example")
+        .await;
+
+    assert_eq!(count_subscribers(&app).await, 1);
+    std::fs::remove_file(&blocklist).unwrap();
+}
+
+#[tokio::test]
+async fn rejected_subscriptions_are_counted_on_dashboard() {
+    let app = spawn_app().await;
+    app.post_subscribe("name=IceFruit%20huang&email=bot%40mailinator.com")
+        .await;
+    app.test_user.login(&app).await;
+
+    let html_page = app.get_admin_dashboard_html().await;
+
+    assert!(html_page.contains("Rejected subscriptions:"));
+    assert!(html_page.contains("<li>blocked_domain: "));
+    assert!(!html_page.contains("<li>blocked_domain: 0</li>"));
+}
@@ -0,0 +1,199 @@
+use sha2::{Digest, Sha256};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helper::{spawn_app, spawn_app_with_config, TestApp};
+
+const DIFFICULTY: u32 = 8;
+
+async fn spawn_app_with_pow() -> TestApp {
+    spawn_app_with_config(|c| {
+        c.proof_of_work.enabled = true;
+        c.proof_of_work.difficulty_bits = DIFFICULTY;
+    })
+    .await
+}
+
+fn meets_difficulty(challenge: &str, nonce: &str, difficulty: u32) -> bool {
+    let hash = Sha256::digest(format!("{challenge}:{nonce}").as_bytes());
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+
+    bits >= difficulty
+}
+
+/// 暴力搜索满足(或不满足)难度要求的nonce
+fn find_nonce(challenge: &str, valid: bool) -> String {
+    (0u64..)
+        .map(|n| n.to_string())
+        .find(|nonce| meets_difficulty(challenge, nonce, DIFFICULTY) == valid)
+        .unwrap()
+}
+
+fn subscribe_body(challenge: &str, nonce: &str) -> String {
+    serde_urlencoded::to_string([
+        ("name", "IceFruit huang"),
+        ("email", "git@github.com"),
+        ("pow_challenge", challenge),
+        ("pow_nonce", nonce),
+    ])
+    .unwrap()
+}
+
+/// 表单页面中隐藏输入框的值
+fn hidden_value<'a>(html: &'a str, name: &str) -> &'a str {
+    let marker = format!(r#"name="{name}" value=""#);
+    let start = html.find(&marker).unwrap() + marker.len();
+    let end = start + html[start..].find('"').unwrap();
+
+    &html[start..end]
+}
+
+async fn count_subscribers(app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM subscription"#)
+        .fetch_one(app.pool.get_ref())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn challenge_is_not_issued_when_disabled() {
+    let app = spawn_app().await;
+
+    let res = app.get_subscription_challenge().await;
+
+    assert_eq!(404, res.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_with_valid_solution_is_accepted() {
+    let app = spawn_app_with_pow().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let challenge: serde_json::Value = app.get_subscription_challenge().await.json().await.unwrap();
+    assert_eq!(challenge["difficulty"], DIFFICULTY);
+    let challenge = challenge["challenge"].as_str().unwrap();
+    let nonce = find_nonce(challenge, true);
+
+    let res = app.post_subscribe(&subscribe_body(challenge, &nonce)).await;
+
+    assert_eq!(200, res.status().as_u16());
+    assert_eq!(count_subscribers(&app).await, 1);
+}
+
+#[tokio::test]
+async fn subscribe_form_carries_a_challenge_and_solver() {
+    let app = spawn_app_with_pow().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let html = app.get_subscribe_html().await;
+    assert!(html.contains(r#"<input hidden type="text" name="pow_nonce" value="" />"#));
+    assert!(html.contains("<script nonce="));
+    // 与页面脚本一样求解表单中的挑战，再提交表单的全部字段
+    let challenge = hidden_value(&html, "pow_challenge");
+    let body = serde_urlencoded::to_string([
+        ("list", hidden_value(&html, "list")),
+        ("name", "IceFruit huang"),
+        ("email", "git@github.com"),
+        ("website", ""),
+        ("form_token", hidden_value(&html, "form_token")),
+        ("pow_challenge", challenge),
+        ("pow_nonce", &find_nonce(challenge, true)),
+    ])
+    .unwrap();
+
+    let res = app.post_subscribe(&body).await;
+
+    assert_eq!(200, res.status().as_u16());
+    assert_eq!(count_subscribers(&app).await, 1);
+}
+
+#[tokio::test]
+async fn subscribe_form_has_no_challenge_when_disabled() {
+    let app = spawn_app().await;
+
+    let html = app.get_subscribe_html().await;
+
+    assert!(!html.contains("pow_challenge"));
+    assert!(!html.contains("<script"));
+}
+
+#[tokio::test]
+async fn subscribe_without_solution_is_rejected() {
+    let app = spawn_app_with_pow().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let res = app
+        .post_subscribe("name=IceFruit%20huang&email=git%40github.com")
+        .await;
+
+    assert_eq!(400, res.status().as_u16());
+    assert_eq!(count_subscribers(&app).await, 0);
+}
+
+#[tokio::test]
+async fn subscribe_with_invalid_solution_is_rejected() {
+    let app = spawn_app_with_pow().await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let challenge: serde_json::Value = app.get_subscription_challenge().await.json().await.unwrap();
+    let challenge = challenge["challenge"].as_str().unwrap();
+    // 伪造的挑战
+    let forged_challenge = format!("{challenge}0");
+
+    for body in [
+        subscribe_body(challenge, &find_nonce(challenge, false)),
+        subscribe_body(&forged_challenge, &find_nonce(&forged_challenge, true)),
+    ] {
+        let res = app.post_subscribe(&body).await;
+        assert_eq!(400, res.status().as_u16());
+    }
+    assert_eq!(count_subscribers(&app).await, 0);
+}
+
+#[tokio::test]
+async fn solution_cannot_be_reused() {
+    let app = spawn_app_with_pow().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let challenge: serde_json::Value = app.get_subscription_challenge().await.json().await.unwrap();
+    let challenge = challenge["challenge"].as_str().unwrap();
+    let nonce = find_nonce(challenge, true);
+    let body = subscribe_body(challenge, &nonce);
+
+    let res = app.post_subscribe(&body).await;
+    assert_eq!(200, res.status().as_u16());
+
+    let res = app.post_subscribe(&body).await;
+    assert_eq!(400, res.status().as_u16());
+    assert_eq!(count_subscribers(&app).await, 1);
+}